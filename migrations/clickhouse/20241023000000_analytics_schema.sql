@@ -0,0 +1,123 @@
+-- Row tables are ReplacingMergeTree versioned by slot, so re-indexing a slot
+-- replaces its rows. Queries read them with FINAL.
+CREATE TABLE IF NOT EXISTS compressed_accounts (
+    pubkey String,
+    slot UInt64,
+    lamports Int64,
+    owner String,
+    executable Bool,
+    rent_epoch Int64,
+    data String,
+    proof String,
+    INDEX owner_idx owner TYPE bloom_filter GRANULARITY 4
+) ENGINE = ReplacingMergeTree(slot)
+ORDER BY pubkey;
+
+CREATE TABLE IF NOT EXISTS compressed_blocks (
+    slot UInt64,
+    blockhash String,
+    previous_blockhash String,
+    parent_slot UInt64,
+    transactions UInt64,
+    block_time DateTime,
+    data String,
+    proof String
+) ENGINE = ReplacingMergeTree(slot)
+PARTITION BY intDiv(slot, 432000)
+ORDER BY slot;
+
+CREATE TABLE IF NOT EXISTS compressed_transactions (
+    signature String,
+    slot UInt64,
+    tx_index UInt32,
+    block_time DateTime,
+    accounts Array(String),
+    fee UInt64,
+    programs Array(String),
+    data String,
+    proof String
+) ENGINE = ReplacingMergeTree(slot)
+ORDER BY signature;
+
+CREATE TABLE IF NOT EXISTS transactions_by_slot (
+    slot UInt64,
+    tx_index UInt32,
+    signature String
+) ENGINE = ReplacingMergeTree
+ORDER BY (slot, tx_index);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS transactions_by_slot_mv TO transactions_by_slot AS
+SELECT slot, tx_index, signature FROM compressed_transactions;
+
+CREATE TABLE IF NOT EXISTS signatures_by_address (
+    address String,
+    slot UInt64,
+    tx_index UInt32,
+    signature String
+) ENGINE = ReplacingMergeTree
+ORDER BY (address, slot, tx_index);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS signatures_by_address_mv TO signatures_by_address AS
+SELECT arrayJoin(accounts) AS address, slot, tx_index, signature FROM compressed_transactions;
+
+CREATE TABLE IF NOT EXISTS token_transfers (
+    signature String,
+    slot UInt64,
+    block_time DateTime,
+    mint String,
+    owner String,
+    amount UInt64
+) ENGINE = ReplacingMergeTree(slot)
+ORDER BY (signature, mint, owner);
+
+CREATE TABLE IF NOT EXISTS indexer_state (
+    key String,
+    value UInt64,
+    updated_at DateTime64(6) DEFAULT now64(6)
+) ENGINE = ReplacingMergeTree(updated_at)
+ORDER BY key;
+
+-- Hourly aggregates. Each slot arrives in a single insert, so rows are kept per
+-- slot and a replayed slot replaces its aggregate instead of counting twice.
+-- Dashboards sum over FINAL, e.g.
+--   SELECT hour, program, sum(transactions) FROM program_transactions_hourly FINAL GROUP BY hour, program
+CREATE TABLE IF NOT EXISTS program_transactions_hourly (
+    hour DateTime,
+    program String,
+    slot UInt64,
+    transactions UInt64,
+    fees UInt64
+) ENGINE = ReplacingMergeTree
+ORDER BY (hour, program, slot);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS program_transactions_hourly_mv TO program_transactions_hourly AS
+SELECT toStartOfHour(block_time) AS hour, arrayJoin(programs) AS program, slot, count() AS transactions, sum(fee) AS fees
+FROM compressed_transactions
+GROUP BY hour, program, slot;
+
+CREATE TABLE IF NOT EXISTS fees_hourly (
+    hour DateTime,
+    slot UInt64,
+    transactions UInt64,
+    fees UInt64
+) ENGINE = ReplacingMergeTree
+ORDER BY (hour, slot);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS fees_hourly_mv TO fees_hourly AS
+SELECT toStartOfHour(block_time) AS hour, slot, count() AS transactions, sum(fee) AS fees
+FROM compressed_transactions
+GROUP BY hour, slot;
+
+CREATE TABLE IF NOT EXISTS token_transfer_volume_hourly (
+    hour DateTime,
+    mint String,
+    slot UInt64,
+    transfers UInt64,
+    volume UInt128
+) ENGINE = ReplacingMergeTree
+ORDER BY (hour, mint, slot);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS token_transfer_volume_hourly_mv TO token_transfer_volume_hourly AS
+SELECT toStartOfHour(block_time) AS hour, mint, slot, count() AS transfers, sum(toUInt128(amount)) AS volume
+FROM token_transfers
+GROUP BY hour, mint, slot;
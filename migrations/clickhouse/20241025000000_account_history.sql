@@ -0,0 +1,19 @@
+-- Every account write, fed from compressed_accounts, which keeps only the newest version.
+ALTER TABLE compressed_accounts ADD COLUMN IF NOT EXISTS write_version UInt64 AFTER slot;
+
+CREATE TABLE IF NOT EXISTS account_versions (
+    pubkey String,
+    slot UInt64,
+    write_version UInt64,
+    lamports Int64,
+    owner String,
+    executable Bool,
+    rent_epoch Int64,
+    data String,
+    proof String
+) ENGINE = ReplacingMergeTree
+PARTITION BY intDiv(slot, 432000)
+ORDER BY (pubkey, slot, write_version);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS account_versions_mv TO account_versions AS
+SELECT pubkey, slot, write_version, lamports, owner, executable, rent_epoch, data, proof FROM compressed_accounts;
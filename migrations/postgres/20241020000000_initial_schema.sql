@@ -0,0 +1,32 @@
+CREATE TABLE IF NOT EXISTS compressed_accounts (
+    pubkey BYTEA PRIMARY KEY,
+    lamports BIGINT NOT NULL,
+    owner BYTEA NOT NULL,
+    executable BOOLEAN NOT NULL,
+    rent_epoch BIGINT NOT NULL,
+    data BYTEA NOT NULL,
+    proof BYTEA NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS compressed_accounts_owner_idx ON compressed_accounts (owner);
+
+CREATE TABLE IF NOT EXISTS compressed_blocks (
+    slot BIGINT PRIMARY KEY,
+    blockhash TEXT NOT NULL,
+    previous_blockhash TEXT NOT NULL,
+    parent_slot BIGINT NOT NULL,
+    transactions BIGINT NOT NULL,
+    data BYTEA NOT NULL,
+    proof BYTEA NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS compressed_transactions (
+    signature BYTEA PRIMARY KEY,
+    data BYTEA NOT NULL,
+    proof BYTEA NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS indexer_state (
+    key TEXT PRIMARY KEY,
+    value BIGINT NOT NULL
+);
@@ -0,0 +1,13 @@
+ALTER TABLE compressed_transactions ADD COLUMN IF NOT EXISTS slot BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE compressed_transactions ADD COLUMN IF NOT EXISTS tx_index INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE compressed_transactions ADD COLUMN IF NOT EXISTS accounts BYTEA[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX IF NOT EXISTS compressed_transactions_slot_idx ON compressed_transactions (slot, tx_index);
+
+CREATE TABLE IF NOT EXISTS signatures_by_address (
+    address BYTEA NOT NULL,
+    slot BIGINT NOT NULL,
+    tx_index INTEGER NOT NULL,
+    signature BYTEA NOT NULL,
+    PRIMARY KEY (address, slot, tx_index)
+);
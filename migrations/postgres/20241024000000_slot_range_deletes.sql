@@ -0,0 +1,2 @@
+-- Lets slot range deletes find index rows without scanning every address.
+CREATE INDEX IF NOT EXISTS signatures_by_address_slot_idx ON signatures_by_address (slot);
@@ -0,0 +1,20 @@
+ALTER TABLE compressed_accounts ADD COLUMN IF NOT EXISTS slot BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE compressed_accounts ADD COLUMN IF NOT EXISTS write_version BIGINT NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS account_versions (
+    pubkey BYTEA NOT NULL,
+    slot BIGINT NOT NULL,
+    write_version BIGINT NOT NULL,
+    lamports BIGINT NOT NULL,
+    owner BYTEA NOT NULL,
+    executable BOOLEAN NOT NULL,
+    rent_epoch BIGINT NOT NULL,
+    data BYTEA NOT NULL,
+    proof BYTEA NOT NULL,
+    PRIMARY KEY (pubkey, slot, write_version)
+);
+
+-- Accounts written before versioning become their own first version.
+INSERT INTO account_versions (pubkey, slot, write_version, lamports, owner, executable, rent_epoch, data, proof)
+SELECT pubkey, slot, write_version, lamports, owner, executable, rent_epoch, data, proof FROM compressed_accounts
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,2 @@
+-- Pruning scans account versions by age.
+CREATE INDEX IF NOT EXISTS account_versions_slot_idx ON account_versions (slot);
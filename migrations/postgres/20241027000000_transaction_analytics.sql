@@ -0,0 +1,3 @@
+ALTER TABLE compressed_blocks ADD COLUMN IF NOT EXISTS block_time BIGINT;
+ALTER TABLE compressed_transactions ADD COLUMN IF NOT EXISTS fee BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE compressed_transactions ADD COLUMN IF NOT EXISTS programs BYTEA[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,207 @@
+-- Every table ScyllaStorage reads and writes. Keyspaces set up by hand from the old V1-V9
+-- scripts already have them, so this is a no-op there apart from recording the version.
+
+CREATE TABLE IF NOT EXISTS compressed_accounts (
+    pubkey blob PRIMARY KEY,
+    lamports bigint,
+    owner blob,
+    executable boolean,
+    rent_epoch bigint,
+    slot bigint,
+    write_version bigint,
+    data blob,
+    proof blob
+);
+
+CREATE TABLE IF NOT EXISTS compressed_accounts_by_owner (
+    owner blob,
+    pubkey blob,
+    PRIMARY KEY (owner, pubkey)
+);
+
+-- Every account write, partitioned by (pubkey, epoch) so busy accounts don't grow one unbounded partition.
+CREATE TABLE IF NOT EXISTS account_versions_by_epoch (
+    pubkey blob,
+    epoch bigint,
+    slot bigint,
+    write_version bigint,
+    lamports bigint,
+    owner blob,
+    executable boolean,
+    rent_epoch bigint,
+    data blob,
+    proof blob,
+    PRIMARY KEY ((pubkey, epoch), slot, write_version)
+) WITH CLUSTERING ORDER BY (slot DESC, write_version DESC);
+
+-- Epochs in which each account has versions.
+CREATE TABLE IF NOT EXISTS account_version_epochs (
+    pubkey blob,
+    epoch bigint,
+    PRIMARY KEY (pubkey, epoch)
+) WITH CLUSTERING ORDER BY (epoch DESC);
+
+-- One partition per epoch (432000 slots), so slot ranges are clustering scans.
+CREATE TABLE IF NOT EXISTS compressed_blocks_by_epoch (
+    epoch bigint,
+    slot bigint,
+    blockhash text,
+    previous_blockhash text,
+    parent_slot bigint,
+    transactions bigint,
+    data blob,
+    proof blob,
+    PRIMARY KEY (epoch, slot)
+) WITH CLUSTERING ORDER BY (slot ASC);
+
+CREATE TABLE IF NOT EXISTS compressed_transactions (
+    signature blob PRIMARY KEY,
+    slot bigint,
+    tx_index int,
+    accounts list<blob>,
+    data blob,
+    proof blob
+);
+
+CREATE TABLE IF NOT EXISTS compressed_transactions_by_slot (
+    slot bigint,
+    tx_index int,
+    signature blob,
+    PRIMARY KEY (slot, tx_index)
+) WITH CLUSTERING ORDER BY (tx_index ASC);
+
+CREATE TABLE IF NOT EXISTS signatures_by_address (
+    address blob,
+    slot bigint,
+    tx_index int,
+    signature blob,
+    PRIMARY KEY (address, slot, tx_index)
+) WITH CLUSTERING ORDER BY (slot DESC, tx_index DESC);
+
+CREATE TABLE IF NOT EXISTS indexer_cursor (
+    name text PRIMARY KEY,
+    slot bigint
+);
+
+CREATE TABLE IF NOT EXISTS merkle_trees (
+    tree blob PRIMARY KEY,
+    max_depth int,
+    max_buffer_size int,
+    leaves list<blob>,
+    recent_roots list<blob>,
+    seq bigint,
+    slot bigint,
+    hasher tinyint
+);
+
+CREATE TABLE IF NOT EXISTS merkle_tree_roots (
+    tree blob,
+    seq bigint,
+    root blob,
+    slot bigint,
+    PRIMARY KEY (tree, seq)
+) WITH CLUSTERING ORDER BY (seq DESC);
+
+CREATE TABLE IF NOT EXISTS merkle_leaf_changes (
+    tree blob,
+    seq bigint,
+    leaf_index int,
+    previous_leaf blob,
+    new_leaf blob,
+    slot bigint,
+    PRIMARY KEY (tree, seq)
+) WITH CLUSTERING ORDER BY (seq DESC);
+
+CREATE TABLE IF NOT EXISTS state_accounts (
+    hash blob PRIMARY KEY,
+    address blob,
+    owner blob,
+    lamports bigint,
+    discriminator blob,
+    data blob,
+    data_hash blob,
+    tree blob,
+    leaf_index int,
+    seq bigint,
+    slot_created bigint,
+    spent boolean,
+    spent_slot bigint
+);
+
+CREATE TABLE IF NOT EXISTS state_accounts_by_owner (
+    owner blob,
+    hash blob,
+    PRIMARY KEY (owner, hash)
+);
+
+CREATE TABLE IF NOT EXISTS state_accounts_by_address (
+    address blob PRIMARY KEY,
+    hash blob
+);
+
+CREATE TABLE IF NOT EXISTS addresses (
+    address blob PRIMARY KEY,
+    tree blob,
+    leaf_index int,
+    slot bigint,
+    next_address blob
+);
+
+CREATE TABLE IF NOT EXISTS addresses_by_tree (
+    tree blob,
+    address blob,
+    PRIMARY KEY (tree, address)
+) WITH CLUSTERING ORDER BY (address ASC);
+
+CREATE TABLE IF NOT EXISTS token_accounts (
+    hash blob PRIMARY KEY,
+    mint blob,
+    owner blob,
+    amount bigint,
+    delegate blob,
+    state tinyint,
+    tlv blob,
+    slot bigint
+);
+
+CREATE TABLE IF NOT EXISTS token_accounts_by_owner (
+    owner blob,
+    hash blob,
+    PRIMARY KEY (owner, hash)
+);
+
+CREATE TABLE IF NOT EXISTS token_accounts_by_mint (
+    mint blob,
+    hash blob,
+    PRIMARY KEY (mint, hash)
+);
+
+CREATE TABLE IF NOT EXISTS token_mints (
+    mint blob PRIMARY KEY,
+    program_id blob,
+    supply bigint,
+    decimals tinyint,
+    slot bigint
+);
+
+CREATE TABLE IF NOT EXISTS compressed_assets (
+    asset_id blob PRIMARY KEY,
+    tree blob,
+    leaf_index int,
+    nonce bigint,
+    owner blob,
+    delegate blob,
+    data_hash blob,
+    creator_hash blob,
+    leaf_hash blob,
+    seq bigint,
+    burned boolean,
+    redeemed boolean,
+    slot_updated bigint
+);
+
+CREATE TABLE IF NOT EXISTS compressed_assets_by_owner (
+    owner blob,
+    asset_id blob,
+    PRIMARY KEY (owner, asset_id)
+);
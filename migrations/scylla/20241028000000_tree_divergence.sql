@@ -0,0 +1,2 @@
+-- Seq at which an indexed tree stopped matching the chain.
+ALTER TABLE merkle_trees ADD diverged_seq bigint;
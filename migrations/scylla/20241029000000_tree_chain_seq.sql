@@ -0,0 +1,2 @@
+-- On-chain sequence number of an indexed tree, which root indices are derived from.
+ALTER TABLE merkle_trees ADD chain_seq bigint;
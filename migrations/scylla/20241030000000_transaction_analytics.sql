@@ -0,0 +1,2 @@
+ALTER TABLE compressed_blocks_by_epoch ADD block_time bigint;
+ALTER TABLE compressed_transactions ADD (fee bigint, programs list<blob>);
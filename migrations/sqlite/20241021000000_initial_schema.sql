@@ -0,0 +1,32 @@
+CREATE TABLE IF NOT EXISTS compressed_accounts (
+    pubkey BLOB PRIMARY KEY,
+    lamports INTEGER NOT NULL,
+    owner BLOB NOT NULL,
+    executable BOOLEAN NOT NULL,
+    rent_epoch INTEGER NOT NULL,
+    data BLOB NOT NULL,
+    proof BLOB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS compressed_accounts_owner_idx ON compressed_accounts (owner);
+
+CREATE TABLE IF NOT EXISTS compressed_blocks (
+    slot INTEGER PRIMARY KEY,
+    blockhash TEXT NOT NULL,
+    previous_blockhash TEXT NOT NULL,
+    parent_slot INTEGER NOT NULL,
+    transactions INTEGER NOT NULL,
+    data BLOB NOT NULL,
+    proof BLOB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS compressed_transactions (
+    signature BLOB PRIMARY KEY,
+    data BLOB NOT NULL,
+    proof BLOB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS indexer_state (
+    key TEXT PRIMARY KEY,
+    value INTEGER NOT NULL
+);
@@ -0,0 +1,14 @@
+ALTER TABLE compressed_transactions ADD COLUMN slot INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE compressed_transactions ADD COLUMN tx_index INTEGER NOT NULL DEFAULT 0;
+-- bincode-encoded list of account keys
+ALTER TABLE compressed_transactions ADD COLUMN accounts BLOB NOT NULL DEFAULT x'0000000000000000';
+
+CREATE INDEX IF NOT EXISTS compressed_transactions_slot_idx ON compressed_transactions (slot, tx_index);
+
+CREATE TABLE IF NOT EXISTS signatures_by_address (
+    address BLOB NOT NULL,
+    slot INTEGER NOT NULL,
+    tx_index INTEGER NOT NULL,
+    signature BLOB NOT NULL,
+    PRIMARY KEY (address, slot, tx_index)
+) WITHOUT ROWID;
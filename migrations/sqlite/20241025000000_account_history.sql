@@ -0,0 +1,19 @@
+ALTER TABLE compressed_accounts ADD COLUMN slot INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE compressed_accounts ADD COLUMN write_version INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS account_versions (
+    pubkey BLOB NOT NULL,
+    slot INTEGER NOT NULL,
+    write_version INTEGER NOT NULL,
+    lamports INTEGER NOT NULL,
+    owner BLOB NOT NULL,
+    executable BOOLEAN NOT NULL,
+    rent_epoch INTEGER NOT NULL,
+    data BLOB NOT NULL,
+    proof BLOB NOT NULL,
+    PRIMARY KEY (pubkey, slot, write_version)
+);
+
+-- Accounts written before versioning become their own first version.
+INSERT OR IGNORE INTO account_versions (pubkey, slot, write_version, lamports, owner, executable, rent_epoch, data, proof)
+SELECT pubkey, slot, write_version, lamports, owner, executable, rent_epoch, data, proof FROM compressed_accounts;
@@ -0,0 +1,4 @@
+ALTER TABLE compressed_blocks ADD COLUMN block_time INTEGER;
+ALTER TABLE compressed_transactions ADD COLUMN fee INTEGER NOT NULL DEFAULT 0;
+-- bincode-encoded list of program ids
+ALTER TABLE compressed_transactions ADD COLUMN programs BLOB NOT NULL DEFAULT x'0000000000000000';
@@ -1,27 +1,115 @@
-use warp::Filter;
-use crate::storage::database::Database;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+use crate::storage::{AccountDataDecoder, Database, StorageError};
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::Arc;
 use crate::utils::error::Error;
 
+const MAX_HISTORY_LIMIT: usize = 1000;
+
+impl warp::reject::Reject for Error {}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    #[serde(default)]
+    start_slot: u64,
+    end_slot: Option<u64>,
+    limit: Option<usize>,
+}
+
 pub fn routes(
-    db: Database,
+    db: Arc<dyn Database>,
+    decode: Arc<AccountDataDecoder>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     get_compressed_account(db.clone())
-        .or(get_compressed_balance(db.clone()))
-        .or(get_compressed_token_balance(db))
+        .or(get_account_at_slot(db.clone()))
+        .or(get_account_history(db, decode))
+}
+
+/// Turns malformed requests into 400 and storage errors into 404, 409 and 503 responses, so
+/// clients can tell a missing account from an outage and know when a retry may help.
+pub async fn recover(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    let Some(error) = rejection.find::<Error>() else {
+        return Err(rejection);
+    };
+    let status = match error {
+        Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+        Error::Storage(StorageError::NotFound(_)) => StatusCode::NOT_FOUND,
+        Error::Storage(StorageError::Conflict(_)) => StatusCode::CONFLICT,
+        Error::Storage(StorageError::Unavailable(_)) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let body = warp::reply::json(&serde_json::json!({ "error": error.to_string() }));
+    Ok(warp::reply::with_status(body, status))
+}
+
+fn parse_pubkey(address: &str) -> Result<Pubkey, warp::Rejection> {
+    Pubkey::from_str(address)
+        .map_err(|e| warp::reject::custom(Error::BadRequest(format!("Invalid pubkey {}: {}", address, e))))
 }
 
 fn get_compressed_account(
-    db: Database,
+    db: Arc<dyn Database>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("api" / "account" / String)
         .and(warp::get())
         .and_then(move |address: String| {
             let db = db.clone();
             async move {
-                db.get_compressed_account(&address)
+                let pubkey = parse_pubkey(&address)?;
+                db.get_compressed_account(pubkey.as_ref())
                     .await
                     .map(|account| warp::reply::json(&account))
                     .map_err(|e| warp::reject::custom(Error::from(e)))
             }
         })
 }
+
+fn get_account_at_slot(
+    db: Arc<dyn Database>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "account" / String / "slot" / u64)
+        .and(warp::get())
+        .and_then(move |address: String, slot: u64| {
+            let db = db.clone();
+            async move {
+                let pubkey = parse_pubkey(&address)?;
+                match db.get_account_at_slot(&pubkey.to_bytes(), slot).await {
+                    Ok(Some(account)) => Ok(warp::reply::json(&account)),
+                    Ok(None) => Err(warp::reject::custom(Error::Storage(StorageError::not_found(format!(
+                        "account {} at slot {}",
+                        address, slot
+                    ))))),
+                    Err(e) => Err(warp::reject::custom(Error::from(e))),
+                }
+            }
+        })
+}
+
+/// Changes to the account over `start_slot..=end_slot`, with lamport and data diffs.
+///
+/// RPC only serves current state, so versions are the states the indexer observed, each at
+/// the slot it was read at. A state that came and went between two reads is not recorded.
+fn get_account_history(
+    db: Arc<dyn Database>,
+    decode: Arc<AccountDataDecoder>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "account" / String / "history")
+        .and(warp::get())
+        .and(warp::query::<HistoryQuery>())
+        .and_then(move |address: String, query: HistoryQuery| {
+            let db = db.clone();
+            let decode = decode.clone();
+            async move {
+                let pubkey = parse_pubkey(&address)?;
+                let limit = query.limit.unwrap_or(MAX_HISTORY_LIMIT).min(MAX_HISTORY_LIMIT);
+                let end_slot = query.end_slot.unwrap_or(u64::MAX);
+                db.get_account_changes(&pubkey.to_bytes(), query.start_slot, end_slot, limit, decode.as_ref())
+                    .await
+                    .map(|changes| warp::reply::json(&changes))
+                    .map_err(|e| warp::reject::custom(Error::from(e)))
+            }
+        })
+}
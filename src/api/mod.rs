@@ -1,17 +1,25 @@
 mod handlers;
 mod middleware;
 
-use crate::storage::database::Database;
+use crate::storage::{AccountDataDecoder, Database};
+use anyhow::Result;
+use std::future::Future;
+use std::sync::Arc;
 use warp::Filter;
 
 pub fn start_server(
     port: u16,
-    storage: Arc<Database>,
-) -> Result<impl std::future::Future<Output = Result<(), anyhow::Error>>> {
-    let api = handlers::routes(db)
+    storage: Arc<dyn Database>,
+    decode: Arc<AccountDataDecoder>,
+) -> Result<impl Future<Output = Result<()>>> {
+    let api = handlers::routes(storage, decode)
+        .recover(handlers::recover)
         .with(middleware::logging())
         .with(middleware::cors());
 
-    Ok(warp::serve(api).run(([0, 0, 0, 0], port)))
-    unimplemented!("API server not implemented yet")
+    let server = warp::serve(api).run(([0, 0, 0, 0], port));
+    Ok(async move {
+        server.await;
+        Ok(())
+    })
 }
@@ -1,4 +1,4 @@
-use crate::compression::poseidon::poseidon_hash;
+use crate::compression::poseidon::{hash_to_field, poseidon_hash};
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_sdk::pubkey::Pubkey;
 
@@ -51,7 +51,7 @@ impl CompressedAccount {
         input.extend_from_slice(&rent_epoch.to_le_bytes());
         input.extend_from_slice(data);
 
-        poseidon_hash(&[input.as_slice()])
+        poseidon_hash(&[&hash_to_field(&input)]).expect("Field-size hashes are valid Poseidon inputs")
     }
 
     pub fn verify_commitment(&self) -> bool {
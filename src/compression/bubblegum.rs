@@ -0,0 +1,327 @@
+use crate::compression::light::{RawInstruction, NOOP_PROGRAM_ID};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::keccak;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+
+pub const BUBBLEGUM_PROGRAM_ID: Pubkey = pubkey!("BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY");
+pub const SPL_ACCOUNT_COMPRESSION_PROGRAM_ID: Pubkey = pubkey!("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK");
+
+// Anchor discriminators: sha256("global:<instruction>")[..8]
+const CREATE_TREE_DISCRIMINATOR: [u8; 8] = [165, 83, 136, 142, 89, 202, 47, 220];
+const MINT_V1_DISCRIMINATOR: [u8; 8] = [145, 98, 192, 118, 184, 147, 118, 104];
+const MINT_TO_COLLECTION_V1_DISCRIMINATOR: [u8; 8] = [153, 18, 178, 47, 197, 158, 86, 15];
+const TRANSFER_DISCRIMINATOR: [u8; 8] = [163, 52, 200, 231, 140, 3, 69, 186];
+const DELEGATE_DISCRIMINATOR: [u8; 8] = [90, 147, 75, 178, 85, 88, 4, 137];
+const BURN_DISCRIMINATOR: [u8; 8] = [116, 110, 29, 56, 107, 219, 42, 93];
+const REDEEM_DISCRIMINATOR: [u8; 8] = [184, 12, 86, 149, 70, 196, 97, 225];
+const CANCEL_REDEEM_DISCRIMINATOR: [u8; 8] = [111, 76, 232, 50, 39, 175, 48, 242];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BubblegumInstruction {
+    CreateTree,
+    MintV1,
+    MintToCollectionV1,
+    Transfer,
+    Delegate,
+    Burn,
+    Redeem,
+    CancelRedeem,
+}
+
+impl BubblegumInstruction {
+    pub fn from_data(data: &[u8]) -> Option<Self> {
+        let discriminator: [u8; 8] = data.get(..8)?.try_into().ok()?;
+        match discriminator {
+            CREATE_TREE_DISCRIMINATOR => Some(Self::CreateTree),
+            MINT_V1_DISCRIMINATOR => Some(Self::MintV1),
+            MINT_TO_COLLECTION_V1_DISCRIMINATOR => Some(Self::MintToCollectionV1),
+            TRANSFER_DISCRIMINATOR => Some(Self::Transfer),
+            DELEGATE_DISCRIMINATOR => Some(Self::Delegate),
+            BURN_DISCRIMINATOR => Some(Self::Burn),
+            REDEEM_DISCRIMINATOR => Some(Self::Redeem),
+            CANCEL_REDEEM_DISCRIMINATOR => Some(Self::CancelRedeem),
+            _ => None,
+        }
+    }
+
+    fn tree_account_index(&self) -> usize {
+        match self {
+            Self::CreateTree => 1,
+            Self::CancelRedeem => 2,
+            Self::MintV1 | Self::MintToCollectionV1 | Self::Burn | Self::Redeem => 3,
+            Self::Transfer | Self::Delegate => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct CreateTreeArgs {
+    pub max_depth: u32,
+    pub max_buffer_size: u32,
+}
+
+/// Arguments shared by transfer, delegate, burn and redeem.
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct LeafArgs {
+    pub root: [u8; 32],
+    pub data_hash: [u8; 32],
+    pub creator_hash: [u8; 32],
+    pub nonce: u64,
+    pub index: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum Version {
+    V1,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum BubblegumEventType {
+    Uninitialized,
+    LeafSchemaEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum LeafSchema {
+    V1 {
+        id: Pubkey,
+        owner: Pubkey,
+        delegate: Pubkey,
+        nonce: u64,
+        data_hash: [u8; 32],
+        creator_hash: [u8; 32],
+    },
+}
+
+impl LeafSchema {
+    pub fn hash(&self) -> [u8; 32] {
+        match self {
+            LeafSchema::V1 {
+                id,
+                owner,
+                delegate,
+                nonce,
+                data_hash,
+                creator_hash,
+            } => keccak::hashv(&[
+                &[1u8],
+                id.as_ref(),
+                owner.as_ref(),
+                delegate.as_ref(),
+                &nonce.to_le_bytes(),
+                data_hash,
+                creator_hash,
+            ])
+            .to_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct LeafSchemaEvent {
+    pub event_type: BubblegumEventType,
+    pub version: Version,
+    pub schema: LeafSchema,
+    pub leaf_hash: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct PathNode {
+    pub node: [u8; 32],
+    pub index: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct ChangeLogEventV1 {
+    pub id: Pubkey,
+    pub path: Vec<PathNode>,
+    pub seq: u64,
+    pub index: u32,
+}
+
+impl ChangeLogEventV1 {
+    pub fn leaf(&self) -> Option<[u8; 32]> {
+        self.path.first().map(|p| p.node)
+    }
+
+    pub fn root(&self) -> Option<[u8; 32]> {
+        self.path.last().map(|p| p.node)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum ChangeLogEvent {
+    V1(ChangeLogEventV1),
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct ApplicationDataEventV1 {
+    pub application_data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum ApplicationDataEvent {
+    V1(ApplicationDataEventV1),
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum AccountCompressionEvent {
+    ChangeLog(ChangeLogEvent),
+    ApplicationData(ApplicationDataEvent),
+}
+
+/// A Bubblegum instruction together with the events logged while it executed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BubblegumOperation {
+    pub instruction: BubblegumInstruction,
+    pub accounts: Vec<Pubkey>,
+    pub tree: Option<Pubkey>,
+    pub create_tree: Option<CreateTreeArgs>,
+    pub leaf_args: Option<LeafArgs>,
+    pub schema: Option<LeafSchema>,
+    pub changelogs: Vec<ChangeLogEventV1>,
+}
+
+pub fn asset_id(tree: &Pubkey, nonce: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"asset", tree.as_ref(), &nonce.to_le_bytes()],
+        &BUBBLEGUM_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Groups the noop events of one transaction under the Bubblegum instruction that emitted them.
+/// `instructions` is the flattened list of outer and inner instructions, in execution order.
+pub fn parse_operations(instructions: &[RawInstruction]) -> Vec<BubblegumOperation> {
+    let mut operations = Vec::new();
+    let mut current: Option<BubblegumOperation> = None;
+
+    for ix in instructions {
+        if ix.program_id == BUBBLEGUM_PROGRAM_ID {
+            operations.extend(current.take());
+            let Some(instruction) = BubblegumInstruction::from_data(&ix.data) else {
+                continue;
+            };
+            let mut args = &ix.data[8..];
+            current = Some(BubblegumOperation {
+                instruction,
+                accounts: ix.accounts.clone(),
+                tree: ix.accounts.get(instruction.tree_account_index()).copied(),
+                create_tree: match instruction {
+                    BubblegumInstruction::CreateTree => CreateTreeArgs::deserialize(&mut args).ok(),
+                    _ => None,
+                },
+                leaf_args: match instruction {
+                    BubblegumInstruction::Transfer
+                    | BubblegumInstruction::Delegate
+                    | BubblegumInstruction::Burn
+                    | BubblegumInstruction::Redeem => LeafArgs::deserialize(&mut args).ok(),
+                    _ => None,
+                },
+                schema: None,
+                changelogs: Vec::new(),
+            });
+        } else if ix.program_id == NOOP_PROGRAM_ID {
+            let Some(operation) = current.as_mut() else {
+                continue;
+            };
+            match AccountCompressionEvent::try_from_slice(&ix.data) {
+                Ok(AccountCompressionEvent::ChangeLog(ChangeLogEvent::V1(event))) => {
+                    operation.changelogs.push(event)
+                }
+                Ok(AccountCompressionEvent::ApplicationData(ApplicationDataEvent::V1(event))) => {
+                    if let Ok(event) = LeafSchemaEvent::try_from_slice(&event.application_data) {
+                        operation.schema = Some(event.schema);
+                    }
+                }
+                Err(_) => {}
+            }
+        } else if ix.program_id != SPL_ACCOUNT_COMPRESSION_PROGRAM_ID {
+            operations.extend(current.take());
+        }
+    }
+    operations.extend(current);
+    operations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop(event: &AccountCompressionEvent) -> RawInstruction {
+        RawInstruction {
+            program_id: NOOP_PROGRAM_ID,
+            accounts: vec![],
+            data: borsh::to_vec(event).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_parse_transfer_operation() {
+        let tree = Pubkey::new_unique();
+        let args = LeafArgs {
+            root: [1u8; 32],
+            data_hash: [2u8; 32],
+            creator_hash: [3u8; 32],
+            nonce: 4,
+            index: 4,
+        };
+        let schema = LeafSchema::V1 {
+            id: asset_id(&tree, 4),
+            owner: Pubkey::new_unique(),
+            delegate: Pubkey::new_unique(),
+            nonce: 4,
+            data_hash: [2u8; 32],
+            creator_hash: [3u8; 32],
+        };
+        let changelog = ChangeLogEventV1 {
+            id: tree,
+            path: vec![PathNode { node: schema.hash(), index: 20 }],
+            seq: 9,
+            index: 4,
+        };
+        let mut data = TRANSFER_DISCRIMINATOR.to_vec();
+        data.extend(borsh::to_vec(&args).unwrap());
+        let mut accounts: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        accounts.push(tree);
+
+        let operations = parse_operations(&[
+            RawInstruction {
+                program_id: BUBBLEGUM_PROGRAM_ID,
+                accounts: accounts.clone(),
+                data,
+            },
+            noop(&AccountCompressionEvent::ApplicationData(ApplicationDataEvent::V1(
+                ApplicationDataEventV1 {
+                    application_data: borsh::to_vec(&LeafSchemaEvent {
+                        event_type: BubblegumEventType::LeafSchemaEvent,
+                        version: Version::V1,
+                        schema: schema.clone(),
+                        leaf_hash: schema.hash(),
+                    })
+                    .unwrap(),
+                },
+            ))),
+            RawInstruction {
+                program_id: SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+                accounts: vec![tree],
+                data: vec![],
+            },
+            noop(&AccountCompressionEvent::ChangeLog(ChangeLogEvent::V1(changelog.clone()))),
+        ]);
+
+        assert_eq!(
+            operations,
+            vec![BubblegumOperation {
+                instruction: BubblegumInstruction::Transfer,
+                accounts,
+                tree: Some(tree),
+                create_tree: None,
+                leaf_args: Some(args),
+                schema: Some(schema),
+                changelogs: vec![changelog],
+            }]
+        );
+    }
+}
@@ -1,9 +1,11 @@
-use ark_bls12_381::Bls12_381;
+use ark_bls12_381::{Bls12_381, Fr};
 use ark_groth16::{Groth16, ProvingKey, VerifyingKey};
+use ark_relations::r1cs::ConstraintSynthesizer;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_snark::SNARK;
-use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem};
-use ark_ff::PrimeField;
 use rand::thread_rng;
+use sha2::{Digest, Sha256};
+use std::path::Path;
 
 pub struct Groth16Prover {
     proving_key: ProvingKey<Bls12_381>,
@@ -11,11 +13,9 @@ pub struct Groth16Prover {
 }
 
 impl Groth16Prover {
-    pub fn new<C, F>(circuit: &C) -> anyhow::Result<Self>
-    where
-        C: ConstraintSynthesizer<F>,
-        F: PrimeField,
-    {
+    /// Runs a fresh circuit-specific setup. Whoever runs it can forge proofs, so serving
+    /// keys are generated once, offline, and loaded with [`read`](Self::read).
+    pub fn new<C: ConstraintSynthesizer<Fr>>(circuit: C) -> anyhow::Result<Self> {
         let mut rng = thread_rng();
         let (proving_key, verifying_key) = Groth16::<Bls12_381>::circuit_specific_setup(circuit, &mut rng)?;
 
@@ -25,32 +25,55 @@ impl Groth16Prover {
         })
     }
 
-    pub fn prove<C, F>(&self, circuit: &C) -> anyhow::Result<ark_groth16::Proof<Bls12_381>>
-    where
-        C: ConstraintSynthesizer<F>,
-        F: PrimeField,
-    {
+    /// Reads a proving key written by [`write`](Self::write); the verifying key is part of it.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let proving_key = ProvingKey::<Bls12_381>::deserialize_compressed(bytes.as_slice())?;
+        Ok(Self {
+            verifying_key: proving_key.vk.clone(),
+            proving_key,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let mut bytes = Vec::new();
+        self.proving_key.serialize_compressed(&mut bytes)?;
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    pub fn write_verifying_key(&self, path: &Path) -> anyhow::Result<()> {
+        let mut bytes = Vec::new();
+        self.verifying_key.serialize_compressed(&mut bytes)?;
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    pub fn verifying_key(&self) -> &VerifyingKey<Bls12_381> {
+        &self.verifying_key
+    }
+
+    /// SHA-256 of the compressed verifying key, which identifies the key pair.
+    pub fn fingerprint(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.verifying_key.serialize_compressed(&mut bytes)?;
+        Ok(Sha256::digest(&bytes).to_vec())
+    }
+
+    pub fn prove<C: ConstraintSynthesizer<Fr>>(&self, circuit: C) -> anyhow::Result<ark_groth16::Proof<Bls12_381>> {
         let mut rng = thread_rng();
         let proof = Groth16::<Bls12_381>::prove(&self.proving_key, circuit, &mut rng)?;
         Ok(proof)
     }
 
-    pub fn verify(&self, proof: &ark_groth16::Proof<Bls12_381>, public_inputs: &[F]) -> anyhow::Result<bool>
-    where
-        F: PrimeField,
-    {
+    pub fn verify(&self, proof: &ark_groth16::Proof<Bls12_381>, public_inputs: &[Fr]) -> anyhow::Result<bool> {
         let result = Groth16::<Bls12_381>::verify(&self.verifying_key, public_inputs, proof)?;
         Ok(result)
     }
+}
 
-    pub fn verify_proof<C, F>(&self, circuit: &C, proof: &ark_groth16::Proof<Bls12_381>) -> anyhow::Result<bool>
-    where
-        C: ConstraintSynthesizer<F>,
-        F: PrimeField,
-    {
-        let cs = ConstraintSystem::<F>::new_ref();
-        circuit.generate_constraints(cs.clone())?;
-        let public_inputs = cs.instance_assignment().to_vec();
-        self.verify(proof, &public_inputs)
-    }
+pub fn compress_proof(proof: &ark_groth16::Proof<Bls12_381>) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    proof.serialize_compressed(&mut bytes)?;
+    Ok(bytes)
 }
\ No newline at end of file
@@ -38,4 +38,13 @@ impl Instruction {
             data,
         }
     }
+
+    pub fn decode(program_id: Pubkey, accounts: Vec<Pubkey>, data: &[u8]) -> anyhow::Result<Self> {
+        let data = InstructionType::try_from_slice(data)?;
+        Ok(Self::new(program_id, accounts, data))
+    }
+
+    pub fn tree(&self) -> Option<&Pubkey> {
+        self.accounts.first()
+    }
 }
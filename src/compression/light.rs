@@ -0,0 +1,266 @@
+use crate::compression::poseidon::poseidon_hash;
+use borsh::{BorshDeserialize, BorshSerialize};
+use light_poseidon::PoseidonError;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+
+pub const LIGHT_SYSTEM_PROGRAM_ID: Pubkey = pubkey!("SySTEM1eSU2p4BGQfQpimFEWWSC1XDFPjC8dPvoqvT9");
+pub const ACCOUNT_COMPRESSION_PROGRAM_ID: Pubkey = pubkey!("compr6CUsB5m2jS4Y3831ztGSTnDpnKJTKS95d64XVq");
+pub const NOOP_PROGRAM_ID: Pubkey = pubkey!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
+
+// Light v1 only emits new addresses into its default address tree
+pub const DEFAULT_ADDRESS_TREE: Pubkey = pubkey!("amt1Ayt45jfbdw5YSo7iz6WZxUmnZsQTYXy82hVwyC2");
+
+pub const STATE_TREE_DEPTH: u32 = 26;
+pub const STATE_TREE_ROOT_HISTORY: u32 = 2400;
+pub const ADDRESS_TREE_DEPTH: u32 = 26;
+pub const ADDRESS_TREE_ROOT_HISTORY: u32 = 2400;
+
+// Addresses are 31-byte big-endian values; address trees are indexed, so every leaf
+// commits to a value, the index of the next larger one and its value.
+pub const HIGHEST_ADDRESS: [u8; 32] = {
+    let mut address = [0xff; 32];
+    address[0] = 0;
+    address
+};
+
+/// The leaf of an indexed element, hashed as Light's address trees hash it.
+pub fn address_leaf(value: &[u8; 32], next_index: u64, next_value: &[u8; 32]) -> Result<[u8; 32], PoseidonError> {
+    poseidon_hash(&[value, &next_index.to_be_bytes(), next_value])
+}
+
+/// The elements Light creates every address tree with: zero at leaf 0, pointing at the
+/// highest address at leaf 1.
+pub fn address_tree_genesis() -> [RawIndexedElement; 2] {
+    [
+        RawIndexedElement {
+            value: [0u8; 32],
+            next_index: 1,
+            next_value: HIGHEST_ADDRESS,
+            index: 0,
+        },
+        RawIndexedElement {
+            value: HIGHEST_ADDRESS,
+            next_index: 0,
+            next_value: [0u8; 32],
+            index: 1,
+        },
+    ]
+}
+
+#[derive(Debug, Clone)]
+pub struct RawInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<Pubkey>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct CompressedAccountData {
+    pub discriminator: [u8; 8],
+    pub data: Vec<u8>,
+    pub data_hash: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct LightCompressedAccount {
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub address: Option<[u8; 32]>,
+    pub data: Option<CompressedAccountData>,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct OutputCompressedAccountWithPackedContext {
+    pub compressed_account: LightCompressedAccount,
+    pub merkle_tree_index: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct MerkleTreeSequenceNumber {
+    pub pubkey: Pubkey,
+    pub seq: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct PublicTransactionEvent {
+    pub input_compressed_account_hashes: Vec<[u8; 32]>,
+    pub output_compressed_account_hashes: Vec<[u8; 32]>,
+    pub output_compressed_accounts: Vec<OutputCompressedAccountWithPackedContext>,
+    pub output_leaf_indices: Vec<u32>,
+    pub sequence_numbers: Vec<MerkleTreeSequenceNumber>,
+    pub relay_fee: Option<u64>,
+    pub is_compress: bool,
+    pub compress_or_decompress_lamports: Option<u64>,
+    pub pubkey_array: Vec<Pubkey>,
+    pub message: Option<Vec<u8>>,
+}
+
+impl PublicTransactionEvent {
+    pub fn output_tree(&self, index: usize) -> Option<Pubkey> {
+        let account = self.output_compressed_accounts.get(index)?;
+        self.pubkey_array.get(account.merkle_tree_index as usize).copied()
+    }
+
+    pub fn sequence_number(&self, tree: &Pubkey) -> Option<u64> {
+        self.sequence_numbers
+            .iter()
+            .find(|s| &s.pubkey == tree)
+            .map(|s| s.seq)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct ChangelogPath(pub Vec<Option<[u8; 32]>>);
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct ChangelogEvent {
+    pub id: [u8; 32],
+    pub paths: Vec<Vec<ChangelogPath>>,
+    pub seq: u64,
+    pub index: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct NullifierEvent {
+    pub id: [u8; 32],
+    pub nullified_leaves_indices: Vec<u64>,
+    pub seq: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct RawIndexedElement {
+    pub value: [u8; 32],
+    pub next_index: u64,
+    pub next_value: [u8; 32],
+    pub index: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct IndexedMerkleTreeUpdate {
+    pub new_low_element: RawIndexedElement,
+    pub new_low_element_hash: [u8; 32],
+    pub new_high_element: RawIndexedElement,
+    pub new_high_element_hash: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct IndexedMerkleTreeEvent {
+    pub id: [u8; 32],
+    pub updates: Vec<IndexedMerkleTreeUpdate>,
+    pub seq: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum MerkleTreeEvent {
+    V1(ChangelogEvent),
+    V2(NullifierEvent),
+    V3(IndexedMerkleTreeEvent),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LightEvent {
+    Transaction(PublicTransactionEvent),
+    MerkleTree(MerkleTreeEvent),
+}
+
+pub fn is_light_instruction(instruction: &RawInstruction) -> bool {
+    instruction.program_id == LIGHT_SYSTEM_PROGRAM_ID
+        || instruction.program_id == ACCOUNT_COMPRESSION_PROGRAM_ID
+}
+
+/// Extracts the events Light programs log through the noop program, in execution order.
+/// `instructions` is the flattened list of outer and inner instructions of one transaction.
+pub fn parse_events(instructions: &[RawInstruction]) -> Vec<LightEvent> {
+    if !instructions.iter().any(is_light_instruction) {
+        return Vec::new();
+    }
+    let invokes_system_program = instructions
+        .iter()
+        .any(|ix| ix.program_id == LIGHT_SYSTEM_PROGRAM_ID);
+
+    instructions
+        .iter()
+        .filter(|ix| ix.program_id == NOOP_PROGRAM_ID)
+        .filter_map(|ix| {
+            if invokes_system_program {
+                if let Ok(event) = PublicTransactionEvent::try_from_slice(&ix.data) {
+                    return Some(LightEvent::Transaction(event));
+                }
+            }
+            MerkleTreeEvent::try_from_slice(&ix.data)
+                .ok()
+                .map(LightEvent::MerkleTree)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_public_transaction_event() {
+        let tree = Pubkey::new_unique();
+        let event = PublicTransactionEvent {
+            input_compressed_account_hashes: vec![[1u8; 32]],
+            output_compressed_account_hashes: vec![[2u8; 32]],
+            output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+                compressed_account: LightCompressedAccount {
+                    owner: Pubkey::new_unique(),
+                    lamports: 42,
+                    address: None,
+                    data: None,
+                },
+                merkle_tree_index: 0,
+            }],
+            output_leaf_indices: vec![7],
+            sequence_numbers: vec![MerkleTreeSequenceNumber { pubkey: tree, seq: 8 }],
+            relay_fee: None,
+            is_compress: false,
+            compress_or_decompress_lamports: None,
+            pubkey_array: vec![tree],
+            message: None,
+        };
+        let instructions = vec![
+            RawInstruction {
+                program_id: LIGHT_SYSTEM_PROGRAM_ID,
+                accounts: vec![],
+                data: vec![],
+            },
+            RawInstruction {
+                program_id: NOOP_PROGRAM_ID,
+                accounts: vec![],
+                data: borsh::to_vec(&event).unwrap(),
+            },
+        ];
+
+        let events = parse_events(&instructions);
+        assert_eq!(events, vec![LightEvent::Transaction(event.clone())]);
+        assert_eq!(event.output_tree(0), Some(tree));
+        assert_eq!(event.sequence_number(&tree), Some(8));
+    }
+
+    #[test]
+    fn test_parse_nullifier_event() {
+        let event = MerkleTreeEvent::V2(NullifierEvent {
+            id: [3u8; 32],
+            nullified_leaves_indices: vec![1, 2],
+            seq: 5,
+        });
+        let instructions = vec![
+            RawInstruction {
+                program_id: ACCOUNT_COMPRESSION_PROGRAM_ID,
+                accounts: vec![],
+                data: vec![],
+            },
+            RawInstruction {
+                program_id: NOOP_PROGRAM_ID,
+                accounts: vec![],
+                data: borsh::to_vec(&event).unwrap(),
+            },
+        ];
+
+        assert_eq!(parse_events(&instructions), vec![LightEvent::MerkleTree(event)]);
+    }
+}
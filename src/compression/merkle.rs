@@ -1,80 +1,166 @@
-use crate::compression::poseidon::poseidon_hash;
+use crate::compression::poseidon::{is_field_element, poseidon_hash};
+use solana_sdk::keccak;
+
+/// Stored as its discriminant. Ids 0 and 2 were Poseidon over earlier parameters; trees
+/// recorded with them are re-hashed when loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TreeHasher {
+    #[default]
+    Poseidon = 3,
+    Keccak = 1,
+}
+
+pub const LEGACY_POSEIDON_HASHERS: [u8; 2] = [0, 2];
+
+impl TreeHasher {
+    /// Poseidon trees only take leaves below the field modulus, like Light's on-chain trees.
+    pub fn accepts(&self, leaf: &[u8; 32]) -> bool {
+        match self {
+            TreeHasher::Poseidon => is_field_element(leaf),
+            TreeHasher::Keccak => true,
+        }
+    }
+
+    /// `left` and `right` are leaves the hasher [`accepts`](Self::accepts) or nodes it produced.
+    pub fn hash(&self, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        match self {
+            TreeHasher::Poseidon => poseidon_hash(&[left, right]).expect("Poseidon tree nodes are field elements"),
+            TreeHasher::Keccak => keccak::hashv(&[left, right]).to_bytes(),
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(TreeHasher::Keccak),
+            3 => Some(TreeHasher::Poseidon),
+            _ => None,
+        }
+    }
+}
 
 pub struct MerkleTree {
     pub max_depth: usize,
+    pub hasher: TreeHasher,
     pub leaves: Vec<[u8; 32]>,
     pub nodes: Vec<Vec<[u8; 32]>>,
+    zeros: Vec<[u8; 32]>,
 }
 
 impl MerkleTree {
     pub fn new(max_depth: usize) -> Self {
+        Self::with_hasher(max_depth, TreeHasher::default())
+    }
+
+    pub fn with_hasher(max_depth: usize, hasher: TreeHasher) -> Self {
+        let mut zeros = vec![[0u8; 32]; max_depth + 1];
+        for i in 0..max_depth {
+            zeros[i + 1] = hasher.hash(&zeros[i], &zeros[i]);
+        }
         MerkleTree {
             max_depth,
+            hasher,
             leaves: Vec::new(),
             nodes: vec![Vec::new(); max_depth + 1],
+            zeros,
         }
     }
 
+    pub fn from_leaves(max_depth: usize, hasher: TreeHasher, leaves: Vec<[u8; 32]>) -> Self {
+        let mut tree = Self::with_hasher(max_depth, hasher);
+        tree.leaves = leaves;
+        tree.update_tree();
+        tree
+    }
+
+    pub fn capacity(&self) -> usize {
+        1 << self.max_depth
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.leaves.len() >= self.capacity()
+    }
+
+    pub fn leaf(&self, index: usize) -> Option<[u8; 32]> {
+        self.leaves.get(index).copied()
+    }
+
     pub fn append(&mut self, leaf: [u8; 32]) {
         self.leaves.push(leaf);
-        self.update_tree();
+        self.update_path(self.leaves.len() - 1);
     }
 
     pub fn update(&mut self, index: usize, new_leaf: [u8; 32]) {
         if index < self.leaves.len() {
             self.leaves[index] = new_leaf;
-            self.update_tree();
+            self.update_path(index);
         }
     }
 
     fn update_tree(&mut self) {
         for i in 0..self.max_depth {
-            let level_size = (self.leaves.len() + (1 << i) - 1) >> i;
-            self.nodes[i] = Vec::with_capacity(level_size);
-            for j in (0..level_size).step_by(2) {
-                let left = if j < level_size {
-                    self.get_node(i, j)
-                } else {
-                    [0u8; 32]
-                };
-                let right = if j + 1 < level_size {
-                    self.get_node(i, j + 1)
-                } else {
-                    [0u8; 32]
-                };
-                let parent = poseidon_hash(&[&left, &right]);
-                self.nodes[i + 1].push(parent);
+            let level_size = if i == 0 {
+                self.leaves.len()
+            } else {
+                self.nodes[i].len()
+            };
+            let parents: Vec<[u8; 32]> = (0..level_size)
+                .step_by(2)
+                .map(|j| self.hasher.hash(&self.get_node(i, j), &self.get_node(i, j + 1)))
+                .collect();
+            self.nodes[i + 1] = parents;
+        }
+    }
+
+    fn update_path(&mut self, index: usize) {
+        let mut current_index = index;
+        for i in 0..self.max_depth {
+            let left = self.get_node(i, current_index & !1);
+            let right = self.get_node(i, current_index | 1);
+            let parent = self.hasher.hash(&left, &right);
+            current_index >>= 1;
+            let level = &mut self.nodes[i + 1];
+            if current_index < level.len() {
+                level[current_index] = parent;
+            } else {
+                level.push(parent);
             }
         }
     }
 
     fn get_node(&self, level: usize, index: usize) -> [u8; 32] {
-        if level == 0 {
-            self.leaves[index]
+        let node = if level == 0 {
+            self.leaves.get(index)
         } else {
-            self.nodes[level - 1][index]
-        }
+            self.nodes[level].get(index)
+        };
+        node.copied().unwrap_or(self.zeros[level])
     }
 
     pub fn root(&self) -> [u8; 32] {
-        if self.nodes[self.max_depth].is_empty() {
-            [0u8; 32]
-        } else {
-            self.nodes[self.max_depth][0]
-        }
+        self.get_node(self.max_depth, 0)
     }
 
     pub fn generate_proof(&self, index: usize) -> Vec<([u8; 32], bool)> {
-        let mut proof = Vec::new();
+        let mut proof = Vec::with_capacity(self.max_depth);
         let mut current_index = index;
         for i in 0..self.max_depth {
-            let sibling_index = current_index ^ 1;
-            if sibling_index < self.nodes[i].len() {
-                let is_left = current_index & 1 == 0;
-                proof.push((self.get_node(i, sibling_index), is_left));
-            }
+            let is_left = current_index & 1 == 0;
+            proof.push((self.get_node(i, current_index ^ 1), is_left));
             current_index >>= 1;
         }
         proof
     }
+
+    /// The nodes from leaf `index` up to the root, one per level.
+    pub fn path(&self, index: usize) -> Vec<[u8; 32]> {
+        (0..=self.max_depth).map(|i| self.get_node(i, index >> i)).collect()
+    }
 }
@@ -1,15 +1,25 @@
 pub mod account;
+pub mod bubblegum;
 pub mod groth16;
 pub mod instruction;
+pub mod light;
 pub mod merkle;
 pub mod poseidon;
+pub mod processor;
+pub mod prover;
+pub mod token;
 pub mod zk_proof;
 
 pub use account::CompressedAccount;
-pub use groth16::{generate_proof, verify_proof};
+pub use groth16::{compress_proof, Groth16Prover};
 pub use instruction::{Instruction, InstructionType};
-pub use merkle::MerkleTree;
+pub use merkle::{MerkleTree, TreeHasher};
 pub use poseidon::poseidon_hash;
+pub use processor::{InstructionProcessor, LeafChange, MerkleProof, ProcessorError, TreeChange};
+pub use prover::{
+    CircuitShape, LightProver, LightVerifyingKey, ValidityProof, ValidityProofError, ValidityProver, VerifyingKeys,
+    CIRCUIT_SHAPES,
+};
 pub use zk_proof::{Proof, VerifyingKey};
 
 pub trait Compressor {
@@ -1,65 +1,60 @@
-use ark_ed_on_bls12_381::Fq as F;
-use ark_ff::PrimeField;
-
-const T: usize = 3;
-const FULL_ROUNDS: usize = 8;
-const PARTIAL_ROUNDS: usize = 57;
-
-pub fn poseidon_hash(inputs: &[&[u8]]) -> [u8; 32] {
-    let mut state = vec![F::zero(); T];
-    for (i, input) in inputs.iter().enumerate() {
-        state[i] = F::from_random_bytes(input).unwrap_or(F::zero());
-    }
-
-    poseidon_permutation(&mut state);
-
-    let result = state[0].into_repr().to_bytes_le();
-    let mut output = [0u8; 32];
-    output.copy_from_slice(&result[..32]);
-    output
-}
-
-fn poseidon_permutation(state: &mut [F]) {
-    for _ in 0..FULL_ROUNDS {
-        add_constants(state);
-        full_round(state);
-    }
-
-    for _ in 0..PARTIAL_ROUNDS {
-        add_constants(state);
-        partial_round(state);
-    }
-
-    for _ in 0..FULL_ROUNDS {
-        add_constants(state);
-        full_round(state);
-    }
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use light_poseidon::{Poseidon, PoseidonBytesHasher, PoseidonError};
+use solana_sdk::keccak;
+
+/// Poseidon over BN254 with the circom parameters Light's programs and circuits use. Takes
+/// 1 to 12 big-endian inputs, each of at most 32 bytes and below the field modulus; larger
+/// values would alias smaller ones, so they are refused rather than reduced.
+pub fn poseidon_hash(inputs: &[&[u8]]) -> Result<[u8; 32], PoseidonError> {
+    Poseidon::<Fr>::new_circom(inputs.len())?.hash_bytes_be(inputs)
 }
 
-fn add_constants(state: &mut [F]) {
-    for i in 0..T {
-        state[i] += F::from(i as u64);
-    }
-}
-
-fn full_round(state: &mut [F]) {
-    for i in 0..T {
-        state[i] = state[i].pow([5u64]);
-    }
-    mix(state);
+/// Whether `bytes`, read big-endian, is below the BN254 scalar field modulus.
+pub fn is_field_element(bytes: &[u8; 32]) -> bool {
+    bytes.as_slice() < Fr::MODULUS.to_bytes_be().as_slice()
 }
 
-fn partial_round(state: &mut [F]) {
-    state[0] = state[0].pow([5u64]);
-    mix(state);
+/// Keccak of `bytes` with the top byte cleared, which Light uses to bring data of any
+/// length below the field modulus.
+pub fn hash_to_field(bytes: &[u8]) -> [u8; 32] {
+    let mut hash = keccak::hash(bytes).to_bytes();
+    hash[0] = 0;
+    hash
 }
 
-fn mix(state: &mut [F]) {
-    let mut new_state = vec![F::zero(); T];
-    for i in 0..T {
-        for j in 0..T {
-            new_state[i] += state[j] * F::from((i * j) as u64);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::MerkleTree;
+
+    #[test]
+    fn test_vectors() {
+        // Light's first zero subtree root, H(0, 0).
+        assert_eq!(
+            hex::encode(poseidon_hash(&[&[0u8; 32], &[0u8; 32]]).unwrap()),
+            "2098f5fb9e239eab3ceac3f27b81e481dc3124d55ffed523a839ee8446b64864"
+        );
+        // light-poseidon's published vector.
+        assert_eq!(
+            poseidon_hash(&[&[1u8; 32], &[2u8; 32]]).unwrap(),
+            [
+                13, 84, 225, 147, 143, 138, 140, 28, 125, 235, 94, 3, 85, 242, 99, 25, 32, 123, 132, 254, 156, 162,
+                206, 27, 38, 231, 53, 200, 41, 130, 25, 144
+            ]
+        );
+
+        let mut tree = MerkleTree::new(2);
+        tree.append([1u8; 32]);
+        tree.append([2u8; 32]);
+        let zero = poseidon_hash(&[&[0u8; 32], &[0u8; 32]]).unwrap();
+        let left = poseidon_hash(&[&[1u8; 32], &[2u8; 32]]).unwrap();
+        assert_eq!(tree.root(), poseidon_hash(&[&left, &zero]).unwrap());
+
+        // Inputs at or above the modulus, and more than 12 of them, are refused.
+        assert!(!is_field_element(&[0xffu8; 32]));
+        assert!(poseidon_hash(&[&[0xffu8; 32], &[0u8; 32]]).is_err());
+        assert!(poseidon_hash(&[&[0u8; 32][..]; 13]).is_err());
+        assert!(is_field_element(&hash_to_field(&[0xffu8; 64])));
     }
-    state.copy_from_slice(&new_state);
 }
@@ -0,0 +1,800 @@
+use crate::compression::instruction::{Instruction, InstructionType};
+use crate::compression::merkle::{MerkleTree, TreeHasher, LEGACY_POSEIDON_HASHERS};
+use crate::storage::{CompressionStore, LeafChangeRecord, MerkleTreeRecord, TreeRootRecord};
+use log::{info, warn};
+use solana_sdk::pubkey::Pubkey;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+pub const MAX_TREE_DEPTH: u32 = 30;
+const CHECKPOINT_INTERVAL: u64 = 1024;
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Error, Debug)]
+pub enum ProcessorError {
+    #[error("Instruction has no tree account")]
+    MissingTreeAccount,
+
+    #[error("Tree not found: {0}")]
+    TreeNotFound(Pubkey),
+
+    #[error("Tree already initialized: {0}")]
+    TreeAlreadyInitialized(Pubkey),
+
+    #[error("Invalid tree config: max_depth {max_depth}, max_buffer_size {max_buffer_size}")]
+    InvalidTreeConfig { max_depth: u32, max_buffer_size: u32 },
+
+    #[error("Root {} is not a recent root of tree {tree}", hex::encode(.root))]
+    UnknownRoot { tree: Pubkey, root: [u8; 32] },
+
+    #[error("Leaf index {index} out of bounds for tree {tree} with {len} leaves")]
+    LeafIndexOutOfBounds { tree: Pubkey, index: u32, len: usize },
+
+    #[error("Leaf mismatch at index {index} of tree {tree}: expected {}, found {}", hex::encode(.expected), hex::encode(.actual))]
+    LeafMismatch {
+        tree: Pubkey,
+        index: u32,
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+
+    #[error("Leaf {} can't be stored in tree {tree}", hex::encode(.leaf))]
+    InvalidLeaf { tree: Pubkey, leaf: [u8; 32] },
+
+    #[error("Tree is full: {0}")]
+    TreeFull(Pubkey),
+
+    #[error("Tree {tree} diverged from the chain at seq {seq} and has not been resynced")]
+    TreeDiverged { tree: Pubkey, seq: u64 },
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] anyhow::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct LeafChange {
+    pub index: u32,
+    pub previous: [u8; 32],
+    pub new: [u8; 32],
+}
+
+#[derive(Debug, Clone)]
+pub struct MerkleProof {
+    pub tree: Pubkey,
+    pub leaf_index: u32,
+    pub leaf: [u8; 32],
+    pub root: [u8; 32],
+    pub seq: u64,
+    /// Position of `root` in the on-chain root history ring buffer, unknown until the
+    /// indexer has seen the tree's on-chain sequence number.
+    pub root_index: Option<u32>,
+    pub path: Vec<[u8; 32]>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TreeChange {
+    pub tree: Pubkey,
+    pub seq: u64,
+    pub slot: u64,
+    pub root: [u8; 32],
+    pub leaf: Option<LeafChange>,
+}
+
+struct TreeState {
+    tree: MerkleTree,
+    max_buffer_size: u32,
+    recent_roots: VecDeque<[u8; 32]>,
+    seq: u64,
+    // The on-chain sequence number after the last change, which can run ahead of `seq`
+    // when the tree was indexed from partway through its history
+    chain_seq: Option<u64>,
+    slot: u64,
+    diverged_seq: Option<u64>,
+}
+
+impl TreeState {
+    fn new(max_depth: u32, max_buffer_size: u32, hasher: TreeHasher, slot: u64) -> Self {
+        let tree = MerkleTree::with_hasher(max_depth as usize, hasher);
+        let recent_roots = VecDeque::from([tree.root()]);
+        Self {
+            tree,
+            max_buffer_size,
+            recent_roots,
+            seq: 0,
+            chain_seq: None,
+            slot,
+            diverged_seq: None,
+        }
+    }
+
+    fn from_record(record: MerkleTreeRecord) -> anyhow::Result<Self> {
+        let leaves = record
+            .leaves
+            .into_iter()
+            .map(to_hash)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut recent_roots = record
+            .recent_roots
+            .into_iter()
+            .map(to_hash)
+            .collect::<anyhow::Result<VecDeque<_>>>()?;
+        let legacy = LEGACY_POSEIDON_HASHERS.contains(&record.hasher);
+        let hasher = match record.hasher {
+            _ if legacy => TreeHasher::Poseidon,
+            hasher => TreeHasher::from_u8(hasher).ok_or_else(|| anyhow::anyhow!("Unknown tree hasher: {}", hasher))?,
+        };
+        if let Some(leaf) = leaves.iter().find(|leaf| !hasher.accepts(leaf)) {
+            anyhow::bail!("Stored leaf {} is not valid under {:?}", hex::encode(leaf), hasher);
+        }
+        let tree = MerkleTree::from_leaves(record.max_depth as usize, hasher, leaves);
+        if legacy {
+            // Roots under the old parameters can't be proven against any more.
+            recent_roots = VecDeque::from([tree.root()]);
+        }
+        Ok(Self {
+            tree,
+            max_buffer_size: record.max_buffer_size,
+            recent_roots,
+            seq: record.seq,
+            chain_seq: record.chain_seq,
+            slot: record.slot,
+            diverged_seq: record.diverged_seq,
+        })
+    }
+
+    fn to_record(&self, tree: &Pubkey) -> MerkleTreeRecord {
+        MerkleTreeRecord {
+            tree: tree.to_bytes().to_vec(),
+            max_depth: self.tree.max_depth as u32,
+            max_buffer_size: self.max_buffer_size,
+            hasher: self.tree.hasher as u8,
+            leaves: self.tree.leaves.iter().map(|l| l.to_vec()).collect(),
+            recent_roots: self.recent_roots.iter().map(|r| r.to_vec()).collect(),
+            seq: self.seq,
+            chain_seq: self.chain_seq,
+            slot: self.slot,
+            diverged_seq: self.diverged_seq,
+        }
+    }
+
+    fn check_root(&self, tree: &Pubkey, root: [u8; 32]) -> Result<(), ProcessorError> {
+        if self.recent_roots.contains(&root) {
+            Ok(())
+        } else {
+            Err(ProcessorError::UnknownRoot { tree: *tree, root })
+        }
+    }
+
+    fn check_leaf(&self, tree: &Pubkey, index: u32, expected: [u8; 32]) -> Result<(), ProcessorError> {
+        let actual = self
+            .tree
+            .leaf(index as usize)
+            .ok_or(ProcessorError::LeafIndexOutOfBounds {
+                tree: *tree,
+                index,
+                len: self.tree.len(),
+            })?;
+        if actual != expected {
+            return Err(ProcessorError::LeafMismatch {
+                tree: *tree,
+                index,
+                expected,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_accepts(&self, tree: &Pubkey, leaf: [u8; 32]) -> Result<(), ProcessorError> {
+        if !self.tree.hasher.accepts(&leaf) {
+            return Err(ProcessorError::InvalidLeaf { tree: *tree, leaf });
+        }
+        Ok(())
+    }
+
+    fn replay(&mut self, tree: &Pubkey, change: LeafChangeRecord) -> anyhow::Result<()> {
+        if change.seq != self.seq + 1 {
+            anyhow::bail!("Leaf changes of tree {} skip from seq {} to {}", tree, self.seq, change.seq);
+        }
+        let index = change.leaf_index as usize;
+        let leaf = to_hash(change.new_leaf)?;
+        if !self.tree.hasher.accepts(&leaf) {
+            anyhow::bail!("Leaf change {} of tree {} is not valid under {:?}", change.seq, tree, self.tree.hasher);
+        }
+        match index.cmp(&self.tree.len()) {
+            Ordering::Less => self.tree.update(index, leaf),
+            Ordering::Equal => self.tree.append(leaf),
+            Ordering::Greater => anyhow::bail!("Leaf change {} of tree {} is past the last leaf", change.seq, tree),
+        }
+        self.record_root(change.slot);
+        Ok(())
+    }
+
+    fn record_root(&mut self, slot: u64) -> [u8; 32] {
+        let root = self.tree.root();
+        self.seq += 1;
+        self.chain_seq = self.chain_seq.map(|seq| seq + 1);
+        self.slot = slot;
+        self.recent_roots.push_back(root);
+        while self.recent_roots.len() > self.max_buffer_size as usize {
+            self.recent_roots.pop_front();
+        }
+        root
+    }
+}
+
+fn to_hash(bytes: Vec<u8>) -> anyhow::Result<[u8; 32]> {
+    bytes
+        .try_into()
+        .map_err(|b: Vec<u8>| anyhow::anyhow!("Invalid hash length: {}", b.len()))
+}
+
+type TreeSlot = Arc<Mutex<Option<TreeState>>>;
+
+struct CachedTree {
+    slot: TreeSlot,
+    last_used: Instant,
+}
+
+struct TreeCache {
+    trees: HashMap<Pubkey, CachedTree>,
+    last_sweep: Instant,
+}
+
+fn loaded<'a>(state: &'a mut Option<TreeState>, tree: &Pubkey) -> Result<&'a mut TreeState, ProcessorError> {
+    state.as_mut().ok_or(ProcessorError::TreeNotFound(*tree))
+}
+
+pub struct InstructionProcessor {
+    store: Arc<dyn CompressionStore>,
+    // Each tree has its own lock, so instructions on different trees don't wait on each other.
+    cache: std::sync::Mutex<TreeCache>,
+    idle_timeout: Duration,
+}
+
+impl InstructionProcessor {
+    pub fn new(store: Arc<dyn CompressionStore>) -> Self {
+        Self {
+            store,
+            cache: std::sync::Mutex::new(TreeCache {
+                trees: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+        }
+    }
+
+    /// Drops trees from memory once unused for `idle_timeout`. They are reloaded from the
+    /// store on their next use.
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    pub async fn contains_tree(&self, tree: &Pubkey) -> Result<bool, ProcessorError> {
+        Ok(self.lock_tree(tree).await?.is_some())
+    }
+
+    pub async fn root(&self, tree: &Pubkey) -> Result<[u8; 32], ProcessorError> {
+        let mut state = self.lock_tree(tree).await?;
+        Ok(loaded(&mut state, tree)?.tree.root())
+    }
+
+    pub async fn seq(&self, tree: &Pubkey) -> Result<u64, ProcessorError> {
+        let mut state = self.lock_tree(tree).await?;
+        Ok(loaded(&mut state, tree)?.seq)
+    }
+
+    pub async fn leaf_count(&self, tree: &Pubkey) -> Result<usize, ProcessorError> {
+        let mut state = self.lock_tree(tree).await?;
+        Ok(loaded(&mut state, tree)?.tree.len())
+    }
+
+    pub async fn leaf(&self, tree: &Pubkey, index: u32) -> Result<[u8; 32], ProcessorError> {
+        let mut state = self.lock_tree(tree).await?;
+        let state = loaded(&mut state, tree)?;
+        state.tree.leaf(index as usize).ok_or(ProcessorError::LeafIndexOutOfBounds {
+            tree: *tree,
+            index,
+            len: state.tree.len(),
+        })
+    }
+
+    /// The nodes from leaf `index` up to the root, also on a diverged tree, to compare with
+    /// the paths the chain reports.
+    pub async fn path(&self, tree: &Pubkey, index: u32) -> Result<Vec<[u8; 32]>, ProcessorError> {
+        let mut state = self.lock_tree(tree).await?;
+        let state = loaded(&mut state, tree)?;
+        if index as usize >= state.tree.len() {
+            return Err(ProcessorError::LeafIndexOutOfBounds {
+                tree: *tree,
+                index,
+                len: state.tree.len(),
+            });
+        }
+        Ok(state.tree.path(index as usize))
+    }
+
+    pub async fn proof(&self, tree: &Pubkey, index: u32) -> Result<MerkleProof, ProcessorError> {
+        let mut state = self.lock_tree(tree).await?;
+        let state = loaded(&mut state, tree)?;
+        if let Some(seq) = state.diverged_seq {
+            return Err(ProcessorError::TreeDiverged { tree: *tree, seq });
+        }
+        let leaf = state.tree.leaf(index as usize).ok_or(ProcessorError::LeafIndexOutOfBounds {
+            tree: *tree,
+            index,
+            len: state.tree.len(),
+        })?;
+        Ok(MerkleProof {
+            tree: *tree,
+            leaf_index: index,
+            leaf,
+            root: state.tree.root(),
+            seq: state.seq,
+            root_index: state
+                .chain_seq
+                .map(|seq| (seq % state.max_buffer_size as u64) as u32),
+            path: state
+                .tree
+                .generate_proof(index as usize)
+                .into_iter()
+                .map(|(node, _)| node)
+                .collect(),
+        })
+    }
+
+    pub async fn diverged_seq(&self, tree: &Pubkey) -> Result<Option<u64>, ProcessorError> {
+        let mut state = self.lock_tree(tree).await?;
+        Ok(loaded(&mut state, tree)?.diverged_seq)
+    }
+
+    /// Records the on-chain sequence number the tree reached with its last change, which
+    /// anchors the root indices of its proofs.
+    pub async fn set_chain_seq(&self, tree: &Pubkey, seq: u64) -> Result<(), ProcessorError> {
+        let mut state = self.lock_tree(tree).await?;
+        let state = loaded(&mut state, tree)?;
+        if state.chain_seq != Some(seq) {
+            state.chain_seq = Some(seq);
+            self.store.upsert_merkle_tree(&state.to_record(tree)).await?;
+        }
+        Ok(())
+    }
+
+    /// Flags `tree` as no longer matching the chain, which stops it serving proofs until
+    /// it is rebuilt with [`reset_tree`](Self::reset_tree).
+    pub async fn mark_diverged(&self, tree: &Pubkey, seq: u64) -> Result<(), ProcessorError> {
+        let mut state = self.lock_tree(tree).await?;
+        let state = loaded(&mut state, tree)?;
+        if state.diverged_seq.is_none() {
+            state.diverged_seq = Some(seq);
+            self.store.upsert_merkle_tree(&state.to_record(tree)).await?;
+            warn!("Tree {} diverged at seq {}", tree, seq);
+        }
+        Ok(())
+    }
+
+    /// Drops everything stored for `tree`, so it can be rebuilt from its history.
+    pub async fn reset_tree(&self, tree: &Pubkey) -> Result<(), ProcessorError> {
+        let mut state = self.lock_tree(tree).await?;
+        self.store.delete_merkle_tree(tree.as_ref()).await?;
+        *state = None;
+        info!("Reset tree {}", tree);
+        Ok(())
+    }
+
+    pub async fn init_tree(
+        &self,
+        slot: u64,
+        tree: Pubkey,
+        max_depth: u32,
+        max_buffer_size: u32,
+        hasher: TreeHasher,
+    ) -> Result<TreeChange, ProcessorError> {
+        let mut state = self.lock_tree_for_write(&tree).await?;
+        let change = Self::create_tree(&mut state, slot, tree, max_depth, max_buffer_size, hasher)?;
+        self.commit(&mut state, &change).await?;
+        info!("Initialized {:?} tree {} with depth {}", hasher, tree, max_depth);
+        Ok(change)
+    }
+
+    pub async fn process_all(
+        &self,
+        slot: u64,
+        instructions: &[Instruction],
+    ) -> Result<Vec<TreeChange>, ProcessorError> {
+        let mut changes = Vec::with_capacity(instructions.len());
+        for instruction in instructions {
+            if let Some(change) = self.process(slot, instruction).await? {
+                changes.push(change);
+            }
+        }
+        Ok(changes)
+    }
+
+    pub async fn process(
+        &self,
+        slot: u64,
+        instruction: &Instruction,
+    ) -> Result<Option<TreeChange>, ProcessorError> {
+        let tree = *instruction.tree().ok_or(ProcessorError::MissingTreeAccount)?;
+        let mut state = self.lock_tree_for_write(&tree).await?;
+
+        let change = match instruction.data {
+            InstructionType::InitTree {
+                max_depth,
+                max_buffer_size,
+            } => Self::create_tree(&mut state, slot, tree, max_depth, max_buffer_size, TreeHasher::default())?,
+            InstructionType::UpdateAccount {
+                root,
+                previous_account,
+                new_account,
+                index,
+            } => {
+                let state = loaded(&mut state, &tree)?;
+                state.check_root(&tree, root)?;
+                state.check_leaf(&tree, index, previous_account)?;
+                state.check_accepts(&tree, new_account)?;
+                state.tree.update(index as usize, new_account);
+                let root = state.record_root(slot);
+                TreeChange {
+                    tree,
+                    seq: state.seq,
+                    slot,
+                    root,
+                    leaf: Some(LeafChange {
+                        index,
+                        previous: previous_account,
+                        new: new_account,
+                    }),
+                }
+            }
+            InstructionType::VerifyAccount {
+                root,
+                account,
+                index,
+            } => {
+                let state = loaded(&mut state, &tree)?;
+                state.check_root(&tree, root)?;
+                state.check_leaf(&tree, index, account)?;
+                return Ok(None);
+            }
+            InstructionType::AppendAccount { account } => {
+                let state = loaded(&mut state, &tree)?;
+                if state.tree.is_full() {
+                    return Err(ProcessorError::TreeFull(tree));
+                }
+                state.check_accepts(&tree, account)?;
+                let index = state.tree.len() as u32;
+                state.tree.append(account);
+                let root = state.record_root(slot);
+                TreeChange {
+                    tree,
+                    seq: state.seq,
+                    slot,
+                    root,
+                    leaf: Some(LeafChange {
+                        index,
+                        previous: [0u8; 32],
+                        new: account,
+                    }),
+                }
+            }
+        };
+
+        self.commit(&mut state, &change).await?;
+        info!("Applied {:?} to tree {} at seq {}", instruction.data, tree, change.seq);
+        Ok(Some(change))
+    }
+
+    fn create_tree(
+        state: &mut Option<TreeState>,
+        slot: u64,
+        tree: Pubkey,
+        max_depth: u32,
+        max_buffer_size: u32,
+        hasher: TreeHasher,
+    ) -> Result<TreeChange, ProcessorError> {
+        if max_depth == 0 || max_depth > MAX_TREE_DEPTH || max_buffer_size == 0 {
+            return Err(ProcessorError::InvalidTreeConfig {
+                max_depth,
+                max_buffer_size,
+            });
+        }
+        if state.is_some() {
+            return Err(ProcessorError::TreeAlreadyInitialized(tree));
+        }
+        let state = state.insert(TreeState::new(max_depth, max_buffer_size, hasher, slot));
+        Ok(TreeChange {
+            tree,
+            seq: state.seq,
+            slot,
+            root: state.tree.root(),
+            leaf: None,
+        })
+    }
+
+    async fn commit(&self, state: &mut Option<TreeState>, change: &TreeChange) -> Result<(), ProcessorError> {
+        // The leaf change log is what a tree is rebuilt from. The full record is only written
+        // when the tree is created and every CHECKPOINT_INTERVAL changes, to bound the replay.
+        let checkpoint = (change.leaf.is_none() || change.seq % CHECKPOINT_INTERVAL == 0)
+            .then(|| loaded(state, &change.tree).map(|state| state.to_record(&change.tree)))
+            .transpose()?;
+        if let Err(e) = self.persist(checkpoint.as_ref(), change).await {
+            // Drop the cached copy so the next instruction reloads the last persisted state
+            *state = None;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Locks `tree` like [`lock_tree_for_write`](Self::lock_tree_for_write), but keeps no
+    /// cache entry for a tree that doesn't exist, so lookups of arbitrary pubkeys don't grow it.
+    async fn lock_tree(&self, tree: &Pubkey) -> Result<OwnedMutexGuard<Option<TreeState>>, ProcessorError> {
+        let slot = self.cached_slot(tree);
+        let state = self.load(tree, Arc::clone(&slot)).await?;
+        if state.is_some() {
+            return Ok(state);
+        }
+        drop(state);
+        {
+            let mut cache = self.cache.lock().unwrap();
+            // Other holders of the slot may be about to create the tree in it.
+            let unshared = cache.trees.get(tree).is_some_and(|cached| Arc::ptr_eq(&cached.slot, &slot))
+                && Arc::strong_count(&slot) == 2;
+            if unshared {
+                cache.trees.remove(tree);
+            }
+        }
+        Ok(TreeSlot::default().lock_owned().await)
+    }
+
+    /// Locks `tree`, loading it from the last checkpoint and the leaf changes after it if it
+    /// isn't cached. The state is `None` if the tree doesn't exist.
+    async fn lock_tree_for_write(&self, tree: &Pubkey) -> Result<OwnedMutexGuard<Option<TreeState>>, ProcessorError> {
+        self.load(tree, self.cached_slot(tree)).await
+    }
+
+    /// The cache slot of `tree`, added if missing. Trees idle for longer than the idle timeout
+    /// are dropped on the way.
+    fn cached_slot(&self, tree: &Pubkey) -> TreeSlot {
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap();
+        if now.duration_since(cache.last_sweep) >= self.idle_timeout {
+            // Slots are only cloned under the cache lock, so one held by the cache alone has
+            // no holder or waiter, and its state is already persisted.
+            let idle_timeout = self.idle_timeout;
+            cache.trees.retain(|_, cached| {
+                Arc::strong_count(&cached.slot) > 1 || now.duration_since(cached.last_used) < idle_timeout
+            });
+            cache.last_sweep = now;
+        }
+        let cached = cache.trees.entry(*tree).or_insert_with(|| CachedTree {
+            slot: TreeSlot::default(),
+            last_used: now,
+        });
+        cached.last_used = now;
+        Arc::clone(&cached.slot)
+    }
+
+    async fn load(&self, tree: &Pubkey, slot: TreeSlot) -> Result<OwnedMutexGuard<Option<TreeState>>, ProcessorError> {
+        let mut state = slot.lock_owned().await;
+        if state.is_none() {
+            if let Some(record) = self.store.get_merkle_tree(tree.as_ref()).await? {
+                let legacy = LEGACY_POSEIDON_HASHERS.contains(&record.hasher);
+                let mut loaded = TreeState::from_record(record)?;
+                for change in self.store.get_leaf_changes(tree.as_ref(), loaded.seq).await? {
+                    loaded.replay(tree, change)?;
+                }
+                if legacy {
+                    self.rehash(tree, &loaded).await?;
+                }
+                *state = Some(loaded);
+            }
+        }
+        Ok(state)
+    }
+
+    /// Stores a tree recorded under the old Poseidon parameters with its nodes recomputed.
+    /// Leaves are kept as they are, so only the roots change.
+    async fn rehash(&self, tree: &Pubkey, state: &TreeState) -> anyhow::Result<()> {
+        self.store
+            .insert_tree_root(&TreeRootRecord {
+                tree: tree.to_bytes().to_vec(),
+                seq: state.seq,
+                root: state.tree.root().to_vec(),
+                slot: state.slot,
+            })
+            .await?;
+        self.store.upsert_merkle_tree(&state.to_record(tree)).await?;
+        info!("Re-hashed tree {} with the current Poseidon parameters", tree);
+        Ok(())
+    }
+
+    async fn persist(&self, checkpoint: Option<&MerkleTreeRecord>, change: &TreeChange) -> anyhow::Result<()> {
+        let tree = change.tree.to_bytes().to_vec();
+        if let Some(leaf) = &change.leaf {
+            self.store
+                .insert_leaf_change(&LeafChangeRecord {
+                    tree: tree.clone(),
+                    seq: change.seq,
+                    leaf_index: leaf.index,
+                    previous_leaf: leaf.previous.to_vec(),
+                    new_leaf: leaf.new.to_vec(),
+                    slot: change.slot,
+                })
+                .await?;
+        }
+        self.store
+            .insert_tree_root(&TreeRootRecord {
+                tree,
+                seq: change.seq,
+                root: change.root.to_vec(),
+                slot: change.slot,
+            })
+            .await?;
+        if let Some(checkpoint) = checkpoint {
+            self.store.upsert_merkle_tree(checkpoint).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::InMemoryStorage;
+
+    fn instruction(tree: Pubkey, data: InstructionType) -> Instruction {
+        Instruction::new(Pubkey::new_unique(), vec![tree], data)
+    }
+
+    #[tokio::test]
+    async fn test_processor_transitions() {
+        let store = Arc::new(InMemoryStorage::new());
+        let processor = InstructionProcessor::new(store.clone());
+        let tree = Pubkey::new_unique();
+
+        processor
+            .process(1, &instruction(tree, InstructionType::InitTree { max_depth: 3, max_buffer_size: 2 }))
+            .await
+            .unwrap();
+        let change = processor
+            .process(2, &instruction(tree, InstructionType::AppendAccount { account: [1u8; 32] }))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(change.seq, 1);
+
+        // Poseidon trees refuse leaves at or above the field modulus, as on chain.
+        let err = processor
+            .process(2, &instruction(tree, InstructionType::AppendAccount { account: [0xffu8; 32] }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProcessorError::InvalidLeaf { .. }));
+
+        let err = processor
+            .process(
+                3,
+                &instruction(
+                    tree,
+                    InstructionType::UpdateAccount {
+                        root: change.root,
+                        previous_account: [9u8; 32],
+                        new_account: [2u8; 32],
+                        index: 0,
+                    },
+                ),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProcessorError::LeafMismatch { .. }));
+
+        processor
+            .process(
+                3,
+                &instruction(
+                    tree,
+                    InstructionType::UpdateAccount {
+                        root: change.root,
+                        previous_account: [1u8; 32],
+                        new_account: [2u8; 32],
+                        index: 0,
+                    },
+                ),
+            )
+            .await
+            .unwrap();
+
+        // Only the initial checkpoint is written; the reload replays both leaf changes onto it.
+        assert_eq!(store.get_merkle_tree(tree.as_ref()).await.unwrap().unwrap().seq, 0);
+        assert_eq!(store.get_leaf_changes(tree.as_ref(), 0).await.unwrap().len(), 2);
+        let reloaded = InstructionProcessor::new(store);
+        assert_eq!(reloaded.root(&tree).await.unwrap(), processor.root(&tree).await.unwrap());
+        assert_eq!(reloaded.seq(&tree).await.unwrap(), 2);
+        assert_eq!(reloaded.leaf(&tree, 0).await.unwrap(), [2u8; 32]);
+        let err = reloaded
+            .process(4, &instruction(tree, InstructionType::InitTree { max_depth: 3, max_buffer_size: 2 }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProcessorError::TreeAlreadyInitialized(_)));
+
+        // Root indices follow the on-chain sequence number once the indexer has seen it.
+        assert_eq!(reloaded.proof(&tree, 0).await.unwrap().root_index, None);
+        reloaded.set_chain_seq(&tree, 7).await.unwrap();
+        assert_eq!(reloaded.proof(&tree, 0).await.unwrap().root_index, Some(1));
+
+        // A diverged tree refuses proofs, across reloads, until it is reset.
+        reloaded.mark_diverged(&tree, 3).await.unwrap();
+        let reloaded = InstructionProcessor::new(Arc::clone(&reloaded.store));
+        let err = reloaded.proof(&tree, 0).await.unwrap_err();
+        assert!(matches!(err, ProcessorError::TreeDiverged { seq: 3, .. }));
+        reloaded.reset_tree(&tree).await.unwrap();
+        assert!(!reloaded.contains_tree(&tree).await.unwrap());
+        assert!(!InstructionProcessor::new(Arc::clone(&reloaded.store)).contains_tree(&tree).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rehash_legacy_poseidon() {
+        let store = Arc::new(InMemoryStorage::new());
+        let tree = Pubkey::new_unique();
+        store
+            .upsert_merkle_tree(&MerkleTreeRecord {
+                tree: tree.to_bytes().to_vec(),
+                max_depth: 3,
+                max_buffer_size: 4,
+                hasher: LEGACY_POSEIDON_HASHERS[1],
+                leaves: vec![vec![1u8; 32]],
+                recent_roots: vec![vec![9u8; 32]],
+                seq: 1,
+                chain_seq: None,
+                slot: 1,
+                diverged_seq: None,
+            })
+            .await
+            .unwrap();
+
+        let processor = InstructionProcessor::new(store.clone());
+        let expected = MerkleTree::from_leaves(3, TreeHasher::Poseidon, vec![[1u8; 32]]).root();
+        assert_eq!(processor.root(&tree).await.unwrap(), expected);
+        let record = store.get_merkle_tree(tree.as_ref()).await.unwrap().unwrap();
+        assert_eq!(record.hasher, TreeHasher::Poseidon as u8);
+        assert_eq!(record.recent_roots, vec![expected.to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_tree_cache_eviction() {
+        let store = Arc::new(InMemoryStorage::new());
+        let processor = InstructionProcessor::new(store).with_idle_timeout(Duration::ZERO);
+        let cached = |processor: &InstructionProcessor| processor.cache.lock().unwrap().trees.len();
+
+        // Lookups of trees that don't exist leave nothing behind.
+        let unknown = Pubkey::new_unique();
+        assert!(matches!(processor.root(&unknown).await, Err(ProcessorError::TreeNotFound(_))));
+        assert!(!processor.contains_tree(&unknown).await.unwrap());
+        assert_eq!(cached(&processor), 0);
+
+        let tree = Pubkey::new_unique();
+        processor
+            .process(1, &instruction(tree, InstructionType::InitTree { max_depth: 3, max_buffer_size: 2 }))
+            .await
+            .unwrap();
+        let change = processor
+            .process(2, &instruction(tree, InstructionType::AppendAccount { account: [1u8; 32] }))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cached(&processor), 1);
+
+        // Using another tree drops the idle one, which reloads as it was.
+        let other = Pubkey::new_unique();
+        processor.init_tree(3, other, 3, 2, TreeHasher::default()).await.unwrap();
+        assert_eq!(cached(&processor), 1);
+        assert_eq!(processor.root(&tree).await.unwrap(), change.root);
+        assert_eq!(processor.seq(&tree).await.unwrap(), 1);
+    }
+}
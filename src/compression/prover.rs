@@ -0,0 +1,764 @@
+use crate::compression::light::{
+    address_leaf, ADDRESS_TREE_DEPTH, DEFAULT_ADDRESS_TREE, HIGHEST_ADDRESS, STATE_TREE_DEPTH,
+};
+use crate::compression::poseidon::is_field_element;
+use crate::compression::{InstructionProcessor, MerkleProof, ProcessorError};
+use crate::storage::{CompressionStore, ProvingKeyFingerprint};
+use ark_bn254::Fq;
+use ark_ff::{BigInteger, PrimeField};
+use log::info;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use solana_sdk::alt_bn128::compression::prelude::{
+    alt_bn128_g1_compress, alt_bn128_g1_decompress, alt_bn128_g2_compress, alt_bn128_g2_decompress,
+};
+use solana_sdk::alt_bn128::prelude::{alt_bn128_addition, alt_bn128_multiplication, alt_bn128_pairing};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ValidityProofError {
+    #[error("Nothing to prove")]
+    EmptyRequest,
+
+    #[error("Compressed account not found: {}", hex::encode(.0))]
+    AccountNotFound([u8; 32]),
+
+    #[error("Compressed account already spent: {}", hex::encode(.0))]
+    AccountSpent([u8; 32]),
+
+    #[error("Address already exists: {}", hex::encode(.0))]
+    AddressExists([u8; 32]),
+
+    #[error("Address is outside the address space: {}", hex::encode(.0))]
+    InvalidAddress([u8; 32]),
+
+    #[error("Inputs span trees of different depths")]
+    MixedTreeDepths,
+
+    #[error("No circuit for depth {depth}, {inclusions} inclusions, {non_inclusions} non-inclusions")]
+    UnsupportedShape {
+        depth: usize,
+        inclusions: usize,
+        non_inclusions: usize,
+    },
+
+    #[error("No verifying key loaded for depth {depth}, {inclusions} inclusions, {non_inclusions} non-inclusions")]
+    MissingVerifyingKey {
+        depth: usize,
+        inclusions: usize,
+        non_inclusions: usize,
+    },
+
+    #[error("Prover unavailable: {0}")]
+    ProverUnavailable(String),
+
+    #[error("Prover returned a proof that does not verify")]
+    InvalidProof,
+
+    #[error("Root index of tree {0} is not known yet")]
+    UnknownRootIndex(Pubkey),
+
+    #[error(transparent)]
+    Processor(#[from] ProcessorError),
+
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidityProof {
+    /// Negated A, B and C compressed as Light's verifier expects them, 128 bytes.
+    pub compressed_proof: Vec<u8>,
+    /// Input hashes first, then new addresses, in request order.
+    pub merkle_proofs: Vec<MerkleProof>,
+}
+
+impl ValidityProof {
+    pub fn root_indices(&self) -> Vec<u32> {
+        // `leaf_proof` only returns proofs with a known root index.
+        self.merkle_proofs.iter().filter_map(|p| p.root_index).collect()
+    }
+}
+
+/// Circuits are keyed by (depth, inclusion count, non-inclusion count).
+pub type CircuitShape = (usize, usize, usize);
+
+const DEPTH: usize = STATE_TREE_DEPTH as usize;
+
+/// The circuits of Light's prover and verifier program. Requests of any other shape are refused.
+pub const CIRCUIT_SHAPES: &[CircuitShape] = &[
+    (DEPTH, 1, 0),
+    (DEPTH, 2, 0),
+    (DEPTH, 3, 0),
+    (DEPTH, 4, 0),
+    (DEPTH, 8, 0),
+    (DEPTH, 0, 1),
+    (DEPTH, 0, 2),
+    (DEPTH, 1, 1),
+    (DEPTH, 1, 2),
+    (DEPTH, 2, 1),
+    (DEPTH, 2, 2),
+    (DEPTH, 3, 1),
+    (DEPTH, 3, 2),
+    (DEPTH, 4, 1),
+    (DEPTH, 4, 2),
+];
+
+// Combined circuits share one depth, so address trees must match state trees.
+const _: () = assert!(ADDRESS_TREE_DEPTH == STATE_TREE_DEPTH);
+
+const G1: usize = 64;
+const G2: usize = 128;
+
+/// A Groth16 verifying key over BN254 in the layout Light's verifier program embeds:
+/// big-endian uncompressed `alpha_g1 | beta_g2 | gamma_g2 | delta_g2 | ic[..]`.
+#[derive(Debug, Clone)]
+pub struct LightVerifyingKey {
+    alpha_g1: [u8; G1],
+    beta_g2: [u8; G2],
+    gamma_g2: [u8; G2],
+    delta_g2: [u8; G2],
+    ic: Vec<[u8; G1]>,
+    fingerprint: Vec<u8>,
+}
+
+impl LightVerifyingKey {
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let header = G1 + 3 * G2;
+        if bytes.len() < header + G1 || (bytes.len() - header) % G1 != 0 {
+            anyhow::bail!("Verifying key has an invalid length of {} bytes", bytes.len());
+        }
+        let g2 = |offset: usize| -> [u8; G2] { bytes[offset..offset + G2].try_into().unwrap() };
+        Ok(Self {
+            alpha_g1: bytes[..G1].try_into().unwrap(),
+            beta_g2: g2(G1),
+            gamma_g2: g2(G1 + G2),
+            delta_g2: g2(G1 + 2 * G2),
+            ic: bytes[header..].chunks(G1).map(|point| point.try_into().unwrap()).collect(),
+            fingerprint: Sha256::digest(bytes).to_vec(),
+        })
+    }
+
+    /// SHA-256 of the key file, which identifies the circuit.
+    pub fn fingerprint(&self) -> &[u8] {
+        &self.fingerprint
+    }
+
+    /// Checks a proof as Light's verifier program does, with the public inputs in circuit
+    /// order. Inputs must be below the scalar field modulus.
+    pub fn verify_compressed(&self, compressed_proof: &[u8], public_inputs: &[[u8; 32]]) -> anyhow::Result<bool> {
+        if compressed_proof.len() != 128 {
+            anyhow::bail!("Compressed proof has {} bytes, expected 128", compressed_proof.len());
+        }
+        if public_inputs.len() + 1 != self.ic.len() {
+            anyhow::bail!(
+                "Expected {} public inputs, got {}",
+                self.ic.len() - 1,
+                public_inputs.len()
+            );
+        }
+        if public_inputs.iter().any(|input| !is_field_element(input)) {
+            return Ok(false);
+        }
+        let neg_a = alt_bn128_g1_decompress(&compressed_proof[..32])?;
+        let b = alt_bn128_g2_decompress(&compressed_proof[32..96])?;
+        let c = alt_bn128_g1_decompress(&compressed_proof[96..])?;
+
+        let mut prepared = self.ic[0].to_vec();
+        for (input, ic) in public_inputs.iter().zip(&self.ic[1..]) {
+            let product = alt_bn128_multiplication(&[ic.as_slice(), input].concat())?;
+            prepared = alt_bn128_addition(&[product, prepared].concat())?;
+        }
+        let pairing = alt_bn128_pairing(
+            &[
+                neg_a.as_slice(),
+                &b,
+                &prepared,
+                &self.gamma_g2,
+                &c,
+                &self.delta_g2,
+                &self.alpha_g1,
+                &self.beta_g2,
+            ]
+            .concat(),
+        )?;
+        Ok(pairing[31] == 1)
+    }
+}
+
+/// Light's verifying keys for the [`CIRCUIT_SHAPES`], loaded from the `.vk` files its prover
+/// ships, named `inclusion_26_1.vk`, `non_inclusion_26_1.vk` and `combined_26_1_1.vk`.
+#[derive(Default)]
+pub struct VerifyingKeys {
+    keys: HashMap<CircuitShape, Arc<LightVerifyingKey>>,
+}
+
+impl VerifyingKeys {
+    /// Loads the keys found in `dir`. Shapes without a key file are refused when proving.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let mut keys = HashMap::new();
+        for &shape in CIRCUIT_SHAPES {
+            let path = key_path(dir, shape);
+            if path.exists() {
+                let key = LightVerifyingKey::from_bytes(&std::fs::read(&path)?)
+                    .map_err(|e| anyhow::anyhow!("Failed to read verifying key {}: {}", path.display(), e))?;
+                keys.insert(shape, Arc::new(key));
+            }
+        }
+        if keys.is_empty() {
+            anyhow::bail!("No verifying keys found in {}", dir.display());
+        }
+        info!("Loaded {} verifying keys from {}", keys.len(), dir.display());
+        Ok(Self { keys })
+    }
+
+    pub fn insert(&mut self, shape: CircuitShape, key: LightVerifyingKey) {
+        self.keys.insert(shape, Arc::new(key));
+    }
+
+    pub fn get(&self, shape: CircuitShape) -> Result<Arc<LightVerifyingKey>, ValidityProofError> {
+        let (depth, inclusions, non_inclusions) = shape;
+        if !CIRCUIT_SHAPES.contains(&shape) {
+            return Err(ValidityProofError::UnsupportedShape {
+                depth,
+                inclusions,
+                non_inclusions,
+            });
+        }
+        self.keys
+            .get(&shape)
+            .cloned()
+            .ok_or(ValidityProofError::MissingVerifyingKey {
+                depth,
+                inclusions,
+                non_inclusions,
+            })
+    }
+
+    /// Fingerprints of the loaded keys, for recording in snapshots.
+    pub fn fingerprints(&self) -> Vec<ProvingKeyFingerprint> {
+        let mut keys: Vec<_> = self
+            .keys
+            .iter()
+            .map(|(&(depth, inclusions, non_inclusions), key)| ProvingKeyFingerprint {
+                depth: depth as u32,
+                inclusions: inclusions as u32,
+                fingerprint: key.fingerprint().to_vec(),
+                non_inclusions: non_inclusions as u32,
+            })
+            .collect();
+        keys.sort_by_key(|key| (key.depth, key.inclusions, key.non_inclusions));
+        keys
+    }
+}
+
+fn key_path(dir: &Path, shape: CircuitShape) -> PathBuf {
+    let name = match shape {
+        (depth, inclusions, 0) => format!("inclusion_{}_{}", depth, inclusions),
+        (depth, 0, non_inclusions) => format!("non_inclusion_{}_{}", depth, non_inclusions),
+        (depth, inclusions, non_inclusions) => format!("combined_{}_{}_{}", depth, inclusions, non_inclusions),
+    };
+    dir.join(format!("{}.vk", name))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InclusionInput {
+    root: String,
+    path_index: u32,
+    path_elements: Vec<String>,
+    leaf: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NonInclusionInput {
+    root: String,
+    value: String,
+    path_index: u32,
+    path_elements: Vec<String>,
+    leaf_lower_range_value: String,
+    leaf_higher_range_value: String,
+    next_index: u32,
+}
+
+#[derive(Default, Serialize)]
+struct ProveRequest {
+    #[serde(rename = "input-compressed-accounts", skip_serializing_if = "Vec::is_empty")]
+    inclusion: Vec<InclusionInput>,
+    #[serde(rename = "new-addresses", skip_serializing_if = "Vec::is_empty")]
+    non_inclusion: Vec<NonInclusionInput>,
+}
+
+/// Proof points as gnark writes them, big-endian with G2 coordinates imaginary part first.
+#[derive(Deserialize)]
+struct ProveResponse {
+    ar: [String; 2],
+    bs: [[String; 2]; 2],
+    krs: [String; 2],
+}
+
+impl ProveResponse {
+    /// Negates A and compresses the points, the form Light's verifier program takes.
+    fn compress(&self) -> anyhow::Result<Vec<u8>> {
+        let [x, y] = &self.ar;
+        let neg_a = [from_hex(x)?, negate(&from_hex(y)?)].concat();
+        let b = self
+            .bs
+            .iter()
+            .flatten()
+            .map(|value| from_hex(value))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .concat();
+        let c = [from_hex(&self.krs[0])?, from_hex(&self.krs[1])?].concat();
+        Ok([
+            alt_bn128_g1_compress(&neg_a)?.as_slice(),
+            &alt_bn128_g2_compress(&b)?,
+            &alt_bn128_g1_compress(&c)?,
+        ]
+        .concat())
+    }
+}
+
+fn negate(y: &[u8; 32]) -> [u8; 32] {
+    let negated = -Fq::from_be_bytes_mod_order(y);
+    negated.into_bigint().to_bytes_be().try_into().unwrap()
+}
+
+fn to_hex(value: &[u8; 32]) -> String {
+    format!("0x{}", hex::encode(value))
+}
+
+fn from_hex(value: &str) -> anyhow::Result<[u8; 32]> {
+    let digits = value.trim_start_matches("0x");
+    if digits.len() > 64 {
+        anyhow::bail!("Field element is longer than 32 bytes: {}", value);
+    }
+    let bytes = hex::decode(format!("{:0>64}", digits))?;
+    Ok(bytes.try_into().unwrap())
+}
+
+/// Client of Light's prover server. Proofs are checked against Light's verifying keys
+/// before they are served, so a misconfigured prover can't hand out unusable proofs.
+#[derive(Default)]
+pub struct LightProver {
+    url: String,
+    client: reqwest::Client,
+    keys: VerifyingKeys,
+}
+
+impl LightProver {
+    pub fn new(url: &str, keys: VerifyingKeys) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+            keys,
+        }
+    }
+
+    pub fn verifying_keys(&self) -> &VerifyingKeys {
+        &self.keys
+    }
+
+    async fn prove(
+        &self,
+        shape: CircuitShape,
+        request: &ProveRequest,
+        public_inputs: &[[u8; 32]],
+    ) -> Result<Vec<u8>, ValidityProofError> {
+        let key = self.keys.get(shape)?;
+        let unavailable = |e: reqwest::Error| ValidityProofError::ProverUnavailable(e.to_string());
+        let response = self
+            .client
+            .post(format!("{}/prove", self.url))
+            .json(request)
+            .send()
+            .await
+            .map_err(unavailable)?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ValidityProofError::ProverUnavailable(format!("{}: {}", status, body)));
+        }
+        let proof = response
+            .json::<ProveResponse>()
+            .await
+            .map_err(unavailable)?
+            .compress()?;
+        if !key.verify_compressed(&proof, public_inputs)? {
+            return Err(ValidityProofError::InvalidProof);
+        }
+        Ok(proof)
+    }
+}
+
+pub struct ValidityProver {
+    store: Arc<dyn CompressionStore>,
+    trees: Arc<InstructionProcessor>,
+    prover: Arc<LightProver>,
+}
+
+impl ValidityProver {
+    pub fn new(store: Arc<dyn CompressionStore>, trees: Arc<InstructionProcessor>, prover: Arc<LightProver>) -> Self {
+        Self { store, trees, prover }
+    }
+
+    pub async fn prove(
+        &self,
+        hashes: &[[u8; 32]],
+        new_addresses: &[[u8; 32]],
+    ) -> Result<ValidityProof, ValidityProofError> {
+        if hashes.is_empty() && new_addresses.is_empty() {
+            return Err(ValidityProofError::EmptyRequest);
+        }
+
+        let mut merkle_proofs = Vec::with_capacity(hashes.len() + new_addresses.len());
+        let mut request = ProveRequest::default();
+        for hash in hashes {
+            let account = self
+                .store
+                .get_state_account(hash)
+                .await?
+                .ok_or(ValidityProofError::AccountNotFound(*hash))?;
+            if account.spent {
+                return Err(ValidityProofError::AccountSpent(*hash));
+            }
+            let tree = Pubkey::try_from(account.tree.as_slice()).map_err(anyhow::Error::from)?;
+            let proof = self.leaf_proof(&tree, account.leaf_index, *hash).await?;
+            request.inclusion.push(InclusionInput {
+                root: to_hex(&proof.root),
+                path_index: proof.leaf_index,
+                path_elements: proof.path.iter().map(to_hex).collect(),
+                leaf: to_hex(hash),
+            });
+            merkle_proofs.push(proof);
+        }
+
+        for address in new_addresses {
+            if *address >= HIGHEST_ADDRESS {
+                return Err(ValidityProofError::InvalidAddress(*address));
+            }
+            if self.store.get_address(address).await?.is_some() {
+                return Err(ValidityProofError::AddressExists(*address));
+            }
+            let tree = DEFAULT_ADDRESS_TREE;
+            let low = self
+                .store
+                .get_low_address(tree.as_ref(), address)
+                .await?
+                .ok_or(ProcessorError::TreeNotFound(tree))?;
+            let low_address: [u8; 32] = low.address.as_slice().try_into().map_err(anyhow::Error::from)?;
+            let next_address: [u8; 32] = low.next_address.as_slice().try_into().map_err(anyhow::Error::from)?;
+            // Every tree holds the highest address from its creation, so the next element exists.
+            let next_index = self
+                .store
+                .get_address(&next_address)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Address {} is missing from its tree", hex::encode(next_address)))?
+                .leaf_index;
+            let leaf = address_leaf(&low_address, u64::from(next_index), &next_address).map_err(anyhow::Error::from)?;
+            let proof = self.leaf_proof(&tree, low.leaf_index, leaf).await?;
+            request.non_inclusion.push(NonInclusionInput {
+                root: to_hex(&proof.root),
+                value: to_hex(address),
+                path_index: proof.leaf_index,
+                path_elements: proof.path.iter().map(to_hex).collect(),
+                leaf_lower_range_value: to_hex(&low_address),
+                leaf_higher_range_value: to_hex(&next_address),
+                next_index,
+            });
+            merkle_proofs.push(proof);
+        }
+
+        let depth = merkle_proofs[0].path.len();
+        if merkle_proofs.iter().any(|p| p.path.len() != depth) {
+            return Err(ValidityProofError::MixedTreeDepths);
+        }
+        let (state, addresses) = merkle_proofs.split_at(hashes.len());
+        let public_inputs: Vec<[u8; 32]> = state
+            .iter()
+            .map(|p| p.root)
+            .chain(hashes.iter().copied())
+            .chain(addresses.iter().map(|p| p.root))
+            .chain(new_addresses.iter().copied())
+            .collect();
+        let compressed_proof = self
+            .prover
+            .prove((depth, hashes.len(), new_addresses.len()), &request, &public_inputs)
+            .await?;
+
+        Ok(ValidityProof {
+            compressed_proof,
+            merkle_proofs,
+        })
+    }
+
+    async fn leaf_proof(&self, tree: &Pubkey, index: u32, expected: [u8; 32]) -> Result<MerkleProof, ValidityProofError> {
+        let proof = self.trees.proof(tree, index).await?;
+        if proof.leaf != expected {
+            return Err(ProcessorError::LeafMismatch {
+                tree: *tree,
+                index,
+                expected,
+                actual: proof.leaf,
+            }
+            .into());
+        }
+        if proof.root_index.is_none() {
+            return Err(ValidityProofError::UnknownRootIndex(*tree));
+        }
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::TreeHasher;
+    use crate::storage::InMemoryStorage;
+    use crate::compression::light::address_tree_genesis;
+    use ark_bn254::{Bn254, Fr, G1Affine, G2Affine};
+    use ark_groth16::{Groth16, ProvingKey};
+    use ark_relations::lc;
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError, Variable};
+    use ark_snark::SNARK;
+    use serde_json::{json, Value};
+    use warp::Filter;
+
+    /// Binds its public inputs and nothing else, standing in for Light's circuits.
+    struct PublicInputs(Vec<Fr>);
+
+    impl ConstraintSynthesizer<Fr> for PublicInputs {
+        fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+            for input in self.0 {
+                let variable = cs.new_input_variable(|| Ok(input))?;
+                cs.enforce_constraint(lc!() + variable, lc!() + Variable::One, lc!() + variable)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn fq(value: Fq) -> Vec<u8> {
+        value.into_bigint().to_bytes_be()
+    }
+
+    fn g1(point: &G1Affine) -> Vec<u8> {
+        [fq(point.x), fq(point.y)].concat()
+    }
+
+    fn g2(point: &G2Affine) -> Vec<u8> {
+        [fq(point.x.c1), fq(point.x.c0), fq(point.y.c1), fq(point.y.c0)].concat()
+    }
+
+    fn hex_field(value: Fq) -> String {
+        format!("0x{}", hex::encode(fq(value)))
+    }
+
+    fn setup(inputs: usize) -> (ProvingKey<Bn254>, LightVerifyingKey) {
+        let circuit = PublicInputs(vec![Fr::from(0u64); inputs]);
+        let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(circuit, &mut rand::thread_rng()).unwrap();
+        let mut bytes = [g1(&vk.alpha_g1), g2(&vk.beta_g2), g2(&vk.gamma_g2), g2(&vk.delta_g2)].concat();
+        for point in &vk.gamma_abc_g1 {
+            bytes.extend(g1(point));
+        }
+        (pk, LightVerifyingKey::from_bytes(&bytes).unwrap())
+    }
+
+    /// A proof of `inputs` in the JSON the prover server returns.
+    fn prove(pk: &ProvingKey<Bn254>, inputs: Vec<Fr>) -> Value {
+        let proof = Groth16::<Bn254>::prove(pk, PublicInputs(inputs), &mut rand::thread_rng()).unwrap();
+        json!({
+            "ar": [hex_field(proof.a.x), hex_field(proof.a.y)],
+            "bs": [
+                [hex_field(proof.b.x.c1), hex_field(proof.b.x.c0)],
+                [hex_field(proof.b.y.c1), hex_field(proof.b.y.c0)],
+            ],
+            "krs": [hex_field(proof.c.x), hex_field(proof.c.y)],
+        })
+    }
+
+    fn field(value: &Value) -> [u8; 32] {
+        from_hex(value.as_str().unwrap()).unwrap()
+    }
+
+    fn path_root(input: &Value, leaf: [u8; 32]) -> [u8; 32] {
+        let index = input["pathIndex"].as_u64().unwrap();
+        let path = input["pathElements"].as_array().unwrap();
+        path.iter().enumerate().fold(leaf, |node, (level, sibling)| {
+            if (index >> level) & 1 == 0 {
+                TreeHasher::default().hash(&node, &field(sibling))
+            } else {
+                TreeHasher::default().hash(&field(sibling), &node)
+            }
+        })
+    }
+
+    /// Serves `/prove` like Light's prover: checks the Merkle paths and address ranges it is
+    /// sent, then proves the public inputs in circuit order.
+    fn mock_prover(pk: ProvingKey<Bn254>) -> String {
+        let route = warp::post().and(warp::path("prove")).and(warp::body::json()).map(move |request: Value| {
+            let inclusion = request["input-compressed-accounts"].as_array().cloned().unwrap_or_default();
+            let non_inclusion = request["new-addresses"].as_array().cloned().unwrap_or_default();
+            let included = inclusion
+                .iter()
+                .all(|input| path_root(input, field(&input["leaf"])) == field(&input["root"]));
+            let excluded = non_inclusion.iter().all(|input| {
+                let (low, value, high) = (
+                    field(&input["leafLowerRangeValue"]),
+                    field(&input["value"]),
+                    field(&input["leafHigherRangeValue"]),
+                );
+                let leaf = address_leaf(&low, input["nextIndex"].as_u64().unwrap(), &high).unwrap();
+                low < value && value < high && path_root(input, leaf) == field(&input["root"])
+            });
+            if !included || !excluded {
+                return warp::reply::with_status(warp::reply::json(&json!("invalid witness")), warp::http::StatusCode::BAD_REQUEST);
+            }
+            let inputs = inclusion
+                .iter()
+                .map(|input| &input["root"])
+                .chain(inclusion.iter().map(|input| &input["leaf"]))
+                .chain(non_inclusion.iter().map(|input| &input["root"]))
+                .chain(non_inclusion.iter().map(|input| &input["value"]))
+                .map(|value| Fr::from_be_bytes_mod_order(&field(value)))
+                .collect();
+            warp::reply::with_status(warp::reply::json(&prove(&pk, inputs)), warp::http::StatusCode::OK)
+        });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        format!("http://{}", address)
+    }
+
+    fn instruction(tree: Pubkey, data: crate::compression::InstructionType) -> crate::compression::Instruction {
+        crate::compression::Instruction::new(Pubkey::new_unique(), vec![tree], data)
+    }
+
+    /// A state tree holding `hashes` and an address tree as Light creates it.
+    async fn tree_state(hashes: &[[u8; 32]]) -> (Arc<InMemoryStorage>, Arc<InstructionProcessor>, Pubkey) {
+        use crate::compression::InstructionType::AppendAccount;
+
+        let store = Arc::new(InMemoryStorage::new());
+        let trees = Arc::new(InstructionProcessor::new(store.clone()));
+        let tree = Pubkey::new_unique();
+        trees.init_tree(1, tree, STATE_TREE_DEPTH, 2400, TreeHasher::default()).await.unwrap();
+        for (index, hash) in hashes.iter().enumerate() {
+            trees.process(2, &instruction(tree, AppendAccount { account: *hash })).await.unwrap();
+            store
+                .upsert_state_account(&crate::storage::CompressedStateAccount {
+                    hash: hash.to_vec(),
+                    address: None,
+                    owner: Pubkey::new_unique().to_bytes().to_vec(),
+                    lamports: 42,
+                    discriminator: None,
+                    data: Vec::new(),
+                    data_hash: None,
+                    tree: tree.to_bytes().to_vec(),
+                    leaf_index: index as u32,
+                    seq: Some(index as u64 + 1),
+                    slot_created: 2,
+                    spent: false,
+                    spent_slot: None,
+                })
+                .await
+                .unwrap();
+        }
+        trees.set_chain_seq(&tree, hashes.len() as u64).await.unwrap();
+
+        let address_tree = DEFAULT_ADDRESS_TREE;
+        trees
+            .init_tree(1, address_tree, ADDRESS_TREE_DEPTH, 2400, TreeHasher::default())
+            .await
+            .unwrap();
+        for element in address_tree_genesis() {
+            let leaf = address_leaf(&element.value, element.next_index, &element.next_value).unwrap();
+            trees.process(1, &instruction(address_tree, AppendAccount { account: leaf })).await.unwrap();
+            store
+                .insert_address(&crate::storage::AddressRecord {
+                    address: element.value.to_vec(),
+                    tree: address_tree.to_bytes().to_vec(),
+                    leaf_index: element.index as u32,
+                    next_address: element.next_value.to_vec(),
+                    slot: 1,
+                })
+                .await
+                .unwrap();
+        }
+        trees.set_chain_seq(&address_tree, 3).await.unwrap();
+        (store, trees, tree)
+    }
+
+    #[test]
+    fn test_verify_compressed() {
+        let (pk, key) = setup(2);
+        let response: ProveResponse = serde_json::from_value(prove(&pk, vec![Fr::from(5u64), Fr::from(7u64)])).unwrap();
+        let proof = response.compress().unwrap();
+        let input = |value: u8| {
+            let mut input = [0u8; 32];
+            input[31] = value;
+            input
+        };
+        assert!(key.verify_compressed(&proof, &[input(5), input(7)]).unwrap());
+        assert!(!key.verify_compressed(&proof, &[input(7), input(5)]).unwrap());
+        assert!(key.verify_compressed(&proof, &[input(5)]).is_err());
+
+        // Inputs at or above the modulus would alias smaller ones on-chain.
+        let mut aliased = Fr::from(5u64).into_bigint();
+        aliased.add_with_carry(&Fr::MODULUS);
+        let aliased: [u8; 32] = aliased.to_bytes_be().try_into().unwrap();
+        assert!(!key.verify_compressed(&proof, &[aliased, input(7)]).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_prove_and_verify() {
+        let hashes = [[1u8; 32], [2u8; 32]];
+        let (store, trees, tree) = tree_state(&hashes).await;
+        let mut address = [7u8; 32];
+        address[0] = 0;
+
+        let (pk, key) = setup(6);
+        let mut keys = VerifyingKeys::default();
+        keys.insert((DEPTH, 2, 1), key.clone());
+        let prover = ValidityProver::new(store, trees.clone(), Arc::new(LightProver::new(&mock_prover(pk), keys)));
+
+        let proof = prover.prove(&hashes, &[address]).await.unwrap();
+        assert_eq!(proof.compressed_proof.len(), 128);
+        assert_eq!(proof.root_indices(), vec![2, 2, 3]);
+        let root = trees.root(&tree).await.unwrap();
+        let address_root = trees.root(&DEFAULT_ADDRESS_TREE).await.unwrap();
+        let inputs = [root, root, hashes[0], hashes[1], address_root, address];
+        assert!(key.verify_compressed(&proof.compressed_proof, &inputs).unwrap());
+        let swapped = [root, root, hashes[1], hashes[0], address_root, address];
+        assert!(!key.verify_compressed(&proof.compressed_proof, &swapped).unwrap());
+
+        assert!(matches!(prover.prove(&hashes[..1], &[]).await, Err(ValidityProofError::MissingVerifyingKey { .. })));
+        assert!(matches!(
+            prover.prove(&[hashes[0]; 5], &[]).await,
+            Err(ValidityProofError::UnsupportedShape { .. })
+        ));
+        assert!(matches!(
+            prover.prove(&[], &[[0u8; 32]]).await,
+            Err(ValidityProofError::AddressExists(_))
+        ));
+    }
+
+    /// Proves against a running Light prover, e.g. `light test-validator`'s, with the
+    /// verifying keys it ships.
+    #[tokio::test]
+    #[ignore]
+    async fn test_light_prover() {
+        let url = std::env::var("WINDEXER_TEST_LIGHT_PROVER_URL").unwrap();
+        let keys = VerifyingKeys::load(Path::new(&std::env::var("WINDEXER_TEST_LIGHT_VERIFYING_KEYS").unwrap())).unwrap();
+        let hashes = [[1u8; 32]];
+        let (store, trees, _) = tree_state(&hashes).await;
+        let prover = ValidityProver::new(store, trees, Arc::new(LightProver::new(&url, keys)));
+
+        let mut address = [7u8; 32];
+        address[0] = 0;
+        prover.prove(&hashes, &[]).await.unwrap();
+        prover.prove(&[], &[address]).await.unwrap();
+    }
+}
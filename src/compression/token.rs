@@ -0,0 +1,128 @@
+use crate::storage::CompressedTokenAccountRecord;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+
+pub const COMPRESSED_TOKEN_PROGRAM_ID: Pubkey = pubkey!("cTokenmWW8bLPjZEBAUgYy3zKxQZW6VKi7bqNFEVv3m");
+pub const SPL_TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+pub const SPL_TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+pub const TOKEN_DATA_DISCRIMINATOR: [u8; 8] = [2, 0, 0, 0, 0, 0, 0, 0];
+
+const MINT_LEN: usize = 82;
+const MINT_SUPPLY_OFFSET: usize = 36;
+const MINT_DECIMALS_OFFSET: usize = 44;
+const MINT_INITIALIZED_OFFSET: usize = 45;
+// Token-2022 pads extended accounts to the token account length before the type byte.
+const ACCOUNT_TYPE_OFFSET: usize = 165;
+const ACCOUNT_TYPE_MINT: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum AccountState {
+    Initialized,
+    Frozen,
+}
+
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct TokenData {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub delegate: Option<Pubkey>,
+    pub state: AccountState,
+    pub tlv: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MintInfo {
+    pub supply: u64,
+    pub decimals: u8,
+}
+
+pub fn is_token_program(program_id: &Pubkey) -> bool {
+    program_id == &SPL_TOKEN_PROGRAM_ID || program_id == &SPL_TOKEN_2022_PROGRAM_ID
+}
+
+pub fn decode_token_data(owner: &Pubkey, discriminator: Option<&[u8]>, data: &[u8]) -> Option<TokenData> {
+    if owner != &COMPRESSED_TOKEN_PROGRAM_ID || discriminator != Some(&TOKEN_DATA_DISCRIMINATOR[..]) {
+        return None;
+    }
+    TokenData::try_from_slice(data).ok()
+}
+
+/// Token-2022 mints with extensions are told apart from token accounts by their account
+/// type byte.
+pub fn decode_mint(program_id: &Pubkey, data: &[u8]) -> Option<MintInfo> {
+    if !is_token_program(program_id) || data.len() < MINT_LEN {
+        return None;
+    }
+    let is_mint = data.len() == MINT_LEN
+        || (program_id == &SPL_TOKEN_2022_PROGRAM_ID && data.get(ACCOUNT_TYPE_OFFSET) == Some(&ACCOUNT_TYPE_MINT));
+    if !is_mint {
+        return None;
+    }
+    if data[MINT_INITIALIZED_OFFSET] != 1 {
+        return None;
+    }
+    let supply = u64::from_le_bytes(data[MINT_SUPPLY_OFFSET..MINT_DECIMALS_OFFSET].try_into().ok()?);
+    Some(MintInfo {
+        supply,
+        decimals: data[MINT_DECIMALS_OFFSET],
+    })
+}
+
+pub fn aggregate_balances(accounts: &[CompressedTokenAccountRecord]) -> BTreeMap<Vec<u8>, u64> {
+    let mut balances = BTreeMap::new();
+    for account in accounts {
+        let balance = balances.entry(account.mint.clone()).or_insert(0u64);
+        *balance = balance.saturating_add(account.amount);
+    }
+    balances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_token_data_and_mint() {
+        let token_data = TokenData {
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount: 1_000,
+            delegate: None,
+            state: AccountState::Initialized,
+            tlv: None,
+        };
+        let data = borsh::to_vec(&token_data).unwrap();
+        assert_eq!(
+            decode_token_data(&COMPRESSED_TOKEN_PROGRAM_ID, Some(&TOKEN_DATA_DISCRIMINATOR), &data),
+            Some(token_data)
+        );
+        assert_eq!(decode_token_data(&Pubkey::new_unique(), Some(&TOKEN_DATA_DISCRIMINATOR), &data), None);
+
+        let mut mint = vec![0u8; MINT_LEN];
+        mint[MINT_SUPPLY_OFFSET..MINT_DECIMALS_OFFSET].copy_from_slice(&5_000u64.to_le_bytes());
+        mint[MINT_DECIMALS_OFFSET] = 6;
+        mint[MINT_INITIALIZED_OFFSET] = 1;
+        assert_eq!(
+            decode_mint(&SPL_TOKEN_PROGRAM_ID, &mint),
+            Some(MintInfo { supply: 5_000, decimals: 6 })
+        );
+        assert_eq!(decode_mint(&SPL_TOKEN_PROGRAM_ID, &mint[..80]), None);
+
+        // A Token-2022 token account is longer than a mint but has account type 2.
+        let mut account = vec![0u8; ACCOUNT_TYPE_OFFSET + 1];
+        account[MINT_INITIALIZED_OFFSET] = 1;
+        account[ACCOUNT_TYPE_OFFSET] = 2;
+        assert_eq!(decode_mint(&SPL_TOKEN_2022_PROGRAM_ID, &account), None);
+        account[..MINT_LEN].copy_from_slice(&mint);
+        account[ACCOUNT_TYPE_OFFSET] = ACCOUNT_TYPE_MINT;
+        assert_eq!(
+            decode_mint(&SPL_TOKEN_2022_PROGRAM_ID, &account),
+            Some(MintInfo { supply: 5_000, decimals: 6 })
+        );
+        assert_eq!(decode_mint(&SPL_TOKEN_PROGRAM_ID, &account), None);
+    }
+}
@@ -1,9 +1,10 @@
 use crate::grpc::methods::*;
 use crate::proto::windexer_client::WindexerClient;
 use crate::proto::{
-    GetAccountRequest, GetCompressedAccountRequest, GetCompressedBalanceRequest,
+    GetAccountRequest, GetAssetProofRequest, GetCompressedAccountRequest, GetCompressedBalanceRequest,
     GetCompressedTokenAccountBalanceRequest, GetCompressedTokenAccountsByOwnerRequest,
-    GetSlotRequest, GetTransactionRequest,
+    GetCompressedTokenBalancesByOwnerRequest, GetSlotRequest, GetTransactionRequest,
+    GetValidityProofRequest,
 };
 use anyhow::Result;
 use solana_sdk::{pubkey::Pubkey, signature::Signature};
@@ -86,6 +87,48 @@ impl GrpcClient {
             .collect())
     }
 
+    pub async fn get_compressed_token_balances_by_owner(
+        &mut self,
+        owner: &Pubkey,
+        filter: Option<RpcTokenAccountsFilter>,
+    ) -> Result<Vec<TokenBalance>> {
+        let request = tonic::Request::new(GetCompressedTokenBalancesByOwnerRequest {
+            owner: owner.to_string(),
+            filter: filter.map(|f| f.into()),
+        });
+        let response = self
+            .inner
+            .get_compressed_token_balances_by_owner(request)
+            .await?;
+        Ok(response
+            .into_inner()
+            .balances
+            .into_iter()
+            .map(|b| b.into())
+            .collect())
+    }
+
+    pub async fn get_validity_proof(
+        &mut self,
+        hashes: &[[u8; 32]],
+        new_addresses: &[[u8; 32]],
+    ) -> Result<CompressedValidityProof> {
+        let request = tonic::Request::new(GetValidityProofRequest {
+            hashes: hashes.iter().map(|h| h.to_vec()).collect(),
+            new_addresses: new_addresses.iter().map(|a| a.to_vec()).collect(),
+        });
+        let response = self.inner.get_validity_proof(request).await?;
+        Ok(response.into_inner().into())
+    }
+
+    pub async fn get_asset_proof(&mut self, asset_id: &Pubkey) -> Result<AssetProof> {
+        let request = tonic::Request::new(GetAssetProofRequest {
+            id: asset_id.to_string(),
+        });
+        let response = self.inner.get_asset_proof(request).await?;
+        Ok(response.into_inner().into())
+    }
+
     pub async fn get_transaction(
         &mut self,
         signature: &Signature,
@@ -17,7 +17,32 @@ pub struct CompressedAccount {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenAccountBalance {
     pub amount: String,
-    pub decimals: u8,
+    pub decimals: Option<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenBalance {
+    pub mint: Pubkey,
+    pub amount: String,
+    pub decimals: Option<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompressedValidityProof {
+    pub compressed_proof: Vec<u8>,
+    pub root_indices: Vec<u32>,
+    pub roots: Vec<Vec<u8>>,
+    pub leaf_indices: Vec<u32>,
+    pub merkle_trees: Vec<Pubkey>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssetProof {
+    pub root: String,
+    pub proof: Vec<String>,
+    pub node_index: u32,
+    pub leaf: String,
+    pub tree_id: Pubkey,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,7 +50,7 @@ pub struct CompressedTokenAccount {
     pub pubkey: Pubkey,
     pub account: CompressedAccount,
     pub amount: String,
-    pub decimals: u8,
+    pub decimals: Option<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -59,11 +84,49 @@ impl From<proto::CompressedAccount> for CompressedAccount {
     }
 }
 
-impl From<proto::TokenAccountBalance> for TokenAccountBalance {
-    fn from(proto_balance: proto::TokenAccountBalance) -> Self {
+impl From<proto::GetCompressedTokenAccountBalanceResponse> for TokenAccountBalance {
+    fn from(proto_balance: proto::GetCompressedTokenAccountBalanceResponse) -> Self {
         Self {
             amount: proto_balance.amount,
-            decimals: proto_balance.decimals as u8,
+            decimals: proto_balance.decimals.map(|decimals| decimals as u8),
+        }
+    }
+}
+
+impl From<proto::TokenBalance> for TokenBalance {
+    fn from(proto_balance: proto::TokenBalance) -> Self {
+        Self {
+            mint: proto_balance.mint.parse().unwrap(),
+            amount: proto_balance.amount,
+            decimals: proto_balance.decimals.map(|decimals| decimals as u8),
+        }
+    }
+}
+
+impl From<proto::GetValidityProofResponse> for CompressedValidityProof {
+    fn from(proto_proof: proto::GetValidityProofResponse) -> Self {
+        Self {
+            compressed_proof: proto_proof.compressed_proof,
+            root_indices: proto_proof.root_indices,
+            roots: proto_proof.roots,
+            leaf_indices: proto_proof.leaf_indices,
+            merkle_trees: proto_proof
+                .merkle_trees
+                .iter()
+                .map(|tree| tree.parse().unwrap())
+                .collect(),
+        }
+    }
+}
+
+impl From<proto::GetAssetProofResponse> for AssetProof {
+    fn from(proto_proof: proto::GetAssetProofResponse) -> Self {
+        Self {
+            root: proto_proof.root,
+            proof: proto_proof.proof,
+            node_index: proto_proof.node_index,
+            leaf: proto_proof.leaf,
+            tree_id: proto_proof.tree_id.parse().unwrap(),
         }
     }
 }
@@ -74,7 +137,7 @@ impl From<proto::CompressedTokenAccount> for CompressedTokenAccount {
             pubkey: Pubkey::new_from_array(proto_token_account.pubkey.try_into().unwrap()),
             account: proto_token_account.account.unwrap().into(),
             amount: proto_token_account.amount,
-            decimals: proto_token_account.decimals as u8,
+            decimals: proto_token_account.decimals.map(|decimals| decimals as u8),
         }
     }
 }
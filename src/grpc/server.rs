@@ -1,11 +1,17 @@
+use crate::compression::{token, InstructionProcessor, LightProver, ProcessorError, ValidityProofError, ValidityProver};
 use crate::grpc::methods::*;
 use crate::proto;
 use crate::proto::windexer_server::{Windexer, WindexerServer};
 use crate::proto::*;
 use anyhow::Result;
+use log::warn;
+use crate::storage::{
+    CompressedAssetRecord, CompressedStateAccount, CompressedTokenAccountRecord, CompressionStore,
+    StorageError, TokenMintRecord,
+};
 use solana_client::rpc_client::RpcClient as SolanaRpcClient;
 use solana_sdk::{
-    commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature,
+    bs58, commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature,
     transaction::Transaction,
 };
 use solana_transaction_status::{
@@ -16,10 +22,36 @@ use solana_transaction_status::{
 use solana_transaction_status::{
     EncodedConfirmedBlock, EncodedConfirmedTransaction, UiTransactionEncoding,
 };
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
 use tonic::{transport::Server, Request, Response, Status};
 
+impl From<StorageError> for Status {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::NotFound(_) => Status::not_found(e.to_string()),
+            StorageError::Conflict(_) => Status::aborted(e.to_string()),
+            StorageError::Unavailable(_) => Status::unavailable(e.to_string()),
+            StorageError::Corrupt(_) => Status::data_loss(e.to_string()),
+            StorageError::Other(_) => Status::internal(e.to_string()),
+        }
+    }
+}
+
+fn store_error(e: anyhow::Error) -> Status {
+    StorageError::from(e).into()
+}
+
+struct CompressionState {
+    store: Arc<dyn CompressionStore>,
+    trees: Arc<InstructionProcessor>,
+    prover: ValidityProver,
+}
+
 pub struct GrpcServer {
     solana_rpc: SolanaRpcClient,
+    compression: Option<CompressionState>,
 }
 
 impl GrpcServer {
@@ -29,7 +61,122 @@ impl GrpcServer {
                 solana_rpc_url.to_string(),
                 CommitmentConfig::confirmed(),
             ),
+            compression: None,
+        }
+    }
+
+    pub fn with_compression_state(
+        mut self,
+        store: Arc<dyn CompressionStore>,
+        trees: Arc<InstructionProcessor>,
+        prover: Arc<LightProver>,
+    ) -> Self {
+        let prover = ValidityProver::new(store.clone(), trees.clone(), prover);
+        self.compression = Some(CompressionState { store, trees, prover });
+        self
+    }
+
+    fn compression(&self) -> Result<&CompressionState, Status> {
+        self.compression
+            .as_ref()
+            .ok_or_else(|| Status::unavailable("Compressed state indexing is not enabled"))
+    }
+
+    async fn find_state_account(&self, key: &Pubkey) -> Result<CompressedStateAccount, Status> {
+        let store = &self.compression()?.store;
+        let account = match store
+            .get_state_account_by_address(key.as_ref())
+            .await
+            .map_err(store_error)?
+        {
+            Some(account) => Some(account),
+            None => store
+                .get_state_account(key.as_ref())
+                .await
+                .map_err(store_error)?,
+        };
+        account.ok_or_else(|| Status::not_found(format!("Compressed account not found: {}", key)))
+    }
+
+    async fn token_accounts_by_owner(
+        &self,
+        owner: &Pubkey,
+        filter: Option<RpcTokenAccountsFilter>,
+    ) -> Result<Vec<(CompressedTokenAccountRecord, Option<TokenMintRecord>)>, Status> {
+        let accounts = self
+            .compression()?
+            .store
+            .get_token_accounts_by_owner(owner.as_ref())
+            .await
+            .map_err(store_error)?;
+
+        // Accounts of a mint we haven't indexed are returned without decimals.
+        let mut mints: HashMap<Vec<u8>, Option<TokenMintRecord>> = HashMap::new();
+        let mut result = Vec::with_capacity(accounts.len());
+        for account in accounts {
+            if let Some(RpcTokenAccountsFilter::Mint(mint)) = &filter {
+                if account.mint.as_slice() != mint.as_ref() {
+                    continue;
+                }
+            }
+            let mint = match mints.get(&account.mint) {
+                Some(mint) => mint.clone(),
+                None => {
+                    let mint = self
+                        .compression()?
+                        .store
+                        .get_token_mint(&account.mint)
+                        .await
+                        .map_err(store_error)?;
+                    if mint.is_none() {
+                        warn!("Mint has not been indexed: {}", bs58::encode(&account.mint).into_string());
+                    }
+                    mints.insert(account.mint.clone(), mint.clone());
+                    mint
+                }
+            };
+            if let Some(RpcTokenAccountsFilter::ProgramId(program_id)) = &filter {
+                // Without the mint there's no telling which token program it belongs to.
+                let matches = mint.as_ref().map_or(false, |mint| mint.program_id.as_slice() == program_id.as_ref());
+                if program_id != &token::COMPRESSED_TOKEN_PROGRAM_ID && !matches {
+                    continue;
+                }
+            }
+            result.push((account, mint));
+        }
+        Ok(result)
+    }
+
+    async fn merkle_context(&self, account: &CompressedStateAccount) -> Result<proto::MerkleContext, Status> {
+        let tree = Pubkey::try_from(account.tree.as_slice())
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let mut context = proto::MerkleContext {
+            hash: account.hash.clone(),
+            tree: tree.to_string(),
+            leaf_index: account.leaf_index,
+            seq: account.seq.unwrap_or_default(),
+            root: Vec::new(),
+            proof: Vec::new(),
+        };
+        match self.compression()?.trees.proof(&tree, account.leaf_index).await {
+            Ok(proof) => {
+                context.root = proof.root.to_vec();
+                context.proof = proof.path.iter().map(|node| node.to_vec()).collect();
+            }
+            Err(ProcessorError::TreeNotFound(_)) | Err(ProcessorError::LeafIndexOutOfBounds { .. }) => {}
+            Err(e @ ProcessorError::TreeDiverged { .. }) => return Err(Status::unavailable(e.to_string())),
+            Err(e) => return Err(Status::internal(e.to_string())),
         }
+        Ok(context)
+    }
+
+    async fn find_asset(&self, id: &Pubkey) -> Result<CompressedAssetRecord, Status> {
+        self.compression()?
+            .store
+            .get_asset(id.as_ref())
+            .await
+            .map_err(store_error)?
+            .ok_or_else(|| Status::not_found(format!("Asset not found: {}", id)))
     }
 
     pub async fn run(self, addr: &str) -> Result<()> {
@@ -134,16 +281,12 @@ impl Windexer for GrpcServer {
     ) -> Result<Response<GetCompressedAccountResponse>, Status> {
         let pubkey = Pubkey::from_str(&request.into_inner().pubkey)
             .map_err(|e| Status::invalid_argument(e.to_string()))?;
-        let compressed_account = get_compressed_account(&self.solana_rpc, &pubkey)
-            .map_err(|e| Status::internal(e.to_string()))?;
+        let compressed_account = self.find_state_account(&pubkey).await?;
+        let merkle_context = self.merkle_context(&compressed_account).await?;
 
         Ok(Response::new(GetCompressedAccountResponse {
-            pubkey: compressed_account.pubkey.to_string(),
-            lamports: compressed_account.lamports,
-            data: compressed_account.data,
-            owner: compressed_account.owner.to_string(),
-            executable: compressed_account.executable,
-            rent_epoch: compressed_account.rent_epoch,
+            account: Some(convert_state_account(compressed_account)),
+            merkle_context: Some(merkle_context),
         }))
     }
 
@@ -153,8 +296,7 @@ impl Windexer for GrpcServer {
     ) -> Result<Response<GetCompressedBalanceResponse>, Status> {
         let pubkey = Pubkey::from_str(&request.into_inner().pubkey)
             .map_err(|e| Status::invalid_argument(e.to_string()))?;
-        let balance = get_compressed_balance(&self.solana_rpc, &pubkey)
-            .map_err(|e| Status::internal(e.to_string()))?;
+        let balance = self.find_state_account(&pubkey).await?.lamports;
 
         Ok(Response::new(GetCompressedBalanceResponse { balance }))
     }
@@ -165,12 +307,28 @@ impl Windexer for GrpcServer {
     ) -> Result<Response<GetCompressedTokenAccountBalanceResponse>, Status> {
         let pubkey = Pubkey::from_str(&request.into_inner().pubkey)
             .map_err(|e| Status::invalid_argument(e.to_string()))?;
-        let balance = get_compressed_token_account_balance(&self.solana_rpc, &pubkey)
-            .map_err(|e| Status::internal(e.to_string()))?;
+        let state_account = self.find_state_account(&pubkey).await?;
+        let token_account = self
+            .compression()?
+            .store
+            .get_token_account(&state_account.hash)
+            .await
+            .map_err(store_error)?
+            .ok_or_else(|| Status::not_found(format!("Compressed token account not found: {}", pubkey)))?;
+        // Like the by-owner endpoints, an account of a mint we haven't indexed has no decimals.
+        let mint = self
+            .compression()?
+            .store
+            .get_token_mint(&token_account.mint)
+            .await
+            .map_err(store_error)?;
+        if mint.is_none() {
+            warn!("Mint has not been indexed: {}", bs58::encode(&token_account.mint).into_string());
+        }
 
         Ok(Response::new(GetCompressedTokenAccountBalanceResponse {
-            amount: balance.amount,
-            decimals: balance.decimals as u32,
+            amount: token_account.amount.to_string(),
+            decimals: mint.map(|mint| mint.decimals as u32),
         }))
     }
 
@@ -181,44 +339,174 @@ impl Windexer for GrpcServer {
         let request = request.into_inner();
         let owner = Pubkey::from_str(&request.owner)
             .map_err(|e| Status::invalid_argument(e.to_string()))?;
-        let filter = request.filter.map(|f| match f.filter_type.unwrap() {
-            proto::rpc_token_accounts_filter::FilterType::Mint(pubkey) => {
-                RpcTokenAccountsFilter::Mint(Pubkey::new_from_array(
-                    pubkey.data.try_into().unwrap(),
-                ))
-            }
-            proto::rpc_token_accounts_filter::FilterType::ProgramId(pubkey) => {
-                RpcTokenAccountsFilter::ProgramId(Pubkey::new_from_array(
-                    pubkey.data.try_into().unwrap(),
-                ))
-            }
-        });
+        let filter = convert_token_accounts_filter(request.filter)?;
+        let accounts = self.token_accounts_by_owner(&owner, filter).await?;
+
+        let mut proto_accounts = Vec::with_capacity(accounts.len());
+        for (token_account, mint) in accounts {
+            let state_account = self
+                .compression()?
+                .store
+                .get_state_account(&token_account.hash)
+                .await
+                .map_err(store_error)?;
+            proto_accounts.push(proto::CompressedTokenAccount {
+                pubkey: bs58::encode(&token_account.hash).into_string(),
+                account: state_account.map(convert_state_account),
+                amount: token_account.amount.to_string(),
+                decimals: mint.map(|mint| mint.decimals as u32),
+                mint: bs58::encode(&token_account.mint).into_string(),
+                owner: bs58::encode(&token_account.owner).into_string(),
+                delegate: token_account
+                    .delegate
+                    .map(|d| bs58::encode(d).into_string())
+                    .unwrap_or_default(),
+                state: token_account.state as u32,
+                tlv: token_account.tlv.unwrap_or_default(),
+            });
+        }
 
-        let accounts = get_compressed_token_accounts_by_owner(&self.solana_rpc, &owner, filter)
-            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(GetCompressedTokenAccountsByOwnerResponse {
+            accounts: proto_accounts,
+        }))
+    }
+
+    async fn get_compressed_token_balances_by_owner(
+        &self,
+        request: Request<GetCompressedTokenBalancesByOwnerRequest>,
+    ) -> Result<Response<GetCompressedTokenBalancesByOwnerResponse>, Status> {
+        let request = request.into_inner();
+        let owner = Pubkey::from_str(&request.owner)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let filter = convert_token_accounts_filter(request.filter)?;
+        let accounts = self.token_accounts_by_owner(&owner, filter).await?;
 
-        let proto_accounts: Vec<proto::CompressedTokenAccount> = accounts
+        let mut decimals = HashMap::new();
+        for (token_account, mint) in &accounts {
+            if let Some(mint) = mint {
+                decimals.insert(token_account.mint.clone(), mint.decimals);
+            }
+        }
+        let token_accounts: Vec<_> = accounts.into_iter().map(|(a, _)| a).collect();
+        let balances = token::aggregate_balances(&token_accounts)
             .into_iter()
-            .map(|a| proto::CompressedTokenAccount {
-                pubkey: a.pubkey.to_string(),
-                account: Some(proto::CompressedAccount {
-                    pubkey: a.account.pubkey.to_string(),
-                    lamports: a.account.lamports,
-                    data: a.account.data,
-                    owner: a.account.owner.to_string(),
-                    executable: a.account.executable,
-                    rent_epoch: a.account.rent_epoch,
-                }),
-                amount: a.amount,
-                decimals: a.decimals as u32,
+            .map(|(mint, amount)| proto::TokenBalance {
+                decimals: decimals.get(&mint).map(|&decimals| decimals as u32),
+                mint: bs58::encode(mint).into_string(),
+                amount: amount.to_string(),
             })
             .collect();
 
-        Ok(Response::new(GetCompressedTokenAccountsByOwnerResponse {
-            accounts: proto_accounts,
+        Ok(Response::new(GetCompressedTokenBalancesByOwnerResponse { balances }))
+    }
+
+    async fn get_validity_proof(
+        &self,
+        request: Request<GetValidityProofRequest>,
+    ) -> Result<Response<GetValidityProofResponse>, Status> {
+        let request = request.into_inner();
+        let to_hash = |bytes: Vec<u8>| {
+            <[u8; 32]>::try_from(bytes).map_err(|b| Status::invalid_argument(format!("Expected 32 bytes, got {}", b.len())))
+        };
+        let hashes = request.hashes.into_iter().map(to_hash).collect::<Result<Vec<_>, _>>()?;
+        let new_addresses = request.new_addresses.into_iter().map(to_hash).collect::<Result<Vec<_>, _>>()?;
+
+        let proof = self
+            .compression()?
+            .prover
+            .prove(&hashes, &new_addresses)
+            .await
+            .map_err(|e| match e {
+                ValidityProofError::EmptyRequest
+                | ValidityProofError::InvalidAddress(_)
+                | ValidityProofError::MixedTreeDepths
+                | ValidityProofError::UnsupportedShape { .. } => Status::invalid_argument(e.to_string()),
+                ValidityProofError::AccountNotFound(_) => Status::not_found(e.to_string()),
+                ValidityProofError::AccountSpent(_)
+                | ValidityProofError::AddressExists(_)
+                | ValidityProofError::Processor(ProcessorError::TreeNotFound(_))
+                | ValidityProofError::Processor(ProcessorError::LeafMismatch { .. }) => {
+                    Status::failed_precondition(e.to_string())
+                }
+                ValidityProofError::Processor(ProcessorError::TreeDiverged { .. })
+                | ValidityProofError::MissingVerifyingKey { .. }
+                | ValidityProofError::ProverUnavailable(_)
+                | ValidityProofError::UnknownRootIndex(_) => Status::unavailable(e.to_string()),
+                _ => Status::internal(e.to_string()),
+            })?;
+
+        Ok(Response::new(GetValidityProofResponse {
+            root_indices: proof.root_indices(),
+            roots: proof.merkle_proofs.iter().map(|p| p.root.to_vec()).collect(),
+            leaf_indices: proof.merkle_proofs.iter().map(|p| p.leaf_index).collect(),
+            merkle_trees: proof.merkle_proofs.iter().map(|p| p.tree.to_string()).collect(),
+            compressed_proof: proof.compressed_proof,
+        }))
+    }
+
+    async fn get_asset(
+        &self,
+        request: Request<GetAssetRequest>,
+    ) -> Result<Response<GetAssetResponse>, Status> {
+        let id = Pubkey::from_str(&request.into_inner().id)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let asset = self.find_asset(&id).await?;
+
+        Ok(Response::new(GetAssetResponse {
+            asset: Some(convert_asset(asset)),
+        }))
+    }
+
+    async fn get_asset_proof(
+        &self,
+        request: Request<GetAssetProofRequest>,
+    ) -> Result<Response<GetAssetProofResponse>, Status> {
+        let id = Pubkey::from_str(&request.into_inner().id)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let asset = self.find_asset(&id).await?;
+        if asset.burned || asset.redeemed {
+            return Err(Status::failed_precondition(format!("Asset is no longer in its tree: {}", id)));
+        }
+        let tree = Pubkey::try_from(asset.tree.as_slice())
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let proof = match self.compression()?.trees.proof(&tree, asset.leaf_index).await {
+            Ok(proof) => proof,
+            Err(ProcessorError::TreeNotFound(_)) | Err(ProcessorError::LeafIndexOutOfBounds { .. }) => {
+                return Err(Status::failed_precondition(format!("Tree is not replicated: {}", tree)))
+            }
+            Err(e @ ProcessorError::TreeDiverged { .. }) => return Err(Status::unavailable(e.to_string())),
+            Err(e) => return Err(Status::internal(e.to_string())),
+        };
+
+        Ok(Response::new(GetAssetProofResponse {
+            root: bs58::encode(proof.root).into_string(),
+            node_index: (1u32 << proof.path.len()) + proof.leaf_index,
+            proof: proof.path.iter().map(|node| bs58::encode(node).into_string()).collect(),
+            leaf: bs58::encode(proof.leaf).into_string(),
+            tree_id: tree.to_string(),
         }))
     }
 
+    async fn get_assets_by_owner(
+        &self,
+        request: Request<GetAssetsByOwnerRequest>,
+    ) -> Result<Response<GetAssetsByOwnerResponse>, Status> {
+        let owner = Pubkey::from_str(&request.into_inner().owner)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let assets = self
+            .compression()?
+            .store
+            .get_assets_by_owner(owner.as_ref())
+            .await
+            .map_err(store_error)?
+            .into_iter()
+            .filter(|asset| !asset.burned)
+            .map(convert_asset)
+            .collect();
+
+        Ok(Response::new(GetAssetsByOwnerResponse { assets }))
+    }
+
     async fn get_transaction_with_compression_info(
         &self,
         request: Request<GetTransactionRequest>,
@@ -440,5 +728,55 @@ fn convert_compressed_account(account: CompressedAccount) -> proto::CompressedAc
         owner: account.owner.to_string(),
         executable: account.executable,
         rent_epoch: account.rent_epoch,
+        address: Vec::new(),
+    }
+}
+
+fn convert_token_accounts_filter(
+    filter: Option<proto::RpcTokenAccountsFilter>,
+) -> Result<Option<RpcTokenAccountsFilter>, Status> {
+    let Some(filter_type) = filter.and_then(|f| f.filter_type) else {
+        return Ok(None);
+    };
+    let to_pubkey = |pubkey: proto::Pubkey| {
+        Pubkey::try_from(pubkey.data.as_slice()).map_err(|e| Status::invalid_argument(e.to_string()))
+    };
+    Ok(Some(match filter_type {
+        proto::rpc_token_accounts_filter::FilterType::Mint(pubkey) => {
+            RpcTokenAccountsFilter::Mint(to_pubkey(pubkey)?)
+        }
+        proto::rpc_token_accounts_filter::FilterType::ProgramId(pubkey) => {
+            RpcTokenAccountsFilter::ProgramId(to_pubkey(pubkey)?)
+        }
+    }))
+}
+
+fn convert_state_account(account: CompressedStateAccount) -> proto::CompressedAccount {
+    let id = account.address.as_ref().unwrap_or(&account.hash);
+    proto::CompressedAccount {
+        pubkey: bs58::encode(id).into_string(),
+        lamports: account.lamports,
+        data: account.data,
+        owner: bs58::encode(&account.owner).into_string(),
+        executable: false,
+        rent_epoch: 0,
+        address: account.address.unwrap_or_default(),
+    }
+}
+
+fn convert_asset(asset: CompressedAssetRecord) -> proto::CompressedAsset {
+    proto::CompressedAsset {
+        id: bs58::encode(&asset.asset_id).into_string(),
+        tree: bs58::encode(&asset.tree).into_string(),
+        leaf_index: asset.leaf_index,
+        nonce: asset.nonce,
+        owner: bs58::encode(&asset.owner).into_string(),
+        delegate: bs58::encode(&asset.delegate).into_string(),
+        data_hash: bs58::encode(&asset.data_hash).into_string(),
+        creator_hash: bs58::encode(&asset.creator_hash).into_string(),
+        leaf_hash: bs58::encode(&asset.leaf_hash).into_string(),
+        seq: asset.seq,
+        burned: asset.burned,
+        redeemed: asset.redeemed,
     }
 }
@@ -1,17 +1,18 @@
-use crate::storage::database::Database;
+use crate::storage::Database;
 use crate::compression::Groth16Prover;
 use crate::storage::models::CompressedAccount;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::account::Account;
 use log::info;
 
-pub async fn index_account(
-    db: &Database,
+pub fn compress_account(
     compressor: &Groth16Prover,
+    slot: u64,
+    write_version: u64,
     pubkey: &Pubkey,
     account: &Account,
-) -> anyhow::Result<()> {
-    info!("Indexing account: {}", pubkey);
+) -> anyhow::Result<CompressedAccount> {
+    info!("Compressing account: {}", pubkey);
 
     let account_data = bincode::serialize(&account)?;
     let compressed_data = compressor.compress(&account_data)?;
@@ -24,17 +25,24 @@ pub async fn index_account(
         owner: account.owner.to_bytes(),
         executable: account.executable,
         rent_epoch: account.rent_epoch,
+        slot,
+        write_version,
         data: compressed_data,
         proof: bincode::serialize(&proof)?,
     };
 
-    db.insert_compressed_account(&compressed_account).await?;
+    Ok(compressed_account)
+}
 
-    Ok(())
+/// The data of the account `compressed` was made from by [`compress_account`].
+pub fn decode_account_data(compressor: &Groth16Prover, compressed: &CompressedAccount) -> anyhow::Result<Vec<u8>> {
+    let decompressed_data = compressor.decompress(&compressed.data)?;
+    let account: Account = bincode::deserialize(&decompressed_data)?;
+    Ok(account.data)
 }
 
 pub async fn get_compressed_account(
-    db: &Database,
+    db: &dyn Database,
     compressor: &Groth16Prover,
     pubkey: &Pubkey,
 ) -> anyhow::Result<Account> {
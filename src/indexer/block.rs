@@ -1,5 +1,5 @@
-use crate::storage::database::Database;
-use crate::rpc::client::RpcClient;
+use crate::storage::Database;
+use solana_client::nonblocking::rpc_client::RpcClient;
 use crate::compression::Groth16Prover;
 use crate::storage::models::CompressedBlock;
 use solana_sdk::clock::Slot;
@@ -7,13 +7,12 @@ use solana_sdk::hash::Hash;
 use solana_transaction_status::EncodedConfirmedBlock;
 use log::info;
 
-pub async fn index_block(
-    db: &Database,
+pub fn compress_block(
     rpc: &RpcClient,
     compressor: &Groth16Prover,
     block: &EncodedConfirmedBlock,
-) -> anyhow::Result<()> {
-    info!("Indexing block at slot: {}", block.blockhash);
+) -> anyhow::Result<CompressedBlock> {
+    info!("Compressing block at slot: {}", block.blockhash);
 
     let block_data = bincode::serialize(&block)?;
     let compressed_data = compressor.compress(&block_data)?;
@@ -26,17 +25,16 @@ pub async fn index_block(
         previous_blockhash: block.previous_blockhash.to_string(),
         parent_slot: block.parent_slot,
         transactions: block.transactions.len() as u64,
+        block_time: block.block_time,
         data: compressed_data,
         proof: bincode::serialize(&proof)?,
     };
 
-    db.insert_compressed_block(&compressed_block).await?;
-
-    Ok(())
+    Ok(compressed_block)
 }
 
 pub async fn get_compressed_block(
-    db: &Database,
+    db: &dyn Database,
     compressor: &Groth16Prover,
     slot: Slot,
 ) -> anyhow::Result<EncodedConfirmedBlock> {
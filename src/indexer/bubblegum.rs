@@ -0,0 +1,288 @@
+use super::light::{flatten_instructions, HistoryWalk, TreeHistory};
+use crate::compression::bubblegum::{
+    self, BubblegumInstruction, BubblegumOperation, ChangeLogEventV1, LeafSchema,
+    SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+};
+use crate::compression::instruction::{Instruction, InstructionType};
+use crate::compression::light::RawInstruction;
+use crate::compression::{InstructionProcessor, TreeHasher};
+use crate::storage::{CompressedAssetRecord, CompressionStore};
+use log::{info, warn};
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::EncodedConfirmedBlock;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+pub struct BubblegumIndexer {
+    store: Arc<dyn CompressionStore>,
+    trees: Arc<InstructionProcessor>,
+    history: Option<Arc<dyn TreeHistory>>,
+    // Trees whose history didn't rebuild them, so they aren't retried on every operation.
+    unsyncable: std::sync::Mutex<HashSet<Pubkey>>,
+}
+
+/// Whether our copy of a tree still matches the chain after a changelog.
+enum TreeSync {
+    InSync,
+    Untracked,
+    Diverged { seq: u64 },
+}
+
+impl BubblegumIndexer {
+    pub fn new(store: Arc<dyn CompressionStore>, trees: Arc<InstructionProcessor>) -> Self {
+        Self {
+            store,
+            trees,
+            history: None,
+            unsyncable: std::sync::Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn with_history(mut self, history: Arc<dyn TreeHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub async fn index_block(&self, slot: u64, block: &EncodedConfirmedBlock) -> anyhow::Result<()> {
+        for transaction in &block.transactions {
+            let instructions = flatten_instructions(transaction)?;
+            self.index_instructions(slot, &instructions).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn index_instructions(&self, slot: u64, instructions: &[RawInstruction]) -> anyhow::Result<()> {
+        for operation in bubblegum::parse_operations(instructions) {
+            let Some(tree) = operation.tree else {
+                continue;
+            };
+            match self.apply_operation(slot, &operation).await? {
+                TreeSync::InSync => {}
+                TreeSync::Untracked => self.resync(tree, slot).await?,
+                TreeSync::Diverged { seq } => {
+                    self.trees.mark_diverged(&tree, seq).await?;
+                    self.resync(tree, slot).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds `tree` from its history up to and including `slot`. The transaction being
+    /// indexed is part of that history, so nothing is left to apply afterwards. Without a
+    /// history the tree stays diverged and refuses proofs.
+    async fn resync(&self, tree: Pubkey, slot: u64) -> anyhow::Result<()> {
+        let Some(history) = &self.history else {
+            warn!("Tree {} is out of sync and there is no history to resync it from", tree);
+            return Ok(());
+        };
+        if self.unsyncable.lock().unwrap().contains(&tree) {
+            return Ok(());
+        }
+        let mut signatures = HistoryWalk::new(history.as_ref(), tree, slot + 1).await?;
+        info!("Resyncing tree {} from {} transactions", tree, signatures.transactions());
+        if self.trees.contains_tree(&tree).await? {
+            self.trees.reset_tree(&tree).await?;
+        }
+        while let Some(signature) = signatures.next().await? {
+            let (slot, instructions) = history.instructions(&signature).await?;
+            let operations = bubblegum::parse_operations(&instructions)
+                .into_iter()
+                .filter(|operation| operation.tree == Some(tree));
+            for operation in operations {
+                match self.apply_operation(slot, &operation).await? {
+                    TreeSync::InSync => {}
+                    TreeSync::Untracked => {
+                        warn!("History of tree {} does not start with its creation", tree);
+                        self.unsyncable.lock().unwrap().insert(tree);
+                        return Ok(());
+                    }
+                    TreeSync::Diverged { seq } => {
+                        self.trees.mark_diverged(&tree, seq).await?;
+                        warn!("Tree {} still diverges from its history at seq {}", tree, seq);
+                        self.unsyncable.lock().unwrap().insert(tree);
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply_operation(&self, slot: u64, operation: &BubblegumOperation) -> anyhow::Result<TreeSync> {
+        if operation.instruction == BubblegumInstruction::CreateTree {
+            self.create_tree(slot, operation).await?;
+            return Ok(TreeSync::InSync);
+        }
+        let mut sync = TreeSync::InSync;
+        for changelog in &operation.changelogs {
+            sync = self.apply_changelog(slot, changelog).await?;
+            if !matches!(sync, TreeSync::InSync) {
+                break;
+            }
+        }
+        self.apply_asset(slot, operation).await?;
+        Ok(sync)
+    }
+
+    async fn create_tree(&self, slot: u64, operation: &BubblegumOperation) -> anyhow::Result<()> {
+        let (Some(tree), Some(args)) = (operation.tree, &operation.create_tree) else {
+            return Ok(());
+        };
+        if self.trees.contains_tree(&tree).await? {
+            return Ok(());
+        }
+        self.trees
+            .init_tree(slot, tree, args.max_depth, args.max_buffer_size, TreeHasher::Keccak)
+            .await?;
+        Ok(())
+    }
+
+    /// Replays a changelog onto our copy of the tree. Changelogs carry the new leaf, so this
+    /// works the same for every instruction, including ones that don't log a leaf schema.
+    async fn apply_changelog(&self, slot: u64, changelog: &ChangeLogEventV1) -> anyhow::Result<TreeSync> {
+        let tree = changelog.id;
+        if !self.trees.contains_tree(&tree).await? {
+            return Ok(TreeSync::Untracked);
+        }
+        if self.trees.diverged_seq(&tree).await?.is_some() {
+            return Ok(TreeSync::Diverged { seq: changelog.seq });
+        }
+        let Some(leaf) = changelog.leaf() else {
+            return Ok(TreeSync::InSync);
+        };
+
+        let seq = self.trees.seq(&tree).await?;
+        if changelog.seq <= seq {
+            return Ok(TreeSync::InSync);
+        }
+        if changelog.seq > seq + 1 {
+            warn!("Gap in tree {}: expected seq {}, got {}", tree, seq + 1, changelog.seq);
+            return Ok(TreeSync::Diverged { seq: seq + 1 });
+        }
+
+        let leaf_count = u32::try_from(self.trees.leaf_count(&tree).await?)?;
+        let data = if changelog.index == leaf_count {
+            InstructionType::AppendAccount { account: leaf }
+        } else if changelog.index < leaf_count {
+            InstructionType::UpdateAccount {
+                root: self.trees.root(&tree).await?,
+                previous_account: self.trees.leaf(&tree, changelog.index).await?,
+                new_account: leaf,
+                index: changelog.index,
+            }
+        } else {
+            warn!(
+                "Gap in tree {}: expected leaf at most {}, got {}",
+                tree, leaf_count, changelog.index
+            );
+            return Ok(TreeSync::Diverged { seq: changelog.seq });
+        };
+        let change = self
+            .trees
+            .process(
+                slot,
+                &Instruction::new(SPL_ACCOUNT_COMPRESSION_PROGRAM_ID, vec![tree], data),
+            )
+            .await?;
+
+        if let (Some(change), Some(root)) = (change, changelog.root()) {
+            if change.root != root {
+                warn!(
+                    "Tree {} diverged at seq {}: expected root {}, computed {}",
+                    tree,
+                    changelog.seq,
+                    hex::encode(root),
+                    hex::encode(change.root)
+                );
+                return Ok(TreeSync::Diverged { seq: changelog.seq });
+            }
+        }
+        self.trees.set_chain_seq(&tree, changelog.seq).await?;
+        Ok(TreeSync::InSync)
+    }
+
+    async fn apply_asset(&self, slot: u64, operation: &BubblegumOperation) -> anyhow::Result<()> {
+        let Some(tree) = operation.tree else {
+            return Ok(());
+        };
+        let seq = operation.changelogs.last().map(|c| c.seq).unwrap_or_default();
+
+        let asset = match (operation.instruction, &operation.schema, &operation.leaf_args) {
+            (_, Some(schema), _) => {
+                let LeafSchema::V1 {
+                    id,
+                    owner,
+                    delegate,
+                    nonce,
+                    data_hash,
+                    creator_hash,
+                } = schema;
+                CompressedAssetRecord {
+                    asset_id: id.to_bytes().to_vec(),
+                    tree: tree.to_bytes().to_vec(),
+                    leaf_index: *nonce as u32,
+                    nonce: *nonce,
+                    owner: owner.to_bytes().to_vec(),
+                    delegate: delegate.to_bytes().to_vec(),
+                    data_hash: data_hash.to_vec(),
+                    creator_hash: creator_hash.to_vec(),
+                    leaf_hash: schema.hash().to_vec(),
+                    seq,
+                    burned: false,
+                    redeemed: false,
+                    slot_updated: slot,
+                }
+            }
+            (BubblegumInstruction::Burn | BubblegumInstruction::Redeem, None, Some(args)) => {
+                let burned = operation.instruction == BubblegumInstruction::Burn;
+                let account = |i: usize| operation.accounts.get(i).copied().unwrap_or_default();
+                CompressedAssetRecord {
+                    asset_id: bubblegum::asset_id(&tree, args.nonce).to_bytes().to_vec(),
+                    tree: tree.to_bytes().to_vec(),
+                    leaf_index: args.index,
+                    nonce: args.nonce,
+                    owner: account(1).to_bytes().to_vec(),
+                    delegate: account(2).to_bytes().to_vec(),
+                    data_hash: args.data_hash.to_vec(),
+                    creator_hash: args.creator_hash.to_vec(),
+                    leaf_hash: [0u8; 32].to_vec(),
+                    seq,
+                    burned,
+                    redeemed: !burned,
+                    slot_updated: slot,
+                }
+            }
+            (BubblegumInstruction::CancelRedeem, None, None) => {
+                let Some(changelog) = operation.changelogs.last() else {
+                    return Ok(());
+                };
+                let asset_id = bubblegum::asset_id(&tree, changelog.index as u64);
+                let Some(mut asset) = self.store.get_asset(asset_id.as_ref()).await? else {
+                    warn!("Cancelled redeem for unknown asset {}", asset_id);
+                    return Ok(());
+                };
+                asset.leaf_hash = changelog.leaf().unwrap_or_default().to_vec();
+                asset.redeemed = false;
+                asset.seq = seq;
+                asset.slot_updated = slot;
+                asset
+            }
+            _ => return Ok(()),
+        };
+
+        if let Some(existing) = self.store.get_asset(&asset.asset_id).await? {
+            if existing.seq > asset.seq {
+                return Ok(());
+            }
+        }
+        self.store.upsert_asset(&asset).await?;
+        info!(
+            "Indexed {:?} of asset {} at slot {}",
+            operation.instruction,
+            Pubkey::try_from(asset.asset_id.as_slice()).unwrap_or_default(),
+            slot
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,723 @@
+use crate::compression::instruction::{Instruction, InstructionType};
+use crate::compression::light::{
+    self, ChangelogEvent, IndexedMerkleTreeEvent, LightEvent, MerkleTreeEvent, NullifierEvent, PublicTransactionEvent,
+    RawIndexedElement, RawInstruction, ACCOUNT_COMPRESSION_PROGRAM_ID, ADDRESS_TREE_DEPTH,
+    ADDRESS_TREE_ROOT_HISTORY, STATE_TREE_DEPTH, STATE_TREE_ROOT_HISTORY,
+};
+use crate::compression::token;
+use crate::compression::InstructionProcessor;
+use crate::storage::{
+    AddressRecord, CompressedStateAccount, CompressedTokenAccountRecord, CompressionStore,
+    TokenMintRecord,
+};
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use log::{info, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::account::Account;
+use solana_sdk::bs58;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{
+    EncodedConfirmedBlock, EncodedTransactionWithStatusMeta, UiInstruction, UiTransactionEncoding,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Where the past transactions of a tree are read from, to backfill trees first seen
+/// mid-stream or with leaves missing.
+#[async_trait]
+pub trait TreeHistory: Send + Sync {
+    /// A page of the transactions that touched `tree` before `before`, or the latest ones,
+    /// newest first. Empty past the tree's first transaction.
+    async fn signatures(&self, tree: &Pubkey, before: Option<Signature>) -> anyhow::Result<Vec<TreeSignature>>;
+
+    /// The slot and flattened instructions of a transaction.
+    async fn instructions(&self, signature: &Signature) -> anyhow::Result<(u64, Vec<RawInstruction>)>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeSignature {
+    pub signature: Signature,
+    pub slot: u64,
+    pub succeeded: bool,
+}
+
+/// The successful transactions of a tree before a slot, oldest first. Only the cursor of
+/// each page is held: the pages are listed newest first, then fetched again one at a time
+/// as the walk reaches them.
+pub struct HistoryWalk<'a> {
+    history: &'a dyn TreeHistory,
+    tree: Pubkey,
+    before_slot: u64,
+    cursors: Vec<Signature>,
+    // The latest page can grow while we walk, so it is kept rather than fetched again.
+    latest: Option<Vec<Signature>>,
+    page: Vec<Signature>,
+    transactions: usize,
+}
+
+impl<'a> HistoryWalk<'a> {
+    pub async fn new(history: &'a dyn TreeHistory, tree: Pubkey, before_slot: u64) -> anyhow::Result<HistoryWalk<'a>> {
+        let page = history.signatures(&tree, None).await?;
+        let latest = successful(&page, before_slot);
+        let mut transactions = latest.len();
+        let mut cursors = Vec::new();
+        let mut before = page.last().map(|entry| entry.signature);
+        while let Some(cursor) = before {
+            let page = history.signatures(&tree, Some(cursor)).await?;
+            let count = successful(&page, before_slot).len();
+            if count > 0 {
+                cursors.push(cursor);
+                transactions += count;
+            }
+            before = page.last().map(|entry| entry.signature);
+        }
+        Ok(Self {
+            history,
+            tree,
+            before_slot,
+            cursors,
+            latest: Some(latest),
+            page: Vec::new(),
+            transactions,
+        })
+    }
+
+    pub fn transactions(&self) -> usize {
+        self.transactions
+    }
+
+    pub async fn next(&mut self) -> anyhow::Result<Option<Signature>> {
+        loop {
+            if let Some(signature) = self.page.pop() {
+                return Ok(Some(signature));
+            }
+            self.page = match self.cursors.pop() {
+                Some(cursor) => successful(&self.history.signatures(&self.tree, Some(cursor)).await?, self.before_slot),
+                None => match self.latest.take() {
+                    Some(latest) => latest,
+                    None => return Ok(None),
+                },
+            };
+        }
+    }
+}
+
+fn successful(page: &[TreeSignature], before_slot: u64) -> Vec<Signature> {
+    page.iter()
+        .filter(|entry| entry.succeeded && entry.slot < before_slot)
+        .map(|entry| entry.signature)
+        .collect()
+}
+
+pub struct RpcTreeHistory {
+    rpc: RpcClient,
+}
+
+impl RpcTreeHistory {
+    pub fn new(rpc_url: &str) -> Self {
+        Self {
+            rpc: RpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed()),
+        }
+    }
+}
+
+#[async_trait]
+impl TreeHistory for RpcTreeHistory {
+    async fn signatures(&self, tree: &Pubkey, before: Option<Signature>) -> anyhow::Result<Vec<TreeSignature>> {
+        let page = self
+            .rpc
+            .get_signatures_for_address_with_config(
+                tree,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    ..Default::default()
+                },
+            )
+            .await?;
+        page.iter()
+            .map(|status| {
+                Ok(TreeSignature {
+                    signature: Signature::from_str(&status.signature)?,
+                    slot: status.slot,
+                    succeeded: status.err.is_none(),
+                })
+            })
+            .collect()
+    }
+
+    async fn instructions(&self, signature: &Signature) -> anyhow::Result<(u64, Vec<RawInstruction>)> {
+        let transaction = self
+            .rpc
+            .get_transaction_with_config(
+                signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await?;
+        Ok((transaction.slot, flatten_instructions(&transaction.transaction)?))
+    }
+}
+
+pub struct LightIndexer {
+    store: Arc<dyn CompressionStore>,
+    trees: Arc<InstructionProcessor>,
+    history: Option<Arc<dyn TreeHistory>>,
+}
+
+impl LightIndexer {
+    pub fn new(store: Arc<dyn CompressionStore>, trees: Arc<InstructionProcessor>) -> Self {
+        Self {
+            store,
+            trees,
+            history: None,
+        }
+    }
+
+    pub fn with_history(mut self, history: Arc<dyn TreeHistory>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub async fn index_block(&self, slot: u64, block: &EncodedConfirmedBlock) -> anyhow::Result<()> {
+        for transaction in &block.transactions {
+            let instructions = flatten_instructions(transaction)?;
+            self.index_instructions(slot, &instructions).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn index_mint_account(&self, slot: u64, pubkey: &Pubkey, account: &Account) -> anyhow::Result<()> {
+        let Some(mint) = token::decode_mint(&account.owner, &account.data) else {
+            return Ok(());
+        };
+        self.store
+            .upsert_token_mint(&TokenMintRecord {
+                mint: pubkey.to_bytes().to_vec(),
+                program_id: account.owner.to_bytes().to_vec(),
+                supply: mint.supply,
+                decimals: mint.decimals,
+                slot,
+            })
+            .await
+    }
+
+    pub async fn index_instructions(&self, slot: u64, instructions: &[RawInstruction]) -> anyhow::Result<()> {
+        let events = light::parse_events(instructions);
+        for event in &events {
+            if let LightEvent::MerkleTree(MerkleTreeEvent::V3(event)) = event {
+                self.init_address_tree(slot, Pubkey::new_from_array(event.id)).await?;
+            }
+        }
+        for (tree, writes) in leaf_writes(&events)? {
+            let tracked = self.trees.contains_tree(&tree).await?;
+            let mut count = if tracked { self.trees.leaf_count(&tree).await? as u64 } else { 0 };
+            let mut missing = false;
+            for index in writes {
+                if index > count {
+                    missing = true;
+                    break;
+                }
+                count = count.max(index + 1);
+            }
+            if missing {
+                self.backfill(tree, slot).await?;
+            }
+        }
+        // A tree whose changelog disagrees with our copy stops the events there. It is rebuilt
+        // from its history, this transaction included, once, and the events are applied again.
+        let mut resynced = HashSet::new();
+        while let Some((tree, _)) = self.apply_events(slot, &events, None).await? {
+            if resynced.insert(tree) {
+                self.resync(tree, slot).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replays the history of `tree` before `slot`, so leaves the stream never showed us
+    /// are in place before the events that build on them.
+    async fn backfill(&self, tree: Pubkey, slot: u64) -> anyhow::Result<()> {
+        let Some(history) = &self.history else {
+            bail!("Tree {} is missing leaves at slot {} and there is no history to backfill it from", tree, slot);
+        };
+        info!("Backfilling tree {} before slot {}", tree, slot);
+        if let Some(seq) = self.replay(history.as_ref(), tree, slot).await? {
+            warn!("Tree {} diverges from its own history at seq {}", tree, seq);
+        }
+        Ok(())
+    }
+
+    /// Rebuilds `tree` from its history up to and including `slot`. Without a history, or if
+    /// the history disagrees too, the tree stays diverged and refuses proofs.
+    async fn resync(&self, tree: Pubkey, slot: u64) -> anyhow::Result<()> {
+        let Some(history) = &self.history else {
+            warn!("Tree {} is out of sync and there is no history to resync it from", tree);
+            return Ok(());
+        };
+        self.trees.reset_tree(&tree).await?;
+        if let Some(seq) = self.replay(history.as_ref(), tree, slot + 1).await? {
+            warn!("Tree {} still diverges from its history at seq {}", tree, seq);
+        }
+        Ok(())
+    }
+
+    /// Applies the history of `tree` before `before_slot` to it. Returns the seq at which
+    /// the history disagrees with the tree it builds, if it does.
+    async fn replay(&self, history: &dyn TreeHistory, tree: Pubkey, before_slot: u64) -> anyhow::Result<Option<u64>> {
+        let mut walk = HistoryWalk::new(history, tree, before_slot).await?;
+        info!("Replaying {} transactions of tree {}", walk.transactions(), tree);
+        while let Some(signature) = walk.next().await? {
+            let (slot, instructions) = history.instructions(&signature).await?;
+            let diverged = self
+                .apply_events(slot, &light::parse_events(&instructions), Some(&tree))
+                .await?;
+            if let Some((_, seq)) = diverged {
+                return Ok(Some(seq));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Applies `events`, or while backfilling only their effects on tree `only`. Stops at a
+    /// changelog that disagrees with our copy of its tree, which is marked diverged, and
+    /// returns the tree and the seq it diverged at.
+    async fn apply_events(
+        &self,
+        slot: u64,
+        events: &[LightEvent],
+        only: Option<&Pubkey>,
+    ) -> anyhow::Result<Option<(Pubkey, u64)>> {
+        let included = |id: &[u8; 32]| !only.is_some_and(|tree| tree.as_ref() != id);
+        // Changelogs go first, so each append is checked against the path the chain
+        // computed for it. The transaction event then finds the leaves in place.
+        let (changelogs, rest): (Vec<_>, Vec<_>) = events
+            .iter()
+            .partition(|event| matches!(event, LightEvent::MerkleTree(MerkleTreeEvent::V1(_))));
+        for event in changelogs.into_iter().chain(rest) {
+            match event {
+                LightEvent::Transaction(event) => self.apply_transaction_event(slot, event, only).await?,
+                LightEvent::MerkleTree(MerkleTreeEvent::V1(event)) if included(&event.id) => {
+                    if let Some(seq) = self.apply_changelog_event(slot, event).await? {
+                        let tree = Pubkey::new_from_array(event.id);
+                        self.trees.mark_diverged(&tree, seq).await?;
+                        return Ok(Some((tree, seq)));
+                    }
+                }
+                LightEvent::MerkleTree(MerkleTreeEvent::V2(event)) if included(&event.id) => {
+                    self.apply_nullifier_event(slot, event).await?
+                }
+                LightEvent::MerkleTree(MerkleTreeEvent::V3(event)) if included(&event.id) => {
+                    self.apply_address_event(slot, event).await?
+                }
+                LightEvent::MerkleTree(_) => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Appends the leaves a changelog reports, and checks each one's path, from the leaf
+    /// up to the root, against our copy of the tree as of that append. Returns the seq of
+    /// the first append that disagrees.
+    async fn apply_changelog_event(&self, slot: u64, event: &ChangelogEvent) -> anyhow::Result<Option<u64>> {
+        let tree = Pubkey::new_from_array(event.id);
+        // A diverged tree is only checked again once it has been rebuilt.
+        if self.trees.contains_tree(&tree).await? && self.trees.diverged_seq(&tree).await?.is_some() {
+            return Ok(None);
+        }
+        for ((seq, index), path) in (event.seq..).zip(event.index..).zip(&event.paths) {
+            // Levels the chain left out are None.
+            let nodes: Vec<Option<[u8; 32]>> = path.iter().flat_map(|path| path.0.iter().copied()).collect();
+            let Some(Some(leaf)) = nodes.first().copied() else {
+                bail!("Changelog of tree {} at seq {} has no leaf", tree, seq);
+            };
+            let appended = self
+                .append_leaf(slot, tree, index, leaf, STATE_TREE_DEPTH, STATE_TREE_ROOT_HISTORY)
+                .await?;
+            // Later appends have changed the path of a leaf we already had, so only the
+            // leaf itself can be compared.
+            let ours = if appended {
+                self.trees.path(&tree, index).await?
+            } else {
+                vec![self.trees.leaf(&tree, index).await?]
+            };
+            let level = nodes
+                .iter()
+                .zip(&ours)
+                .position(|(node, ours)| node.is_some_and(|node| node != *ours));
+            if let Some(level) = level {
+                warn!(
+                    "Tree {} diverged at seq {}: leaf {} differs from the changelog at level {}",
+                    tree, seq, index, level
+                );
+                return Ok(Some(seq));
+            }
+            if appended {
+                self.trees.set_chain_seq(&tree, seq).await?;
+            }
+        }
+        Ok(None)
+    }
+
+    async fn apply_transaction_event(
+        &self,
+        slot: u64,
+        event: &PublicTransactionEvent,
+        only: Option<&Pubkey>,
+    ) -> anyhow::Result<()> {
+        let outputs = event.output_compressed_accounts.len();
+        if event.output_compressed_account_hashes.len() != outputs || event.output_leaf_indices.len() != outputs {
+            bail!(
+                "Malformed transaction event at slot {}: {} outputs, {} hashes, {} leaf indices",
+                slot,
+                outputs,
+                event.output_compressed_account_hashes.len(),
+                event.output_leaf_indices.len()
+            );
+        }
+
+        for hash in &event.input_compressed_account_hashes {
+            if let Some(tree) = only {
+                match self.store.get_state_account(hash).await? {
+                    Some(account) if account.tree == tree.as_ref() => {}
+                    _ => continue,
+                }
+            }
+            self.store.spend_state_account(hash, slot).await?;
+            self.store.delete_token_account(hash).await?;
+        }
+
+        let outputs = event
+            .output_compressed_accounts
+            .iter()
+            .zip(&event.output_compressed_account_hashes)
+            .zip(&event.output_leaf_indices);
+        // The event carries each tree's sequence number for its first append.
+        let mut appends = HashMap::<Pubkey, u64>::new();
+        for (i, ((output, &hash), &leaf_index)) in outputs.enumerate() {
+            let tree = event
+                .output_tree(i)
+                .ok_or_else(|| anyhow!("Output {} references an unknown merkle tree", i))?;
+            let chain_seq = event.sequence_number(&tree).map(|seq| {
+                let append = appends.entry(tree).or_default();
+                *append += 1;
+                seq + *append - 1
+            });
+            if only.is_some_and(|only| *only != tree) {
+                continue;
+            }
+            let account = &output.compressed_account;
+
+            self.store
+                .upsert_state_account(&CompressedStateAccount {
+                    hash: hash.to_vec(),
+                    address: account.address.map(|a| a.to_vec()),
+                    owner: account.owner.to_bytes().to_vec(),
+                    lamports: account.lamports,
+                    discriminator: account.data.as_ref().map(|d| d.discriminator.to_vec()),
+                    data: account.data.as_ref().map(|d| d.data.clone()).unwrap_or_default(),
+                    data_hash: account.data.as_ref().map(|d| d.data_hash.to_vec()),
+                    tree: tree.to_bytes().to_vec(),
+                    leaf_index,
+                    seq: chain_seq,
+                    slot_created: slot,
+                    spent: false,
+                    spent_slot: None,
+                })
+                .await?;
+            let appended = self
+                .append_leaf(slot, tree, leaf_index, hash, STATE_TREE_DEPTH, STATE_TREE_ROOT_HISTORY)
+                .await?;
+            if let Some(seq) = chain_seq.filter(|_| appended) {
+                self.trees.set_chain_seq(&tree, seq).await?;
+            }
+
+            if let Some(data) = &account.data {
+                if let Some(token_data) =
+                    token::decode_token_data(&account.owner, Some(&data.discriminator), &data.data)
+                {
+                    self.store
+                        .upsert_token_account(&CompressedTokenAccountRecord {
+                            hash: hash.to_vec(),
+                            mint: token_data.mint.to_bytes().to_vec(),
+                            owner: token_data.owner.to_bytes().to_vec(),
+                            amount: token_data.amount,
+                            delegate: token_data.delegate.map(|d| d.to_bytes().to_vec()),
+                            state: token_data.state as u8,
+                            tlv: token_data.tlv,
+                            slot,
+                        })
+                        .await?;
+                }
+            }
+        }
+
+        info!(
+            "Indexed light transaction event at slot {}: {} inputs, {} outputs",
+            slot,
+            event.input_compressed_account_hashes.len(),
+            event.output_compressed_accounts.len()
+        );
+        Ok(())
+    }
+
+    async fn apply_nullifier_event(&self, slot: u64, event: &NullifierEvent) -> anyhow::Result<()> {
+        let tree = Pubkey::new_from_array(event.id);
+        for (seq, &index) in (event.seq..).zip(&event.nullified_leaves_indices) {
+            let index = u32::try_from(index)?;
+            let leaf = self.trees.leaf(&tree, index).await?;
+            if self.update_leaf(slot, tree, index, leaf, [0u8; 32]).await? {
+                self.trees.set_chain_seq(&tree, seq).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the address tree's own record of an insertion: the low element is
+    /// re-pointed at the new address and the new (high) element is appended.
+    async fn apply_address_event(&self, slot: u64, event: &IndexedMerkleTreeEvent) -> anyhow::Result<()> {
+        let tree = Pubkey::new_from_array(event.id);
+        self.init_address_tree(slot, tree).await?;
+        // Each insertion is two changes on chain: the low element update and the append.
+        for (seq, update) in (event.seq..).step_by(2).zip(&event.updates) {
+            let low_index = u32::try_from(update.new_low_element.index)?;
+            let high_index = u32::try_from(update.new_high_element.index)?;
+            if high_index < u32::try_from(self.trees.leaf_count(&tree).await?)? {
+                continue;
+            }
+
+            // The low element is already in the tree, at the latest as part of its genesis.
+            let previous = self.trees.leaf(&tree, low_index).await?;
+            self.update_leaf(slot, tree, low_index, previous, update.new_low_element_hash)
+                .await?;
+            let appended = self
+                .append_leaf(
+                    slot,
+                    tree,
+                    high_index,
+                    update.new_high_element_hash,
+                    ADDRESS_TREE_DEPTH,
+                    ADDRESS_TREE_ROOT_HISTORY,
+                )
+                .await?;
+            if appended {
+                self.trees.set_chain_seq(&tree, seq + 1).await?;
+            }
+
+            for element in [&update.new_low_element, &update.new_high_element] {
+                self.store
+                    .insert_address(&address_record(tree, element, slot)?)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates an address tree with the elements Light initializes it with, which no event
+    /// reports. Does nothing if the tree exists.
+    async fn init_address_tree(&self, slot: u64, tree: Pubkey) -> anyhow::Result<()> {
+        if self.trees.contains_tree(&tree).await? {
+            return Ok(());
+        }
+        for element in &light::address_tree_genesis() {
+            let leaf = light::address_leaf(&element.value, element.next_index, &element.next_value)?;
+            self.append_leaf(
+                slot,
+                tree,
+                u32::try_from(element.index)?,
+                leaf,
+                ADDRESS_TREE_DEPTH,
+                ADDRESS_TREE_ROOT_HISTORY,
+            )
+            .await?;
+            self.store
+                .insert_address(&address_record(tree, element, slot)?)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn update_leaf(
+        &self,
+        slot: u64,
+        tree: Pubkey,
+        index: u32,
+        previous: [u8; 32],
+        leaf: [u8; 32],
+    ) -> anyhow::Result<bool> {
+        if previous == leaf {
+            return Ok(false);
+        }
+        let root = self.trees.root(&tree).await?;
+        self.trees
+            .process(
+                slot,
+                &Instruction::new(
+                    ACCOUNT_COMPRESSION_PROGRAM_ID,
+                    vec![tree],
+                    InstructionType::UpdateAccount {
+                        root,
+                        previous_account: previous,
+                        new_account: leaf,
+                        index,
+                    },
+                ),
+            )
+            .await?;
+        Ok(true)
+    }
+
+    /// Appends `leaf` at `leaf_index`, creating the tree on its first leaf. Leaves already
+    /// in the tree are skipped, so replayed history is a no-op. Returns whether it appended.
+    async fn append_leaf(
+        &self,
+        slot: u64,
+        tree: Pubkey,
+        leaf_index: u32,
+        leaf: [u8; 32],
+        max_depth: u32,
+        max_buffer_size: u32,
+    ) -> anyhow::Result<bool> {
+        if !self.trees.contains_tree(&tree).await? {
+            if leaf_index != 0 {
+                bail!("Tree {} first seen at leaf {}", tree, leaf_index);
+            }
+            self.trees
+                .process(
+                    slot,
+                    &Instruction::new(
+                        ACCOUNT_COMPRESSION_PROGRAM_ID,
+                        vec![tree],
+                        InstructionType::InitTree {
+                            max_depth,
+                            max_buffer_size,
+                        },
+                    ),
+                )
+                .await?;
+        }
+
+        let next_index = u32::try_from(self.trees.leaf_count(&tree).await?)?;
+        if leaf_index < next_index {
+            return Ok(false);
+        }
+        if leaf_index > next_index {
+            bail!("Gap in tree {}: expected leaf {}, got {}", tree, next_index, leaf_index);
+        }
+        self.trees
+            .process(
+                slot,
+                &Instruction::new(
+                    ACCOUNT_COMPRESSION_PROGRAM_ID,
+                    vec![tree],
+                    InstructionType::AppendAccount { account: leaf },
+                ),
+            )
+            .await?;
+        Ok(true)
+    }
+}
+
+fn address_record(tree: Pubkey, element: &RawIndexedElement, slot: u64) -> anyhow::Result<AddressRecord> {
+    Ok(AddressRecord {
+        address: element.value.to_vec(),
+        tree: tree.to_bytes().to_vec(),
+        leaf_index: u32::try_from(element.index)?,
+        next_address: element.next_value.to_vec(),
+        slot,
+    })
+}
+
+/// The leaf indices each tree's events write, in order, to find trees whose earlier
+/// leaves we never saw.
+fn leaf_writes(events: &[LightEvent]) -> anyhow::Result<BTreeMap<Pubkey, Vec<u64>>> {
+    let mut writes: BTreeMap<Pubkey, Vec<u64>> = BTreeMap::new();
+    for event in events {
+        match event {
+            LightEvent::Transaction(event) => {
+                for (i, &index) in event.output_leaf_indices.iter().enumerate() {
+                    let tree = event
+                        .output_tree(i)
+                        .ok_or_else(|| anyhow!("Output {} references an unknown merkle tree", i))?;
+                    writes.entry(tree).or_default().push(u64::from(index));
+                }
+            }
+            LightEvent::MerkleTree(MerkleTreeEvent::V2(event)) => {
+                let tree = Pubkey::new_from_array(event.id);
+                writes.entry(tree).or_default().extend(&event.nullified_leaves_indices);
+            }
+            LightEvent::MerkleTree(MerkleTreeEvent::V3(event)) => {
+                let tree = writes.entry(Pubkey::new_from_array(event.id)).or_default();
+                for update in &event.updates {
+                    tree.extend([update.new_low_element.index, update.new_high_element.index]);
+                }
+            }
+            LightEvent::MerkleTree(MerkleTreeEvent::V1(event)) => {
+                let tree = writes.entry(Pubkey::new_from_array(event.id)).or_default();
+                tree.extend((0..event.paths.len() as u64).map(|i| u64::from(event.index) + i));
+            }
+        }
+    }
+    for indices in writes.values_mut() {
+        indices.sort_unstable();
+    }
+    Ok(writes)
+}
+
+pub fn flatten_instructions(transaction: &EncodedTransactionWithStatusMeta) -> anyhow::Result<Vec<RawInstruction>> {
+    let meta = match &transaction.meta {
+        Some(meta) if meta.err.is_none() => meta,
+        _ => return Ok(Vec::new()),
+    };
+    let Some(versioned) = transaction.transaction.decode() else {
+        return Ok(Vec::new());
+    };
+
+    let mut keys = versioned.message.static_account_keys().to_vec();
+    if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+        for key in loaded.writable.iter().chain(loaded.readonly.iter()) {
+            keys.push(Pubkey::from_str(key)?);
+        }
+    }
+    let resolve = |index: u8| {
+        keys.get(index as usize)
+            .copied()
+            .ok_or_else(|| anyhow!("Account index {} out of range", index))
+    };
+
+    let mut instructions = Vec::new();
+    for (i, ix) in versioned.message.instructions().iter().enumerate() {
+        instructions.push(RawInstruction {
+            program_id: resolve(ix.program_id_index)?,
+            accounts: ix.accounts.iter().map(|a| resolve(*a)).collect::<anyhow::Result<_>>()?,
+            data: ix.data.clone(),
+        });
+        if let OptionSerializer::Some(inner) = &meta.inner_instructions {
+            for group in inner.iter().filter(|g| g.index as usize == i) {
+                for inner_ix in &group.instructions {
+                    if let UiInstruction::Compiled(compiled) = inner_ix {
+                        instructions.push(RawInstruction {
+                            program_id: resolve(compiled.program_id_index)?,
+                            accounts: compiled
+                                .accounts
+                                .iter()
+                                .map(|a| resolve(*a))
+                                .collect::<anyhow::Result<_>>()?,
+                            data: bs58::decode(&compiled.data).into_vec()?,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(instructions)
+}
@@ -1,22 +1,67 @@
 mod account;
 mod block;
+mod bubblegum;
+mod light;
 mod transaction;
 
-use crate::storage::database::Database;
-use crate::rpc::client::RpcClient;
+pub use bubblegum::BubblegumIndexer;
+pub use light::{flatten_instructions, HistoryWalk, LightIndexer, RpcTreeHistory, TreeHistory, TreeSignature};
+
+use crate::storage::{AccountDataDecoder, BlockBatch, CompressionStore, Database};
+use std::sync::Arc;
+use solana_client::nonblocking::rpc_client::RpcClient;
 use crate::compression::{Compressor, Groth16Prover};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::EncodedConfirmedBlock;
 use tokio::time::{interval, Duration};
-use log::{info, error};
+use log::{info, error, warn};
+
+// getMultipleAccounts takes at most this many keys per request
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Compressed state indexing. Its writes go to `store` as they are applied rather than
+/// through the block batch, so the store keeps its own cursor.
+struct CompressionIndexing {
+    store: Arc<dyn CompressionStore>,
+    light: LightIndexer,
+    bubblegum: BubblegumIndexer,
+}
 
 pub struct Indexer {
-    db: Database,
+    db: Arc<dyn Database>,
     rpc: RpcClient,
-    compressor: Groth16Prover,
+    compressor: Arc<Groth16Prover>,
+    compression: Option<CompressionIndexing>,
 }
 
 impl Indexer {
-    pub fn new(db: Database, rpc: RpcClient, compressor: Groth16Prover) -> Self {
-        Self { db, rpc, compressor }
+    pub fn new(db: Arc<dyn Database>, rpc: RpcClient, compressor: Arc<Groth16Prover>) -> Self {
+        Self {
+            db,
+            rpc,
+            compressor,
+            compression: None,
+        }
+    }
+
+    pub fn with_compression_indexers(
+        mut self,
+        store: Arc<dyn CompressionStore>,
+        light: LightIndexer,
+        bubblegum: BubblegumIndexer,
+    ) -> Self {
+        self.compression = Some(CompressionIndexing { store, light, bubblegum });
+        self
+    }
+
+    /// Decodes the account versions this indexer writes, for diffing their data.
+    pub fn account_data_decoder(&self) -> Arc<AccountDataDecoder> {
+        let compressor = Arc::clone(&self.compressor);
+        Arc::new(move |account| account::decode_account_data(&compressor, account))
     }
 
     pub async fn run(&self) -> anyhow::Result<()> {
@@ -29,10 +74,16 @@ impl Indexer {
             match self.process_new_blocks(last_processed_slot).await {
                 Ok(new_last_processed_slot) => {
                     last_processed_slot = new_last_processed_slot;
-                    self.db.update_last_processed_slot(last_processed_slot).await?;
                 }
                 Err(e) => {
                     error!("Error processing new blocks: {:?}", e);
+                    // Resume after the last slot whose batch was committed. Re-indexing slots
+                    // is idempotent, so an unreachable store only costs repeated work.
+                    match self.db.get_last_processed_slot().await {
+                        Ok(slot) => last_processed_slot = slot,
+                        Err(e) if e.is_retryable() => warn!("Could not re-read the cursor: {}", e),
+                        Err(e) => return Err(e.into()),
+                    }
                 }
             }
         }
@@ -44,19 +95,93 @@ impl Indexer {
         for slot in (last_processed_slot + 1)..=current_slot {
             info!("Processing block at slot {}", slot);
             let block = self.rpc.get_block(slot).await?;
-            
-            block::index_block(&self.db, &self.rpc, &self.compressor, &block).await?;
-
-            for transaction in block.transactions {
-                transaction::index_transaction(&self.db, &self.compressor, &transaction).await?;
-                
-                for account_key in transaction.message.account_keys {
-                    let account = self.rpc.get_account(&account_key).await?;
-                    account::index_account(&self.db, &self.compressor, &account_key, &account).await?;
+            let mut batch = BlockBatch::new(slot);
+            batch.block = Some(block::compress_block(&self.rpc, &self.compressor, &block)?);
+
+            let mut account_keys = Vec::new();
+            for (index, transaction) in block.transactions.iter().enumerate() {
+                let transaction = transaction::decode_transaction(transaction)?;
+                batch.transactions.push(transaction::compress_transaction(
+                    &self.compressor,
+                    slot,
+                    index as u32,
+                    &transaction,
+                )?);
+                batch.token_transfers.extend(transaction::token_transfers(slot, &transaction)?);
+                for account_key in &transaction.account_keys {
+                    if !account_keys.contains(account_key) {
+                        account_keys.push(*account_key);
+                    }
                 }
             }
+
+            let accounts = self.fetch_accounts(slot, &account_keys).await?;
+            for (account_key, observed_slot, account) in &accounts {
+                // Each account is read once per block, so an observed slot has one version of it.
+                batch.accounts.push(account::compress_account(
+                    &self.compressor,
+                    *observed_slot,
+                    0,
+                    account_key,
+                    account,
+                )?);
+            }
+
+            self.index_compressed(slot, &block, &accounts).await?;
+            self.db.commit_block(&batch).await?;
         }
 
         Ok(current_slot)
     }
-}
\ No newline at end of file
+
+    /// Reads the current state of `keys`. RPC has no historical account state, so each
+    /// account is labelled with the slot the read observed, never earlier than `slot`.
+    async fn fetch_accounts(&self, slot: u64, keys: &[Pubkey]) -> anyhow::Result<Vec<(Pubkey, u64, Account)>> {
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            min_context_slot: Some(slot),
+            data_slice: None,
+        };
+        let mut accounts = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let response = self.rpc.get_multiple_accounts_with_config(chunk, config.clone()).await?;
+            let observed_slot = response.context.slot;
+            // Closed accounts have no state to record.
+            accounts.extend(
+                chunk
+                    .iter()
+                    .zip(response.value)
+                    .filter_map(|(key, account)| Some((*key, observed_slot, account?))),
+            );
+        }
+        Ok(accounts)
+    }
+
+    /// Applies the compressed state changes of `block`, unless a run that stopped before
+    /// committing the block already did.
+    async fn index_compressed(
+        &self,
+        slot: u64,
+        block: &EncodedConfirmedBlock,
+        accounts: &[(Pubkey, u64, Account)],
+    ) -> anyhow::Result<()> {
+        let Some(compression) = &self.compression else {
+            return Ok(());
+        };
+        if slot <= compression.store.get_compressed_slot().await? {
+            return Ok(());
+        }
+        // A slot interrupted partway is applied again in full; tree updates skip the
+        // leaves and sequence numbers they already hold, and the rest are upserts.
+        compression.light.index_block(slot, block).await?;
+        compression.bubblegum.index_block(slot, block).await?;
+        for (account_key, observed_slot, account) in accounts {
+            compression
+                .light
+                .index_mint_account(*observed_slot, account_key, account)
+                .await?;
+        }
+        compression.store.update_compressed_slot(slot).await
+    }
+}
@@ -1,38 +1,144 @@
-use crate::storage::database::Database;
+use crate::storage::Database;
 use crate::compression::Groth16Prover;
-use crate::storage::models::CompressedTransaction;
-use solana_sdk::transaction::Transaction;
+use crate::storage::models::{CompressedTransaction, TokenTransfer};
+use anyhow::anyhow;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::VersionedTransaction;
 use solana_sdk::signature::Signature;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{EncodedTransactionWithStatusMeta, UiTransactionStatusMeta};
+use std::collections::BTreeMap;
+use std::str::FromStr;
 use log::info;
 
-pub async fn index_transaction(
-    db: &Database,
+/// A block transaction decoded from its RPC encoding, with its account keys resolved.
+pub struct DecodedTransaction<'a> {
+    pub transaction: VersionedTransaction,
+    pub meta: Option<&'a UiTransactionStatusMeta>,
+    /// Static keys followed by the keys loaded from lookup tables, in message order.
+    pub account_keys: Vec<Pubkey>,
+}
+
+pub fn decode_transaction(transaction: &EncodedTransactionWithStatusMeta) -> anyhow::Result<DecodedTransaction<'_>> {
+    let versioned = transaction
+        .transaction
+        .decode()
+        .ok_or_else(|| anyhow!("Block transaction is not binary encoded"))?;
+    let meta = transaction.meta.as_ref();
+
+    let mut account_keys = versioned.message.static_account_keys().to_vec();
+    if let Some(OptionSerializer::Some(loaded)) = meta.map(|meta| &meta.loaded_addresses) {
+        for key in loaded.writable.iter().chain(loaded.readonly.iter()) {
+            account_keys.push(Pubkey::from_str(key)?);
+        }
+    }
+
+    Ok(DecodedTransaction {
+        transaction: versioned,
+        meta,
+        account_keys,
+    })
+}
+
+pub fn compress_transaction(
     compressor: &Groth16Prover,
-    transaction: &Transaction,
-) -> anyhow::Result<()> {
-    info!("Indexing transaction: {}", transaction.signatures[0]);
+    slot: u64,
+    index: u32,
+    transaction: &DecodedTransaction,
+) -> anyhow::Result<CompressedTransaction> {
+    let signature = transaction.transaction.signatures[0];
+    info!("Compressing transaction: {}", signature);
 
-    let transaction_data = bincode::serialize(&transaction)?;
+    let transaction_data = bincode::serialize(&transaction.transaction)?;
     let compressed_data = compressor.compress(&transaction_data)?;
-    
+
     let proof = compressor.prove(&compressed_data)?;
 
     let compressed_transaction = CompressedTransaction {
-        signature: transaction.signatures[0].to_bytes(),
+        signature: signature.as_ref().to_vec(),
+        slot,
+        index,
+        accounts: transaction
+            .account_keys
+            .iter()
+            .map(|key| key.to_bytes().to_vec())
+            .collect(),
+        fee: transaction.meta.map_or(0, |meta| meta.fee),
+        programs: program_ids(transaction)?,
         data: compressed_data,
         proof: bincode::serialize(&proof)?,
     };
 
-    db.insert_compressed_transaction(&compressed_transaction).await?;
+    Ok(compressed_transaction)
+}
+
+fn program_ids(transaction: &DecodedTransaction) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut programs: Vec<Vec<u8>> = Vec::new();
+    for instruction in transaction.transaction.message.instructions() {
+        let program = transaction
+            .account_keys
+            .get(instruction.program_id_index as usize)
+            .ok_or_else(|| anyhow!("Program index {} out of range", instruction.program_id_index))?
+            .to_bytes()
+            .to_vec();
+        if !programs.contains(&program) {
+            programs.push(program);
+        }
+    }
+    Ok(programs)
+}
+
+/// Tokens received per mint and owner, from the token balances that grew in a successful
+/// transaction. Balances without a reported owner are skipped.
+pub fn token_transfers(slot: u64, transaction: &DecodedTransaction) -> anyhow::Result<Vec<TokenTransfer>> {
+    let meta = match transaction.meta {
+        Some(meta) if meta.err.is_none() => meta,
+        _ => return Ok(Vec::new()),
+    };
+    let (OptionSerializer::Some(pre), OptionSerializer::Some(post)) = (&meta.pre_token_balances, &meta.post_token_balances) else {
+        return Ok(Vec::new());
+    };
+
+    let mut received: BTreeMap<(Vec<u8>, Vec<u8>), u64> = BTreeMap::new();
+    for balance in post {
+        let OptionSerializer::Some(owner) = &balance.owner else {
+            continue;
+        };
+        let before = match pre
+            .iter()
+            .find(|pre| pre.account_index == balance.account_index && pre.mint == balance.mint)
+        {
+            Some(pre) => pre.ui_token_amount.amount.parse::<u64>()?,
+            None => 0,
+        };
+        let after = balance.ui_token_amount.amount.parse::<u64>()?;
+        if after > before {
+            let key = (
+                Pubkey::from_str(&balance.mint)?.to_bytes().to_vec(),
+                Pubkey::from_str(owner)?.to_bytes().to_vec(),
+            );
+            *received.entry(key).or_default() += after - before;
+        }
+    }
 
-    Ok(())
+    let signature = transaction.transaction.signatures[0].as_ref().to_vec();
+    Ok(received
+        .into_iter()
+        .map(|((mint, owner), amount)| TokenTransfer {
+            signature: signature.clone(),
+            slot,
+            mint,
+            owner,
+            amount,
+        })
+        .collect())
 }
 
 pub async fn get_compressed_transaction(
-    db: &Database,
+    db: &dyn Database,
     compressor: &Groth16Prover,
     signature: &Signature,
-) -> anyhow::Result<Transaction> {
+) -> anyhow::Result<VersionedTransaction> {
     let compressed_transaction = db.get_compressed_transaction(signature).await?;
     let proof: ark_groth16::Proof<ark_bls12_381::Bls12_381> = bincode::deserialize(&compressed_transaction.proof)?;
 
@@ -41,7 +147,7 @@ pub async fn get_compressed_transaction(
     }
 
     let decompressed_data = compressor.decompress(&compressed_transaction.data)?;
-    let transaction: Transaction = bincode::deserialize(&decompressed_data)?;
+    let transaction: VersionedTransaction = bincode::deserialize(&decompressed_data)?;
 
     Ok(transaction)
-}
\ No newline at end of file
+}
@@ -9,28 +9,263 @@ pub mod utils;
 pub mod wasm;
 
 use anyhow::Result;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio;
 
+/// Checks every configured database against this build's migrations and, unless `check`,
+/// applies the pending ones. With `check`, pending migrations are an error too.
+pub async fn migrate(check: bool) -> Result<()> {
+    utils::logging::init_logger()?;
+    let config = utils::config::load_config()?;
+    let cold = config.tiering.as_ref().map(|tiering| &tiering.cold_database_url);
+    let mut pending = false;
+    for url in std::iter::once(&config.database_url).chain(cold) {
+        let backend = storage::StorageUrl::parse(url)?.backend;
+        match storage::migrate(url, !check).await? {
+            None => println!("{}: no schema to migrate", backend),
+            Some(report) => {
+                let current = report.current.map_or("none".to_string(), |version| version.to_string());
+                let verb = if check { "pending" } else { "applied" };
+                println!("{}: at version {}, {} {:?}", backend, current, verb, report.pending);
+                pending |= !report.pending.is_empty();
+            }
+        }
+    }
+    if check && pending {
+        anyhow::bail!("Migrations are pending");
+    }
+    Ok(())
+}
+
+fn encrypted_storage(
+    storage: Arc<dyn storage::Database>,
+    compression: Option<Arc<dyn storage::CompressionStore>>,
+    encryption: &utils::config::EncryptionConfig,
+) -> Result<storage::EncryptedStorage> {
+    let mut keys = storage::MasterKeys::load(encryption.key_file.as_deref(), &encryption.key_env)?;
+    if let Some(id) = encryption.active_key_id {
+        keys = keys.with_active(id)?;
+    }
+    let mut encrypted = storage::EncryptedStorage::new(storage, keys);
+    if let Some(store) = compression {
+        encrypted = encrypted.with_compression_store(store);
+    }
+    if encryption.require_encryption {
+        encrypted = encrypted.with_required_encryption();
+    }
+    Ok(encrypted)
+}
+
+// The configured storage, tiered and encrypted if configured. With `serving`, lookups are also
+// cached and the tiering mover and re-encryption run in the background, which only `run` wants.
+async fn connect_storage(
+    config: &utils::config::Config,
+    serving: bool,
+) -> Result<(Arc<dyn storage::Database>, Option<Arc<dyn storage::CompressionStore>>)> {
+    let (mut storage, mut compression) = storage::StorageUrl::parse(&config.database_url)?
+        .connect_with_compression()
+        .await?;
+    if let Some(tiering) = &config.tiering {
+        let cold = storage::connect(&tiering.cold_database_url).await?;
+        let max_hot_age = Duration::from_secs(tiering.max_hot_age_secs);
+        let tiered = Arc::new(storage::TieredStorage::new(storage, cold, max_hot_age).await?);
+        if serving {
+            tokio::spawn(Arc::clone(&tiered).run_mover(Duration::from_secs(tiering.move_interval_secs)));
+        }
+        storage = tiered;
+    }
+    if let Some(cache) = config.cache.as_ref().filter(|_| serving) {
+        storage = Arc::new(
+            storage::CachedStorage::new(storage, &cache.redis_url)
+                .await?
+                .with_ttl(Duration::from_secs(cache.ttl_secs))?,
+        );
+    }
+    // Outside the cache, so Redis only holds ciphertext.
+    if let Some(encryption) = &config.encryption {
+        let encrypted = Arc::new(encrypted_storage(storage, compression, encryption)?);
+        compression = encrypted.compression_store();
+        if serving {
+            let interval = Duration::from_secs(encryption.reencrypt_interval_secs);
+            tokio::spawn(Arc::clone(&encrypted).run_reencryption(interval));
+        }
+        storage = encrypted;
+    }
+    Ok((storage, compression))
+}
+
+/// A snapshot of `storage` and `compression` that records, or checks against, the fingerprints
+/// of the configured verifying keys.
+fn configured_snapshot<'a>(
+    config: &utils::config::Config,
+    storage: &'a dyn storage::Database,
+    compression: Option<&'a dyn storage::CompressionStore>,
+) -> Result<storage::Snapshot<'a>> {
+    let mut snapshot = storage::Snapshot::new(storage);
+    if let Some(store) = compression {
+        snapshot = snapshot.with_compression_store(store);
+    }
+    if let Some(prover) = &config.prover {
+        let keys = compression::VerifyingKeys::load(Path::new(&prover.verifying_keys_dir))?;
+        snapshot = snapshot.with_proving_keys(keys.fingerprints());
+    }
+    Ok(snapshot)
+}
+
+/// Writes a snapshot of the configured storage as of `slot`, the last processed slot by default.
+pub async fn create_snapshot(path: &Path, slot: Option<u64>) -> Result<()> {
+    utils::logging::init_logger()?;
+    let config = utils::config::load_config()?;
+    let (storage, compression) = connect_storage(&config, false).await?;
+    let slot = match slot {
+        Some(slot) => slot,
+        None => storage.get_last_processed_slot().await?,
+    };
+    let snapshot = configured_snapshot(&config, storage.as_ref(), compression.as_deref())?;
+    let root = snapshot.create(slot, path).await?;
+    println!("Wrote snapshot of slot {} to {}, root {}", slot, path.display(), root);
+    Ok(())
+}
+
+/// Loads a snapshot into the configured storage, which must not have indexed anything yet.
+pub async fn restore_snapshot(path: &Path) -> Result<()> {
+    utils::logging::init_logger()?;
+    let config = utils::config::load_config()?;
+    let (storage, compression) = connect_storage(&config, false).await?;
+    let snapshot = configured_snapshot(&config, storage.as_ref(), compression.as_deref())?;
+    let manifest = snapshot.restore(path).await?;
+    println!("Restored snapshot of slot {} from {}", manifest.slot, path.display());
+    for key in &manifest.proving_keys {
+        println!(
+            "Verifying key for depth {}, {} inclusions, {} non-inclusions: {}",
+            key.depth,
+            key.inclusions,
+            key.non_inclusions,
+            hex::encode(&key.fingerprint)
+        );
+    }
+    Ok(())
+}
+
+fn parquet_exporter(storage: Arc<dyn storage::Database>, dir: &Path, tables: &[String]) -> Result<storage::ParquetExporter> {
+    let exporter = storage::ParquetExporter::new(storage, dir);
+    if tables.is_empty() {
+        return Ok(exporter);
+    }
+    let tables = tables.iter().map(|table| table.parse()).collect::<Result<Vec<_>>>()?;
+    Ok(exporter.with_tables(tables))
+}
+
+/// Exports `tables` (all the storage keeps when empty) to Parquet files under `dir`: the given slot range, or
+/// the slots processed since the last incremental export.
+pub async fn export(dir: &Path, tables: &[String], slots: Option<RangeInclusive<u64>>) -> Result<()> {
+    utils::logging::init_logger()?;
+    let config = utils::config::load_config()?;
+    let (storage, _) = connect_storage(&config, false).await?;
+    let exporter = parquet_exporter(storage, dir, tables)?;
+    match slots {
+        Some(slots) => {
+            let rows = exporter.export(slots.clone()).await?;
+            println!("Exported {} rows for slots {:?} to {}", rows, slots, dir.display());
+        }
+        None => match exporter.export_new().await? {
+            Some((slots, rows)) => println!("Exported {} rows for slots {:?} to {}", rows, slots, dir.display()),
+            None => println!("Nothing to export, {} is up to date", dir.display()),
+        },
+    }
+    Ok(())
+}
+
+/// Compressed state indexing and the gRPC endpoints that serve it. Both use one tree
+/// processor, so proofs come from the trees as they are indexed.
+struct CompressionServices {
+    store: Arc<dyn storage::CompressionStore>,
+    light: indexer::LightIndexer,
+    bubblegum: indexer::BubblegumIndexer,
+    grpc_server: grpc::server::GrpcServer,
+}
+
+fn compression_services(
+    store: Arc<dyn storage::CompressionStore>,
+    history: Arc<dyn indexer::TreeHistory>,
+    prover: Arc<compression::LightProver>,
+    grpc_server: grpc::server::GrpcServer,
+) -> CompressionServices {
+    let trees = Arc::new(compression::InstructionProcessor::new(Arc::clone(&store)));
+    CompressionServices {
+        light: indexer::LightIndexer::new(Arc::clone(&store), Arc::clone(&trees)).with_history(Arc::clone(&history)),
+        bubblegum: indexer::BubblegumIndexer::new(Arc::clone(&store), Arc::clone(&trees)).with_history(history),
+        grpc_server: grpc_server.with_compression_state(Arc::clone(&store), trees, prover),
+        store,
+    }
+}
+
 pub async fn run() -> Result<()> {
     utils::logging::init_logger()?;
     let config = utils::config::load_config()?;
 
-    let storage = Arc::new(storage::database::Database::new(&config.database_url).await?);
+    let (storage, compression) = connect_storage(&config, true).await?;
+
+    if let Some(retention) = &config.retention {
+        let age = |secs: Option<u64>| secs.map(Duration::from_secs);
+        let policy = storage::RetentionPolicy {
+            block_max_age: age(retention.block_max_age_secs),
+            transaction_max_age: age(retention.transaction_max_age_secs),
+            vote_transaction_max_age: age(retention.vote_transaction_max_age_secs),
+            account_history_max_age: age(retention.account_history_max_age_secs),
+            protected_addresses: retention
+                .watched_programs
+                .iter()
+                .chain(&retention.watched_accounts)
+                .map(|address| Ok(Pubkey::from_str(address)?.to_bytes().to_vec()))
+                .collect::<Result<_>>()?,
+        };
+        let pruner = storage::Pruner::new(Arc::clone(&storage), policy);
+        tokio::spawn(pruner.run(Duration::from_secs(retention.prune_interval_secs)));
+    }
+    if let Some(export) = &config.export {
+        let exporter = parquet_exporter(Arc::clone(&storage), Path::new(&export.dir), &export.tables)?;
+        tokio::spawn(exporter.run(Duration::from_secs(export.interval_secs)));
+    }
 
-    let grpc_server = grpc::server::GrpcServer::new(&config.solana_rpc_url);
+    let mut grpc_server = grpc::server::GrpcServer::new(&config.solana_rpc_url);
 
-    let grpc_client = grpc::client::GrpcClient::new(&config.grpc_server_url).await?;
+    let rpc = RpcClient::new_with_commitment(config.solana_rpc_url.clone(), CommitmentConfig::confirmed());
+    let compressor = Arc::new(compression::Groth16Prover::read(Path::new(&config.compressor_key))?);
+    let mut indexer = indexer::Indexer::new(Arc::clone(&storage), rpc, compressor);
 
-    let indexer = indexer::Indexer::new(Arc::clone(&storage), grpc_client.clone());
+    if let Some(store) = compression {
+        let history = Arc::new(indexer::RpcTreeHistory::new(&config.solana_rpc_url));
+        let prover = match &config.prover {
+            Some(prover) => compression::LightProver::new(
+                &prover.url,
+                compression::VerifyingKeys::load(Path::new(&prover.verifying_keys_dir))?,
+            ),
+            None => {
+                log::warn!("No prover configured, validity proofs are disabled");
+                compression::LightProver::default()
+            }
+        };
+        let services = compression_services(store, history, Arc::new(prover), grpc_server);
+        indexer = indexer.with_compression_indexers(services.store, services.light, services.bubblegum);
+        grpc_server = services.grpc_server;
+    }
 
     let wasm_runtime = Arc::new(wasm::WasmRuntime::new());
 
-    let api_server = api::start_server(config.api_port, Arc::clone(&storage))?;
+    let api_server = api::start_server(config.api_port, Arc::clone(&storage), indexer.account_data_decoder())?;
 
     let metrics_server = metrics::start_server(config.metrics_port)?;
 
-    let grpc_server_handle = tokio::spawn(grpc_server.run(&config.grpc_server_url));
+    let grpc_addr = format!("0.0.0.0:{}", config.grpc_port);
+    let grpc_server_handle = tokio::spawn(async move { grpc_server.run(&grpc_addr).await });
 
     tokio::select! {
         result = indexer.run() => {
@@ -65,3 +300,112 @@ pub async fn run() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::light::{
+        LightCompressedAccount, OutputCompressedAccountWithPackedContext, PublicTransactionEvent,
+        RawInstruction, LIGHT_SYSTEM_PROGRAM_ID, NOOP_PROGRAM_ID,
+    };
+    use crate::proto::windexer_server::Windexer;
+    use crate::proto::GetCompressedAccountRequest;
+    use solana_sdk::bs58;
+    use solana_sdk::signature::Signature;
+    use tonic::Request;
+
+    /// History holding the transaction that appended leaf 0, which the stream missed.
+    struct MissedFirstLeaf(Vec<RawInstruction>);
+
+    #[async_trait::async_trait]
+    impl indexer::TreeHistory for MissedFirstLeaf {
+        async fn signatures(&self, _tree: &Pubkey, before: Option<Signature>) -> Result<Vec<indexer::TreeSignature>> {
+            let first = indexer::TreeSignature {
+                signature: Signature::default(),
+                slot: 1,
+                succeeded: true,
+            };
+            Ok(before.map_or(vec![first], |_| vec![]))
+        }
+
+        async fn instructions(&self, _signature: &Signature) -> Result<(u64, Vec<RawInstruction>)> {
+            Ok((1, self.0.clone()))
+        }
+    }
+
+    fn output_event(tree: Pubkey, hash: [u8; 32], leaf_index: u32) -> Vec<RawInstruction> {
+        let event = PublicTransactionEvent {
+            input_compressed_account_hashes: vec![],
+            output_compressed_account_hashes: vec![hash],
+            output_compressed_accounts: vec![OutputCompressedAccountWithPackedContext {
+                compressed_account: LightCompressedAccount {
+                    owner: Pubkey::new_unique(),
+                    lamports: 42,
+                    address: None,
+                    data: None,
+                },
+                merkle_tree_index: 0,
+            }],
+            output_leaf_indices: vec![leaf_index],
+            sequence_numbers: vec![],
+            relay_fee: None,
+            is_compress: false,
+            compress_or_decompress_lamports: None,
+            pubkey_array: vec![tree],
+            message: None,
+        };
+        vec![
+            RawInstruction {
+                program_id: LIGHT_SYSTEM_PROGRAM_ID,
+                accounts: vec![],
+                data: vec![],
+            },
+            RawInstruction {
+                program_id: NOOP_PROGRAM_ID,
+                accounts: vec![],
+                data: borsh::to_vec(&event).unwrap(),
+            },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_compression_services() {
+        let (_, store) = storage::StorageUrl::parse("memory://")
+            .unwrap()
+            .connect_with_compression()
+            .await
+            .unwrap();
+        let tree = Pubkey::new_unique();
+        let services = compression_services(
+            store.expect("memory storage is a compression store"),
+            Arc::new(MissedFirstLeaf(output_event(tree, [6u8; 32], 0))),
+            Arc::new(compression::LightProver::default()),
+            grpc::server::GrpcServer::new("http://localhost:8899"),
+        );
+
+        // The tree is first seen at leaf 1, so leaf 0 is backfilled from history.
+        let hash = [7u8; 32];
+        services
+            .light
+            .index_instructions(2, &output_event(tree, hash, 1))
+            .await
+            .unwrap();
+
+        // The gRPC server proves the accounts against the tree the indexer built.
+        for hash in [[6u8; 32], hash] {
+            let response = services
+                .grpc_server
+                .get_compressed_account(Request::new(GetCompressedAccountRequest {
+                    pubkey: bs58::encode(hash).into_string(),
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(response.account.unwrap().lamports, 42);
+            let context = response.merkle_context.unwrap();
+            assert_eq!(context.tree, tree.to_string());
+            assert!(!context.root.is_empty());
+            assert_eq!(context.proof.len(), compression::light::STATE_TREE_DEPTH as usize);
+        }
+    }
+}
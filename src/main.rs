@@ -1,7 +1,73 @@
 use anyhow::Result;
-use windexer::run;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use windexer::{create_snapshot, export, migrate, restore_snapshot, run};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Brings the configured databases up to this build's schema and exits.
+    Migrate {
+        /// Only report pending migrations, failing if there are any.
+        #[arg(long)]
+        check: bool,
+    },
+    /// Writes or restores a point-in-time snapshot of the index.
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommand,
+    },
+    /// Exports indexed tables to Parquet files partitioned by epoch. Without a slot range,
+    /// exports the slots processed since the last run.
+    Export {
+        dir: PathBuf,
+        /// Tables to export, by default every one the storage keeps: blocks, transactions,
+        /// transaction_programs, token_transfers and account_versions.
+        #[arg(long, value_delimiter = ',')]
+        tables: Vec<String>,
+        #[arg(long, requires = "end_slot")]
+        start_slot: Option<u64>,
+        #[arg(long, requires = "start_slot")]
+        end_slot: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotCommand {
+    /// Writes the index as of a slot to a file.
+    Create {
+        path: PathBuf,
+        /// Slot to snapshot, the last processed one by default.
+        #[arg(long)]
+        slot: Option<u64>,
+    },
+    /// Bootstraps an empty index from a snapshot file.
+    Restore { path: PathBuf },
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    run().await
+    match Cli::parse().command {
+        Some(Command::Migrate { check }) => migrate(check).await,
+        Some(Command::Snapshot { command }) => match command {
+            SnapshotCommand::Create { path, slot } => create_snapshot(&path, slot).await,
+            SnapshotCommand::Restore { path } => restore_snapshot(&path).await,
+        },
+        Some(Command::Export {
+            dir,
+            tables,
+            start_slot,
+            end_slot,
+        }) => {
+            let slots = start_slot.zip(end_slot).map(|(start, end)| start..=end);
+            export(&dir, &tables, slots).await
+        }
+        None => run().await,
+    }
 }
\ No newline at end of file
@@ -40,4 +40,16 @@ pub async fn set_last_processed_slot(slot: i64) {
 
 pub async fn set_indexer_status(status: &str) {
     METRICS.lock().await.set_indexer_status(status);
+}
+
+pub async fn increment_storage_tier_reads(tier: &str) {
+    METRICS.lock().await.increment_storage_tier_reads(tier);
+}
+
+pub async fn record_storage_cache_request(hit: bool) {
+    METRICS.lock().await.record_storage_cache_request(hit);
+}
+
+pub async fn record_pruned(kind: &str, rows: u64, bytes: u64) {
+    METRICS.lock().await.record_pruned(kind, rows, bytes);
 }
\ No newline at end of file
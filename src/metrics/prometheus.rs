@@ -1,5 +1,5 @@
 use prometheus::{
-    Encoder, IntCounter, IntGauge, Opts, Registry, TextEncoder,
+    Encoder, Gauge, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
     Histogram, HistogramOpts,
 };
 use warp::Filter;
@@ -16,6 +16,11 @@ pub struct PrometheusMetrics {
     account_processing_time: Histogram,
     last_processed_slot: IntGauge,
     indexer_status: IntGauge,
+    storage_tier_reads: IntCounterVec,
+    storage_cache_requests: IntCounterVec,
+    storage_cache_hit_ratio: Gauge,
+    storage_pruned_rows: IntCounterVec,
+    storage_pruned_bytes: IntCounterVec,
 }
 
 impl PrometheusMetrics {
@@ -41,6 +46,24 @@ impl PrometheusMetrics {
             Opts::new("indexer_status", "Indexer status (0: stopped, 1: running, 2: error)")
         ).unwrap();
 
+        let storage_tier_reads = IntCounterVec::new(
+            Opts::new("storage_tier_reads_total", "Reads served by each storage tier"),
+            &["tier"],
+        ).unwrap();
+        let storage_cache_requests = IntCounterVec::new(
+            Opts::new("storage_cache_requests_total", "Storage cache lookups by result (hit, miss)"),
+            &["result"],
+        ).unwrap();
+        let storage_cache_hit_ratio = Gauge::new("storage_cache_hit_ratio", "Fraction of storage cache lookups that hit").unwrap();
+        let storage_pruned_rows = IntCounterVec::new(
+            Opts::new("storage_pruned_rows_total", "Rows deleted by retention pruning, by kind"),
+            &["kind"],
+        ).unwrap();
+        let storage_pruned_bytes = IntCounterVec::new(
+            Opts::new("storage_pruned_bytes_total", "Data and proof bytes reclaimed by retention pruning, by kind"),
+            &["kind"],
+        ).unwrap();
+
         registry.register(Box::new(processed_blocks.clone())).unwrap();
         registry.register(Box::new(processed_transactions.clone())).unwrap();
         registry.register(Box::new(processed_accounts.clone())).unwrap();
@@ -49,6 +72,11 @@ impl PrometheusMetrics {
         registry.register(Box::new(account_processing_time.clone())).unwrap();
         registry.register(Box::new(last_processed_slot.clone())).unwrap();
         registry.register(Box::new(indexer_status.clone())).unwrap();
+        registry.register(Box::new(storage_tier_reads.clone())).unwrap();
+        registry.register(Box::new(storage_cache_requests.clone())).unwrap();
+        registry.register(Box::new(storage_cache_hit_ratio.clone())).unwrap();
+        registry.register(Box::new(storage_pruned_rows.clone())).unwrap();
+        registry.register(Box::new(storage_pruned_bytes.clone())).unwrap();
 
         Self {
             registry,
@@ -60,6 +88,11 @@ impl PrometheusMetrics {
             account_processing_time,
             last_processed_slot,
             indexer_status,
+            storage_tier_reads,
+            storage_cache_requests,
+            storage_cache_hit_ratio,
+            storage_pruned_rows,
+            storage_pruned_bytes,
         }
     }
 
@@ -100,6 +133,24 @@ impl PrometheusMetrics {
         self.indexer_status.set(status_value);
     }
 
+    pub fn increment_storage_tier_reads(&self, tier: &str) {
+        self.storage_tier_reads.with_label_values(&[tier]).inc();
+    }
+
+    pub fn record_storage_cache_request(&self, hit: bool) {
+        self.storage_cache_requests
+            .with_label_values(&[if hit { "hit" } else { "miss" }])
+            .inc();
+        let hits = self.storage_cache_requests.with_label_values(&["hit"]).get() as f64;
+        let misses = self.storage_cache_requests.with_label_values(&["miss"]).get() as f64;
+        self.storage_cache_hit_ratio.set(hits / (hits + misses));
+    }
+
+    pub fn record_pruned(&self, kind: &str, rows: u64, bytes: u64) {
+        self.storage_pruned_rows.with_label_values(&[kind]).inc_by(rows);
+        self.storage_pruned_bytes.with_label_values(&[kind]).inc_by(bytes);
+    }
+
     pub fn gather(&self) -> Vec<u8> {
         let mut buffer = Vec::new();
         let encoder = TextEncoder::new();
@@ -121,4 +172,18 @@ pub async fn start_metrics_server(port: u16) -> Result<()> {
 async fn serve_metrics() -> Result<impl warp::Reply, Infallible> {
     let metrics = METRICS.lock().await.gather();
     Ok(warp::reply::with_header(metrics, "Content-Type", "text/plain"))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage_cache_hit_ratio() {
+        let metrics = PrometheusMetrics::new();
+        for hit in [true, false, true, true] {
+            metrics.record_storage_cache_request(hit);
+        }
+        assert_eq!(metrics.storage_cache_requests.with_label_values(&["miss"]).get(), 1);
+        assert_eq!(metrics.storage_cache_hit_ratio.get(), 0.75);
+    }
+}
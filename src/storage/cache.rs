@@ -0,0 +1,714 @@
+use async_trait::async_trait;
+use anyhow::{anyhow, bail};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use log::warn;
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncCommands, Script};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::metrics;
+use crate::storage::error::{Result, StorageError};
+use crate::storage::{
+    BlockBatch, Database, CompressedAccount, CompressedBlock, CompressedTransaction, PruneRequest, PruneStats,
+    SignatureInfo, SignatureQuery, TokenTransfer,
+};
+
+const DEFAULT_PREFIX: &str = "windexer:";
+const DEFAULT_TTL: Duration = Duration::from_secs(300);
+const DEFAULT_MAX_SLOT_ENTRIES: usize = 1_000_000;
+// Invalidations remembered for checking fills. A fill that started longer ago is dropped.
+const INVALIDATION_WINDOW: u64 = 1_000;
+
+// KEYS: entry, slot index, generation, invalidated keys, invalidated slots.
+// ARGV: value, ttl in ms, slot ('' for none), max index size, generation the fill started at,
+// invalidation window.
+// A fill is dropped if an invalidation since it started covered its key or slot, so a value
+// read before a write can't be stored after the write's invalidation. Entries tied to a slot
+// are recorded in the index so a rollback can find them. When the index is full, the entries
+// of the oldest slots are evicted with it, so every cached entry of a slot is always
+// reachable from the index.
+const FILL_SCRIPT: &str = r"
+local generation = tonumber(ARGV[5])
+local current = tonumber(redis.call('GET', KEYS[3]) or '0')
+if current > generation then
+    if current - generation >= tonumber(ARGV[6]) then
+        return 0
+    end
+    local invalidated = redis.call('ZSCORE', KEYS[4], KEYS[1])
+    if invalidated and tonumber(invalidated) > generation then
+        return 0
+    end
+    if ARGV[3] ~= '' then
+        local slot = tonumber(ARGV[3])
+        for _, range in ipairs(redis.call('ZRANGEBYSCORE', KEYS[5], '(' .. generation, '+inf')) do
+            local first, last = string.match(range, '^%d+:(%d+):(%d+)$')
+            if slot >= tonumber(first) and slot <= tonumber(last) then
+                return 0
+            end
+        end
+    end
+end
+redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+if ARGV[3] ~= '' then
+    redis.call('ZADD', KEYS[2], ARGV[3], KEYS[1])
+    local excess = redis.call('ZCARD', KEYS[2]) - tonumber(ARGV[4])
+    if excess > 0 then
+        for _, key in ipairs(redis.call('ZRANGE', KEYS[2], 0, excess - 1)) do
+            redis.call('DEL', key)
+        end
+        redis.call('ZREMRANGEBYRANK', KEYS[2], 0, excess - 1)
+    end
+end
+return 1
+";
+
+// KEYS: generation, invalidated keys, invalidated slots, then the entries. ARGV: invalidation window.
+const INVALIDATE_SCRIPT: &str = r"
+local generation = redis.call('INCR', KEYS[1])
+for i = 4, #KEYS do
+    redis.call('DEL', KEYS[i])
+    redis.call('ZADD', KEYS[2], generation, KEYS[i])
+end
+redis.call('ZREMRANGEBYSCORE', KEYS[2], '-inf', generation - tonumber(ARGV[1]))
+redis.call('ZREMRANGEBYSCORE', KEYS[3], '-inf', generation - tonumber(ARGV[1]))
+";
+
+// KEYS: slot index, generation, invalidated keys, invalidated slots. ARGV: start slot, end
+// slot, invalidation window.
+const INVALIDATE_SLOTS_SCRIPT: &str = r"
+local generation = redis.call('INCR', KEYS[2])
+local keys = redis.call('ZRANGEBYSCORE', KEYS[1], ARGV[1], ARGV[2])
+for _, key in ipairs(keys) do
+    redis.call('DEL', key)
+end
+redis.call('ZREMRANGEBYSCORE', KEYS[1], ARGV[1], ARGV[2])
+redis.call('ZADD', KEYS[4], generation, generation .. ':' .. ARGV[1] .. ':' .. ARGV[2])
+redis.call('ZREMRANGEBYSCORE', KEYS[3], '-inf', generation - tonumber(ARGV[3]))
+redis.call('ZREMRANGEBYSCORE', KEYS[4], '-inf', generation - tonumber(ARGV[3]))
+return #keys
+";
+
+type Fill = Shared<BoxFuture<'static, Result<Vec<u8>, Arc<StorageError>>>>;
+
+#[derive(Clone)]
+struct CacheSettings {
+    prefix: String,
+    ttl: Duration,
+    max_slot_entries: usize,
+}
+
+impl CacheSettings {
+    fn key(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+}
+
+/// Where `CachedStorage` keeps its entries: Redis, or memory in tests.
+#[async_trait]
+trait CacheBackend: Send + Sync {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Counts invalidations. A fill reads it before loading.
+    async fn generation(&self, settings: &CacheSettings) -> anyhow::Result<u64>;
+
+    /// Stores `value` under `key`, tied to `slot`, unless an invalidation after `generation`
+    /// covered either. Returns whether it was stored.
+    async fn fill(
+        &self,
+        settings: &CacheSettings,
+        key: &str,
+        value: &[u8],
+        slot: Option<u64>,
+        generation: u64,
+    ) -> anyhow::Result<bool>;
+
+    async fn invalidate(&self, settings: &CacheSettings, keys: &[String]) -> anyhow::Result<()>;
+
+    async fn invalidate_slots(&self, settings: &CacheSettings, start_slot: u64, end_slot: u64) -> anyhow::Result<()>;
+}
+
+struct RedisCache {
+    redis: MultiplexedConnection,
+    fill_script: Script,
+    invalidate_script: Script,
+    invalidate_slots_script: Script,
+}
+
+#[async_trait]
+impl CacheBackend for RedisCache {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.redis.clone().get(key).await?)
+    }
+
+    async fn generation(&self, settings: &CacheSettings) -> anyhow::Result<u64> {
+        let generation: Option<u64> = self.redis.clone().get(settings.key("generation")).await?;
+        Ok(generation.unwrap_or_default())
+    }
+
+    async fn fill(
+        &self,
+        settings: &CacheSettings,
+        key: &str,
+        value: &[u8],
+        slot: Option<u64>,
+        generation: u64,
+    ) -> anyhow::Result<bool> {
+        let filled = self
+            .fill_script
+            .key(key)
+            .key(settings.key("slots"))
+            .key(settings.key("generation"))
+            .key(settings.key("invalidated-keys"))
+            .key(settings.key("invalidated-slots"))
+            .arg(value)
+            .arg(settings.ttl.as_millis() as u64)
+            .arg(slot.map(|slot| slot.to_string()).unwrap_or_default())
+            .arg(settings.max_slot_entries)
+            .arg(generation)
+            .arg(INVALIDATION_WINDOW)
+            .invoke_async::<u8>(&mut self.redis.clone())
+            .await?;
+        Ok(filled == 1)
+    }
+
+    async fn invalidate(&self, settings: &CacheSettings, keys: &[String]) -> anyhow::Result<()> {
+        let mut invocation = self.invalidate_script.prepare_invoke();
+        invocation
+            .key(settings.key("generation"))
+            .key(settings.key("invalidated-keys"))
+            .key(settings.key("invalidated-slots"));
+        for key in keys {
+            invocation.key(key);
+        }
+        invocation
+            .arg(INVALIDATION_WINDOW)
+            .invoke_async::<()>(&mut self.redis.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn invalidate_slots(&self, settings: &CacheSettings, start_slot: u64, end_slot: u64) -> anyhow::Result<()> {
+        self.invalidate_slots_script
+            .key(settings.key("slots"))
+            .key(settings.key("generation"))
+            .key(settings.key("invalidated-keys"))
+            .key(settings.key("invalidated-slots"))
+            .arg(start_slot)
+            .arg(end_slot)
+            .arg(INVALIDATION_WINDOW)
+            .invoke_async::<u64>(&mut self.redis.clone())
+            .await?;
+        Ok(())
+    }
+}
+
+/// Read-through Redis cache in front of another `Database`.
+///
+/// Accounts by pubkey, blocks by slot and transactions by signature are cached as bincode for
+/// `ttl`. Concurrent misses on the same key share one backend read. Writes go to the inner
+/// database first and then invalidate what they touched; `commit_block` and
+/// `delete_slot_range` also drop everything cached for the affected slots, so a re-indexed
+/// or rolled back slot is never served from the cache. Every invalidation bumps a generation
+/// counter, and a read that raced a write doesn't store what it read if an invalidation since
+/// it started covered its key or slot.
+///
+/// Redis errors on the read path fall back to the inner database. On the write path they are
+/// returned after the inner write has succeeded, so the caller retries the invalidation.
+pub struct CachedStorage {
+    inner: Arc<dyn Database>,
+    cache: Arc<dyn CacheBackend>,
+    settings: CacheSettings,
+    in_flight: Arc<Mutex<HashMap<String, Fill>>>,
+}
+
+impl CachedStorage {
+    pub async fn new(inner: Arc<dyn Database>, redis_url: &str) -> anyhow::Result<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let redis = client.get_multiplexed_async_connection().await?;
+        let cache = RedisCache {
+            redis,
+            fill_script: Script::new(FILL_SCRIPT),
+            invalidate_script: Script::new(INVALIDATE_SCRIPT),
+            invalidate_slots_script: Script::new(INVALIDATE_SLOTS_SCRIPT),
+        };
+        Ok(Self::with_backend(inner, Arc::new(cache)))
+    }
+
+    fn with_backend(inner: Arc<dyn Database>, cache: Arc<dyn CacheBackend>) -> Self {
+        Self {
+            inner,
+            cache,
+            settings: CacheSettings {
+                prefix: DEFAULT_PREFIX.to_string(),
+                ttl: DEFAULT_TTL,
+                max_slot_entries: DEFAULT_MAX_SLOT_ENTRIES,
+            },
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Namespace for every key this cache writes, so several indexers can share one Redis.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.settings.prefix = prefix.to_string();
+        self
+    }
+
+    /// Redis expires entries in whole milliseconds and rejects an expiry of zero.
+    pub fn with_ttl(mut self, ttl: Duration) -> anyhow::Result<Self> {
+        if ttl.as_millis() == 0 {
+            bail!("Cache TTL must be at least 1ms, got {:?}", ttl);
+        }
+        self.settings.ttl = ttl;
+        Ok(self)
+    }
+
+    /// Caps how many block and transaction entries are cached, oldest slots evicted first.
+    pub fn with_max_slot_entries(mut self, max_slot_entries: usize) -> Self {
+        self.settings.max_slot_entries = max_slot_entries;
+        self
+    }
+
+    pub fn inner(&self) -> &Arc<dyn Database> {
+        &self.inner
+    }
+
+    fn account_key(&self, pubkey: &[u8]) -> String {
+        self.settings.key(&format!("account:{}", hex::encode(pubkey)))
+    }
+
+    fn block_key(&self, slot: u64) -> String {
+        self.settings.key(&format!("block:{}", slot))
+    }
+
+    fn transaction_key(&self, signature: &[u8]) -> String {
+        self.settings.key(&format!("tx:{}", hex::encode(signature)))
+    }
+
+    async fn read_through<T>(
+        &self,
+        key: String,
+        slot_of: fn(&T) -> Option<u64>,
+        load: impl FnOnce(Arc<dyn Database>) -> BoxFuture<'static, Result<T>>,
+    ) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+    {
+        match self.cache.get(&key).await {
+            Ok(Some(cached)) => match bincode::deserialize(&cached) {
+                Ok(value) => {
+                    metrics::record_storage_cache_request(true).await;
+                    return Ok(value);
+                }
+                Err(e) => warn!("Discarding undecodable cache entry {}: {}", key, e),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("Cache read of {} failed: {}", key, e),
+        }
+        metrics::record_storage_cache_request(false).await;
+
+        let fill = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(fill) => fill.clone(),
+                None => {
+                    let fill = self.fill(key.clone(), slot_of, load(Arc::clone(&self.inner)));
+                    in_flight.insert(key, fill.clone());
+                    fill
+                }
+            }
+        };
+        let encoded = fill.await.map_err(|e| unshare(&e))?;
+        Ok(bincode::deserialize(&encoded)?)
+    }
+
+    // Loads from the inner database and stores the result. The future removes itself from
+    // `in_flight` when done, whichever caller ends up driving it.
+    fn fill<T>(
+        &self,
+        key: String,
+        slot_of: fn(&T) -> Option<u64>,
+        load: BoxFuture<'static, Result<T>>,
+    ) -> Fill
+    where
+        T: Serialize + Send + 'static,
+    {
+        let cache = Arc::clone(&self.cache);
+        let settings = self.settings.clone();
+        let in_flight = Arc::clone(&self.in_flight);
+        async move {
+            let loaded = async {
+                // Without a generation to check against, the value isn't stored.
+                let generation = cache.generation(&settings).await;
+                let value = load.await?;
+                let encoded = bincode::serialize(&value)?;
+                let filled = match generation {
+                    Ok(generation) => cache.fill(&settings, &key, &encoded, slot_of(&value), generation).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = filled {
+                    warn!("Cache fill of {} failed: {}", key, e);
+                }
+                Ok::<_, StorageError>(encoded)
+            }
+            .await;
+            in_flight.lock().unwrap().remove(&key);
+            loaded.map_err(Arc::new)
+        }
+        .boxed()
+        .shared()
+    }
+
+    async fn invalidate(&self, keys: Vec<String>) -> Result<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            for key in &keys {
+                in_flight.remove(key);
+            }
+        }
+        Ok(self.cache.invalidate(&self.settings, &keys).await?)
+    }
+
+    async fn invalidate_slots(&self, start_slot: u64, end_slot: u64) -> Result<()> {
+        Ok(self.cache.invalidate_slots(&self.settings, start_slot, end_slot).await?)
+    }
+}
+
+// Every caller waiting on a fill gets its own copy of the error, with the same kind.
+fn unshare(error: &StorageError) -> StorageError {
+    match error {
+        StorageError::NotFound(what) => StorageError::NotFound(what.clone()),
+        StorageError::Conflict(e) => StorageError::Conflict(anyhow!("{:#}", e)),
+        StorageError::Unavailable(e) => StorageError::Unavailable(anyhow!("{:#}", e)),
+        StorageError::Corrupt(e) => StorageError::Corrupt(anyhow!("{:#}", e)),
+        StorageError::Other(e) => StorageError::Other(anyhow!("{:#}", e)),
+    }
+}
+
+#[async_trait]
+impl Database for CachedStorage {
+    async fn insert_compressed_account(&self, account: &CompressedAccount) -> Result<()> {
+        self.inner.insert_compressed_account(account).await?;
+        self.invalidate(vec![self.account_key(&account.pubkey)]).await
+    }
+
+    async fn get_compressed_account(&self, pubkey: &[u8]) -> Result<CompressedAccount> {
+        let owned = pubkey.to_vec();
+        self.read_through(self.account_key(pubkey), |_| None, move |db| {
+            async move { db.get_compressed_account(&owned).await }.boxed()
+        })
+        .await
+    }
+
+    async fn insert_compressed_block(&self, block: &CompressedBlock) -> Result<()> {
+        self.inner.insert_compressed_block(block).await?;
+        self.invalidate(vec![self.block_key(block.slot)]).await
+    }
+
+    async fn get_compressed_block(&self, slot: u64) -> Result<CompressedBlock> {
+        self.read_through(self.block_key(slot), |block: &CompressedBlock| Some(block.slot), move |db| {
+            async move { db.get_compressed_block(slot).await }.boxed()
+        })
+        .await
+    }
+
+    async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> Result<()> {
+        self.inner.insert_compressed_transaction(transaction).await?;
+        self.invalidate(vec![self.transaction_key(&transaction.signature)]).await
+    }
+
+    async fn get_compressed_transaction(&self, signature: &[u8]) -> Result<CompressedTransaction> {
+        let owned = signature.to_vec();
+        self.read_through(
+            self.transaction_key(signature),
+            |transaction: &CompressedTransaction| Some(transaction.slot),
+            move |db| async move { db.get_compressed_transaction(&owned).await }.boxed(),
+        )
+        .await
+    }
+
+    async fn get_accounts_by_owner(&self, owner: &[u8], limit: usize) -> Result<Vec<CompressedAccount>> {
+        self.inner.get_accounts_by_owner(owner, limit).await
+    }
+
+    async fn get_signatures_for_address(&self, address: &[u8], query: &SignatureQuery) -> Result<Vec<SignatureInfo>> {
+        self.inner.get_signatures_for_address(address, query).await
+    }
+
+    async fn get_blocks_in_range(&self, start_slot: u64, end_slot: u64) -> Result<Vec<CompressedBlock>> {
+        self.inner.get_blocks_in_range(start_slot, end_slot).await
+    }
+
+    async fn get_first_block_slot(&self) -> Result<Option<u64>> {
+        self.inner.get_first_block_slot().await
+    }
+
+    async fn get_transactions_in_block(&self, slot: u64) -> Result<Vec<CompressedTransaction>> {
+        self.inner.get_transactions_in_block(slot).await
+    }
+
+    fn stores_token_transfers(&self) -> bool {
+        self.inner.stores_token_transfers()
+    }
+
+    async fn get_token_transfers_in_block(&self, slot: u64) -> Result<Vec<TokenTransfer>> {
+        self.inner.get_token_transfers_in_block(slot).await
+    }
+
+    async fn delete_slot_range(&self, start_slot: u64, end_slot: u64) -> Result<u64> {
+        let deleted = self.inner.delete_slot_range(start_slot, end_slot).await?;
+        self.invalidate_slots(start_slot, end_slot).await?;
+        Ok(deleted)
+    }
+
+    async fn get_account_at_slot(&self, pubkey: &[u8], slot: u64) -> Result<Option<CompressedAccount>> {
+        self.inner.get_account_at_slot(pubkey, slot).await
+    }
+
+    async fn get_account_versions(
+        &self,
+        pubkey: &[u8],
+        start_slot: u64,
+        end_slot: u64,
+        limit: usize,
+    ) -> Result<Vec<CompressedAccount>> {
+        self.inner.get_account_versions(pubkey, start_slot, end_slot, limit).await
+    }
+
+    async fn get_account_pubkeys(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        self.inner.get_account_pubkeys(after, limit).await
+    }
+
+    async fn get_transaction_signatures(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        self.inner.get_transaction_signatures(after, limit).await
+    }
+
+    // Pruning only touches history, so cached current account state stays valid.
+    async fn prune(&self, request: &PruneRequest) -> Result<PruneStats> {
+        let stats = self.inner.prune(request).await?;
+        let before = [request.blocks_before, request.transactions_before, request.vote_transactions_before]
+            .into_iter()
+            .flatten()
+            .max();
+        if let Some(before) = before.filter(|before| *before > 0) {
+            self.invalidate_slots(0, before - 1).await?;
+        }
+        Ok(stats)
+    }
+
+    async fn get_last_processed_slot(&self) -> Result<u64> {
+        self.inner.get_last_processed_slot().await
+    }
+
+    async fn update_last_processed_slot(&self, slot: u64) -> Result<()> {
+        self.inner.update_last_processed_slot(slot).await
+    }
+
+    // Committing a slot that was already indexed replaces it, so whatever was cached for the
+    // slot goes, along with the new rows' keys in case they were cached under another slot.
+    async fn commit_block(&self, batch: &BlockBatch) -> Result<()> {
+        self.inner.commit_block(batch).await?;
+        let mut keys = vec![self.block_key(batch.slot)];
+        keys.extend(batch.transactions.iter().map(|transaction| self.transaction_key(&transaction.signature)));
+        keys.extend(batch.accounts.iter().map(|account| self.account_key(&account.pubkey)));
+        self.invalidate(keys).await?;
+        self.invalidate_slots(batch.slot, batch.slot).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance::{service_url, storage_conformance};
+    use crate::storage::{DatabaseMethod, Fault, InMemoryStorage};
+    use std::collections::BTreeMap;
+
+    // A prefix per test keeps concurrent tests from invalidating each other's slots.
+    storage_conformance!(
+        ignore = "needs Redis at WINDEXER_TEST_REDIS_URL",
+        CachedStorage::new(
+            Arc::new(InMemoryStorage::new()),
+            &service_url("WINDEXER_TEST_REDIS_URL", "redis://localhost:6379"),
+        )
+        .await
+        .unwrap()
+        .with_prefix(&format!("windexer-test-{}:", rand::random::<u32>()))
+    );
+
+    // What the Redis scripts do, without expiry or eviction.
+    #[derive(Default)]
+    struct MemoryCache {
+        state: Mutex<MemoryCacheState>,
+        fill_delay: Option<Duration>,
+    }
+
+    #[derive(Default)]
+    struct MemoryCacheState {
+        entries: HashMap<String, Vec<u8>>,
+        slots: BTreeMap<u64, Vec<String>>,
+        generation: u64,
+        invalidated_keys: HashMap<String, u64>,
+        // (generation, start slot, end slot)
+        invalidated_slots: Vec<(u64, u64, u64)>,
+    }
+
+    #[async_trait]
+    impl CacheBackend for MemoryCache {
+        async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(self.state.lock().unwrap().entries.get(key).cloned())
+        }
+
+        async fn generation(&self, _settings: &CacheSettings) -> anyhow::Result<u64> {
+            Ok(self.state.lock().unwrap().generation)
+        }
+
+        async fn fill(
+            &self,
+            _settings: &CacheSettings,
+            key: &str,
+            value: &[u8],
+            slot: Option<u64>,
+            generation: u64,
+        ) -> anyhow::Result<bool> {
+            if let Some(delay) = self.fill_delay {
+                tokio::time::sleep(delay).await;
+            }
+            let mut state = self.state.lock().unwrap();
+            let stale = state.generation - generation >= INVALIDATION_WINDOW
+                || state.invalidated_keys.get(key).is_some_and(|at| *at > generation)
+                || slot.is_some_and(|slot| {
+                    state
+                        .invalidated_slots
+                        .iter()
+                        .any(|(at, start, end)| *at > generation && (*start..=*end).contains(&slot))
+                });
+            if stale {
+                return Ok(false);
+            }
+            state.entries.insert(key.to_string(), value.to_vec());
+            if let Some(slot) = slot {
+                state.slots.entry(slot).or_default().push(key.to_string());
+            }
+            Ok(true)
+        }
+
+        async fn invalidate(&self, _settings: &CacheSettings, keys: &[String]) -> anyhow::Result<()> {
+            let mut state = self.state.lock().unwrap();
+            state.generation += 1;
+            let generation = state.generation;
+            for key in keys {
+                state.entries.remove(key);
+                state.invalidated_keys.insert(key.clone(), generation);
+            }
+            Ok(())
+        }
+
+        async fn invalidate_slots(&self, _settings: &CacheSettings, start_slot: u64, end_slot: u64) -> anyhow::Result<()> {
+            let mut state = self.state.lock().unwrap();
+            state.generation += 1;
+            let generation = state.generation;
+            let slots: Vec<u64> = state.slots.range(start_slot..=end_slot).map(|(slot, _)| *slot).collect();
+            for slot in slots {
+                for key in state.slots.remove(&slot).unwrap_or_default() {
+                    state.entries.remove(&key);
+                }
+            }
+            state.invalidated_slots.push((generation, start_slot, end_slot));
+            Ok(())
+        }
+    }
+
+    fn block(slot: u64, data: u8) -> CompressedBlock {
+        CompressedBlock {
+            slot,
+            blockhash: slot.to_string(),
+            previous_blockhash: (slot - 1).to_string(),
+            parent_slot: slot - 1,
+            transactions: 0,
+            block_time: None,
+            data: vec![data],
+            proof: vec![],
+        }
+    }
+
+    fn account(slot: u64, lamports: i64) -> CompressedAccount {
+        CompressedAccount {
+            pubkey: vec![1; 32],
+            lamports,
+            owner: vec![2; 32],
+            executable: false,
+            rent_epoch: 0,
+            slot,
+            write_version: 0,
+            data: vec![],
+            proof: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_misses() {
+        let inner = Arc::new(InMemoryStorage::new());
+        inner.insert_compressed_block(&block(5, 1)).await.unwrap();
+        inner.inject_times(DatabaseMethod::GetCompressedBlock, Fault::Latency(Duration::from_millis(50)), 1);
+        let storage = CachedStorage::with_backend(inner.clone(), Arc::new(MemoryCache::default()));
+        assert!(CachedStorage::with_backend(inner.clone(), Arc::new(MemoryCache::default()))
+            .with_ttl(Duration::ZERO)
+            .is_err());
+
+        let reads = futures::future::join_all((0..8).map(|_| storage.get_compressed_block(5))).await;
+        assert!(reads.iter().all(|read| read.as_ref().unwrap().data == vec![1]));
+        assert_eq!(inner.calls(DatabaseMethod::GetCompressedBlock), 1);
+        storage.get_compressed_block(5).await.unwrap();
+        assert_eq!(inner.calls(DatabaseMethod::GetCompressedBlock), 1);
+    }
+
+    #[tokio::test]
+    async fn test_slot_invalidation() {
+        let inner = Arc::new(InMemoryStorage::new());
+        let storage = CachedStorage::with_backend(inner.clone(), Arc::new(MemoryCache::default()));
+        for slot in 5..=7 {
+            let mut batch = BlockBatch::new(slot);
+            batch.block = Some(block(slot, 1));
+            storage.commit_block(&batch).await.unwrap();
+            storage.get_compressed_block(slot).await.unwrap();
+        }
+
+        // Rolled back slots are gone, a re-indexed one is read again, the others stay cached.
+        storage.delete_slot_range(6, 7).await.unwrap();
+        assert!(matches!(storage.get_compressed_block(7).await, Err(StorageError::NotFound(_))));
+        let mut batch = BlockBatch::new(6);
+        batch.block = Some(block(6, 2));
+        storage.commit_block(&batch).await.unwrap();
+        assert_eq!(storage.get_compressed_block(6).await.unwrap().data, vec![2]);
+
+        let calls = inner.calls(DatabaseMethod::GetCompressedBlock);
+        storage.get_compressed_block(5).await.unwrap();
+        assert_eq!(inner.calls(DatabaseMethod::GetCompressedBlock), calls);
+    }
+
+    #[tokio::test]
+    async fn test_stale_fill() {
+        let inner = Arc::new(InMemoryStorage::new());
+        inner.insert_compressed_account(&account(1, 1)).await.unwrap();
+        let cache = MemoryCache {
+            fill_delay: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let storage = Arc::new(CachedStorage::with_backend(inner, Arc::new(cache)));
+
+        // The read loads the old state, then the write lands before it is stored.
+        let read = tokio::spawn({
+            let storage = Arc::clone(&storage);
+            async move { storage.get_compressed_account(&[1; 32]).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        storage.insert_compressed_account(&account(2, 2)).await.unwrap();
+        assert_eq!(read.await.unwrap().unwrap().lamports, 1);
+        assert_eq!(storage.get_compressed_account(&[1; 32]).await.unwrap().lamports, 2);
+    }
+}
@@ -0,0 +1,571 @@
+use anyhow::{anyhow, bail, Context, Result};
+use cid::multihash::Multihash;
+use cid::Cid;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_bytes::ByteBuf;
+use serde_cbor::tags::Tagged;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt, BufWriter};
+use crate::storage::{CompressedAccount, CompressedBlock, CompressedTransaction, Database, StorageError, TokenTransfer};
+
+const DAG_CBOR: u64 = 0x71;
+const RAW: u64 = 0x55;
+const SHA2_256: u64 = 0x12;
+const CID_TAG: u64 = 42;
+pub(crate) const SLOTS_PER_EPOCH: u64 = 432_000;
+pub(crate) const MANIFEST_VERSION: u64 = 1;
+const LINKS_PER_PAGE: usize = 4096;
+const CARV2_PRAGMA: [u8; 11] = [0x0a, 0xa1, 0x67, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x02];
+const CARV2_HEADER_LEN: u64 = 40;
+// Largest header and section `CarArchive::open` accepts; exports stay far below both.
+const MAX_HEADER_LEN: u64 = 1 << 20;
+const MAX_SECTION_LEN: u64 = 1 << 30;
+// Blocks read from the database at a time while exporting.
+const SLOTS_PER_READ: u64 = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CarVersion {
+    V1,
+    /// CARv1 payload behind a CARv2 header, without an index.
+    V2,
+}
+
+pub fn epoch_slots(epoch: u64) -> RangeInclusive<u64> {
+    epoch * SLOTS_PER_EPOCH..=(epoch + 1) * SLOTS_PER_EPOCH - 1
+}
+
+/// A DAG-CBOR link, encoded as tag 42 over the CID bytes with a leading zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Link(pub Cid);
+
+impl Serialize for Link {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut bytes = vec![0];
+        bytes.extend(self.0.to_bytes());
+        Tagged::new(Some(CID_TAG), ByteBuf::from(bytes)).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Link {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let tagged = Tagged::<ByteBuf>::deserialize(deserializer)?;
+        match (tagged.tag, tagged.value.split_first()) {
+            (Some(CID_TAG), Some((0, cid))) => Cid::try_from(cid).map(Link).map_err(D::Error::custom),
+            _ => Err(D::Error::custom("expected a CID link")),
+        }
+    }
+}
+
+// Struct fields are declared in DAG-CBOR canonical order (shorter keys first),
+// since serde_cbor writes them as declared.
+
+#[derive(Serialize, Deserialize)]
+struct CarHeader {
+    roots: Vec<Link>,
+    version: u64,
+}
+
+/// Root node of an archive. `blocks` and `accounts` link to pages of at most 4096 links each.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CarManifest {
+    pub blocks: Vec<Link>,
+    pub version: u64,
+    pub accounts: Vec<Link>,
+    pub end_slot: u64,
+    pub start_slot: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Page {
+    links: Vec<Link>,
+}
+
+/// One slot: the block, its transactions and its token transfers, each a raw bincode-encoded
+/// leaf. Archives written before token transfers were kept have none.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SlotNode {
+    pub(crate) slot: u64,
+    pub(crate) block: Link,
+    pub(crate) transactions: Vec<Link>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) token_transfers: Vec<Link>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchivedSlot {
+    pub block: CompressedBlock,
+    pub transactions: Vec<CompressedTransaction>,
+    pub token_transfers: Vec<TokenTransfer>,
+}
+
+fn cid_for(codec: u64, data: &[u8]) -> Result<Cid> {
+    let digest = Sha256::digest(data);
+    Ok(Cid::new_v1(codec, Multihash::<64>::wrap(SHA2_256, &digest)?))
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(reader: &mut impl Read) -> Result<Option<u64>> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        if reader.read(&mut byte)? == 0 {
+            if shift == 0 {
+                return Ok(None);
+            }
+            bail!("Truncated varint in CAR file");
+        }
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    bail!("Varint overflow in CAR file")
+}
+
+fn header_bytes(root: Cid) -> Result<Vec<u8>> {
+    let header = serde_cbor::to_vec(&CarHeader {
+        roots: vec![Link(root)],
+        version: 1,
+    })?;
+    let mut bytes = Vec::new();
+    write_varint(header.len() as u64, &mut bytes);
+    bytes.extend(header);
+    Ok(bytes)
+}
+
+pub(crate) struct CarWriter {
+    file: BufWriter<tokio::fs::File>,
+    data_offset: u64,
+    written: u64,
+    header_len: u64,
+}
+
+impl CarWriter {
+    // The root is only known once every node is written, so a header for a
+    // placeholder root of the same length is written first and patched at the end.
+    pub(crate) async fn create(path: &Path, version: CarVersion) -> Result<Self> {
+        let file = tokio::fs::File::create(path)
+            .await
+            .with_context(|| format!("Failed to create CAR file {}", path.display()))?;
+        let mut writer = Self {
+            file: BufWriter::new(file),
+            data_offset: 0,
+            written: 0,
+            header_len: 0,
+        };
+        if version == CarVersion::V2 {
+            writer.data_offset = CARV2_PRAGMA.len() as u64 + CARV2_HEADER_LEN;
+            writer.file.write_all(&vec![0; writer.data_offset as usize]).await?;
+        }
+        let header = header_bytes(cid_for(DAG_CBOR, &[])?)?;
+        writer.header_len = header.len() as u64;
+        writer.file.write_all(&header).await?;
+        Ok(writer)
+    }
+
+    async fn put_raw(&mut self, codec: u64, data: &[u8]) -> Result<Link> {
+        let cid = cid_for(codec, data)?;
+        let cid_bytes = cid.to_bytes();
+        let mut section = Vec::new();
+        write_varint((cid_bytes.len() + data.len()) as u64, &mut section);
+        section.extend(cid_bytes);
+        section.extend_from_slice(data);
+        self.file.write_all(&section).await?;
+        self.written += section.len() as u64;
+        Ok(Link(cid))
+    }
+
+    pub(crate) async fn put_leaf<T: Serialize>(&mut self, value: &T) -> Result<Link> {
+        self.put_raw(RAW, &bincode::serialize(value)?).await
+    }
+
+    pub(crate) async fn put_node<T: Serialize>(&mut self, value: &T) -> Result<Link> {
+        self.put_raw(DAG_CBOR, &serde_cbor::to_vec(value)?).await
+    }
+
+    pub(crate) async fn put_pages(&mut self, links: Vec<Link>) -> Result<Vec<Link>> {
+        let mut pages = Vec::new();
+        for chunk in links.chunks(LINKS_PER_PAGE) {
+            pages.push(self.put_node(&Page { links: chunk.to_vec() }).await?);
+        }
+        Ok(pages)
+    }
+
+    pub(crate) async fn finish(mut self, root: Cid, version: CarVersion) -> Result<()> {
+        let header = header_bytes(root)?;
+        debug_assert_eq!(header.len() as u64, self.header_len);
+        self.file.seek(SeekFrom::Start(self.data_offset)).await?;
+        self.file.write_all(&header).await?;
+        if version == CarVersion::V2 {
+            let mut prefix = CARV2_PRAGMA.to_vec();
+            prefix.extend([0; 16]);
+            prefix.extend(self.data_offset.to_le_bytes());
+            prefix.extend((self.header_len + self.written).to_le_bytes());
+            prefix.extend(0u64.to_le_bytes());
+            self.file.seek(SeekFrom::Start(0)).await?;
+            self.file.write_all(&prefix).await?;
+        }
+        self.file.flush().await?;
+        Ok(())
+    }
+
+    /// Writes a slot node for `block`. Returns its link and the links of its transactions.
+    pub(crate) async fn put_slot(
+        &mut self,
+        block: &CompressedBlock,
+        transactions: &[CompressedTransaction],
+        token_transfers: &[TokenTransfer],
+    ) -> Result<(Link, Vec<Link>)> {
+        let mut transaction_links = Vec::with_capacity(transactions.len());
+        for transaction in transactions {
+            transaction_links.push(self.put_leaf(transaction).await?);
+        }
+        let mut transfer_links = Vec::with_capacity(token_transfers.len());
+        for transfer in token_transfers {
+            transfer_links.push(self.put_leaf(transfer).await?);
+        }
+        let node = SlotNode {
+            slot: block.slot,
+            block: self.put_leaf(block).await?,
+            transactions: transaction_links.clone(),
+            token_transfers: transfer_links,
+        };
+        Ok((self.put_node(&node).await?, transaction_links))
+    }
+
+    /// Writes a node for each block in `slots`, with its transactions and token transfers, and
+    /// adds the keys those transactions reference to `addresses` if given. Returns the nodes'
+    /// links in slot order.
+    pub(crate) async fn put_slots(
+        &mut self,
+        db: &dyn Database,
+        slots: RangeInclusive<u64>,
+        mut addresses: Option<&mut BTreeSet<Vec<u8>>>,
+    ) -> Result<Vec<Link>> {
+        let mut slot_links = Vec::new();
+        let mut start = *slots.start();
+        while start <= *slots.end() {
+            let end = start.saturating_add(SLOTS_PER_READ - 1).min(*slots.end());
+            for block in db.get_blocks_in_range(start, end).await? {
+                let transactions = db.get_transactions_in_block(block.slot).await?;
+                if let Some(addresses) = addresses.as_deref_mut() {
+                    for transaction in &transactions {
+                        addresses.extend(transaction.accounts.iter().cloned());
+                    }
+                }
+                let token_transfers = db.get_token_transfers_in_block(block.slot).await?;
+                slot_links.push(self.put_slot(&block, &transactions, &token_transfers).await?.0);
+            }
+            match end.checked_add(1) {
+                Some(next) => start = next,
+                None => break,
+            }
+        }
+        Ok(slot_links)
+    }
+}
+
+/// Writes the blocks in `slots` with their transactions, and the state as of the
+/// range's last slot of every account those transactions reference, as a CAR file.
+/// Returns the manifest CID, which is also the file's only root.
+pub async fn export_car(
+    db: &dyn Database,
+    slots: RangeInclusive<u64>,
+    path: impl AsRef<Path>,
+    version: CarVersion,
+) -> Result<Cid> {
+    let mut writer = CarWriter::create(path.as_ref(), version).await?;
+    let mut addresses = BTreeSet::new();
+    let slot_links = writer.put_slots(db, slots.clone(), Some(&mut addresses)).await?;
+
+    let mut account_links = Vec::new();
+    for address in addresses {
+        // Referenced keys without a stored account (e.g. programs) are skipped.
+        match db.get_account_at_slot(&address, *slots.end()).await {
+            Ok(Some(account)) => account_links.push(writer.put_leaf(&account).await?),
+            Ok(None) | Err(StorageError::NotFound(_)) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let manifest = CarManifest {
+        blocks: writer.put_pages(slot_links).await?,
+        version: MANIFEST_VERSION,
+        accounts: writer.put_pages(account_links).await?,
+        end_slot: *slots.end(),
+        start_slot: *slots.start(),
+    };
+    let root = writer.put_node(&manifest).await?.0;
+    writer.finish(root, version).await?;
+    Ok(root)
+}
+
+pub async fn export_epoch(db: &dyn Database, epoch: u64, path: impl AsRef<Path>, version: CarVersion) -> Result<Cid> {
+    export_car(db, epoch_slots(epoch), path, version).await
+}
+
+/// Offline reader for archives written by `export_car`. Opening indexes the
+/// sections; every read checks the data against its CID.
+pub struct CarArchive {
+    file: BufReader<File>,
+    root: Cid,
+    sections: HashMap<Cid, (u64, usize)>,
+}
+
+impl CarArchive {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Failed to open CAR file {}", path.display()))?;
+        let file_len = file.metadata()?.len();
+        let mut file = BufReader::new(file);
+
+        let mut pragma = [0u8; CARV2_PRAGMA.len()];
+        let is_v2 = file.read_exact(&mut pragma).is_ok() && pragma == CARV2_PRAGMA;
+        let end = if is_v2 {
+            let mut header = [0u8; CARV2_HEADER_LEN as usize];
+            file.read_exact(&mut header)?;
+            let data_offset = u64::from_le_bytes(header[16..24].try_into()?);
+            let data_size = u64::from_le_bytes(header[24..32].try_into()?);
+            let end = data_offset
+                .checked_add(data_size)
+                .filter(|end| *end <= file_len)
+                .ok_or_else(|| anyhow!("CARv2 payload runs past the end of {}", path.display()))?;
+            file.seek(SeekFrom::Start(data_offset))?;
+            Some(end)
+        } else {
+            file.seek(SeekFrom::Start(0))?;
+            None
+        };
+
+        let header_len = read_varint(&mut file)?.ok_or_else(|| anyhow!("Empty CAR file {}", path.display()))?;
+        if header_len > MAX_HEADER_LEN {
+            bail!("CAR header of {} bytes is too large", header_len);
+        }
+        let mut header = vec![0u8; header_len as usize];
+        file.read_exact(&mut header)?;
+        let header: CarHeader = serde_cbor::from_slice(&header).context("Invalid CAR header")?;
+        if header.version != 1 {
+            bail!("Unsupported CAR payload version {}", header.version);
+        }
+        let root = match header.roots.as_slice() {
+            [root] => root.0,
+            roots => bail!("Expected a single CAR root, found {}", roots.len()),
+        };
+
+        let end = end.unwrap_or(file_len);
+        let mut sections = HashMap::new();
+        loop {
+            let position = file.stream_position()?;
+            if position >= end {
+                break;
+            }
+            let Some(len) = read_varint(&mut file)? else {
+                break;
+            };
+            if len > MAX_SECTION_LEN {
+                bail!("CAR section of {} bytes at offset {} is too large", len, position);
+            }
+            let cid = Cid::read_bytes(&mut file)?;
+            let offset = file.stream_position()?;
+            let data_len = (len as usize)
+                .checked_sub(cid.encoded_len())
+                .filter(|data_len| offset + *data_len as u64 <= end)
+                .ok_or_else(|| anyhow!("Malformed CAR section at offset {}", position))?;
+            sections.insert(cid, (offset, data_len));
+            file.seek_relative(data_len as i64)?;
+        }
+        Ok(Self { file, root, sections })
+    }
+
+    pub fn root(&self) -> Cid {
+        self.root
+    }
+
+    pub fn get(&mut self, cid: &Cid) -> Result<Vec<u8>> {
+        let (offset, len) = *self.sections.get(cid).ok_or_else(|| anyhow!("Block {} is not in the archive", cid))?;
+        let mut data = vec![0u8; len];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut data)?;
+        if cid_for(cid.codec(), &data)? != *cid {
+            bail!("Block {} does not match its CID", cid);
+        }
+        Ok(data)
+    }
+
+    pub(crate) fn node<T: DeserializeOwned>(&mut self, link: &Link) -> Result<T> {
+        Ok(serde_cbor::from_slice(&self.get(&link.0)?)?)
+    }
+
+    pub(crate) fn leaf<T: DeserializeOwned>(&mut self, link: &Link) -> Result<T> {
+        Ok(bincode::deserialize(&self.get(&link.0)?)?)
+    }
+
+    pub(crate) fn paged(&mut self, pages: &[Link]) -> Result<Vec<Link>> {
+        let mut links = Vec::new();
+        for page in pages {
+            links.extend(self.node::<Page>(page)?.links);
+        }
+        Ok(links)
+    }
+
+    pub fn manifest(&mut self) -> Result<CarManifest> {
+        let root = Link(self.root);
+        self.node(&root)
+    }
+
+    pub fn slots(&mut self) -> Result<Vec<ArchivedSlot>> {
+        let manifest = self.manifest()?;
+        self.paged(&manifest.blocks)?.iter().map(|link| self.slot(link)).collect()
+    }
+
+    pub(crate) fn slot(&mut self, link: &Link) -> Result<ArchivedSlot> {
+        let node: SlotNode = self.node(link)?;
+        let transactions = node
+            .transactions
+            .iter()
+            .map(|link| self.leaf(link))
+            .collect::<Result<_>>()?;
+        let token_transfers = node
+            .token_transfers
+            .iter()
+            .map(|link| self.leaf(link))
+            .collect::<Result<_>>()?;
+        Ok(ArchivedSlot {
+            block: self.leaf(&node.block)?,
+            transactions,
+            token_transfers,
+        })
+    }
+
+    pub fn accounts(&mut self) -> Result<Vec<CompressedAccount>> {
+        let manifest = self.manifest()?;
+        self.paged(&manifest.accounts)?.iter().map(|link| self.leaf(link)).collect()
+    }
+}
+
+/// Loads an archive into `db`. Rows are inserted without touching the cursor,
+/// so older ranges can be imported into a node that is already indexing. Token
+/// transfers are only written with a block batch, so they are not imported.
+pub async fn import_car(db: &dyn Database, path: impl AsRef<Path>) -> Result<CarManifest> {
+    let mut archive = CarArchive::open(path)?;
+    let manifest = archive.manifest()?;
+    for slot in archive.slots()? {
+        db.insert_compressed_block(&slot.block).await?;
+        for transaction in &slot.transactions {
+            db.insert_compressed_transaction(transaction).await?;
+        }
+    }
+    for account in archive.accounts()? {
+        db.insert_compressed_account(&account).await?;
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{BlockBatch, InMemoryStorage};
+
+    #[tokio::test]
+    async fn test_car_round_trip() {
+        let source = InMemoryStorage::new();
+        for slot in 10..13 {
+            let mut batch = BlockBatch::new(slot);
+            batch.block = Some(CompressedBlock {
+                slot,
+                blockhash: format!("hash-{}", slot),
+                previous_blockhash: format!("hash-{}", slot - 1),
+                parent_slot: slot - 1,
+                transactions: 1,
+                block_time: Some(1_700_000_000),
+                data: vec![slot as u8; 8],
+                proof: vec![],
+            });
+            batch.transactions.push(CompressedTransaction {
+                signature: vec![slot as u8; 64],
+                slot,
+                index: 0,
+                accounts: vec![vec![1; 32], vec![2; 32]],
+                fee: 5000,
+                programs: Vec::new(),
+                data: vec![],
+                proof: vec![],
+            });
+            source.commit_block(&batch).await.unwrap();
+        }
+        source
+            .insert_compressed_account(&CompressedAccount {
+                pubkey: vec![1; 32],
+                lamports: 42,
+                owner: vec![3; 32],
+                executable: false,
+                rent_epoch: 0,
+                slot: 0,
+                write_version: 0,
+                data: vec![],
+                proof: vec![],
+            })
+            .await
+            .unwrap();
+        // Written after the exported range, so the archive keeps the earlier state.
+        let mut later = source.get_compressed_account(&[1; 32]).await.unwrap();
+        (later.slot, later.lamports) = (20, 99);
+        source.insert_compressed_account(&later).await.unwrap();
+
+        for version in [CarVersion::V1, CarVersion::V2] {
+            let path = std::env::temp_dir().join(format!("windexer-test-{:?}-{}.car", version, std::process::id()));
+            let root = export_car(&source, 11..=12, &path, version).await.unwrap();
+
+            let mut archive = CarArchive::open(&path).unwrap();
+            assert_eq!(archive.root(), root);
+            assert_eq!(archive.manifest().unwrap().start_slot, 11);
+
+            let target = InMemoryStorage::new();
+            import_car(&target, &path).await.unwrap();
+            assert_eq!(target.block_count(), 2);
+            assert_eq!(target.get_compressed_block(12).await.unwrap().data, vec![12; 8]);
+            assert_eq!(target.get_compressed_transaction(&[11; 64]).await.unwrap().fee, 5000);
+            assert_eq!(target.get_compressed_account(&[1; 32]).await.unwrap().lamports, 42);
+            assert_eq!(target.get_last_processed_slot().await.unwrap(), 0);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_malformed_car() {
+        let path = std::env::temp_dir().join(format!("windexer-test-malformed-{}.car", std::process::id()));
+        let header = header_bytes(cid_for(DAG_CBOR, &[]).unwrap()).unwrap();
+        let cid = cid_for(RAW, b"leaf").unwrap().to_bytes();
+
+        // A header length far beyond the file, then a section shorter than its own CID.
+        let mut oversized = Vec::new();
+        write_varint(u64::MAX >> 1, &mut oversized);
+        let mut short = header.clone();
+        write_varint(cid.len() as u64 - 1, &mut short);
+        short.extend(&cid);
+        let mut truncated = header;
+        write_varint(cid.len() as u64 + 100, &mut truncated);
+        truncated.extend(&cid);
+        truncated.extend(b"leaf");
+
+        for bytes in [oversized, short, truncated] {
+            std::fs::write(&path, bytes).unwrap();
+            assert!(CarArchive::open(&path).is_err());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,770 @@
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use cid::Cid;
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use crate::storage::car::{CarWriter, SlotNode, MANIFEST_VERSION, SLOTS_PER_EPOCH};
+use crate::storage::database::{cursor_moved_back, signature_bounds};
+use crate::storage::error::{Result, StorageError};
+use crate::storage::{
+    BlockBatch, CarArchive, CarManifest, CarVersion, CompressedAccount, CompressedBlock, CompressedTransaction, Database,
+    Link, PruneRequest, PruneStats, SignatureInfo, SignatureQuery, TokenTransfer, VOTE_PROGRAM_ID,
+};
+
+const OPEN_ARCHIVE: &str = "open.car";
+const REWRITTEN_ARCHIVE: &str = "rewrite.car";
+
+// (slot, index within block, signature)
+type Positions = Vec<(u64, u32, Vec<u8>)>;
+
+/// Blocks and transactions kept as a directory of CAR files, for use as a cold tier.
+///
+/// Rows are appended, in slot order, to an open archive that moving the cursor seals under
+/// the range of slots it covers. The cursor is the end of the newest sealed archive, so it
+/// needs no file of its own; an archive still open when the process stopped is discarded and
+/// its slots are written again. Sealed archives are ordinary `export_car` archives without
+/// accounts, each with an index next to it that is rebuilt when missing. Accounts are not
+/// archived: they stay in the hot tier.
+pub struct CarStorage {
+    dir: PathBuf,
+    // By first slot
+    archives: RwLock<BTreeMap<u64, Arc<SealedArchive>>>,
+    writing: tokio::sync::Mutex<Writing>,
+}
+
+struct Writing {
+    cursor: u64,
+    open: Option<OpenArchive>,
+}
+
+/// What an archive holds, so lookups don't read it.
+#[derive(Default, Serialize, Deserialize)]
+struct ArchiveIndex {
+    root: Vec<u8>,
+    start_slot: u64,
+    end_slot: u64,
+    /// Slot node CIDs.
+    slots: BTreeMap<u64, Vec<u8>>,
+    /// Leaf CID and position of each transaction, by signature.
+    transactions: HashMap<Vec<u8>, (Vec<u8>, u64, u32)>,
+    /// Positions and signatures of the transactions referencing each address, oldest first.
+    addresses: HashMap<Vec<u8>, Positions>,
+}
+
+impl ArchiveIndex {
+    fn add_slot(&mut self, slot: u64, link: &Link, transactions: &[CompressedTransaction], links: &[Link]) {
+        self.slots.insert(slot, link.0.to_bytes());
+        for (transaction, link) in transactions.iter().zip(links) {
+            let position = (transaction.slot, transaction.index);
+            self.transactions
+                .insert(transaction.signature.clone(), (link.0.to_bytes(), position.0, position.1));
+            for address in &transaction.accounts {
+                let positions = self.addresses.entry(address.clone()).or_default();
+                if positions.last().map_or(true, |(slot, index, _)| (*slot, *index) != position) {
+                    positions.push((position.0, position.1, transaction.signature.clone()));
+                }
+            }
+        }
+    }
+
+    /// Slots and transactions `request` deletes. A block is only deleted with all of its
+    /// transactions, since a slot node can't be written without its block.
+    fn expired(&self, request: &PruneRequest) -> (BTreeSet<u64>, HashSet<Vec<u8>>) {
+        let positions = |address: &[u8]| {
+            self.addresses
+                .get(address)
+                .into_iter()
+                .flatten()
+                .map(|(slot, index, _)| (*slot, *index))
+        };
+        let votes: HashSet<(u64, u32)> = positions(&VOTE_PROGRAM_ID[..]).collect();
+        let protected: HashSet<(u64, u32)> = request
+            .protected_addresses
+            .iter()
+            .flat_map(|address| positions(address))
+            .collect();
+
+        let mut remaining: BTreeMap<u64, usize> = BTreeMap::new();
+        let mut transactions = HashSet::new();
+        for (signature, (_, slot, index)) in &self.transactions {
+            let position = (*slot, *index);
+            let expired = request
+                .transaction_cutoff(votes.contains(&position))
+                .is_some_and(|before| *slot < before)
+                && !protected.contains(&position);
+            if expired {
+                transactions.insert(signature.clone());
+            } else {
+                *remaining.entry(*slot).or_default() += 1;
+            }
+        }
+        let slots = match request.blocks_before {
+            Some(before) => self
+                .slots
+                .range(..before)
+                .map(|(slot, _)| *slot)
+                .filter(|slot| !remaining.contains_key(slot))
+                .collect(),
+            None => BTreeSet::new(),
+        };
+        (slots, transactions)
+    }
+}
+
+struct SealedArchive {
+    index: ArchiveIndex,
+    reader: Mutex<CarArchive>,
+}
+
+impl SealedArchive {
+    fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut reader = CarArchive::open(path)?;
+        let index_path = path.with_extension("idx");
+        let stored = std::fs::read(&index_path)
+            .ok()
+            .and_then(|bytes| bincode::deserialize::<ArchiveIndex>(&bytes).ok())
+            .filter(|index| index.root == reader.root().to_bytes());
+        let index = match stored {
+            Some(index) => index,
+            None => {
+                let index = Self::build_index(&mut reader)?;
+                write_index(&index_path, &index);
+                index
+            }
+        };
+        Ok(Self {
+            index,
+            reader: Mutex::new(reader),
+        })
+    }
+
+    fn build_index(reader: &mut CarArchive) -> anyhow::Result<ArchiveIndex> {
+        let manifest = reader.manifest()?;
+        let mut index = ArchiveIndex {
+            root: reader.root().to_bytes(),
+            start_slot: manifest.start_slot,
+            end_slot: manifest.end_slot,
+            ..Default::default()
+        };
+        for link in reader.paged(&manifest.blocks)? {
+            let node: SlotNode = reader.node(&link)?;
+            let transactions = node
+                .transactions
+                .iter()
+                .map(|link| reader.leaf(link))
+                .collect::<anyhow::Result<Vec<CompressedTransaction>>>()?;
+            index.add_slot(node.slot, &link, &transactions, &node.transactions);
+        }
+        Ok(index)
+    }
+
+    fn get<T: DeserializeOwned>(&self, cid: &[u8]) -> anyhow::Result<T> {
+        self.reader.lock().unwrap().leaf(&link(cid)?)
+    }
+
+    fn slot_node(&self, slot: u64) -> anyhow::Result<Option<SlotNode>> {
+        match self.index.slots.get(&slot) {
+            Some(cid) => Ok(Some(self.reader.lock().unwrap().node(&link(cid)?)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+// The rows of one slot, held until the next slot starts so its node is written once.
+#[derive(Default)]
+struct OpenSlot {
+    block: Option<CompressedBlock>,
+    transactions: BTreeMap<u32, CompressedTransaction>,
+    token_transfers: Vec<TokenTransfer>,
+}
+
+struct OpenArchive {
+    writer: CarWriter,
+    index: ArchiveIndex,
+    slot_links: Vec<Link>,
+    current: Option<(u64, OpenSlot)>,
+}
+
+impl OpenArchive {
+    async fn create(path: &Path, start_slot: u64) -> anyhow::Result<Self> {
+        Ok(Self {
+            writer: CarWriter::create(path, CarVersion::V1).await?,
+            index: ArchiveIndex {
+                start_slot,
+                ..Default::default()
+            },
+            slot_links: Vec::new(),
+            current: None,
+        })
+    }
+
+    async fn rows(&mut self, slot: u64) -> anyhow::Result<&mut OpenSlot> {
+        let latest = self
+            .current
+            .as_ref()
+            .map(|(slot, _)| *slot)
+            .or_else(|| self.index.slots.keys().next_back().copied());
+        match latest {
+            Some(latest) if slot < latest => bail!("Slot {} arrived after slot {}, CAR archives are written in slot order", slot, latest),
+            Some(latest) if slot > latest => self.flush().await?,
+            _ => {}
+        }
+        Ok(&mut self.current.get_or_insert_with(|| (slot, OpenSlot::default())).1)
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        let Some((slot, rows)) = self.current.take() else {
+            return Ok(());
+        };
+        let Some(block) = rows.block else {
+            if rows.transactions.is_empty() && rows.token_transfers.is_empty() {
+                return Ok(());
+            }
+            bail!("Slot {} has transactions but no block", slot);
+        };
+        let transactions: Vec<CompressedTransaction> = rows.transactions.into_values().collect();
+        let (link, transaction_links) = self.writer.put_slot(&block, &transactions, &rows.token_transfers).await?;
+        self.index.add_slot(slot, &link, &transactions, &transaction_links);
+        self.slot_links.push(link);
+        Ok(())
+    }
+
+    /// Writes the manifest for `start_slot..=end_slot` and closes the file.
+    async fn finish(mut self, end_slot: u64) -> anyhow::Result<ArchiveIndex> {
+        if let Some((slot, _)) = &self.current {
+            if *slot > end_slot {
+                bail!("Cannot seal the CAR archive at slot {} with rows for slot {}", end_slot, slot);
+            }
+        }
+        self.flush().await?;
+        let manifest = CarManifest {
+            blocks: self.writer.put_pages(self.slot_links).await?,
+            version: MANIFEST_VERSION,
+            accounts: Vec::new(),
+            end_slot,
+            start_slot: self.index.start_slot,
+        };
+        let root = self.writer.put_node(&manifest).await?.0;
+        self.writer.finish(root, CarVersion::V1).await?;
+        self.index.root = root.to_bytes();
+        self.index.end_slot = end_slot;
+        Ok(self.index)
+    }
+}
+
+fn link(cid: &[u8]) -> anyhow::Result<Link> {
+    Ok(Link(Cid::try_from(cid)?))
+}
+
+fn archive_name(start_slot: u64, end_slot: u64) -> String {
+    format!("{:020}-{:020}.car", start_slot, end_slot)
+}
+
+// The index is rebuilt from the archive when missing, so failing to write it is not fatal.
+fn write_index(path: &Path, index: &ArchiveIndex) {
+    let written = bincode::serialize(index)
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| Ok(std::fs::write(path, bytes)?));
+    if let Err(e) = written {
+        warn!("Could not write CAR archive index {}: {:#}", path.display(), e);
+    }
+}
+
+fn no_accounts() -> StorageError {
+    StorageError::Other(anyhow!("CAR storage archives blocks and transactions only, accounts stay in the hot tier"))
+}
+
+impl CarStorage {
+    pub fn open(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create CAR storage directory {}", dir.display()))?;
+        for unsealed in [OPEN_ARCHIVE, REWRITTEN_ARCHIVE] {
+            match std::fs::remove_file(dir.join(unsealed)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "car") {
+                paths.push(path);
+            }
+        }
+        // Names are zero-padded slot ranges, so they sort in slot order.
+        paths.sort();
+
+        let mut archives = BTreeMap::new();
+        let mut cursor = 0;
+        for path in paths {
+            let archive = SealedArchive::open(&path).with_context(|| format!("Failed to open CAR archive {}", path.display()))?;
+            cursor = cursor.max(archive.index.end_slot);
+            archives.insert(archive.index.start_slot, Arc::new(archive));
+        }
+        Ok(Self {
+            dir,
+            archives: RwLock::new(archives),
+            writing: tokio::sync::Mutex::new(Writing { cursor, open: None }),
+        })
+    }
+
+    fn archive_for(&self, slot: u64) -> Option<Arc<SealedArchive>> {
+        let archives = self.archives.read().unwrap();
+        archives
+            .range(..=slot)
+            .next_back()
+            .map(|(_, archive)| archive.clone())
+            .filter(|archive| archive.index.end_slot >= slot)
+    }
+
+    fn archives_in(&self, start_slot: u64, end_slot: u64) -> Vec<Arc<SealedArchive>> {
+        let archives = self.archives.read().unwrap();
+        archives
+            .range(..=end_slot)
+            .map(|(_, archive)| archive.clone())
+            .filter(|archive| archive.index.end_slot >= start_slot)
+            .collect()
+    }
+
+    /// Adds rows for `slot` to the open archive. A sealed slot takes no new rows, so writing
+    /// one it holds, which `archived` reports, is a no-op and anything else an error.
+    async fn append(
+        &self,
+        slot: u64,
+        archived: impl FnOnce(&ArchiveIndex) -> bool,
+        add: impl FnOnce(&mut OpenSlot),
+    ) -> Result<()> {
+        let mut writing = self.writing.lock().await;
+        if slot <= writing.cursor {
+            return match self.archive_for(slot) {
+                Some(archive) if archived(&archive.index) => Ok(()),
+                _ => Err(StorageError::Other(anyhow!("Slot {} is sealed in a CAR archive", slot))),
+            };
+        }
+        if writing.open.is_none() {
+            writing.open = Some(OpenArchive::create(&self.dir.join(OPEN_ARCHIVE), writing.cursor + 1).await?);
+        }
+        if let Some(open) = &mut writing.open {
+            add(open.rows(slot).await?);
+        }
+        Ok(())
+    }
+
+    /// Seals the open archive, or an empty one, as `cursor + 1..=end_slot`.
+    async fn seal(&self, writing: &mut Writing, end_slot: u64) -> anyhow::Result<()> {
+        let open = match writing.open.take() {
+            Some(open) => open,
+            None => OpenArchive::create(&self.dir.join(OPEN_ARCHIVE), writing.cursor + 1).await?,
+        };
+        let index = open.finish(end_slot).await?;
+        let path = self.dir.join(archive_name(index.start_slot, end_slot));
+        tokio::fs::rename(self.dir.join(OPEN_ARCHIVE), &path).await?;
+        write_index(&path.with_extension("idx"), &index);
+        let archive = SealedArchive {
+            reader: Mutex::new(CarArchive::open(&path)?),
+            index,
+        };
+        self.archives
+            .write()
+            .unwrap()
+            .insert(archive.index.start_slot, Arc::new(archive));
+        writing.cursor = end_slot;
+        Ok(())
+    }
+
+    /// Writes `archive` again without `slots` and `transactions`, and with it the token
+    /// transfers of those transactions. Returns what was left out.
+    async fn rewrite(
+        &self,
+        archive: &SealedArchive,
+        slots: &BTreeSet<u64>,
+        transactions: &HashSet<Vec<u8>>,
+    ) -> anyhow::Result<PruneStats> {
+        let (start_slot, end_slot) = (archive.index.start_slot, archive.index.end_slot);
+        let rewritten = self.dir.join(REWRITTEN_ARCHIVE);
+        let mut open = OpenArchive::create(&rewritten, start_slot).await?;
+        let mut stats = PruneStats::default();
+        for (slot, cid) in &archive.index.slots {
+            let mut rows = archive.reader.lock().unwrap().slot(&link(cid)?)?;
+            rows.transactions.retain(|transaction| {
+                let expired = transactions.contains(&transaction.signature);
+                if expired {
+                    stats
+                        .transactions
+                        .add((transaction.data.len() + transaction.proof.len()) as u64);
+                }
+                !expired
+            });
+            rows.token_transfers
+                .retain(|transfer| !transactions.contains(&transfer.signature));
+            if slots.contains(slot) {
+                stats.blocks.add((rows.block.data.len() + rows.block.proof.len()) as u64);
+                continue;
+            }
+            let slot_rows = open.rows(*slot).await?;
+            slot_rows.transactions = rows
+                .transactions
+                .into_iter()
+                .map(|transaction| (transaction.index, transaction))
+                .collect();
+            slot_rows.token_transfers = rows.token_transfers;
+            slot_rows.block = Some(rows.block);
+        }
+        let index = open.finish(end_slot).await?;
+        let path = self.dir.join(archive_name(start_slot, end_slot));
+        tokio::fs::rename(&rewritten, &path).await?;
+        write_index(&path.with_extension("idx"), &index);
+        let archive = SealedArchive {
+            reader: Mutex::new(CarArchive::open(&path)?),
+            index,
+        };
+        self.archives.write().unwrap().insert(start_slot, Arc::new(archive));
+        Ok(stats)
+    }
+}
+
+#[async_trait]
+impl Database for CarStorage {
+    async fn insert_compressed_account(&self, _account: &CompressedAccount) -> Result<()> {
+        Err(no_accounts())
+    }
+
+    async fn get_compressed_account(&self, pubkey: &[u8]) -> Result<CompressedAccount> {
+        Err(StorageError::not_found(format!("compressed account {}", hex::encode(pubkey))))
+    }
+
+    async fn insert_compressed_block(&self, block: &CompressedBlock) -> Result<()> {
+        self.append(
+            block.slot,
+            |index| index.slots.contains_key(&block.slot),
+            |rows| rows.block = Some(block.clone()),
+        )
+        .await
+    }
+
+    async fn get_compressed_block(&self, slot: u64) -> Result<CompressedBlock> {
+        let node = match self.archive_for(slot) {
+            Some(archive) => archive
+                .slot_node(slot)?
+                .map(|node| archive.reader.lock().unwrap().leaf(&node.block))
+                .transpose()?,
+            None => None,
+        };
+        node.ok_or_else(|| StorageError::not_found(format!("compressed block {}", slot)))
+    }
+
+    async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> Result<()> {
+        self.append(
+            transaction.slot,
+            |index| index.transactions.contains_key(&transaction.signature),
+            |rows| {
+                rows.transactions.insert(transaction.index, transaction.clone());
+            },
+        )
+        .await
+    }
+
+    async fn get_compressed_transaction(&self, signature: &[u8]) -> Result<CompressedTransaction> {
+        let archives: Vec<Arc<SealedArchive>> = self.archives.read().unwrap().values().rev().cloned().collect();
+        for archive in archives {
+            if let Some((cid, _, _)) = archive.index.transactions.get(signature) {
+                return Ok(archive.get(cid)?);
+            }
+        }
+        Err(StorageError::not_found(format!("compressed transaction {}", hex::encode(signature))))
+    }
+
+    async fn get_accounts_by_owner(&self, _owner: &[u8], _limit: usize) -> Result<Vec<CompressedAccount>> {
+        Ok(Vec::new())
+    }
+
+    async fn get_signatures_for_address(&self, address: &[u8], query: &SignatureQuery) -> Result<Vec<SignatureInfo>> {
+        let (before, until) = signature_bounds(self, query).await?;
+        let archives: Vec<Arc<SealedArchive>> = self.archives.read().unwrap().values().rev().cloned().collect();
+        let mut signatures = Vec::new();
+        for archive in archives {
+            if signatures.len() >= query.limit {
+                break;
+            }
+            let Some(positions) = archive.index.addresses.get(address) else {
+                continue;
+            };
+            let remaining = query.limit - signatures.len();
+            signatures.extend(
+                positions
+                    .iter()
+                    .rev()
+                    .filter(|(slot, index, _)| {
+                        before.map_or(true, |before| (*slot, *index) < before)
+                            && until.map_or(true, |until| (*slot, *index) > until)
+                    })
+                    .take(remaining)
+                    .map(|(slot, index, signature)| SignatureInfo {
+                        signature: signature.clone(),
+                        slot: *slot,
+                        index: *index,
+                    }),
+            );
+        }
+        Ok(signatures)
+    }
+
+    async fn get_blocks_in_range(&self, start_slot: u64, end_slot: u64) -> Result<Vec<CompressedBlock>> {
+        let mut blocks = Vec::new();
+        for archive in self.archives_in(start_slot, end_slot) {
+            for cid in archive.index.slots.range(start_slot..=end_slot).map(|(_, cid)| cid) {
+                let mut reader = archive.reader.lock().unwrap();
+                let node: SlotNode = reader.node(&link(cid)?)?;
+                blocks.push(reader.leaf(&node.block)?);
+            }
+        }
+        Ok(blocks)
+    }
+
+    async fn get_first_block_slot(&self) -> Result<Option<u64>> {
+        let archives = self.archives.read().unwrap();
+        Ok(archives.values().find_map(|archive| archive.index.slots.keys().next().copied()))
+    }
+
+    async fn get_transactions_in_block(&self, slot: u64) -> Result<Vec<CompressedTransaction>> {
+        let Some(archive) = self.archive_for(slot) else {
+            return Ok(Vec::new());
+        };
+        let Some(node) = archive.slot_node(slot)? else {
+            return Ok(Vec::new());
+        };
+        let mut reader = archive.reader.lock().unwrap();
+        Ok(node
+            .transactions
+            .iter()
+            .map(|link| reader.leaf(link))
+            .collect::<anyhow::Result<_>>()?)
+    }
+
+    fn stores_token_transfers(&self) -> bool {
+        true
+    }
+
+    async fn get_token_transfers_in_block(&self, slot: u64) -> Result<Vec<TokenTransfer>> {
+        let Some(archive) = self.archive_for(slot) else {
+            return Ok(Vec::new());
+        };
+        let Some(node) = archive.slot_node(slot)? else {
+            return Ok(Vec::new());
+        };
+        let mut reader = archive.reader.lock().unwrap();
+        Ok(node
+            .token_transfers
+            .iter()
+            .map(|link| reader.leaf(link))
+            .collect::<anyhow::Result<_>>()?)
+    }
+
+    // Only sealed slots are deleted; each archive they are in is written again without them.
+    // Sealed archives keep the range in their name, so the cursor never moves back.
+    async fn delete_slot_range(&self, start_slot: u64, end_slot: u64) -> Result<u64> {
+        let _writing = self.writing.lock().await;
+        let mut deleted = 0;
+        for archive in self.archives_in(start_slot, end_slot) {
+            let slots: BTreeSet<u64> = archive.index.slots.range(start_slot..=end_slot).map(|(slot, _)| *slot).collect();
+            if slots.is_empty() {
+                continue;
+            }
+            let transactions = archive
+                .index
+                .transactions
+                .iter()
+                .filter(|(_, (_, slot, _))| slots.contains(slot))
+                .map(|(signature, _)| signature.clone())
+                .collect();
+            let stats = self.rewrite(&archive, &slots, &transactions).await?;
+            deleted += stats.blocks.rows + stats.transactions.rows;
+        }
+        Ok(deleted)
+    }
+
+    async fn get_account_at_slot(&self, _pubkey: &[u8], _slot: u64) -> Result<Option<CompressedAccount>> {
+        Ok(None)
+    }
+
+    async fn get_account_versions(
+        &self,
+        _pubkey: &[u8],
+        _start_slot: u64,
+        _end_slot: u64,
+        _limit: usize,
+    ) -> Result<Vec<CompressedAccount>> {
+        Ok(Vec::new())
+    }
+
+    async fn get_account_pubkeys(&self, _after: Option<&[u8]>, _limit: usize) -> Result<Vec<Vec<u8>>> {
+        Ok(Vec::new())
+    }
+
+    async fn get_transaction_signatures(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        let archives = self.archives.read().unwrap();
+        let signatures: BTreeSet<&Vec<u8>> = archives
+            .values()
+            .flat_map(|archive| archive.index.transactions.keys())
+            .filter(|signature| after.map_or(true, |after| signature.as_slice() > after))
+            .collect();
+        Ok(signatures.into_iter().take(limit).cloned().collect())
+    }
+
+    async fn prune(&self, request: &PruneRequest) -> Result<PruneStats> {
+        let _writing = self.writing.lock().await;
+        let archives: Vec<Arc<SealedArchive>> = self.archives.read().unwrap().values().cloned().collect();
+        let mut stats = PruneStats::default();
+        for archive in archives {
+            let (slots, transactions) = archive.index.expired(request);
+            if !slots.is_empty() || !transactions.is_empty() {
+                stats += self.rewrite(&archive, &slots, &transactions).await?;
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn get_last_processed_slot(&self) -> Result<u64> {
+        Ok(self.writing.lock().await.cursor)
+    }
+
+    async fn update_last_processed_slot(&self, slot: u64) -> Result<()> {
+        let mut writing = self.writing.lock().await;
+        if slot < writing.cursor {
+            return Err(cursor_moved_back(writing.cursor, slot));
+        }
+        if slot > writing.cursor {
+            self.seal(&mut writing, slot).await?;
+        }
+        Ok(())
+    }
+
+    /// Appends the batch's rows to the open archive. Sealing each slot would leave a file per
+    /// slot, so the cursor only moves at the end of an epoch or when set explicitly; until
+    /// then a restart writes the open archive's slots again.
+    async fn commit_block(&self, batch: &BlockBatch) -> Result<()> {
+        if !batch.accounts.is_empty() {
+            return Err(no_accounts());
+        }
+        if let Some(block) = &batch.block {
+            self.insert_compressed_block(block).await?;
+        }
+        for transaction in &batch.transactions {
+            self.insert_compressed_transaction(transaction).await?;
+        }
+        if !batch.token_transfers.is_empty() {
+            self.append(
+                batch.slot,
+                |index| index.slots.contains_key(&batch.slot),
+                |rows| rows.token_transfers.extend(batch.token_transfers.iter().cloned()),
+            )
+            .await?;
+        }
+        if (batch.slot + 1) % SLOTS_PER_EPOCH == 0 {
+            self.update_last_processed_slot(batch.slot).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(slot: u64) -> BlockBatch {
+        let mut batch = BlockBatch::new(slot);
+        batch.block = Some(CompressedBlock {
+            slot,
+            blockhash: slot.to_string(),
+            previous_blockhash: (slot - 1).to_string(),
+            parent_slot: slot - 1,
+            transactions: 1,
+            block_time: None,
+            data: vec![slot as u8; 4],
+            proof: vec![],
+        });
+        batch.transactions.push(CompressedTransaction {
+            signature: slot.to_be_bytes().to_vec(),
+            slot,
+            index: 0,
+            accounts: vec![vec![7; 32]],
+            fee: 5000,
+            programs: Vec::new(),
+            data: vec![0; 6],
+            proof: vec![],
+        });
+        batch.token_transfers.push(TokenTransfer {
+            signature: slot.to_be_bytes().to_vec(),
+            slot,
+            mint: vec![8; 32],
+            owner: vec![9; 32],
+            amount: slot,
+        });
+        batch
+    }
+
+    #[tokio::test]
+    async fn test_car_storage() {
+        let dir = std::env::temp_dir().join(format!("windexer-test-car-storage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let storage = CarStorage::open(&dir).unwrap();
+        for slot in 1..=10 {
+            storage.commit_block(&batch(slot)).await.unwrap();
+        }
+        storage.update_last_processed_slot(10).await.unwrap();
+        // Left open when the process stops, so discarded on reopen.
+        storage.commit_block(&batch(11)).await.unwrap();
+        assert!(storage.update_last_processed_slot(9).await.is_err());
+        assert!(storage.insert_compressed_block(&batch(12).block.unwrap()).await.is_ok());
+        assert!(storage.insert_compressed_block(&batch(3).block.unwrap()).await.is_ok());
+        drop(storage);
+
+        let storage = CarStorage::open(&dir).unwrap();
+        assert_eq!(storage.get_last_processed_slot().await.unwrap(), 10);
+        assert_eq!(storage.get_compressed_block(4).await.unwrap().data, vec![4; 4]);
+        assert!(matches!(storage.get_compressed_block(11).await, Err(StorageError::NotFound(_))));
+        assert_eq!(storage.get_compressed_transaction(&7u64.to_be_bytes()).await.unwrap().fee, 5000);
+        assert_eq!(storage.get_token_transfers_in_block(7).await.unwrap()[0].amount, 7);
+        let blocks = storage.get_blocks_in_range(3, 5).await.unwrap();
+        assert_eq!(blocks.iter().map(|block| block.slot).collect::<Vec<_>>(), vec![3, 4, 5]);
+
+        let query = SignatureQuery {
+            before: Some(8u64.to_be_bytes().to_vec()),
+            limit: 3,
+            ..Default::default()
+        };
+        let signatures = storage.get_signatures_for_address(&[7; 32], &query).await.unwrap();
+        assert_eq!(signatures.iter().map(|s| s.slot).collect::<Vec<_>>(), vec![7, 6, 5]);
+
+        // The archive stays a plain export: it can be imported elsewhere.
+        let archive = dir.join(archive_name(1, 10));
+        let target = crate::storage::InMemoryStorage::new();
+        crate::storage::import_car(&target, &archive).await.unwrap();
+        assert_eq!(target.block_count(), 10);
+
+        assert_eq!(storage.delete_slot_range(1, 2).await.unwrap(), 4);
+        let request = PruneRequest {
+            transactions_before: Some(5),
+            ..Default::default()
+        };
+        let stats = storage.prune(&request).await.unwrap();
+        assert_eq!((stats.transactions.rows, stats.transactions.bytes), (2, 12));
+        assert!(storage.get_transactions_in_block(4).await.unwrap().is_empty());
+        assert!(storage.get_token_transfers_in_block(4).await.unwrap().is_empty());
+        assert_eq!(storage.get_transactions_in_block(5).await.unwrap().len(), 1);
+
+        // The rebuilt index matches the rewritten archive.
+        std::fs::remove_file(archive.with_extension("idx")).unwrap();
+        let storage = CarStorage::open(&dir).unwrap();
+        assert!(matches!(storage.get_compressed_block(2).await, Err(StorageError::NotFound(_))));
+        assert_eq!(storage.get_compressed_block(4).await.unwrap().slot, 4);
+        assert_eq!(storage.get_signatures_for_address(&[7; 32], &SignatureQuery::default()).await.unwrap().len(), 6);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
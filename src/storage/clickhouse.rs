@@ -1,126 +1,757 @@
 use async_trait::async_trait;
-use anyhow::Result;
+use anyhow::Context;
 use clickhouse::{Client, Row};
-use crate::storage::{Database, CompressedAccount, CompressedBlock, CompressedTransaction};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use crate::storage::database::{cursor_moved_back, signature_bounds};
+use crate::storage::error::{Result, StorageError};
+use crate::storage::migrations::{embedded, plan, MigrationReport, CLICKHOUSE};
+use crate::storage::{
+    BlockBatch, Database, CompressedAccount, CompressedBlock, CompressedTransaction, PruneRequest, PruneStats,
+    Reclaimed, SignatureInfo, SignatureQuery, TokenTransfer, VOTE_PROGRAM_ID,
+};
 
+const MAX_BUFFERED_ROWS: usize = 50_000;
+const MAX_BUFFER_DELAY: Duration = Duration::from_secs(5);
+const CURSOR_KEY: &str = "last_processed_slot";
+// Transactions that may be pruned, bound as (cutoff, vote cutoff, vote program, protected addresses).
+const EXPIRED_TRANSACTIONS: &str = "(slot < ? OR (slot < ? AND has(accounts, unhex(?)))) \
+     AND NOT hasAny(accounts, arrayMap(x -> unhex(x), ?))";
+// Aggregate tables the materialized views add each inserted row to.
+const HOURLY_TABLES: &[&str] = &["program_transactions_hourly", "fees_hourly", "token_transfer_volume_hourly"];
+// Superseded account versions, bound as (cutoff, protected addresses).
+const EXPIRED_ACCOUNT_VERSIONS: &str = "slot < ? \
+     AND (pubkey, slot, write_version) NOT IN (SELECT pubkey, slot, write_version FROM compressed_accounts FINAL) \
+     AND NOT hasAny([pubkey, owner], arrayMap(x -> unhex(x), ?))";
+
+#[derive(Row, Serialize, Deserialize)]
+struct AccountRow {
+    #[serde(with = "serde_bytes")]
+    pubkey: Vec<u8>,
+    slot: u64,
+    write_version: u64,
+    lamports: i64,
+    #[serde(with = "serde_bytes")]
+    owner: Vec<u8>,
+    executable: bool,
+    rent_epoch: i64,
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    proof: Vec<u8>,
+}
+
+#[derive(Row, Deserialize)]
+struct PubkeyRow {
+    #[serde(with = "serde_bytes")]
+    pubkey: Vec<u8>,
+}
+
+#[derive(Row, Serialize, Deserialize)]
+struct BlockRow {
+    slot: u64,
+    blockhash: String,
+    previous_blockhash: String,
+    parent_slot: u64,
+    transactions: u64,
+    block_time: u32,
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    proof: Vec<u8>,
+}
+
+#[derive(Row, Serialize, Deserialize)]
+struct TransactionRow {
+    #[serde(with = "serde_bytes")]
+    signature: Vec<u8>,
+    slot: u64,
+    tx_index: u32,
+    block_time: u32,
+    accounts: Vec<ByteBuf>,
+    fee: u64,
+    programs: Vec<ByteBuf>,
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    proof: Vec<u8>,
+}
+
+#[derive(Row, Serialize, Deserialize)]
+struct TokenTransferRow {
+    #[serde(with = "serde_bytes")]
+    signature: Vec<u8>,
+    slot: u64,
+    block_time: u32,
+    #[serde(with = "serde_bytes")]
+    mint: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    owner: Vec<u8>,
+    amount: u64,
+}
+
+#[derive(Row, Deserialize)]
+struct TransactionKeyRow {
+    #[serde(with = "serde_bytes")]
+    signature: Vec<u8>,
+}
+
+#[derive(Row, Serialize, Deserialize)]
+struct SignatureRow {
+    #[serde(with = "serde_bytes")]
+    signature: Vec<u8>,
+    slot: u64,
+    tx_index: u32,
+}
+
+#[derive(Row, Serialize)]
+struct CursorRow<'a> {
+    key: &'a str,
+    value: u64,
+}
+
+/// Rows committed since the last flush, together with the slot the cursor moves to once they are written.
+#[derive(Default)]
+struct Buffer {
+    first_slot: Option<u64>,
+    slot: Option<u64>,
+    started: Option<Instant>,
+    blocks: Vec<BlockRow>,
+    transactions: Vec<TransactionRow>,
+    accounts: Vec<AccountRow>,
+    token_transfers: Vec<TokenTransferRow>,
+}
+
+impl Buffer {
+    fn len(&self) -> usize {
+        self.blocks.len() + self.transactions.len() + self.accounts.len() + self.token_transfers.len()
+    }
+}
+
+/// Analytics backend. Batches from `commit_block` are buffered and written with
+/// one insert per table; they become visible, and the cursor advances, when the
+/// buffer is flushed.
 pub struct ClickHouseStorage {
     client: Client,
+    buffer: Mutex<Buffer>,
+    max_buffered_rows: usize,
+    max_buffer_delay: Duration,
+    // Set while slots past the cursor may already be in the hourly aggregates, which a
+    // replay would add to again.
+    replaying: AtomicBool,
 }
 
 impl ClickHouseStorage {
-    pub fn new(url: &str) -> Result<Self> {
-        let client = Client::default()
-            .with_url(url)
-            .with_database("windexer");
-        Ok(Self { client })
+    pub async fn new(url: &str) -> anyhow::Result<Self> {
+        Self::with_database(url, "windexer").await
     }
-}
 
-#[async_trait]
-impl Database for ClickHouseStorage {
-    async fn insert_compressed_account(&self, account: &CompressedAccount) -> Result<()> {
+    pub async fn with_database(url: &str, database: &str) -> anyhow::Result<Self> {
+        let client = Client::default().with_url(url);
+        migrate_client(&client, database, true).await?;
+        Ok(Self {
+            client: client.with_database(database),
+            buffer: Mutex::new(Buffer::default()),
+            max_buffered_rows: MAX_BUFFERED_ROWS,
+            max_buffer_delay: MAX_BUFFER_DELAY,
+            replaying: AtomicBool::new(true),
+        })
+    }
+
+    /// Applies pending migrations, or with `apply` unset only reports them.
+    pub async fn migrate(url: &str, database: &str, apply: bool) -> anyhow::Result<MigrationReport> {
+        migrate_client(&Client::default().with_url(url), database, apply).await
+    }
+
+    /// A flush happens once either limit is reached; a zero row limit writes every batch immediately.
+    pub fn with_buffer(mut self, max_rows: usize, max_delay: Duration) -> Self {
+        self.max_buffered_rows = max_rows;
+        self.max_buffer_delay = max_delay;
+        self
+    }
+
+    async fn reclaimable(&self, query: clickhouse::query::Query) -> Result<Reclaimed> {
+        let (rows, bytes) = query.fetch_one::<(u64, u64)>().await?;
+        Ok(Reclaimed { rows, bytes })
+    }
+
+    // Deletes are lightweight, so the reclaimed rows are counted beforehand.
+    // Slots that still hold a transaction keep their block, so transactions are pruned first.
+    async fn prune_blocks(&self, before: u64) -> Result<Reclaimed> {
+        const EXPIRED_BLOCKS: &str =
+            "slot < ? AND slot NOT IN (SELECT DISTINCT slot FROM compressed_transactions WHERE slot < ?)";
+        let reclaimed = self
+            .reclaimable(
+                self.client
+                    .query(&format!(
+                        "SELECT count(), sum(length(data) + length(proof)) FROM compressed_blocks FINAL WHERE {}",
+                        EXPIRED_BLOCKS
+                    ))
+                    .bind(before)
+                    .bind(before),
+            )
+            .await?;
         self.client
-            .query("INSERT INTO compressed_accounts (pubkey, lamports, owner, executable, rent_epoch, data, proof) VALUES (?, ?, ?, ?, ?, ?, ?)")
-            .bind(&account.pubkey)
-            .bind(account.lamports)
-            .bind(&account.owner)
-            .bind(account.executable)
-            .bind(account.rent_epoch)
-            .bind(&account.data)
-            .bind(&account.proof)
+            .query(&format!("DELETE FROM compressed_blocks WHERE {}", EXPIRED_BLOCKS))
+            .bind(before)
+            .bind(before)
             .execute()
             .await?;
-        Ok(())
+        Ok(reclaimed)
     }
 
-    async fn get_compressed_account(&self, pubkey: &[u8]) -> Result<CompressedAccount> {
-        let row: Row = self.client
-            .query("SELECT * FROM compressed_accounts WHERE pubkey = ?")
-            .bind(pubkey)
-            .fetch_one()
+    // Index rows are matched through the transactions, so they go first.
+    async fn prune_transactions(&self, before: u64, vote_before: u64, protected: &[String]) -> Result<Reclaimed> {
+        let reclaimed = self
+            .reclaimable(
+                self.client
+                    .query(&format!(
+                        "SELECT count(), sum(length(data) + length(proof)) FROM compressed_transactions FINAL WHERE {}",
+                        EXPIRED_TRANSACTIONS
+                    ))
+                    .bind(before)
+                    .bind(vote_before)
+                    .bind(hex::encode(VOTE_PROGRAM_ID))
+                    .bind(protected),
+            )
             .await?;
-        Ok(CompressedAccount {
-            pubkey: row.get("pubkey")?,
-            lamports: row.get("lamports")?,
-            owner: row.get("owner")?,
-            executable: row.get("executable")?,
-            rent_epoch: row.get("rent_epoch")?,
-            data: row.get("data")?,
-            proof: row.get("proof")?,
-        })
+        for table in ["transactions_by_slot", "signatures_by_address", "token_transfers", "compressed_transactions"] {
+            let predicate = match table {
+                "compressed_transactions" => EXPIRED_TRANSACTIONS.to_string(),
+                _ => format!("signature IN (SELECT signature FROM compressed_transactions WHERE {})", EXPIRED_TRANSACTIONS),
+            };
+            self.client
+                .query(&format!("DELETE FROM {} WHERE {}", table, predicate))
+                .bind(before)
+                .bind(vote_before)
+                .bind(hex::encode(VOTE_PROGRAM_ID))
+                .bind(protected)
+                .execute()
+                .await?;
+        }
+        Ok(reclaimed)
     }
 
-    async fn insert_compressed_block(&self, block: &CompressedBlock) -> Result<()> {
+    async fn prune_account_versions(&self, before: u64, protected: &[String]) -> Result<Reclaimed> {
+        let reclaimed = self
+            .reclaimable(
+                self.client
+                    .query(&format!(
+                        "SELECT count(), sum(length(data) + length(proof)) FROM account_versions FINAL WHERE {}",
+                        EXPIRED_ACCOUNT_VERSIONS
+                    ))
+                    .bind(before)
+                    .bind(protected),
+            )
+            .await?;
         self.client
-            .query("INSERT INTO compressed_blocks (slot, blockhash, previous_blockhash, parent_slot, transactions, data, proof) VALUES (?, ?, ?, ?, ?, ?, ?)")
-            .bind(block.slot)
-            .bind(&block.blockhash)
-            .bind(&block.previous_blockhash)
-            .bind(block.parent_slot)
-            .bind(block.transactions)
-            .bind(&block.data)
-            .bind(&block.proof)
+            .query(&format!("DELETE FROM account_versions WHERE {}", EXPIRED_ACCOUNT_VERSIONS))
+            .bind(before)
+            .bind(protected)
             .execute()
             .await?;
+        Ok(reclaimed)
+    }
+
+    /// Writes buffered rows and advances the cursor. Call before shutdown.
+    pub async fn flush(&self) -> Result<()> {
+        let mut buffer = self.buffer.lock().await;
+        self.write_buffer(&mut buffer).await
+    }
+
+    // The buffer is emptied up front: after a failure the indexer replays from
+    // the stored cursor, and replacing merges absorb any rows already written. The
+    // hourly aggregates of the replayed slots are dropped before they are added again.
+    async fn write_buffer(&self, buffer: &mut Buffer) -> Result<()> {
+        let Buffer {
+            first_slot,
+            slot,
+            blocks,
+            transactions,
+            accounts,
+            token_transfers,
+            ..
+        } = std::mem::take(buffer);
+        let (Some(first_slot), Some(slot)) = (first_slot, slot) else {
+            return Ok(());
+        };
+        if self.replaying.swap(true, Ordering::SeqCst) {
+            for table in HOURLY_TABLES {
+                self.client
+                    .query(&format!("DELETE FROM {} WHERE slot >= ?", table))
+                    .bind(first_slot)
+                    .execute()
+                    .await?;
+            }
+        }
+        self.insert("compressed_blocks", &blocks).await?;
+        self.insert("compressed_transactions", &transactions).await?;
+        self.insert("compressed_accounts", &accounts).await?;
+        self.insert("token_transfers", &token_transfers).await?;
+        self.update_last_processed_slot(slot).await?;
+        self.replaying.store(false, Ordering::SeqCst);
         Ok(())
     }
 
+    async fn insert<T: Row + Serialize>(&self, table: &str, rows: &[T]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut insert = self.client.insert(table)?;
+        for row in rows {
+            insert.write(row).await?;
+        }
+        insert.end().await?;
+        Ok(())
+    }
+}
+
+fn block_time(block_time: Option<i64>) -> u32 {
+    block_time.unwrap_or(0).clamp(0, u32::MAX as i64) as u32
+}
+
+fn account_row(account: &CompressedAccount) -> AccountRow {
+    AccountRow {
+        pubkey: account.pubkey.clone(),
+        slot: account.slot,
+        write_version: account.write_version,
+        lamports: account.lamports,
+        owner: account.owner.clone(),
+        executable: account.executable,
+        rent_epoch: account.rent_epoch,
+        data: account.data.clone(),
+        proof: account.proof.clone(),
+    }
+}
+
+fn block_row(block: &CompressedBlock) -> BlockRow {
+    BlockRow {
+        slot: block.slot,
+        blockhash: block.blockhash.clone(),
+        previous_blockhash: block.previous_blockhash.clone(),
+        parent_slot: block.parent_slot,
+        transactions: block.transactions,
+        block_time: block_time(block.block_time),
+        data: block.data.clone(),
+        proof: block.proof.clone(),
+    }
+}
+
+fn transaction_row(transaction: &CompressedTransaction, block_time: u32) -> TransactionRow {
+    TransactionRow {
+        signature: transaction.signature.clone(),
+        slot: transaction.slot,
+        tx_index: transaction.index,
+        block_time,
+        accounts: transaction.accounts.iter().cloned().map(ByteBuf::from).collect(),
+        fee: transaction.fee,
+        programs: transaction.programs.iter().cloned().map(ByteBuf::from).collect(),
+        data: transaction.data.clone(),
+        proof: transaction.proof.clone(),
+    }
+}
+
+fn token_transfer_row(transfer: &TokenTransfer, block_time: u32) -> TokenTransferRow {
+    TokenTransferRow {
+        signature: transfer.signature.clone(),
+        slot: transfer.slot,
+        block_time,
+        mint: transfer.mint.clone(),
+        owner: transfer.owner.clone(),
+        amount: transfer.amount,
+    }
+}
+
+fn account_from_row(row: AccountRow) -> CompressedAccount {
+    CompressedAccount {
+        pubkey: row.pubkey,
+        lamports: row.lamports,
+        owner: row.owner,
+        executable: row.executable,
+        rent_epoch: row.rent_epoch,
+        slot: row.slot,
+        write_version: row.write_version,
+        data: row.data,
+        proof: row.proof,
+    }
+}
+
+fn block_from_row(row: BlockRow) -> CompressedBlock {
+    CompressedBlock {
+        slot: row.slot,
+        blockhash: row.blockhash,
+        previous_blockhash: row.previous_blockhash,
+        parent_slot: row.parent_slot,
+        transactions: row.transactions,
+        block_time: (row.block_time != 0).then_some(row.block_time as i64),
+        data: row.data,
+        proof: row.proof,
+    }
+}
+
+fn transaction_from_row(row: TransactionRow) -> CompressedTransaction {
+    CompressedTransaction {
+        signature: row.signature,
+        slot: row.slot,
+        index: row.tx_index,
+        accounts: row.accounts.into_iter().map(ByteBuf::into_vec).collect(),
+        fee: row.fee,
+        programs: row.programs.into_iter().map(ByteBuf::into_vec).collect(),
+        data: row.data,
+        proof: row.proof,
+    }
+}
+
+// Creates the database and the tracking table if missing. Like CQL, ClickHouse DDL is not
+// transactional, so migrations only use statements that are safe to re-run.
+async fn migrate_client(client: &Client, database: &str, apply: bool) -> anyhow::Result<MigrationReport> {
+    client
+        .query(&format!("CREATE DATABASE IF NOT EXISTS {}", database))
+        .execute()
+        .await?;
+    let client = client.clone().with_database(database);
+    client
+        .query(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (version Int64, description String, checksum String, \
+             applied_at DateTime DEFAULT now()) ENGINE = ReplacingMergeTree ORDER BY version",
+        )
+        .execute()
+        .await?;
+    let applied = client
+        .query("SELECT version, checksum FROM schema_migrations FINAL ORDER BY version")
+        .fetch_all::<(i64, String)>()
+        .await?
+        .into_iter()
+        .map(|(version, checksum)| Ok((version, hex::decode(checksum)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let report = plan("clickhouse", &embedded(CLICKHOUSE), &applied)?;
+    if apply {
+        for migration in CLICKHOUSE.iter().filter(|m| report.pending.contains(&m.version)) {
+            for statement in migration.statements() {
+                client
+                    .query(&statement)
+                    .execute()
+                    .await
+                    .with_context(|| format!("Failed to apply clickhouse migration {}", migration.version))?;
+            }
+            client
+                .query("INSERT INTO schema_migrations (version, description, checksum) VALUES (?, ?, ?)")
+                .bind(migration.version)
+                .bind(migration.description)
+                .bind(hex::encode(migration.checksum()))
+                .execute()
+                .await?;
+        }
+    }
+    Ok(report)
+}
+
+#[async_trait]
+impl Database for ClickHouseStorage {
+    async fn insert_compressed_account(&self, account: &CompressedAccount) -> Result<()> {
+        self.insert("compressed_accounts", &[account_row(account)]).await
+    }
+
+    async fn get_compressed_account(&self, pubkey: &[u8]) -> Result<CompressedAccount> {
+        let row = self.client
+            .query("SELECT ?fields FROM compressed_accounts FINAL WHERE pubkey = unhex(?)")
+            .bind(hex::encode(pubkey))
+            .fetch_optional::<AccountRow>()
+            .await?
+            .ok_or_else(|| StorageError::not_found(format!("compressed account {}", hex::encode(pubkey))))?;
+        Ok(account_from_row(row))
+    }
+
+    async fn insert_compressed_block(&self, block: &CompressedBlock) -> Result<()> {
+        self.insert("compressed_blocks", &[block_row(block)]).await
+    }
+
     async fn get_compressed_block(&self, slot: u64) -> Result<CompressedBlock> {
-        let row: Row = self.client
-            .query("SELECT * FROM compressed_blocks WHERE slot = ?")
+        let row = self.client
+            .query("SELECT ?fields FROM compressed_blocks FINAL WHERE slot = ?")
             .bind(slot)
-            .fetch_one()
-            .await?;
-        Ok(CompressedBlock {
-            slot: row.get("slot")?,
-            blockhash: row.get("blockhash")?,
-            previous_blockhash: row.get("previous_blockhash")?,
-            parent_slot: row.get("parent_slot")?,
-            transactions: row.get("transactions")?,
-            data: row.get("data")?,
-            proof: row.get("proof")?,
-        })
+            .fetch_optional::<BlockRow>()
+            .await?
+            .ok_or_else(|| StorageError::not_found(format!("compressed block {}", slot)))?;
+        Ok(block_from_row(row))
     }
 
     async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> Result<()> {
-        self.client
-            .query("INSERT INTO compressed_transactions (signature, data, proof) VALUES (?, ?, ?)")
-            .bind(&transaction.signature)
-            .bind(&transaction.data)
-            .bind(&transaction.proof)
-            .execute()
-            .await?;
-        Ok(())
+        self.insert("compressed_transactions", &[transaction_row(transaction, 0)]).await
     }
 
     async fn get_compressed_transaction(&self, signature: &[u8]) -> Result<CompressedTransaction> {
-        let row: Row = self.client
-            .query("SELECT * FROM compressed_transactions WHERE signature = ?")
-            .bind(signature)
-            .fetch_one()
+        let row = self.client
+            .query("SELECT ?fields FROM compressed_transactions FINAL WHERE signature = unhex(?)")
+            .bind(hex::encode(signature))
+            .fetch_optional::<TransactionRow>()
+            .await?
+            .ok_or_else(|| StorageError::not_found(format!("compressed transaction {}", hex::encode(signature))))?;
+        Ok(transaction_from_row(row))
+    }
+
+    async fn get_accounts_by_owner(&self, owner: &[u8], limit: usize) -> Result<Vec<CompressedAccount>> {
+        let rows = self.client
+            .query("SELECT ?fields FROM compressed_accounts FINAL WHERE owner = unhex(?) ORDER BY pubkey LIMIT ?")
+            .bind(hex::encode(owner))
+            .bind(limit as u64)
+            .fetch_all::<AccountRow>()
             .await?;
-        Ok(CompressedTransaction {
-            signature: row.get("signature")?,
-            data: row.get("data")?,
-            proof: row.get("proof")?,
-        })
+        Ok(rows.into_iter().map(account_from_row).collect())
     }
 
-    async fn get_last_processed_slot(&self) -> Result<u64> {
-        let row: Row = self.client
-            .query("SELECT MAX(slot) as last_slot FROM compressed_blocks")
-            .fetch_one()
+    async fn get_signatures_for_address(&self, address: &[u8], query: &SignatureQuery) -> Result<Vec<SignatureInfo>> {
+        let (before, until) = signature_bounds(self, query).await?;
+        let before = before.unwrap_or((u64::MAX, u32::MAX));
+        let rows = self.client
+            .query(
+                "SELECT ?fields FROM signatures_by_address FINAL \
+                 WHERE address = unhex(?) AND (slot, tx_index) < (?, ?) AND (slot, tx_index) > (?, ?) \
+                 ORDER BY slot DESC, tx_index DESC LIMIT ?",
+            )
+            .bind(hex::encode(address))
+            .bind(before.0)
+            .bind(before.1)
+            // `until` is exclusive, so an absent bound sits just below (0, 0).
+            .bind(until.map_or(0, |(slot, _)| slot))
+            .bind(until.map_or(-1, |(_, index)| index as i64))
+            .bind(query.limit as u64)
+            .fetch_all::<SignatureRow>()
             .await?;
-        Ok(row.get::<Option<u64>, _>("last_slot")?.unwrap_or(0))
+        Ok(rows
+            .into_iter()
+            .map(|row| SignatureInfo {
+                signature: row.signature,
+                slot: row.slot,
+                index: row.tx_index,
+            })
+            .collect())
     }
 
-    async fn update_last_processed_slot(&self, slot: u64) -> Result<()> {
-        self.client
-            .query("INSERT INTO indexer_state (key, value) VALUES ('last_processed_slot', ?) ON CONFLICT (key) DO UPDATE SET value = ?")
+    async fn get_blocks_in_range(&self, start_slot: u64, end_slot: u64) -> Result<Vec<CompressedBlock>> {
+        let rows = self.client
+            .query("SELECT ?fields FROM compressed_blocks FINAL WHERE slot BETWEEN ? AND ? ORDER BY slot")
+            .bind(start_slot)
+            .bind(end_slot)
+            .fetch_all::<BlockRow>()
+            .await?;
+        Ok(rows.into_iter().map(block_from_row).collect())
+    }
+
+    async fn get_first_block_slot(&self) -> Result<Option<u64>> {
+        Ok(self.client
+            .query("SELECT slot FROM compressed_blocks FINAL ORDER BY slot LIMIT 1")
+            .fetch_optional::<u64>()
+            .await?)
+    }
+
+    async fn get_transactions_in_block(&self, slot: u64) -> Result<Vec<CompressedTransaction>> {
+        // compressed_transactions is sorted by signature, so the slot index narrows the lookup.
+        let rows = self.client
+            .query(
+                "SELECT ?fields FROM compressed_transactions FINAL \
+                 WHERE signature IN (SELECT signature FROM transactions_by_slot WHERE slot = ?) AND slot = ? \
+                 ORDER BY tx_index",
+            )
             .bind(slot)
             .bind(slot)
-            .execute()
+            .fetch_all::<TransactionRow>()
+            .await?;
+        Ok(rows.into_iter().map(transaction_from_row).collect())
+    }
+
+    fn stores_token_transfers(&self) -> bool {
+        true
+    }
+
+    async fn get_token_transfers_in_block(&self, slot: u64) -> Result<Vec<TokenTransfer>> {
+        let rows = self.client
+            .query("SELECT ?fields FROM token_transfers FINAL WHERE slot = ? ORDER BY signature, mint, owner")
+            .bind(slot)
+            .fetch_all::<TokenTransferRow>()
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| TokenTransfer {
+                signature: row.signature,
+                slot: row.slot,
+                mint: row.mint,
+                owner: row.owner,
+                amount: row.amount,
+            })
+            .collect())
+    }
+
+    // Hourly aggregates are left alone, so dashboards keep the history, unless the cursor
+    // is rewound and the slots are indexed again.
+    async fn delete_slot_range(&self, start_slot: u64, end_slot: u64) -> Result<u64> {
+        let mut deleted = 0;
+        for table in ["compressed_blocks", "compressed_transactions"] {
+            deleted += self.client
+                .query(&format!("SELECT count() FROM {} FINAL WHERE slot BETWEEN ? AND ?", table))
+                .bind(start_slot)
+                .bind(end_slot)
+                .fetch_one::<u64>()
+                .await?;
+        }
+        for table in [
+            "compressed_blocks",
+            "compressed_transactions",
+            "transactions_by_slot",
+            "signatures_by_address",
+            "token_transfers",
+        ] {
+            self.client
+                .query(&format!("DELETE FROM {} WHERE slot BETWEEN ? AND ?", table))
+                .bind(start_slot)
+                .bind(end_slot)
+                .execute()
+                .await?;
+        }
+        let cursor = self.get_last_processed_slot().await?;
+        if (start_slot..=end_slot).contains(&cursor) {
+            let rewound = CursorRow { key: CURSOR_KEY, value: start_slot.saturating_sub(1) };
+            self.insert("indexer_state", &[rewound]).await?;
+            self.replaying.store(true, Ordering::SeqCst);
+        }
+        Ok(deleted)
+    }
+
+    async fn get_account_at_slot(&self, pubkey: &[u8], slot: u64) -> Result<Option<CompressedAccount>> {
+        let row = self.client
+            .query(
+                "SELECT ?fields FROM account_versions FINAL WHERE pubkey = unhex(?) AND slot <= ? \
+                 ORDER BY slot DESC, write_version DESC LIMIT 1",
+            )
+            .bind(hex::encode(pubkey))
+            .bind(slot)
+            .fetch_optional::<AccountRow>()
             .await?;
+        Ok(row.map(account_from_row))
+    }
+
+    async fn get_account_versions(
+        &self,
+        pubkey: &[u8],
+        start_slot: u64,
+        end_slot: u64,
+        limit: usize,
+    ) -> Result<Vec<CompressedAccount>> {
+        let rows = self.client
+            .query(
+                "SELECT ?fields FROM account_versions FINAL WHERE pubkey = unhex(?) AND slot BETWEEN ? AND ? \
+                 ORDER BY slot, write_version LIMIT ?",
+            )
+            .bind(hex::encode(pubkey))
+            .bind(start_slot)
+            .bind(end_slot)
+            .bind(limit as u64)
+            .fetch_all::<AccountRow>()
+            .await?;
+        Ok(rows.into_iter().map(account_from_row).collect())
+    }
+
+    async fn get_account_pubkeys(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        let rows = self.client
+            .query("SELECT DISTINCT ?fields FROM account_versions WHERE pubkey > unhex(?) ORDER BY pubkey LIMIT ?")
+            .bind(hex::encode(after.unwrap_or_default()))
+            .bind(limit as u64)
+            .fetch_all::<PubkeyRow>()
+            .await?;
+        Ok(rows.into_iter().map(|row| row.pubkey).collect())
+    }
+
+    async fn get_transaction_signatures(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        let rows = self.client
+            .query("SELECT ?fields FROM compressed_transactions FINAL WHERE signature > unhex(?) ORDER BY signature LIMIT ?")
+            .bind(hex::encode(after.unwrap_or_default()))
+            .bind(limit as u64)
+            .fetch_all::<TransactionKeyRow>()
+            .await?;
+        Ok(rows.into_iter().map(|row| row.signature).collect())
+    }
+
+    async fn prune(&self, request: &PruneRequest) -> Result<PruneStats> {
+        let protected: Vec<String> = request.protected_addresses.iter().map(hex::encode).collect();
+        let mut stats = PruneStats::default();
+        if request.transactions_before.is_some() || request.vote_transactions_before.is_some() {
+            stats.transactions = self
+                .prune_transactions(
+                    request.transactions_before.unwrap_or(0),
+                    request.vote_transactions_before.unwrap_or(0),
+                    &protected,
+                )
+                .await?;
+        }
+        if let Some(before) = request.blocks_before {
+            stats.blocks = self.prune_blocks(before).await?;
+        }
+        if let Some(before) = request.account_versions_before {
+            stats.account_versions = self.prune_account_versions(before, &protected).await?;
+        }
+        Ok(stats)
+    }
+
+    async fn get_last_processed_slot(&self) -> Result<u64> {
+        let slot = self.client
+            .query("SELECT value FROM indexer_state FINAL WHERE key = ?")
+            .bind(CURSOR_KEY)
+            .fetch_optional::<u64>()
+            .await?;
+        Ok(slot.unwrap_or(0))
+    }
+
+    async fn update_last_processed_slot(&self, slot: u64) -> Result<()> {
+        let cursor = self.get_last_processed_slot().await?;
+        if slot < cursor {
+            return Err(cursor_moved_back(cursor, slot));
+        }
+        self.insert("indexer_state", &[CursorRow { key: CURSOR_KEY, value: slot }]).await
+    }
+
+    async fn commit_block(&self, batch: &BlockBatch) -> Result<()> {
+        let block_time = block_time(batch.block.as_ref().and_then(|block| block.block_time));
+        let mut buffer = self.buffer.lock().await;
+        buffer.first_slot = Some(buffer.first_slot.map_or(batch.slot, |first| first.min(batch.slot)));
+        buffer.slot = Some(batch.slot);
+        buffer.started.get_or_insert_with(Instant::now);
+        buffer.blocks.extend(batch.block.iter().map(block_row));
+        buffer.transactions.extend(
+            batch
+                .transactions
+                .iter()
+                .map(|transaction| transaction_row(transaction, block_time)),
+        );
+        buffer.accounts.extend(batch.accounts.iter().map(account_row));
+        buffer.token_transfers.extend(
+            batch
+                .token_transfers
+                .iter()
+                .map(|transfer| token_transfer_row(transfer, block_time)),
+        );
+
+        let expired = buffer.started.is_some_and(|started| started.elapsed() >= self.max_buffer_delay);
+        if buffer.len() >= self.max_buffered_rows || expired {
+            self.write_buffer(&mut buffer).await?;
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance::{service_url, storage_conformance};
+
+    // Unbuffered, so every commit is visible to the reads that follow it.
+    storage_conformance!(
+        ignore = "needs ClickHouse at WINDEXER_TEST_CLICKHOUSE_URL",
+        ClickHouseStorage::new(&service_url("WINDEXER_TEST_CLICKHOUSE_URL", "http://localhost:8123"))
+            .await
+            .unwrap()
+            .with_buffer(0, Duration::ZERO)
+    );
+}
@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+use anyhow::Result;
+use crate::storage::models::*;
+
+#[async_trait]
+pub trait CompressionStore: Send + Sync {
+    /// Last slot whose compressed state was fully indexed. Tree updates are written as they
+    /// are applied rather than with the block batch, so replayed slots at or below it are skipped.
+    async fn get_compressed_slot(&self) -> Result<u64>;
+    async fn update_compressed_slot(&self, slot: u64) -> Result<()>;
+    async fn upsert_merkle_tree(&self, tree: &MerkleTreeRecord) -> Result<()>;
+    async fn get_merkle_tree(&self, tree: &[u8]) -> Result<Option<MerkleTreeRecord>>;
+    async fn get_merkle_trees(&self) -> Result<Vec<MerkleTreeRecord>>;
+    /// Removes a tree with its roots and leaf changes, so it can be rebuilt from scratch.
+    async fn delete_merkle_tree(&self, tree: &[u8]) -> Result<()>;
+    async fn insert_tree_root(&self, root: &TreeRootRecord) -> Result<()>;
+    /// Roots of `tree`, oldest first.
+    async fn get_tree_roots(&self, tree: &[u8]) -> Result<Vec<TreeRootRecord>>;
+    async fn insert_leaf_change(&self, change: &LeafChangeRecord) -> Result<()>;
+    /// Changes to `tree` with a seq above `after_seq`, oldest first.
+    async fn get_leaf_changes(&self, tree: &[u8], after_seq: u64) -> Result<Vec<LeafChangeRecord>>;
+    async fn upsert_state_account(&self, account: &CompressedStateAccount) -> Result<()>;
+    async fn get_state_account(&self, hash: &[u8]) -> Result<Option<CompressedStateAccount>>;
+    async fn get_state_account_by_address(&self, address: &[u8]) -> Result<Option<CompressedStateAccount>>;
+    async fn get_state_accounts_by_owner(&self, owner: &[u8]) -> Result<Vec<CompressedStateAccount>>;
+    async fn spend_state_account(&self, hash: &[u8], slot: u64) -> Result<()>;
+    async fn insert_address(&self, address: &AddressRecord) -> Result<()>;
+    async fn get_address(&self, address: &[u8]) -> Result<Option<AddressRecord>>;
+    async fn get_low_address(&self, tree: &[u8], address: &[u8]) -> Result<Option<AddressRecord>>;
+    async fn upsert_token_account(&self, account: &CompressedTokenAccountRecord) -> Result<()>;
+    async fn delete_token_account(&self, hash: &[u8]) -> Result<()>;
+    async fn get_token_account(&self, hash: &[u8]) -> Result<Option<CompressedTokenAccountRecord>>;
+    async fn get_token_accounts_by_owner(&self, owner: &[u8]) -> Result<Vec<CompressedTokenAccountRecord>>;
+    async fn get_token_accounts_by_mint(&self, mint: &[u8]) -> Result<Vec<CompressedTokenAccountRecord>>;
+    async fn upsert_token_mint(&self, mint: &TokenMintRecord) -> Result<()>;
+    async fn get_token_mint(&self, mint: &[u8]) -> Result<Option<TokenMintRecord>>;
+    async fn upsert_asset(&self, asset: &CompressedAssetRecord) -> Result<()>;
+    async fn get_asset(&self, asset_id: &[u8]) -> Result<Option<CompressedAssetRecord>>;
+    async fn get_assets_by_owner(&self, owner: &[u8]) -> Result<Vec<CompressedAssetRecord>>;
+
+    // Whole-table reads, e.g. for snapshots. Each returns up to `limit` rows following the one
+    // keyed `after`, in an order of the backend's choosing that holds across calls.
+    async fn list_state_accounts(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<CompressedStateAccount>>;
+    async fn list_addresses(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<AddressRecord>>;
+    async fn list_token_accounts(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<CompressedTokenAccountRecord>>;
+    async fn list_token_mints(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<TokenMintRecord>>;
+    async fn list_assets(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<CompressedAssetRecord>>;
+}
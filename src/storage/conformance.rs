@@ -0,0 +1,314 @@
+//! Checks every `Database` backend must pass. A backend opts in from its test module with
+//! `storage_conformance!(<expression building a fresh storage>)`, which expands to one test
+//! per check. Keys are random and slots lie past the cursor, so backends behind a shared server
+//! can keep rows from earlier runs, but checks should not run concurrently against one server.
+
+use crate::storage::{
+    BlockBatch, CompressedAccount, CompressedBlock, CompressedTransaction, Database, SignatureQuery, StorageError,
+};
+
+/// Expands to a `conformance` module with one `#[tokio::test]` per check. Backends that need
+/// a running service pass `ignore = "<what it needs>"` first, so their tests only run with
+/// `--ignored`.
+macro_rules! storage_conformance {
+    (@tests $storage:expr, $(#[$attr:meta])*) => {
+        mod conformance {
+            use super::*;
+
+            $crate::storage::conformance::conformance_test!(round_trip, $storage, $(#[$attr])*);
+            $crate::storage::conformance::conformance_test!(missing_keys, $storage, $(#[$attr])*);
+            $crate::storage::conformance::conformance_test!(cursor_monotonicity, $storage, $(#[$attr])*);
+            $crate::storage::conformance::conformance_test!(idempotent_reinserts, $storage, $(#[$attr])*);
+            $crate::storage::conformance::conformance_test!(rollback, $storage, $(#[$attr])*);
+            $crate::storage::conformance::conformance_test!(account_pubkey_paging, $storage, $(#[$attr])*);
+            $crate::storage::conformance::conformance_test!(transaction_signature_paging, $storage, $(#[$attr])*);
+        }
+    };
+    (ignore = $reason:literal, $storage:expr) => {
+        $crate::storage::conformance::storage_conformance!(@tests $storage, #[ignore = $reason]);
+    };
+    ($storage:expr) => {
+        $crate::storage::conformance::storage_conformance!(@tests $storage,);
+    };
+}
+
+macro_rules! conformance_test {
+    ($check:ident, $storage:expr, $(#[$attr:meta])*) => {
+        #[tokio::test]
+        $(#[$attr])*
+        async fn $check() {
+            let storage = $storage;
+            $crate::storage::conformance::$check(&storage).await;
+        }
+    };
+}
+
+pub(crate) use {conformance_test, storage_conformance};
+
+/// URL of a test service, from `var` if set.
+pub fn service_url(var: &str, default: &str) -> String {
+    std::env::var(var).unwrap_or_else(|_| default.to_string())
+}
+
+/// A random slot past the cursor, with room for a few hundred slots after it, so rows and
+/// cursors left by earlier runs on a shared server don't get in the way.
+async fn random_slot(storage: &dyn Database) -> u64 {
+    storage.get_last_processed_slot().await.unwrap() + (rand::random::<u32>() as u64 + 1) * 1_000
+}
+
+fn random_key(len: usize) -> Vec<u8> {
+    (0..len).map(|_| rand::random()).collect()
+}
+
+fn account(pubkey: &[u8], owner: &[u8], slot: u64, lamports: i64) -> CompressedAccount {
+    CompressedAccount {
+        pubkey: pubkey.to_vec(),
+        lamports,
+        owner: owner.to_vec(),
+        executable: false,
+        rent_epoch: 0,
+        slot,
+        write_version: 0,
+        data: vec![lamports as u8; 8],
+        proof: vec![1, 2, 3],
+    }
+}
+
+fn block(slot: u64) -> CompressedBlock {
+    CompressedBlock {
+        slot,
+        blockhash: format!("hash-{}", slot),
+        previous_blockhash: format!("hash-{}", slot - 1),
+        parent_slot: slot - 1,
+        transactions: 1,
+        block_time: Some(1_700_000_000),
+        data: vec![4, 5],
+        proof: vec![6],
+    }
+}
+
+fn transaction(slot: u64, index: u32, accounts: Vec<Vec<u8>>) -> CompressedTransaction {
+    CompressedTransaction {
+        signature: random_key(64),
+        slot,
+        index,
+        programs: accounts.iter().take(1).cloned().collect(),
+        accounts,
+        fee: 5000,
+        data: vec![7, 8, 9],
+        proof: vec![10],
+    }
+}
+
+/// A block at `slot` with one transaction touching `address`, and one account write.
+fn batch(slot: u64, address: &[u8], owner: &[u8]) -> BlockBatch {
+    let mut batch = BlockBatch::new(slot);
+    batch.block = Some(block(slot));
+    batch.transactions = vec![transaction(slot, 0, vec![address.to_vec()])];
+    batch.accounts = vec![account(&random_key(32), owner, slot, 1)];
+    batch
+}
+
+async fn signature_slots(storage: &dyn Database, address: &[u8]) -> Vec<u64> {
+    let signatures = storage
+        .get_signatures_for_address(address, &SignatureQuery::default())
+        .await
+        .unwrap();
+    signatures.iter().map(|signature| signature.slot).collect()
+}
+
+pub async fn round_trip(storage: &dyn Database) {
+    let slot = random_slot(storage).await;
+    let (pubkey, owner) = (random_key(32), random_key(32));
+    let inserted = account(&pubkey, &owner, slot, 42);
+    storage.insert_compressed_account(&inserted).await.unwrap();
+    let fetched = storage.get_compressed_account(&pubkey).await.unwrap();
+    assert_eq!(fetched.version(), (slot, 0));
+    assert_eq!(
+        (fetched.pubkey, fetched.lamports, fetched.owner, fetched.data, fetched.proof),
+        (inserted.pubkey, inserted.lamports, inserted.owner, inserted.data, inserted.proof)
+    );
+    assert_eq!(storage.get_accounts_by_owner(&owner, 10).await.unwrap().len(), 1);
+
+    let inserted = block(slot);
+    storage.insert_compressed_block(&inserted).await.unwrap();
+    let fetched = storage.get_compressed_block(slot).await.unwrap();
+    assert_eq!(
+        (fetched.blockhash, fetched.previous_blockhash, fetched.parent_slot, fetched.transactions),
+        (inserted.blockhash, inserted.previous_blockhash, inserted.parent_slot, inserted.transactions)
+    );
+    assert_eq!(fetched.block_time, inserted.block_time);
+    assert_eq!((fetched.data, fetched.proof), (inserted.data, inserted.proof));
+
+    let address = random_key(32);
+    let inserted = transaction(slot, 3, vec![address.clone(), pubkey]);
+    storage.insert_compressed_transaction(&inserted).await.unwrap();
+    let fetched = storage.get_compressed_transaction(&inserted.signature).await.unwrap();
+    assert_eq!(
+        (fetched.slot, fetched.index, fetched.accounts, fetched.data, fetched.proof),
+        (inserted.slot, inserted.index, inserted.accounts, inserted.data, inserted.proof)
+    );
+    assert_eq!((fetched.fee, fetched.programs), (inserted.fee, inserted.programs));
+    let signatures = storage
+        .get_signatures_for_address(&address, &SignatureQuery::default())
+        .await
+        .unwrap();
+    assert_eq!(signatures.len(), 1);
+    assert_eq!((signatures[0].signature.clone(), signatures[0].index), (inserted.signature, 3));
+
+    let batch = batch(slot + 1, &address, &owner);
+    storage.commit_block(&batch).await.unwrap();
+    assert_eq!(storage.get_blocks_in_range(slot, slot + 1).await.unwrap().len(), 2);
+    // Shared servers may hold blocks of earlier runs.
+    assert!(storage.get_first_block_slot().await.unwrap().is_some_and(|first| first <= slot));
+    let transactions = storage.get_transactions_in_block(slot + 1).await.unwrap();
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(transactions[0].signature, batch.transactions[0].signature);
+    assert_eq!(signature_slots(storage, &address).await, vec![slot + 1, slot]);
+    assert_eq!(storage.get_compressed_account(&batch.accounts[0].pubkey).await.unwrap().lamports, 1);
+}
+
+pub async fn account_pubkey_paging(storage: &dyn Database) {
+    let slot = random_slot(storage).await;
+    let owner = random_key(32);
+    let mut inserted: Vec<Vec<u8>> = (0..3).map(|_| random_key(32)).collect();
+    for pubkey in &inserted {
+        storage.insert_compressed_account(&account(pubkey, &owner, slot, 1)).await.unwrap();
+    }
+    storage.insert_compressed_account(&account(&inserted[0], &owner, slot + 1, 2)).await.unwrap();
+
+    let mut listed = Vec::new();
+    let mut after: Option<Vec<u8>> = None;
+    loop {
+        let page = storage.get_account_pubkeys(after.as_deref(), 2).await.unwrap();
+        assert!(page.len() <= 2);
+        let Some(last) = page.last() else {
+            break;
+        };
+        after = Some(last.clone());
+        listed.extend(page.into_iter().filter(|pubkey| inserted.contains(pubkey)));
+    }
+    listed.sort();
+    inserted.sort();
+    assert_eq!(listed, inserted);
+}
+
+pub async fn transaction_signature_paging(storage: &dyn Database) {
+    let slot = random_slot(storage).await;
+    let mut inserted = Vec::new();
+    for index in 0..3 {
+        let transaction = transaction(slot, index, vec![random_key(32)]);
+        storage.insert_compressed_transaction(&transaction).await.unwrap();
+        inserted.push(transaction.signature);
+    }
+
+    let mut listed = Vec::new();
+    let mut after: Option<Vec<u8>> = None;
+    loop {
+        let page = storage.get_transaction_signatures(after.as_deref(), 2).await.unwrap();
+        assert!(page.len() <= 2);
+        let Some(last) = page.last() else {
+            break;
+        };
+        after = Some(last.clone());
+        listed.extend(page.into_iter().filter(|signature| inserted.contains(signature)));
+    }
+    listed.sort();
+    inserted.sort();
+    assert_eq!(listed, inserted);
+}
+
+pub async fn missing_keys(storage: &dyn Database) {
+    let slot = random_slot(storage).await;
+    let key = random_key(32);
+    assert!(matches!(storage.get_compressed_account(&key).await, Err(StorageError::NotFound(_))));
+    assert!(matches!(storage.get_compressed_block(slot).await, Err(StorageError::NotFound(_))));
+    assert!(matches!(
+        storage.get_compressed_transaction(&random_key(64)).await,
+        Err(StorageError::NotFound(_))
+    ));
+    let query = SignatureQuery { before: Some(random_key(64)), ..Default::default() };
+    assert!(matches!(
+        storage.get_signatures_for_address(&key, &query).await,
+        Err(StorageError::NotFound(_))
+    ));
+    assert!(storage.get_account_at_slot(&key, slot).await.unwrap().is_none());
+    assert!(storage.get_account_versions(&key, 0, slot, 10).await.unwrap().is_empty());
+    assert!(storage.get_accounts_by_owner(&key, 10).await.unwrap().is_empty());
+    assert!(signature_slots(storage, &key).await.is_empty());
+    assert!(storage.get_blocks_in_range(slot, slot + 10).await.unwrap().is_empty());
+    assert!(storage.get_transactions_in_block(slot).await.unwrap().is_empty());
+}
+
+pub async fn cursor_monotonicity(storage: &dyn Database) {
+    let slot = random_slot(storage).await;
+    let (address, owner) = (random_key(32), random_key(32));
+    storage.update_last_processed_slot(slot).await.unwrap();
+    assert_eq!(storage.get_last_processed_slot().await.unwrap(), slot);
+
+    let mut previous = slot;
+    for next in [slot + 1, slot + 2, slot + 5] {
+        storage.commit_block(&batch(next, &address, &owner)).await.unwrap();
+        let cursor = storage.get_last_processed_slot().await.unwrap();
+        assert!(cursor >= previous, "cursor went back from {} to {}", previous, cursor);
+        assert_eq!(cursor, next);
+        previous = cursor;
+    }
+    // Skipped slots still advance the cursor.
+    storage.commit_block(&BlockBatch::new(slot + 6)).await.unwrap();
+    assert_eq!(storage.get_last_processed_slot().await.unwrap(), slot + 6);
+    assert!(storage.get_compressed_block(slot + 6).await.is_err());
+
+    // Moving back would leave the later slots to be written again.
+    let error = storage.update_last_processed_slot(slot + 1).await.unwrap_err();
+    assert!(matches!(error, StorageError::Conflict(_)), "moving the cursor back gave {:?}", error);
+    assert_eq!(storage.get_last_processed_slot().await.unwrap(), slot + 6);
+    storage.update_last_processed_slot(slot + 6).await.unwrap();
+}
+
+pub async fn idempotent_reinserts(storage: &dyn Database) {
+    let slot = random_slot(storage).await;
+    let (address, owner) = (random_key(32), random_key(32));
+    let batch = batch(slot, &address, &owner);
+    for _ in 0..2 {
+        storage.insert_compressed_account(&batch.accounts[0]).await.unwrap();
+        storage.insert_compressed_block(batch.block.as_ref().unwrap()).await.unwrap();
+        storage.insert_compressed_transaction(&batch.transactions[0]).await.unwrap();
+        storage.commit_block(&batch).await.unwrap();
+    }
+    let pubkey = &batch.accounts[0].pubkey;
+    assert_eq!(storage.get_accounts_by_owner(&owner, 10).await.unwrap().len(), 1);
+    assert_eq!(storage.get_account_versions(pubkey, 0, slot, 10).await.unwrap().len(), 1);
+    assert_eq!(storage.get_blocks_in_range(slot, slot).await.unwrap().len(), 1);
+    assert_eq!(storage.get_transactions_in_block(slot).await.unwrap().len(), 1);
+    assert_eq!(signature_slots(storage, &address).await, vec![slot]);
+    assert_eq!(storage.get_last_processed_slot().await.unwrap(), slot);
+}
+
+/// Rolling back slots removes their blocks and transactions, keeps accounts, and lets the
+/// slots be indexed again.
+pub async fn rollback(storage: &dyn Database) {
+    let slot = random_slot(storage).await;
+    let (address, owner) = (random_key(32), random_key(32));
+    let batches: Vec<BlockBatch> = (slot..slot + 3).map(|slot| batch(slot, &address, &owner)).collect();
+    for batch in &batches {
+        storage.commit_block(batch).await.unwrap();
+    }
+
+    // Deleting the slots up to the cursor moves it back before them.
+    assert_eq!(storage.delete_slot_range(slot + 1, slot + 2).await.unwrap(), 4);
+    assert_eq!(storage.get_last_processed_slot().await.unwrap(), slot);
+    assert_eq!(storage.get_blocks_in_range(slot, slot + 2).await.unwrap().len(), 1);
+    assert!(storage.get_compressed_block(slot + 1).await.is_err());
+    assert!(storage.get_transactions_in_block(slot + 2).await.unwrap().is_empty());
+    assert!(storage
+        .get_compressed_transaction(&batches[1].transactions[0].signature)
+        .await
+        .is_err());
+    assert_eq!(signature_slots(storage, &address).await, vec![slot]);
+    assert_eq!(storage.get_accounts_by_owner(&owner, 10).await.unwrap().len(), 3);
+
+    storage.commit_block(&batches[1]).await.unwrap();
+    assert_eq!(storage.get_last_processed_slot().await.unwrap(), slot + 1);
+    assert_eq!(signature_slots(storage, &address).await, vec![slot + 1, slot]);
+}
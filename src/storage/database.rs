@@ -1,5 +1,5 @@
 use async_trait::async_trait;
-use anyhow::Result;
+use crate::storage::error::{Result, StorageError};
 use crate::storage::models::*;
 
 #[async_trait]
@@ -10,6 +10,123 @@ pub trait Database: Send + Sync {
     async fn get_compressed_block(&self, slot: u64) -> Result<CompressedBlock>;
     async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> Result<()>;
     async fn get_compressed_transaction(&self, signature: &[u8]) -> Result<CompressedTransaction>;
+    async fn get_accounts_by_owner(&self, owner: &[u8], limit: usize) -> Result<Vec<CompressedAccount>>;
+    async fn get_signatures_for_address(&self, address: &[u8], query: &SignatureQuery) -> Result<Vec<SignatureInfo>>;
+    /// Blocks with `start_slot <= slot <= end_slot`, in slot order.
+    async fn get_blocks_in_range(&self, start_slot: u64, end_slot: u64) -> Result<Vec<CompressedBlock>>;
+    /// The slot of the oldest stored block, if any.
+    async fn get_first_block_slot(&self) -> Result<Option<u64>>;
+    async fn get_transactions_in_block(&self, slot: u64) -> Result<Vec<CompressedTransaction>>;
+    /// Deletes the blocks and transactions with `start_slot <= slot <= end_slot`, with their
+    /// index rows. Accounts are left alone. A cursor inside the range moves back to
+    /// `start_slot - 1`. Returns the number of blocks and transactions removed.
+    async fn delete_slot_range(&self, start_slot: u64, end_slot: u64) -> Result<u64>;
+    /// The newest version of the account written at or before `slot`.
+    async fn get_account_at_slot(&self, pubkey: &[u8], slot: u64) -> Result<Option<CompressedAccount>>;
+    /// Versions of the account written in `start_slot..=end_slot`, oldest first.
+    async fn get_account_versions(
+        &self,
+        pubkey: &[u8],
+        start_slot: u64,
+        end_slot: u64,
+        limit: usize,
+    ) -> Result<Vec<CompressedAccount>>;
+    /// Up to `limit` keys of accounts with stored versions, following `after` in an order of
+    /// the backend's choosing, for walking every account.
+    async fn get_account_pubkeys(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>>;
+    /// Up to `limit` signatures of stored transactions, following `after` in an order of the
+    /// backend's choosing, for walking every transaction.
+    async fn get_transaction_signatures(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>>;
+    /// Deletes what `request` no longer retains. Returns what was reclaimed of each kind.
+    async fn prune(&self, request: &PruneRequest) -> Result<PruneStats>;
     async fn get_last_processed_slot(&self) -> Result<u64>;
+    /// Moves the cursor to `slot`. Moving it back is a [`StorageError::Conflict`], since the
+    /// slots after `slot` would be kept but written again; `delete_slot_range` rewinds it.
     async fn update_last_processed_slot(&self, slot: u64) -> Result<()>;
-}
\ No newline at end of file
+
+    /// Writes every row of `batch`, then moves the cursor to `batch.slot`.
+    ///
+    /// Backends with transactions override this to commit atomically. The default relies on
+    /// idempotent upserts and uses the cursor as the commit marker: if it fails part way, the
+    /// cursor stays put and replaying the same batch converges.
+    async fn commit_block(&self, batch: &BlockBatch) -> Result<()> {
+        if let Some(block) = &batch.block {
+            self.insert_compressed_block(block).await?;
+        }
+        for transaction in &batch.transactions {
+            self.insert_compressed_transaction(transaction).await?;
+        }
+        for account in &batch.accounts {
+            self.insert_compressed_account(account).await?;
+        }
+        self.update_last_processed_slot(batch.slot).await
+    }
+
+    /// Each version written in `start_slot..=end_slot`, diffed against the version before it.
+    /// Versions are compared by the account data `decode` recovers from them.
+    async fn get_account_changes(
+        &self,
+        pubkey: &[u8],
+        start_slot: u64,
+        end_slot: u64,
+        limit: usize,
+        decode: &AccountDataDecoder,
+    ) -> Result<Vec<AccountChange>> {
+        let mut previous = match start_slot.checked_sub(1) {
+            Some(slot) => match self.get_account_at_slot(pubkey, slot).await? {
+                Some(account) => {
+                    let data = decode(&account).map_err(StorageError::Corrupt)?;
+                    Some((account, data))
+                }
+                None => None,
+            },
+            None => None,
+        };
+        let mut changes = Vec::new();
+        for account in self.get_account_versions(pubkey, start_slot, end_slot, limit).await? {
+            let data = decode(&account).map_err(StorageError::Corrupt)?;
+            let before = previous.as_ref().map(|(account, data)| (account, &data[..]));
+            changes.push(AccountChange::new(before, &account, &data));
+            previous = Some((account, data));
+        }
+        Ok(changes)
+    }
+
+    /// Token transfers in the block at `slot`. Only backends that [store
+    /// them](Self::stores_token_transfers) return any.
+    async fn get_token_transfers_in_block(&self, _slot: u64) -> Result<Vec<TokenTransfer>> {
+        Ok(Vec::new())
+    }
+
+    /// Whether `commit_block` keeps the batch's token transfers.
+    fn stores_token_transfers(&self) -> bool {
+        false
+    }
+}
+
+/// The error for moving the cursor back from `cursor` to `slot`.
+pub(crate) fn cursor_moved_back(cursor: u64, slot: u64) -> StorageError {
+    StorageError::Conflict(anyhow::anyhow!(
+        "Cannot move the cursor back from slot {} to {} without deleting the slots after it",
+        cursor,
+        slot
+    ))
+}
+
+/// Resolves the `before`/`until` signatures of a query to exclusive `(slot, index)` bounds.
+pub(crate) async fn signature_bounds<D: Database + ?Sized>(
+    db: &D,
+    query: &SignatureQuery,
+) -> Result<(Option<(u64, u32)>, Option<(u64, u32)>)> {
+    let mut bounds = [None, None];
+    for (bound, signature) in bounds.iter_mut().zip([&query.before, &query.until]) {
+        if let Some(signature) = signature {
+            let transaction = db.get_compressed_transaction(signature).await.map_err(|e| match e {
+                StorageError::NotFound(_) => StorageError::not_found(format!("cursor signature {}", hex::encode(signature))),
+                e => e,
+            })?;
+            *bound = Some((transaction.slot, transaction.index));
+        }
+    }
+    Ok((bounds[0], bounds[1]))
+}
@@ -0,0 +1,990 @@
+//! Envelope encryption of the `data` and `proof` payloads, and of compressed account data and
+//! token TLV, at rest.
+//!
+//! Every non-empty payload is encrypted with XChaCha20-Poly1305 under a fresh random data key,
+//! and the data key is wrapped under a master key. The result replaces the payload as
+//!
+//! ```text
+//! "WXE\x01" | master key id (u32 LE) | key nonce (24) | wrapped data key (48) | nonce (24) | ciphertext
+//! ```
+//!
+//! so each row records the master key it was written with. The payload is authenticated
+//! together with the row it belongs to, so payloads can't be swapped between rows. Slots,
+//! pubkeys, signatures and the other lookup columns are stored as they are.
+
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use futures::future::BoxFuture;
+use log::{error, info, warn};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use crate::storage::error::{Result, StorageError};
+use crate::storage::{
+    AddressRecord, BlockBatch, CompressedAccount, CompressedAssetRecord, CompressedBlock, CompressedStateAccount,
+    CompressedTokenAccountRecord, CompressedTransaction, CompressionStore, Database, LeafChangeRecord, MerkleTreeRecord,
+    PruneRequest, PruneStats, SignatureInfo, SignatureQuery, TokenMintRecord, TokenTransfer, TreeRootRecord,
+};
+
+const MAGIC: &[u8; 4] = b"WXE\x01";
+const HEADER_LEN: usize = MAGIC.len() + 4;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+const WRAPPED_KEY_LEN: usize = KEY_LEN + 16;
+const PAYLOAD_OFFSET: usize = HEADER_LEN + NONCE_LEN + WRAPPED_KEY_LEN;
+// The largest limit every backend can bind.
+const ALL_VERSIONS: usize = i64::MAX as usize;
+const SLOTS_PER_READ: u64 = 1_000;
+const ROWS_PER_READ: usize = 1_000;
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Master keys by id. New payloads are encrypted under the active key; the others are kept
+/// to decrypt what was written before a rotation.
+pub struct MasterKeys {
+    keys: BTreeMap<u32, XChaCha20Poly1305>,
+    active: u32,
+}
+
+impl MasterKeys {
+    /// Parses `<id>:<64 hex digits>` entries separated by newlines, commas or spaces, with
+    /// `#` starting a comment. The highest id is active.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut keys = BTreeMap::new();
+        for entry in text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|entry| !entry.is_empty())
+        {
+            let (id, key) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid master key entry, expected <id>:<hex key>"))?;
+            let id: u32 = id.parse().with_context(|| format!("Invalid master key id '{}'", id))?;
+            let key = hex::decode(key).with_context(|| format!("Master key {} is not hex", id))?;
+            if key.len() != KEY_LEN {
+                bail!("Master key {} is {} bytes, expected {}", id, key.len(), KEY_LEN);
+            }
+            if keys.insert(id, XChaCha20Poly1305::new_from_slice(&key)?).is_some() {
+                bail!("Master key {} is listed twice", id);
+            }
+        }
+        let active = *keys.keys().next_back().ok_or_else(|| anyhow!("No master keys configured"))?;
+        Ok(Self { keys, active })
+    }
+
+    /// Reads the keys from `key_file` if given, otherwise from the `key_env` variable.
+    pub fn load(key_file: Option<&str>, key_env: &str) -> anyhow::Result<Self> {
+        let text = match key_file {
+            Some(path) => std::fs::read_to_string(path).with_context(|| format!("Failed to read master keys from {}", path))?,
+            None => std::env::var(key_env).with_context(|| format!("Master keys not set in ${}", key_env))?,
+        };
+        Self::parse(&text)
+    }
+
+    /// Encrypts new payloads under `id` instead of the highest id, so a new key can be
+    /// distributed to every node before any of them writes with it.
+    pub fn with_active(mut self, id: u32) -> anyhow::Result<Self> {
+        if !self.keys.contains_key(&id) {
+            bail!("Active master key {} is not configured", id);
+        }
+        self.active = id;
+        Ok(self)
+    }
+
+    pub fn active(&self) -> u32 {
+        self.active
+    }
+
+    /// The master key id a stored payload was encrypted with; `None` for plaintext, such as
+    /// rows written before encryption was enabled.
+    pub fn key_id(payload: &[u8]) -> Option<u32> {
+        if payload.len() < PAYLOAD_OFFSET || &payload[..MAGIC.len()] != MAGIC {
+            return None;
+        }
+        Some(u32::from_le_bytes(payload[MAGIC.len()..HEADER_LEN].try_into().unwrap()))
+    }
+
+    fn seal(&self, plaintext: &[u8], context: &[u8]) -> Vec<u8> {
+        if plaintext.is_empty() {
+            return Vec::new();
+        }
+        let mut sealed = MAGIC.to_vec();
+        sealed.extend(self.active.to_le_bytes());
+        let header = sealed.clone();
+        let data_key: [u8; KEY_LEN] = random();
+        let key_nonce: [u8; NONCE_LEN] = random();
+        let wrapped = self.keys[&self.active]
+            .encrypt(XNonce::from_slice(&key_nonce), Payload { msg: &data_key, aad: &header })
+            .expect("XChaCha20-Poly1305 encryption does not fail");
+        let nonce: [u8; NONCE_LEN] = random();
+        let aad = [&header[..], context].concat();
+        let ciphertext = XChaCha20Poly1305::new_from_slice(&data_key)
+            .expect("data keys are 32 bytes")
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad })
+            .expect("XChaCha20-Poly1305 encryption does not fail");
+        sealed.extend(key_nonce);
+        sealed.extend(wrapped);
+        sealed.extend(nonce);
+        sealed.extend(ciphertext);
+        sealed
+    }
+
+    fn open(&self, payload: Vec<u8>, context: &[u8]) -> Result<Vec<u8>> {
+        let Some(id) = Self::key_id(&payload) else {
+            return Ok(payload);
+        };
+        let master_key = self.keys.get(&id).ok_or_else(|| {
+            StorageError::Corrupt(anyhow!("payload is encrypted with master key {}, which is not configured", id))
+        })?;
+        let header = &payload[..HEADER_LEN];
+        let (key_nonce, rest) = payload[HEADER_LEN..].split_at(NONCE_LEN);
+        let (wrapped, rest) = rest.split_at(WRAPPED_KEY_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let data_key = master_key
+            .decrypt(XNonce::from_slice(key_nonce), Payload { msg: wrapped, aad: header })
+            .map_err(|_| StorageError::Corrupt(anyhow!("data key does not unwrap with master key {}", id)))?;
+        let aad = [header, context].concat();
+        XChaCha20Poly1305::new_from_slice(&data_key)
+            .map_err(|_| StorageError::Corrupt(anyhow!("unwrapped data key has the wrong length")))?
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| StorageError::Corrupt(anyhow!("payload failed authentication")))
+    }
+
+    fn is_stale(&self, payload: &[u8]) -> bool {
+        !payload.is_empty() && Self::key_id(payload) != Some(self.active)
+    }
+}
+
+/// A row with encrypted payloads.
+trait Sealed: Clone {
+    /// What the row's payloads are bound to: its kind and the columns that identify it.
+    fn context(&self) -> Vec<u8>;
+    fn payloads(&self) -> Vec<&[u8]>;
+    /// The payloads with the labels that bind each to its column, in the order of `payloads`.
+    fn payloads_mut(&mut self) -> Vec<(&'static [u8], &mut Vec<u8>)>;
+}
+
+impl Sealed for CompressedAccount {
+    fn context(&self) -> Vec<u8> {
+        [&b"account"[..], &self.pubkey, &self.slot.to_le_bytes(), &self.write_version.to_le_bytes()].concat()
+    }
+
+    fn payloads(&self) -> Vec<&[u8]> {
+        vec![&self.data, &self.proof]
+    }
+
+    fn payloads_mut(&mut self) -> Vec<(&'static [u8], &mut Vec<u8>)> {
+        vec![(b"data", &mut self.data), (b"proof", &mut self.proof)]
+    }
+}
+
+impl Sealed for CompressedBlock {
+    fn context(&self) -> Vec<u8> {
+        [&b"block"[..], &self.slot.to_le_bytes()].concat()
+    }
+
+    fn payloads(&self) -> Vec<&[u8]> {
+        vec![&self.data, &self.proof]
+    }
+
+    fn payloads_mut(&mut self) -> Vec<(&'static [u8], &mut Vec<u8>)> {
+        vec![(b"data", &mut self.data), (b"proof", &mut self.proof)]
+    }
+}
+
+impl Sealed for CompressedTransaction {
+    fn context(&self) -> Vec<u8> {
+        [&b"transaction"[..], &self.signature].concat()
+    }
+
+    fn payloads(&self) -> Vec<&[u8]> {
+        vec![&self.data, &self.proof]
+    }
+
+    fn payloads_mut(&mut self) -> Vec<(&'static [u8], &mut Vec<u8>)> {
+        vec![(b"data", &mut self.data), (b"proof", &mut self.proof)]
+    }
+}
+
+impl Sealed for CompressedStateAccount {
+    fn context(&self) -> Vec<u8> {
+        [&b"state_account"[..], &self.hash].concat()
+    }
+
+    fn payloads(&self) -> Vec<&[u8]> {
+        vec![&self.data]
+    }
+
+    fn payloads_mut(&mut self) -> Vec<(&'static [u8], &mut Vec<u8>)> {
+        vec![(b"data", &mut self.data)]
+    }
+}
+
+impl Sealed for CompressedTokenAccountRecord {
+    fn context(&self) -> Vec<u8> {
+        [&b"token_account"[..], &self.hash].concat()
+    }
+
+    fn payloads(&self) -> Vec<&[u8]> {
+        self.tlv.iter().map(Vec::as_slice).collect()
+    }
+
+    fn payloads_mut(&mut self) -> Vec<(&'static [u8], &mut Vec<u8>)> {
+        self.tlv.iter_mut().map(|tlv| (&b"tlv"[..], tlv)).collect()
+    }
+}
+
+/// The master keys, and whether plaintext payloads are refused on the way out.
+#[derive(Clone)]
+struct Envelope {
+    keys: Arc<MasterKeys>,
+    required: bool,
+}
+
+impl Envelope {
+    fn encrypt<T: Sealed>(&self, row: &T) -> T {
+        let mut row = row.clone();
+        let context = row.context();
+        for (label, payload) in row.payloads_mut() {
+            *payload = self.keys.seal(payload, &[&context[..], label].concat());
+        }
+        row
+    }
+
+    fn decrypt<T: Sealed>(&self, row: T) -> Result<T> {
+        self.open(row, self.required)
+    }
+
+    fn decrypt_all<T: Sealed>(&self, rows: Vec<T>) -> Result<Vec<T>> {
+        rows.into_iter().map(|row| self.decrypt(row)).collect()
+    }
+
+    fn open<T: Sealed>(&self, mut row: T, required: bool) -> Result<T> {
+        let context = row.context();
+        for (label, payload) in row.payloads_mut() {
+            if required && !payload.is_empty() && MasterKeys::key_id(payload).is_none() {
+                return Err(StorageError::Corrupt(anyhow!(
+                    "{} payload is not encrypted, and encryption is required",
+                    String::from_utf8_lossy(label)
+                )));
+            }
+            *payload = self.keys.open(std::mem::take(payload), &[&context[..], label].concat())?;
+        }
+        Ok(row)
+    }
+
+    /// The key of the first payload that is not under the active master key, `Some(None)`
+    /// for plaintext, or `None` if there is no such payload.
+    fn stale_key<T: Sealed>(&self, row: &T) -> Option<Option<u32>> {
+        row.payloads()
+            .into_iter()
+            .find(|payload| self.keys.is_stale(payload))
+            .map(MasterKeys::key_id)
+    }
+}
+
+fn found<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(row) => Ok(Some(row)),
+        Err(StorageError::NotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// What a re-encryption pass did.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Reencryption {
+    /// Rows rewritten under the active master key.
+    pub rewritten: u64,
+    /// Rows the pass left in plaintext (`None`) or under another master key, by key: those
+    /// under a key that is not configured or doesn't decrypt them, and those the backend keeps
+    /// as first written.
+    pub remaining: BTreeMap<Option<u32>, u64>,
+}
+
+/// Encrypts `data` and `proof` on the way into another `Database` and decrypts them on the way
+/// out, and does the same for the payloads of a compression store given with
+/// [`with_compression_store`](Self::with_compression_store). Rows that are still plaintext are
+/// read as they are unless encryption is [required](Self::with_required_encryption), so
+/// encryption can be enabled on an existing index; [`reencrypt`](Self::reencrypt) brings them,
+/// and rows under an older master key, up to the active key. `get_account_changes` is not
+/// forwarded, so changes are diffed from decrypted versions.
+pub struct EncryptedStorage {
+    inner: Arc<dyn Database>,
+    compression: Option<Arc<dyn CompressionStore>>,
+    envelope: Envelope,
+    // Writes through this storage and its compression store hold it shared; re-encryption
+    // holds it exclusively from re-reading a row to rewriting it, so it never undoes a write.
+    writes: Arc<RwLock<()>>,
+}
+
+impl EncryptedStorage {
+    pub fn new(inner: Arc<dyn Database>, keys: MasterKeys) -> Self {
+        Self {
+            inner,
+            compression: None,
+            envelope: Envelope {
+                keys: Arc::new(keys),
+                required: false,
+            },
+            writes: Arc::new(RwLock::new(())),
+        }
+    }
+
+    /// Also encrypts the state account data and token account TLV of `store`, as seen through
+    /// [`compression_store`](Self::compression_store), and re-encrypts them with the rest.
+    pub fn with_compression_store(mut self, store: Arc<dyn CompressionStore>) -> Self {
+        self.compression = Some(store);
+        self
+    }
+
+    /// Rejects plaintext payloads as corrupt instead of reading them as they are, for once
+    /// re-encryption has left none.
+    pub fn with_required_encryption(mut self) -> Self {
+        self.envelope.required = true;
+        self
+    }
+
+    /// The compression store given to [`with_compression_store`](Self::with_compression_store),
+    /// encrypting on the way in and decrypting on the way out.
+    pub fn compression_store(&self) -> Option<Arc<dyn CompressionStore>> {
+        self.compression.as_ref().map(|inner| {
+            Arc::new(EncryptedCompressionStore {
+                inner: Arc::clone(inner),
+                envelope: self.envelope.clone(),
+                writes: Arc::clone(&self.writes),
+            }) as Arc<dyn CompressionStore>
+        })
+    }
+
+    /// Rewrites the blocks up to the cursor, and every transaction, account version and
+    /// compression store row, whose payloads are plaintext or under a master key other than
+    /// the active one. Each table is walked directly, so rows whose block was pruned and
+    /// accounts no transaction references are covered too. Blocks past the cursor were written
+    /// by an interrupted commit, which replaying the slot writes again.
+    ///
+    /// Once a pass leaves nothing [remaining](Reencryption::remaining), older master keys can
+    /// be retired.
+    pub async fn reencrypt(&self) -> Result<Reencryption> {
+        let mut pass = Reencryption::default();
+        self.reencrypt_blocks(&mut pass).await?;
+        self.reencrypt_transactions(&mut pass).await?;
+        self.reencrypt_accounts(&mut pass).await?;
+        if let Some(store) = &self.compression {
+            self.reencrypt_compression(store.as_ref(), &mut pass).await?;
+        }
+        Ok(pass)
+    }
+
+    async fn reencrypt_blocks(&self, pass: &mut Reencryption) -> Result<()> {
+        let cursor = self.inner.get_last_processed_slot().await?;
+        let Some(mut start) = self.inner.get_first_block_slot().await? else {
+            return Ok(());
+        };
+        while start <= cursor {
+            let end = start.saturating_add(SLOTS_PER_READ - 1).min(cursor);
+            for block in self.inner.get_blocks_in_range(start, end).await? {
+                if self.envelope.stale_key(&block).is_some() {
+                    let slot = block.slot;
+                    self.rewrite(
+                        pass,
+                        || found_in(self.inner.get_compressed_block(slot)),
+                        |block| Box::pin(async move { self.inner.insert_compressed_block(&block).await }),
+                    )
+                    .await?;
+                }
+            }
+            start = end + 1;
+        }
+        Ok(())
+    }
+
+    async fn reencrypt_transactions(&self, pass: &mut Reencryption) -> Result<()> {
+        let mut after: Option<Vec<u8>> = None;
+        loop {
+            let signatures = self.inner.get_transaction_signatures(after.as_deref(), ROWS_PER_READ).await?;
+            for signature in &signatures {
+                let Some(transaction) = found(self.inner.get_compressed_transaction(signature).await)? else {
+                    continue;
+                };
+                if self.envelope.stale_key(&transaction).is_some() {
+                    self.rewrite(
+                        pass,
+                        || found_in(self.inner.get_compressed_transaction(signature)),
+                        |transaction| Box::pin(async move { self.inner.insert_compressed_transaction(&transaction).await }),
+                    )
+                    .await?;
+                }
+            }
+            match signatures.last() {
+                Some(last) => after = Some(last.clone()),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    async fn reencrypt_accounts(&self, pass: &mut Reencryption) -> Result<()> {
+        let mut after: Option<Vec<u8>> = None;
+        loop {
+            let pubkeys = self.inner.get_account_pubkeys(after.as_deref(), ROWS_PER_READ).await?;
+            for pubkey in &pubkeys {
+                for account in self.inner.get_account_versions(pubkey, 0, u64::MAX, ALL_VERSIONS).await? {
+                    if self.envelope.stale_key(&account).is_some() {
+                        let (slot, write_version) = (account.slot, account.write_version);
+                        self.rewrite(
+                            pass,
+                            || {
+                                Box::pin(async move {
+                                    let versions = self.inner.get_account_versions(pubkey, slot, slot, ALL_VERSIONS).await?;
+                                    Ok(versions.into_iter().find(|account| account.write_version == write_version))
+                                })
+                            },
+                            |account| Box::pin(async move { self.inner.insert_compressed_account(&account).await }),
+                        )
+                        .await?;
+                    }
+                }
+                // The current row is the newest version, unless that was written concurrently.
+                let current = found(self.inner.get_compressed_account(pubkey).await)?;
+                if current.is_some_and(|account| self.envelope.stale_key(&account).is_some()) {
+                    self.rewrite(
+                        pass,
+                        || found_in(self.inner.get_compressed_account(pubkey)),
+                        |account| Box::pin(async move { self.inner.insert_compressed_account(&account).await }),
+                    )
+                    .await?;
+                }
+            }
+            match pubkeys.last() {
+                Some(last) => after = Some(last.clone()),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    async fn reencrypt_compression(&self, store: &dyn CompressionStore, pass: &mut Reencryption) -> Result<()> {
+        let mut after: Option<Vec<u8>> = None;
+        loop {
+            let accounts = store.list_state_accounts(after.as_deref(), ROWS_PER_READ).await?;
+            for account in &accounts {
+                if self.envelope.stale_key(account).is_some() {
+                    self.rewrite(
+                        pass,
+                        || Box::pin(async move { Ok(store.get_state_account(&account.hash).await?) }),
+                        |account| Box::pin(async move { Ok(store.upsert_state_account(&account).await?) }),
+                    )
+                    .await?;
+                }
+            }
+            match accounts.last() {
+                Some(last) => after = Some(last.hash.clone()),
+                None => break,
+            }
+        }
+
+        let mut after: Option<Vec<u8>> = None;
+        loop {
+            let accounts = store.list_token_accounts(after.as_deref(), ROWS_PER_READ).await?;
+            for account in &accounts {
+                if self.envelope.stale_key(account).is_some() {
+                    self.rewrite(
+                        pass,
+                        || Box::pin(async move { Ok(store.get_token_account(&account.hash).await?) }),
+                        |account| Box::pin(async move { Ok(store.upsert_token_account(&account).await?) }),
+                    )
+                    .await?;
+                }
+            }
+            match accounts.last() {
+                Some(last) => after = Some(last.hash.clone()),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Re-reads a stale row with writes held off and rewrites it under the active key, then
+    /// reads it back to count it as rewritten or remaining.
+    async fn rewrite<'a, T: Sealed + Send + 'a>(
+        &'a self,
+        pass: &mut Reencryption,
+        read: impl Fn() -> BoxFuture<'a, Result<Option<T>>>,
+        write: impl Fn(T) -> BoxFuture<'a, Result<()>>,
+    ) -> Result<()> {
+        let _rewriting = self.writes.write().await;
+        let Some(row) = read().await? else {
+            return Ok(());
+        };
+        let Some(key) = self.envelope.stale_key(&row) else {
+            return Ok(());
+        };
+        match self.envelope.open(row, false) {
+            Ok(row) => write(self.envelope.encrypt(&row)).await?,
+            Err(StorageError::Corrupt(e)) => warn!("Cannot re-encrypt a row under master key {:?}: {:#}", key, e),
+            Err(e) => return Err(e),
+        }
+        match read().await?.and_then(|row| self.envelope.stale_key(&row)) {
+            Some(key) => *pass.remaining.entry(key).or_default() += 1,
+            None => pass.rewritten += 1,
+        }
+        Ok(())
+    }
+
+    /// Re-encrypts every `interval` until a pass has nothing left to rewrite, then reports
+    /// whatever it could not.
+    pub async fn run_reencryption(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match self.reencrypt().await {
+                Ok(pass) if pass.rewritten > 0 => {
+                    info!("Re-encrypted {} rows with master key {}", pass.rewritten, self.envelope.keys.active())
+                }
+                Ok(pass) if pass.remaining.is_empty() => {
+                    info!("Every stored payload is encrypted with master key {}", self.envelope.keys.active());
+                    return;
+                }
+                Ok(pass) => {
+                    for (key, rows) in pass.remaining {
+                        match key {
+                            Some(id) => error!("{} rows are still encrypted with master key {}", rows, id),
+                            None => error!("{} rows are still stored in plaintext", rows),
+                        }
+                    }
+                    return;
+                }
+                Err(e) => error!("Error re-encrypting storage: {:?}", e),
+            }
+        }
+    }
+}
+
+fn found_in<'a, T: Send + 'a>(read: BoxFuture<'a, Result<T>>) -> BoxFuture<'a, Result<Option<T>>> {
+    Box::pin(async move { found(read.await) })
+}
+
+#[async_trait]
+impl Database for EncryptedStorage {
+    async fn insert_compressed_account(&self, account: &CompressedAccount) -> Result<()> {
+        let _writing = self.writes.read().await;
+        self.inner.insert_compressed_account(&self.envelope.encrypt(account)).await
+    }
+
+    async fn get_compressed_account(&self, pubkey: &[u8]) -> Result<CompressedAccount> {
+        self.envelope.decrypt(self.inner.get_compressed_account(pubkey).await?)
+    }
+
+    async fn insert_compressed_block(&self, block: &CompressedBlock) -> Result<()> {
+        let _writing = self.writes.read().await;
+        self.inner.insert_compressed_block(&self.envelope.encrypt(block)).await
+    }
+
+    async fn get_compressed_block(&self, slot: u64) -> Result<CompressedBlock> {
+        self.envelope.decrypt(self.inner.get_compressed_block(slot).await?)
+    }
+
+    async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> Result<()> {
+        let _writing = self.writes.read().await;
+        self.inner.insert_compressed_transaction(&self.envelope.encrypt(transaction)).await
+    }
+
+    async fn get_compressed_transaction(&self, signature: &[u8]) -> Result<CompressedTransaction> {
+        self.envelope.decrypt(self.inner.get_compressed_transaction(signature).await?)
+    }
+
+    async fn get_accounts_by_owner(&self, owner: &[u8], limit: usize) -> Result<Vec<CompressedAccount>> {
+        self.envelope.decrypt_all(self.inner.get_accounts_by_owner(owner, limit).await?)
+    }
+
+    async fn get_signatures_for_address(&self, address: &[u8], query: &SignatureQuery) -> Result<Vec<SignatureInfo>> {
+        self.inner.get_signatures_for_address(address, query).await
+    }
+
+    async fn get_blocks_in_range(&self, start_slot: u64, end_slot: u64) -> Result<Vec<CompressedBlock>> {
+        self.envelope.decrypt_all(self.inner.get_blocks_in_range(start_slot, end_slot).await?)
+    }
+
+    async fn get_first_block_slot(&self) -> Result<Option<u64>> {
+        self.inner.get_first_block_slot().await
+    }
+
+    async fn get_transactions_in_block(&self, slot: u64) -> Result<Vec<CompressedTransaction>> {
+        self.envelope.decrypt_all(self.inner.get_transactions_in_block(slot).await?)
+    }
+
+    async fn delete_slot_range(&self, start_slot: u64, end_slot: u64) -> Result<u64> {
+        let _writing = self.writes.read().await;
+        self.inner.delete_slot_range(start_slot, end_slot).await
+    }
+
+    async fn get_account_at_slot(&self, pubkey: &[u8], slot: u64) -> Result<Option<CompressedAccount>> {
+        match self.inner.get_account_at_slot(pubkey, slot).await? {
+            Some(account) => Ok(Some(self.envelope.decrypt(account)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_account_versions(
+        &self,
+        pubkey: &[u8],
+        start_slot: u64,
+        end_slot: u64,
+        limit: usize,
+    ) -> Result<Vec<CompressedAccount>> {
+        self.envelope.decrypt_all(self.inner.get_account_versions(pubkey, start_slot, end_slot, limit).await?)
+    }
+
+    async fn get_account_pubkeys(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        self.inner.get_account_pubkeys(after, limit).await
+    }
+
+    async fn get_transaction_signatures(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        self.inner.get_transaction_signatures(after, limit).await
+    }
+
+    async fn prune(&self, request: &PruneRequest) -> Result<PruneStats> {
+        let _writing = self.writes.read().await;
+        self.inner.prune(request).await
+    }
+
+    async fn get_last_processed_slot(&self) -> Result<u64> {
+        self.inner.get_last_processed_slot().await
+    }
+
+    async fn update_last_processed_slot(&self, slot: u64) -> Result<()> {
+        self.inner.update_last_processed_slot(slot).await
+    }
+
+    async fn commit_block(&self, batch: &BlockBatch) -> Result<()> {
+        let batch = BlockBatch {
+            slot: batch.slot,
+            block: batch.block.as_ref().map(|block| self.envelope.encrypt(block)),
+            transactions: batch
+                .transactions
+                .iter()
+                .map(|transaction| self.envelope.encrypt(transaction))
+                .collect(),
+            accounts: batch.accounts.iter().map(|account| self.envelope.encrypt(account)).collect(),
+            token_transfers: batch.token_transfers.clone(),
+        };
+        let _writing = self.writes.read().await;
+        self.inner.commit_block(&batch).await
+    }
+
+    fn stores_token_transfers(&self) -> bool {
+        self.inner.stores_token_transfers()
+    }
+
+    async fn get_token_transfers_in_block(&self, slot: u64) -> Result<Vec<TokenTransfer>> {
+        self.inner.get_token_transfers_in_block(slot).await
+    }
+}
+
+/// The compression store side of [`EncryptedStorage`].
+struct EncryptedCompressionStore {
+    inner: Arc<dyn CompressionStore>,
+    envelope: Envelope,
+    writes: Arc<RwLock<()>>,
+}
+
+impl EncryptedCompressionStore {
+    fn decrypt<T: Sealed>(&self, row: Option<T>) -> anyhow::Result<Option<T>> {
+        Ok(row.map(|row| self.envelope.decrypt(row)).transpose()?)
+    }
+
+    fn decrypt_all<T: Sealed>(&self, rows: Vec<T>) -> anyhow::Result<Vec<T>> {
+        Ok(self.envelope.decrypt_all(rows)?)
+    }
+}
+
+#[async_trait]
+impl CompressionStore for EncryptedCompressionStore {
+    async fn get_compressed_slot(&self) -> anyhow::Result<u64> {
+        self.inner.get_compressed_slot().await
+    }
+
+    async fn update_compressed_slot(&self, slot: u64) -> anyhow::Result<()> {
+        self.inner.update_compressed_slot(slot).await
+    }
+
+    async fn upsert_merkle_tree(&self, tree: &MerkleTreeRecord) -> anyhow::Result<()> {
+        self.inner.upsert_merkle_tree(tree).await
+    }
+
+    async fn get_merkle_tree(&self, tree: &[u8]) -> anyhow::Result<Option<MerkleTreeRecord>> {
+        self.inner.get_merkle_tree(tree).await
+    }
+
+    async fn get_merkle_trees(&self) -> anyhow::Result<Vec<MerkleTreeRecord>> {
+        self.inner.get_merkle_trees().await
+    }
+
+    async fn delete_merkle_tree(&self, tree: &[u8]) -> anyhow::Result<()> {
+        self.inner.delete_merkle_tree(tree).await
+    }
+
+    async fn insert_tree_root(&self, root: &TreeRootRecord) -> anyhow::Result<()> {
+        self.inner.insert_tree_root(root).await
+    }
+
+    async fn get_tree_roots(&self, tree: &[u8]) -> anyhow::Result<Vec<TreeRootRecord>> {
+        self.inner.get_tree_roots(tree).await
+    }
+
+    async fn insert_leaf_change(&self, change: &LeafChangeRecord) -> anyhow::Result<()> {
+        self.inner.insert_leaf_change(change).await
+    }
+
+    async fn get_leaf_changes(&self, tree: &[u8], after_seq: u64) -> anyhow::Result<Vec<LeafChangeRecord>> {
+        self.inner.get_leaf_changes(tree, after_seq).await
+    }
+
+    async fn upsert_state_account(&self, account: &CompressedStateAccount) -> anyhow::Result<()> {
+        let _writing = self.writes.read().await;
+        self.inner.upsert_state_account(&self.envelope.encrypt(account)).await
+    }
+
+    async fn get_state_account(&self, hash: &[u8]) -> anyhow::Result<Option<CompressedStateAccount>> {
+        self.decrypt(self.inner.get_state_account(hash).await?)
+    }
+
+    async fn get_state_account_by_address(&self, address: &[u8]) -> anyhow::Result<Option<CompressedStateAccount>> {
+        self.decrypt(self.inner.get_state_account_by_address(address).await?)
+    }
+
+    async fn get_state_accounts_by_owner(&self, owner: &[u8]) -> anyhow::Result<Vec<CompressedStateAccount>> {
+        self.decrypt_all(self.inner.get_state_accounts_by_owner(owner).await?)
+    }
+
+    async fn spend_state_account(&self, hash: &[u8], slot: u64) -> anyhow::Result<()> {
+        let _writing = self.writes.read().await;
+        self.inner.spend_state_account(hash, slot).await
+    }
+
+    async fn insert_address(&self, address: &AddressRecord) -> anyhow::Result<()> {
+        self.inner.insert_address(address).await
+    }
+
+    async fn get_address(&self, address: &[u8]) -> anyhow::Result<Option<AddressRecord>> {
+        self.inner.get_address(address).await
+    }
+
+    async fn get_low_address(&self, tree: &[u8], address: &[u8]) -> anyhow::Result<Option<AddressRecord>> {
+        self.inner.get_low_address(tree, address).await
+    }
+
+    async fn upsert_token_account(&self, account: &CompressedTokenAccountRecord) -> anyhow::Result<()> {
+        let _writing = self.writes.read().await;
+        self.inner.upsert_token_account(&self.envelope.encrypt(account)).await
+    }
+
+    async fn delete_token_account(&self, hash: &[u8]) -> anyhow::Result<()> {
+        let _writing = self.writes.read().await;
+        self.inner.delete_token_account(hash).await
+    }
+
+    async fn get_token_account(&self, hash: &[u8]) -> anyhow::Result<Option<CompressedTokenAccountRecord>> {
+        self.decrypt(self.inner.get_token_account(hash).await?)
+    }
+
+    async fn get_token_accounts_by_owner(&self, owner: &[u8]) -> anyhow::Result<Vec<CompressedTokenAccountRecord>> {
+        self.decrypt_all(self.inner.get_token_accounts_by_owner(owner).await?)
+    }
+
+    async fn get_token_accounts_by_mint(&self, mint: &[u8]) -> anyhow::Result<Vec<CompressedTokenAccountRecord>> {
+        self.decrypt_all(self.inner.get_token_accounts_by_mint(mint).await?)
+    }
+
+    async fn upsert_token_mint(&self, mint: &TokenMintRecord) -> anyhow::Result<()> {
+        self.inner.upsert_token_mint(mint).await
+    }
+
+    async fn get_token_mint(&self, mint: &[u8]) -> anyhow::Result<Option<TokenMintRecord>> {
+        self.inner.get_token_mint(mint).await
+    }
+
+    async fn upsert_asset(&self, asset: &CompressedAssetRecord) -> anyhow::Result<()> {
+        self.inner.upsert_asset(asset).await
+    }
+
+    async fn get_asset(&self, asset_id: &[u8]) -> anyhow::Result<Option<CompressedAssetRecord>> {
+        self.inner.get_asset(asset_id).await
+    }
+
+    async fn get_assets_by_owner(&self, owner: &[u8]) -> anyhow::Result<Vec<CompressedAssetRecord>> {
+        self.inner.get_assets_by_owner(owner).await
+    }
+
+    async fn list_state_accounts(&self, after: Option<&[u8]>, limit: usize) -> anyhow::Result<Vec<CompressedStateAccount>> {
+        self.decrypt_all(self.inner.list_state_accounts(after, limit).await?)
+    }
+
+    async fn list_addresses(&self, after: Option<&[u8]>, limit: usize) -> anyhow::Result<Vec<AddressRecord>> {
+        self.inner.list_addresses(after, limit).await
+    }
+
+    async fn list_token_accounts(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<CompressedTokenAccountRecord>> {
+        self.decrypt_all(self.inner.list_token_accounts(after, limit).await?)
+    }
+
+    async fn list_token_mints(&self, after: Option<&[u8]>, limit: usize) -> anyhow::Result<Vec<TokenMintRecord>> {
+        self.inner.list_token_mints(after, limit).await
+    }
+
+    async fn list_assets(&self, after: Option<&[u8]>, limit: usize) -> anyhow::Result<Vec<CompressedAssetRecord>> {
+        self.inner.list_assets(after, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance::storage_conformance;
+    use crate::storage::InMemoryStorage;
+
+    const KEY_1: &str = "1:0101010101010101010101010101010101010101010101010101010101010101";
+    const KEY_2: &str = "2:0202020202020202020202020202020202020202020202020202020202020202";
+
+    storage_conformance!(EncryptedStorage::new(
+        Arc::new(InMemoryStorage::new()),
+        MasterKeys::parse(KEY_1).unwrap()
+    ));
+
+    fn block(slot: u64) -> CompressedBlock {
+        CompressedBlock {
+            slot,
+            blockhash: format!("hash-{}", slot),
+            previous_blockhash: format!("hash-{}", slot - 1),
+            parent_slot: slot - 1,
+            transactions: 0,
+            block_time: None,
+            data: b"block payload".to_vec(),
+            proof: vec![],
+        }
+    }
+
+    #[test]
+    fn test_master_keys() {
+        let keys = MasterKeys::parse(&format!("# rotated 2024-10-28\n{}\n{}", KEY_1, KEY_2)).unwrap();
+        assert_eq!(keys.active(), 2);
+        assert_eq!(MasterKeys::parse(&format!("{},{}", KEY_1, KEY_2)).unwrap().with_active(1).unwrap().active(), 1);
+        assert!(MasterKeys::parse("").is_err());
+        assert!(MasterKeys::parse("1:0101").is_err());
+        assert!(MasterKeys::parse(&format!("{} {}", KEY_1, KEY_1)).is_err());
+
+        let sealed = keys.seal(b"payload", b"row");
+        assert_eq!(MasterKeys::key_id(&sealed), Some(2));
+        assert_eq!(keys.open(sealed.clone(), b"row").unwrap(), b"payload");
+        assert!(matches!(keys.open(sealed, b"other row"), Err(StorageError::Corrupt(_))));
+        assert_eq!(keys.open(b"plaintext".to_vec(), b"row").unwrap(), b"plaintext");
+    }
+
+    #[tokio::test]
+    async fn test_rotation() {
+        let inner = Arc::new(InMemoryStorage::new());
+        inner.insert_compressed_block(&block(1)).await.unwrap();
+        let old = EncryptedStorage::new(inner.clone(), MasterKeys::parse(KEY_1).unwrap());
+        let mut batch = BlockBatch::new(2);
+        batch.block = Some(block(2));
+        old.commit_block(&batch).await.unwrap();
+        assert_eq!(MasterKeys::key_id(&inner.get_compressed_block(2).await.unwrap().data), Some(1));
+
+        let rotated = EncryptedStorage::new(inner.clone(), MasterKeys::parse(&format!("{},{}", KEY_1, KEY_2)).unwrap());
+        assert_eq!(rotated.get_compressed_block(2).await.unwrap().data, b"block payload");
+        assert_eq!(rotated.reencrypt().await.unwrap().rewritten, 2);
+        assert_eq!(rotated.reencrypt().await.unwrap(), Reencryption::default());
+
+        let retired = EncryptedStorage::new(inner.clone(), MasterKeys::parse(KEY_2).unwrap());
+        for slot in [1, 2] {
+            assert_eq!(MasterKeys::key_id(&inner.get_compressed_block(slot).await.unwrap().data), Some(2));
+            assert_eq!(retired.get_compressed_block(slot).await.unwrap().data, b"block payload");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reencrypt_every_table() {
+        let inner = Arc::new(InMemoryStorage::new());
+        // Written before encryption was enabled: a transaction whose block was pruned, an
+        // account no transaction references and a compressed account.
+        let transaction = CompressedTransaction {
+            signature: vec![7; 64],
+            slot: 5,
+            index: 0,
+            accounts: vec![],
+            fee: 0,
+            programs: vec![],
+            data: b"transaction payload".to_vec(),
+            proof: vec![],
+        };
+        inner.insert_compressed_transaction(&transaction).await.unwrap();
+        let account = CompressedAccount {
+            pubkey: vec![8; 32],
+            lamports: 1,
+            owner: vec![9; 32],
+            executable: false,
+            rent_epoch: 0,
+            slot: 5,
+            write_version: 0,
+            data: b"account payload".to_vec(),
+            proof: vec![],
+        };
+        inner.insert_compressed_account(&account).await.unwrap();
+        let state_account = CompressedStateAccount {
+            hash: vec![10; 32],
+            address: None,
+            owner: vec![9; 32],
+            lamports: 1,
+            discriminator: None,
+            data: b"state payload".to_vec(),
+            data_hash: None,
+            tree: vec![11; 32],
+            leaf_index: 0,
+            seq: None,
+            slot_created: 5,
+            spent: false,
+            spent_slot: None,
+        };
+        inner.upsert_state_account(&state_account).await.unwrap();
+
+        let storage = EncryptedStorage::new(inner.clone(), MasterKeys::parse(KEY_1).unwrap())
+            .with_compression_store(inner.clone())
+            .with_required_encryption();
+        let store = storage.compression_store().unwrap();
+        assert!(matches!(
+            storage.get_compressed_transaction(&transaction.signature).await,
+            Err(StorageError::Corrupt(_))
+        ));
+        assert!(store.get_state_account(&state_account.hash).await.is_err());
+
+        let pass = storage.reencrypt().await.unwrap();
+        assert_eq!(pass.rewritten, 3);
+        assert!(pass.remaining.is_empty());
+        let stored = inner.get_compressed_transaction(&transaction.signature).await.unwrap();
+        assert_eq!(MasterKeys::key_id(&stored.data), Some(1));
+        let stored = inner.get_state_account(&state_account.hash).await.unwrap().unwrap();
+        assert_eq!(MasterKeys::key_id(&stored.data), Some(1));
+        assert_eq!(storage.get_compressed_account(&account.pubkey).await.unwrap().data, b"account payload");
+        assert_eq!(store.get_state_account(&state_account.hash).await.unwrap().unwrap().data, b"state payload");
+
+        // A row under a key this node doesn't have is reported, not counted as rewritten.
+        let other = EncryptedStorage::new(inner.clone(), MasterKeys::parse(KEY_2).unwrap());
+        other.insert_compressed_block(&block(6)).await.unwrap();
+        other.update_last_processed_slot(6).await.unwrap();
+        let pass = storage.reencrypt().await.unwrap();
+        assert_eq!(pass.rewritten, 0);
+        assert_eq!(pass.remaining, BTreeMap::from([(Some(2), 1)]));
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt::Display;
+use thiserror::Error;
+
+pub type Result<T, E = StorageError> = std::result::Result<T, E>;
+
+/// What went wrong in a storage call, as far as a caller needs to know to decide between
+/// reporting, retrying and giving up. Backends convert their native errors with `?`; errors
+/// that reach here through `anyhow` are classified by the first native error in their chain.
+#[derive(Error, Debug)]
+pub enum StorageError {
+    /// The row does not exist.
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// The write clashed with a concurrent one or with a uniqueness constraint.
+    #[error("Conflict: {0:#}")]
+    Conflict(anyhow::Error),
+    /// The backend could not be reached or is overloaded. Retrying later may succeed.
+    #[error("Storage unavailable: {0:#}")]
+    Unavailable(anyhow::Error),
+    /// Stored data could not be decoded, e.g. after a schema change the code does not know about.
+    #[error("Corrupt data: {0:#}")]
+    Corrupt(anyhow::Error),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl StorageError {
+    pub fn not_found(what: impl Display) -> Self {
+        Self::NotFound(what.to_string())
+    }
+
+    /// Whether the same call may succeed if repeated.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Unavailable(_) | Self::Conflict(_))
+    }
+
+    fn classify(error: anyhow::Error) -> Self {
+        let kind = error.chain().find_map(|cause| {
+            if let Some(error) = cause.downcast_ref::<StorageError>() {
+                return Some(Kind::of(error));
+            }
+            if let Some(error) = cause.downcast_ref::<sqlx::Error>() {
+                return Some(Kind::of_sqlx(error));
+            }
+            if let Some(error) = cause.downcast_ref::<redis::RedisError>() {
+                return Some(Kind::of_redis(error));
+            }
+            if let Some(error) = cause.downcast_ref::<scylla::transport::errors::QueryError>() {
+                return Some(Kind::of_scylla(error));
+            }
+            if let Some(error) = cause.downcast_ref::<clickhouse::error::Error>() {
+                return Some(Kind::of_clickhouse(error));
+            }
+            if cause.is::<bincode::Error>() || cause.is::<bincode::ErrorKind>() || cause.is::<serde_json::Error>() {
+                return Some(Kind::Corrupt);
+            }
+            cause.downcast_ref::<std::io::Error>().map(Kind::of_io)
+        });
+        match kind {
+            Some(Kind::NotFound) => Self::NotFound(format!("{:#}", error)),
+            Some(Kind::Conflict) => Self::Conflict(error),
+            Some(Kind::Unavailable) => Self::Unavailable(error),
+            Some(Kind::Corrupt) => Self::Corrupt(error),
+            Some(Kind::Other) | None => Self::Other(error),
+        }
+    }
+}
+
+enum Kind {
+    NotFound,
+    Conflict,
+    Unavailable,
+    Corrupt,
+    Other,
+}
+
+impl Kind {
+    fn of(error: &StorageError) -> Self {
+        match error {
+            StorageError::NotFound(_) => Self::NotFound,
+            StorageError::Conflict(_) => Self::Conflict,
+            StorageError::Unavailable(_) => Self::Unavailable,
+            StorageError::Corrupt(_) => Self::Corrupt,
+            StorageError::Other(_) => Self::Other,
+        }
+    }
+
+    fn of_sqlx(error: &sqlx::Error) -> Self {
+        match error {
+            sqlx::Error::RowNotFound => Self::NotFound,
+            sqlx::Error::Database(error) => match error.code().as_deref() {
+                // Postgres serialization failure and deadlock.
+                Some("40001") | Some("40P01") => Self::Conflict,
+                // SQLite busy and locked, including their extended codes.
+                Some(code) if code.parse::<u32>().is_ok_and(|code| matches!(code & 0xff, 5 | 6)) => Self::Unavailable,
+                _ if error.is_unique_violation() => Self::Conflict,
+                _ => Self::Other,
+            },
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::Protocol(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => Self::Unavailable,
+            sqlx::Error::ColumnDecode { .. } | sqlx::Error::Decode(_) | sqlx::Error::ColumnNotFound(_) => Self::Corrupt,
+            _ => Self::Other,
+        }
+    }
+
+    fn of_redis(error: &redis::RedisError) -> Self {
+        if error.is_io_error() || error.is_timeout() || error.is_connection_dropped() || error.is_connection_refusal() {
+            Self::Unavailable
+        } else if error.kind() == redis::ErrorKind::TypeError {
+            Self::Corrupt
+        } else {
+            Self::Other
+        }
+    }
+
+    fn of_scylla(error: &scylla::transport::errors::QueryError) -> Self {
+        use scylla::transport::errors::{DbError, QueryError};
+        match error {
+            QueryError::DbError(
+                DbError::Unavailable { .. }
+                | DbError::Overloaded
+                | DbError::IsBootstrapping
+                | DbError::ReadTimeout { .. }
+                | DbError::WriteTimeout { .. },
+                _,
+            )
+            | QueryError::IoError(_)
+            | QueryError::TimeoutError
+            | QueryError::RequestTimeout(_)
+            | QueryError::BrokenConnection(_)
+            | QueryError::ConnectionPoolError(_)
+            | QueryError::UnableToAllocStreamId => Self::Unavailable,
+            _ => Self::Other,
+        }
+    }
+
+    fn of_clickhouse(error: &clickhouse::error::Error) -> Self {
+        use clickhouse::error::Error;
+        match error {
+            Error::Network(_) | Error::TimedOut => Self::Unavailable,
+            Error::RowNotFound => Self::NotFound,
+            Error::NotEnoughData | Error::InvalidUtf8Encoding(_) | Error::InvalidTagEncoding(_) => Self::Corrupt,
+            _ => Self::Other,
+        }
+    }
+
+    // A missing file is a broken or misconfigured store, not a missing record, so
+    // `ErrorKind::NotFound` is left to `Other`.
+    fn of_io(error: &std::io::Error) -> Self {
+        use std::io::ErrorKind;
+        match error.kind() {
+            ErrorKind::InvalidData | ErrorKind::UnexpectedEof => Self::Corrupt,
+            ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+            | ErrorKind::TimedOut
+            | ErrorKind::Interrupted => Self::Unavailable,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl From<anyhow::Error> for StorageError {
+    fn from(error: anyhow::Error) -> Self {
+        // Errors that already went through here keep their classification.
+        match error.downcast::<StorageError>() {
+            Ok(error) => error,
+            Err(error) => Self::classify(error),
+        }
+    }
+}
+
+macro_rules! from_native {
+    ($($error:ty),* $(,)?) => {
+        $(
+            impl From<$error> for StorageError {
+                fn from(error: $error) -> Self {
+                    Self::classify(error.into())
+                }
+            }
+        )*
+    };
+}
+
+from_native!(
+    sqlx::Error,
+    redis::RedisError,
+    bincode::Error,
+    serde_json::Error,
+    std::io::Error,
+    scylla::transport::errors::QueryError,
+    clickhouse::error::Error,
+);
+
+// Row conversions only fail when stored data does not match the schema the code expects.
+macro_rules! from_corrupt {
+    ($($error:ty),* $(,)?) => {
+        $(
+            impl From<$error> for StorageError {
+                fn from(error: $error) -> Self {
+                    Self::Corrupt(error.into())
+                }
+            }
+        )*
+    };
+}
+
+from_corrupt!(
+    scylla::transport::query_result::RowsExpectedError,
+    scylla::transport::query_result::MaybeFirstRowTypedError,
+    scylla::cql_to_rust::FromRowError,
+);
@@ -0,0 +1,671 @@
+//! Columnar export of indexed tables to Parquet, for analysis in DuckDB, Spark and the like.
+//! Files are Hive-partitioned by epoch, as `<dir>/<table>/epoch=<epoch>/part-<first slot>.parquet`,
+//! and `<dir>/_watermark` holds the last slot exported by incremental runs.
+//!
+//! The index doesn't keep individual instructions, so there is no instructions table;
+//! `transaction_programs` has the programs each transaction's top-level instructions invoke.
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{error, info};
+use parquet::basic::Compression;
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use solana_sdk::bs58;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::{self, File};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::storage::car::SLOTS_PER_EPOCH;
+use crate::storage::{epoch_slots, CompressedAccount, CompressedBlock, CompressedTransaction, Database, TokenTransfer};
+
+const WATERMARK_FILE: &str = "_watermark";
+const DEFAULT_ROW_GROUP_SIZE: usize = 100_000;
+// The largest limit every backend can bind.
+const ALL_VERSIONS: usize = i64::MAX as usize;
+const SLOTS_PER_READ: u64 = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Int64,
+    UInt64,
+    OptionalInt64,
+    OptionalUInt64,
+    Int32,
+    OptionalInt32,
+    Boolean,
+    /// A string; keys, hashes and signatures are exported as base58.
+    Utf8,
+}
+
+enum Value {
+    Int64(i64),
+    OptionalInt64(Option<i64>),
+    Int32(i32),
+    OptionalInt32(Option<i32>),
+    Boolean(bool),
+    Utf8(String),
+}
+
+fn base58(bytes: &[u8]) -> Value {
+    Value::Utf8(bs58::encode(bytes).into_string())
+}
+
+// Unsigned columns hold the value's bits, as the UINT_64 annotation specifies.
+fn uint64(value: u64) -> Value {
+    Value::Int64(value as i64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ExportTable {
+    Blocks,
+    Transactions,
+    /// One row per distinct program a transaction's top-level instructions invoke. This is
+    /// what the index keeps of instructions.
+    TransactionPrograms,
+    /// Only exported from backends that [store](Database::stores_token_transfers) them.
+    TokenTransfers,
+    /// Versions of the accounts referenced by the exported transactions.
+    AccountVersions,
+}
+
+impl ExportTable {
+    pub const ALL: &'static [ExportTable] = &[
+        Self::Blocks,
+        Self::Transactions,
+        Self::TransactionPrograms,
+        Self::TokenTransfers,
+        Self::AccountVersions,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Blocks => "blocks",
+            Self::Transactions => "transactions",
+            Self::TransactionPrograms => "transaction_programs",
+            Self::TokenTransfers => "token_transfers",
+            Self::AccountVersions => "account_versions",
+        }
+    }
+
+    fn columns(&self) -> &'static [(&'static str, Kind)] {
+        use Kind::*;
+        match self {
+            Self::Blocks => &[
+                ("slot", Int64),
+                ("blockhash", Utf8),
+                ("previous_blockhash", Utf8),
+                ("parent_slot", Int64),
+                ("transaction_count", UInt64),
+                ("block_time", OptionalInt64),
+            ],
+            Self::Transactions => &[
+                ("signature", Utf8),
+                ("slot", Int64),
+                ("tx_index", Int32),
+                ("fee", OptionalUInt64),
+                ("account_count", Int32),
+                ("program_count", OptionalInt32),
+            ],
+            Self::TransactionPrograms => &[
+                ("signature", Utf8),
+                ("slot", Int64),
+                ("tx_index", Int32),
+                ("program_id", Utf8),
+            ],
+            Self::TokenTransfers => &[
+                ("signature", Utf8),
+                ("slot", Int64),
+                ("mint", Utf8),
+                ("owner", Utf8),
+                ("amount", UInt64),
+            ],
+            Self::AccountVersions => &[
+                ("pubkey", Utf8),
+                ("slot", Int64),
+                ("write_version", UInt64),
+                ("lamports", Int64),
+                ("owner", Utf8),
+                ("executable", Boolean),
+                ("rent_epoch", Int64),
+                ("data_len", Int64),
+            ],
+        }
+    }
+
+    /// The table's Parquet schema, in the message type syntax.
+    pub fn schema(&self) -> String {
+        let fields: String = self
+            .columns()
+            .iter()
+            .map(|(name, kind)| match kind {
+                Kind::Int64 => format!("  required int64 {};\n", name),
+                Kind::UInt64 => format!("  required int64 {} (UINT_64);\n", name),
+                Kind::OptionalInt64 => format!("  optional int64 {};\n", name),
+                Kind::OptionalUInt64 => format!("  optional int64 {} (UINT_64);\n", name),
+                Kind::Int32 => format!("  required int32 {};\n", name),
+                Kind::OptionalInt32 => format!("  optional int32 {};\n", name),
+                Kind::Boolean => format!("  required boolean {};\n", name),
+                Kind::Utf8 => format!("  required binary {} (UTF8);\n", name),
+            })
+            .collect();
+        format!("message {} {{\n{}}}\n", self.name(), fields)
+    }
+}
+
+impl fmt::Display for ExportTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ExportTable {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        if name == "instructions" {
+            bail!("The index doesn't keep instructions; export transaction_programs for the programs each transaction invokes");
+        }
+        Self::ALL.iter().copied().find(|table| table.name() == name).ok_or_else(|| {
+            let names: Vec<&str> = Self::ALL.iter().map(ExportTable::name).collect();
+            anyhow!("Unknown export table '{}', expected one of: {}", name, names.join(", "))
+        })
+    }
+}
+
+fn block_row(block: &CompressedBlock) -> Vec<Value> {
+    vec![
+        uint64(block.slot),
+        Value::Utf8(block.blockhash.clone()),
+        Value::Utf8(block.previous_blockhash.clone()),
+        uint64(block.parent_slot),
+        uint64(block.transactions),
+        Value::OptionalInt64(block.block_time),
+    ]
+}
+
+// Every transaction pays a fee, so a zero one marks a row indexed before fees and programs
+// were recorded. Those are exported as nulls rather than as zeros.
+fn has_analytics(transaction: &CompressedTransaction) -> bool {
+    transaction.fee > 0
+}
+
+fn transaction_row(transaction: &CompressedTransaction) -> Vec<Value> {
+    let recorded = has_analytics(transaction);
+    vec![
+        base58(&transaction.signature),
+        uint64(transaction.slot),
+        Value::Int32(transaction.index as i32),
+        Value::OptionalInt64(recorded.then_some(transaction.fee as i64)),
+        Value::Int32(transaction.accounts.len() as i32),
+        Value::OptionalInt32(recorded.then_some(transaction.programs.len() as i32)),
+    ]
+}
+
+fn program_row(transaction: &CompressedTransaction, program: &[u8]) -> Vec<Value> {
+    vec![
+        base58(&transaction.signature),
+        uint64(transaction.slot),
+        Value::Int32(transaction.index as i32),
+        base58(program),
+    ]
+}
+
+fn token_transfer_row(transfer: &TokenTransfer) -> Vec<Value> {
+    vec![
+        base58(&transfer.signature),
+        uint64(transfer.slot),
+        base58(&transfer.mint),
+        base58(&transfer.owner),
+        uint64(transfer.amount),
+    ]
+}
+
+fn account_version_row(account: &CompressedAccount) -> Vec<Value> {
+    vec![
+        base58(&account.pubkey),
+        uint64(account.slot),
+        uint64(account.write_version),
+        Value::Int64(account.lamports),
+        base58(&account.owner),
+        Value::Boolean(account.executable),
+        Value::Int64(account.rent_epoch),
+        Value::Int64(account.data.len() as i64),
+    ]
+}
+
+enum Column {
+    Int64(Vec<i64>),
+    OptionalInt64(Vec<Option<i64>>),
+    Int32(Vec<i32>),
+    OptionalInt32(Vec<Option<i32>>),
+    Boolean(Vec<bool>),
+    Utf8(Vec<ByteArray>),
+}
+
+impl Column {
+    fn new(kind: Kind) -> Self {
+        match kind {
+            Kind::Int64 | Kind::UInt64 => Self::Int64(Vec::new()),
+            Kind::OptionalInt64 | Kind::OptionalUInt64 => Self::OptionalInt64(Vec::new()),
+            Kind::Int32 => Self::Int32(Vec::new()),
+            Kind::OptionalInt32 => Self::OptionalInt32(Vec::new()),
+            Kind::Boolean => Self::Boolean(Vec::new()),
+            Kind::Utf8 => Self::Utf8(Vec::new()),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        match (self, value) {
+            (Self::Int64(values), Value::Int64(value)) => values.push(value),
+            (Self::OptionalInt64(values), Value::OptionalInt64(value)) => values.push(value),
+            (Self::Int32(values), Value::Int32(value)) => values.push(value),
+            (Self::OptionalInt32(values), Value::OptionalInt32(value)) => values.push(value),
+            (Self::Boolean(values), Value::Boolean(value)) => values.push(value),
+            (Self::Utf8(values), Value::Utf8(value)) => values.push(ByteArray::from(value.into_bytes())),
+            _ => unreachable!("row does not match the table's columns"),
+        }
+    }
+}
+
+/// Buffers one table's rows for a slot range and writes them out a row group at a time. The
+/// file is written under a temporary name and only appears, complete, once finished.
+struct TableWriter {
+    table: ExportTable,
+    path: PathBuf,
+    file: Option<SerializedFileWriter<File>>,
+    columns: Vec<Column>,
+    rows: usize,
+    written: u64,
+    row_group_size: usize,
+}
+
+impl TableWriter {
+    fn new(table: ExportTable, path: PathBuf, row_group_size: usize) -> Self {
+        Self {
+            table,
+            path,
+            file: None,
+            columns: table.columns().iter().map(|(_, kind)| Column::new(*kind)).collect(),
+            rows: 0,
+            written: 0,
+            row_group_size,
+        }
+    }
+
+    fn temporary_path(&self) -> PathBuf {
+        self.path.with_extension("parquet.tmp")
+    }
+
+    fn push(&mut self, row: Vec<Value>) -> Result<()> {
+        for (column, value) in self.columns.iter_mut().zip(row) {
+            column.push(value);
+        }
+        self.rows += 1;
+        if self.rows >= self.row_group_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        if self.file.is_none() {
+            fs::create_dir_all(self.path.parent().expect("export paths have a parent"))?;
+            let temporary = self.temporary_path();
+            let file = File::create(&temporary)
+                .with_context(|| format!("Failed to create Parquet file {}", temporary.display()))?;
+            let schema = Arc::new(parse_message_type(&self.table.schema())?);
+            let properties = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+            self.file = Some(SerializedFileWriter::new(file, schema, properties)?);
+        }
+        let file = self.file.as_mut().expect("file was just created");
+        let mut row_group = file.next_row_group()?;
+        for column in &mut self.columns {
+            let mut writer = row_group
+                .next_column()?
+                .ok_or_else(|| anyhow!("Parquet schema of {} is missing columns", self.table))?;
+            match column {
+                Column::Int64(values) => {
+                    writer.typed::<Int64Type>().write_batch(values, None, None)?;
+                    values.clear();
+                }
+                Column::OptionalInt64(values) => {
+                    let present: Vec<i64> = values.iter().flatten().copied().collect();
+                    let levels: Vec<i16> = values.iter().map(|value| value.is_some() as i16).collect();
+                    writer.typed::<Int64Type>().write_batch(&present, Some(&levels), None)?;
+                    values.clear();
+                }
+                Column::Int32(values) => {
+                    writer.typed::<Int32Type>().write_batch(values, None, None)?;
+                    values.clear();
+                }
+                Column::OptionalInt32(values) => {
+                    let present: Vec<i32> = values.iter().flatten().copied().collect();
+                    let levels: Vec<i16> = values.iter().map(|value| value.is_some() as i16).collect();
+                    writer.typed::<Int32Type>().write_batch(&present, Some(&levels), None)?;
+                    values.clear();
+                }
+                Column::Boolean(values) => {
+                    writer.typed::<BoolType>().write_batch(values, None, None)?;
+                    values.clear();
+                }
+                Column::Utf8(values) => {
+                    writer.typed::<ByteArrayType>().write_batch(values, None, None)?;
+                    values.clear();
+                }
+            }
+            writer.close()?;
+        }
+        row_group.close()?;
+        self.written += self.rows as u64;
+        self.rows = 0;
+        Ok(())
+    }
+
+    /// Writes what is left and moves the file into place. Returns the rows written; a table
+    /// without rows in the range leaves no file.
+    fn finish(mut self) -> Result<u64> {
+        self.flush()?;
+        if let Some(file) = self.file.take() {
+            file.close()?;
+            fs::rename(self.temporary_path(), &self.path)?;
+        }
+        Ok(self.written)
+    }
+}
+
+/// Exports slot ranges of the selected tables from a `Database` to Parquet files under `dir`.
+pub struct ParquetExporter {
+    storage: Arc<dyn Database>,
+    dir: PathBuf,
+    tables: BTreeSet<ExportTable>,
+    row_group_size: usize,
+}
+
+impl ParquetExporter {
+    /// Exports every table `storage` keeps.
+    pub fn new(storage: Arc<dyn Database>, dir: impl Into<PathBuf>) -> Self {
+        let tables = ExportTable::ALL
+            .iter()
+            .copied()
+            .filter(|&table| table != ExportTable::TokenTransfers || storage.stores_token_transfers())
+            .collect();
+        Self {
+            storage,
+            dir: dir.into(),
+            tables,
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+        }
+    }
+
+    /// Exports only `tables`, instead of all of them. Exports fail if they include token
+    /// transfers and the storage doesn't keep them.
+    pub fn with_tables(mut self, tables: impl IntoIterator<Item = ExportTable>) -> Self {
+        self.tables = tables.into_iter().collect();
+        self
+    }
+
+    pub fn with_row_group_size(mut self, rows: usize) -> Self {
+        self.row_group_size = rows.max(1);
+        self
+    }
+
+    fn watermark_path(&self) -> PathBuf {
+        self.dir.join(WATERMARK_FILE)
+    }
+
+    /// The last slot exported by [`export_new`](Self::export_new), if it has run.
+    pub fn watermark(&self) -> Result<Option<u64>> {
+        let path = self.watermark_path();
+        match fs::read_to_string(&path) {
+            Ok(watermark) => {
+                let slot = watermark
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid export watermark in {}", path.display()))?;
+                Ok(Some(slot))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_watermark(&self, slot: u64) -> Result<()> {
+        let path = self.watermark_path();
+        let temporary = path.with_extension("tmp");
+        fs::create_dir_all(&self.dir)?;
+        fs::write(&temporary, slot.to_string())?;
+        fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+
+    /// Exports `slots`, one file per table and epoch. Files are named after the first slot of
+    /// the range in their epoch, so exporting the same range again replaces them. Returns the
+    /// number of rows written.
+    pub async fn export(&self, slots: RangeInclusive<u64>) -> Result<u64> {
+        self.check_tables()?;
+        let mut rows = 0;
+        for epoch in slots.start() / SLOTS_PER_EPOCH..=slots.end() / SLOTS_PER_EPOCH {
+            let epoch_range = epoch_slots(epoch);
+            let start = (*epoch_range.start()).max(*slots.start());
+            let end = (*epoch_range.end()).min(*slots.end());
+            rows += self.export_epoch(epoch, start..=end).await?;
+        }
+        Ok(rows)
+    }
+
+    /// Exports the slots processed since the watermark, or from the oldest stored block on the
+    /// first run, moving the watermark after each epoch so an interrupted run resumes where it
+    /// stopped. Returns the range exported, if any.
+    pub async fn export_new(&self) -> Result<Option<(RangeInclusive<u64>, u64)>> {
+        self.check_tables()?;
+        let cursor = self.storage.get_last_processed_slot().await?;
+        let start = match self.watermark()? {
+            Some(watermark) => watermark + 1,
+            None => match self.storage.get_first_block_slot().await? {
+                Some(slot) => slot,
+                None => return Ok(None),
+            },
+        };
+        if start > cursor {
+            return Ok(None);
+        }
+        let mut rows = 0;
+        for epoch in start / SLOTS_PER_EPOCH..=cursor / SLOTS_PER_EPOCH {
+            let epoch_range = epoch_slots(epoch);
+            let end = (*epoch_range.end()).min(cursor);
+            rows += self.export_epoch(epoch, (*epoch_range.start()).max(start)..=end).await?;
+            self.set_watermark(end)?;
+        }
+        Ok(Some((start..=cursor, rows)))
+    }
+
+    fn check_tables(&self) -> Result<()> {
+        if self.tables.contains(&ExportTable::TokenTransfers) && !self.storage.stores_token_transfers() {
+            bail!("This storage backend doesn't keep token transfers, so they can't be exported");
+        }
+        Ok(())
+    }
+
+    /// Reads the epoch's blocks `SLOTS_PER_READ` slots at a time, so memory use is bounded by
+    /// the row group size and the accounts referenced in the epoch.
+    async fn export_epoch(&self, epoch: u64, slots: RangeInclusive<u64>) -> Result<u64> {
+        if slots.is_empty() {
+            bail!("Empty export range {:?}", slots);
+        }
+        let mut writers: Vec<TableWriter> = self
+            .tables
+            .iter()
+            .map(|&table| {
+                let path = self
+                    .dir
+                    .join(table.name())
+                    .join(format!("epoch={}", epoch))
+                    .join(format!("part-{}.parquet", slots.start()));
+                TableWriter::new(table, path, self.row_group_size)
+            })
+            .collect();
+        let wants = |table| self.tables.contains(&table);
+        let needs_transactions = wants(ExportTable::Transactions)
+            || wants(ExportTable::TransactionPrograms)
+            || wants(ExportTable::AccountVersions);
+
+        let mut addresses = BTreeSet::new();
+        for start in slots.clone().step_by(SLOTS_PER_READ as usize) {
+            let end = start.saturating_add(SLOTS_PER_READ - 1).min(*slots.end());
+            for block in self.storage.get_blocks_in_range(start, end).await? {
+                push(&mut writers, ExportTable::Blocks, || block_row(&block))?;
+                if needs_transactions {
+                    for transaction in self.storage.get_transactions_in_block(block.slot).await? {
+                        push(&mut writers, ExportTable::Transactions, || transaction_row(&transaction))?;
+                        for program in &transaction.programs {
+                            push(&mut writers, ExportTable::TransactionPrograms, || program_row(&transaction, program))?;
+                        }
+                        if wants(ExportTable::AccountVersions) {
+                            addresses.extend(transaction.accounts);
+                        }
+                    }
+                }
+                if wants(ExportTable::TokenTransfers) {
+                    for transfer in self.storage.get_token_transfers_in_block(block.slot).await? {
+                        push(&mut writers, ExportTable::TokenTransfers, || token_transfer_row(&transfer))?;
+                    }
+                }
+            }
+        }
+        for address in addresses {
+            let versions = self
+                .storage
+                .get_account_versions(&address, *slots.start(), *slots.end(), ALL_VERSIONS)
+                .await?;
+            for account in versions {
+                push(&mut writers, ExportTable::AccountVersions, || account_version_row(&account))?;
+            }
+        }
+
+        let mut rows = 0;
+        for writer in writers {
+            rows += writer.finish()?;
+        }
+        Ok(rows)
+    }
+
+    pub async fn run(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match self.export_new().await {
+                Ok(Some((slots, rows))) => {
+                    info!("Exported {} rows for slots {:?} to {}", rows, slots, self.dir.display())
+                }
+                Ok(None) => {}
+                Err(e) => error!("Error exporting to Parquet: {:?}", e),
+            }
+        }
+    }
+}
+
+fn push(writers: &mut [TableWriter], table: ExportTable, row: impl FnOnce() -> Vec<Value>) -> Result<()> {
+    match writers.iter_mut().find(|writer| writer.table == table) {
+        Some(writer) => writer.push(row()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{BlockBatch, InMemoryStorage};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Row;
+    use std::path::Path;
+
+    fn read_rows(path: &Path) -> Vec<Row> {
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        reader.get_row_iter(None).unwrap().map(Result::unwrap).collect()
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        let storage = Arc::new(InMemoryStorage::new());
+        for slot in [SLOTS_PER_EPOCH - 1, SLOTS_PER_EPOCH] {
+            let mut batch = BlockBatch::new(slot);
+            batch.block = Some(CompressedBlock {
+                slot,
+                blockhash: format!("hash-{}", slot),
+                previous_blockhash: format!("hash-{}", slot - 1),
+                parent_slot: slot - 1,
+                transactions: 1,
+                block_time: None,
+                data: vec![],
+                proof: vec![],
+            });
+            batch.transactions.push(CompressedTransaction {
+                signature: vec![slot as u8; 64],
+                slot,
+                index: 0,
+                accounts: vec![vec![1; 32]],
+                // The second transaction predates fee and program indexing.
+                fee: if slot < SLOTS_PER_EPOCH { 5000 } else { 0 },
+                programs: if slot < SLOTS_PER_EPOCH { vec![vec![2; 32], vec![3; 32]] } else { vec![] },
+                data: vec![],
+                proof: vec![],
+            });
+            batch.accounts.push(CompressedAccount {
+                pubkey: vec![1; 32],
+                lamports: slot as i64,
+                owner: vec![3; 32],
+                executable: false,
+                rent_epoch: 0,
+                slot,
+                write_version: 0,
+                data: vec![0; 10],
+                proof: vec![],
+            });
+            storage.commit_block(&batch).await.unwrap();
+        }
+
+        let dir = std::env::temp_dir().join(format!("windexer-test-export-{}", std::process::id()));
+        // The in-memory backend doesn't keep token transfers, so they are left out or refused.
+        assert!(!ParquetExporter::new(storage.clone(), &dir).tables.contains(&ExportTable::TokenTransfers));
+        let transfers = ParquetExporter::new(storage.clone(), &dir).with_tables([ExportTable::TokenTransfers]);
+        assert!(transfers.export(0..=SLOTS_PER_EPOCH).await.is_err());
+
+        let exporter = ParquetExporter::new(storage.clone(), &dir).with_tables([
+            ExportTable::Blocks,
+            ExportTable::Transactions,
+            ExportTable::TransactionPrograms,
+            ExportTable::AccountVersions,
+        ]);
+        let (slots, rows) = exporter.export_new().await.unwrap().unwrap();
+        assert_eq!(slots, SLOTS_PER_EPOCH - 1..=SLOTS_PER_EPOCH);
+        assert_eq!(rows, 2 + 2 + 2 + 2);
+        assert_eq!(exporter.watermark().unwrap(), Some(SLOTS_PER_EPOCH));
+        assert!(exporter.export_new().await.unwrap().is_none());
+
+        let first = format!("part-{}.parquet", SLOTS_PER_EPOCH - 1);
+        let blocks = read_rows(&dir.join("blocks/epoch=1").join(format!("part-{}.parquet", SLOTS_PER_EPOCH)));
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(read_rows(&dir.join("transaction_programs/epoch=0").join(&first)).len(), 2);
+        assert_eq!(read_rows(&dir.join("account_versions/epoch=0").join(&first)).len(), 1);
+        let transactions = [
+            dir.join("transactions/epoch=0").join(&first),
+            dir.join("transactions/epoch=1").join(format!("part-{}.parquet", SLOTS_PER_EPOCH)),
+        ];
+        let fees: Vec<String> = transactions
+            .iter()
+            .flat_map(|path| read_rows(path))
+            .map(|row| row.get_column_iter().find(|(name, _)| *name == "fee").unwrap().1.to_string())
+            .collect();
+        assert_eq!(fees, ["5000", "null"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
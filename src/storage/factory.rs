@@ -0,0 +1,254 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+use crate::storage::filecoin::{DEFAULT_API_URL, DEFAULT_INDEX_PATH};
+use crate::storage::{
+    CarStorage, ClickHouseStorage, CompressionStore, Database, FilecoinStorage, InMemoryStorage, MigrationReport, PostgresStorage, ScyllaStorage,
+    SqliteStorage,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackend {
+    Scylla,
+    ClickHouse,
+    Postgres,
+    Sqlite,
+    Ipfs,
+    Car,
+    Memory,
+}
+
+impl StorageBackend {
+    pub const SCHEMES: &'static [&'static str] =
+        &["scylla", "clickhouse", "postgres", "postgresql", "sqlite", "ipfs", "car", "memory"];
+
+    pub fn from_scheme(scheme: &str) -> Option<Self> {
+        match scheme {
+            "scylla" => Some(Self::Scylla),
+            "clickhouse" => Some(Self::ClickHouse),
+            "postgres" | "postgresql" => Some(Self::Postgres),
+            "sqlite" => Some(Self::Sqlite),
+            "ipfs" => Some(Self::Ipfs),
+            "car" => Some(Self::Car),
+            "memory" => Some(Self::Memory),
+            _ => None,
+        }
+    }
+
+    // Postgres and SQLite URLs go to sqlx untouched, so their parameters are sqlx's.
+    fn params(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Self::Scylla => Some(&["keyspace", "max_in_flight"]),
+            Self::ClickHouse => Some(&["database", "secure"]),
+            Self::Ipfs => Some(&["index", "checkpoint_secs"]),
+            Self::Car | Self::Memory => Some(&[]),
+            Self::Postgres | Self::Sqlite => None,
+        }
+    }
+}
+
+impl fmt::Display for StorageBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Scylla => "scylla",
+            Self::ClickHouse => "clickhouse",
+            Self::Postgres => "postgres",
+            Self::Sqlite => "sqlite",
+            Self::Ipfs => "ipfs",
+            Self::Car => "car",
+            Self::Memory => "memory",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A validated `database_url`, split into backend and options.
+#[derive(Debug, Clone)]
+pub struct StorageUrl {
+    pub backend: StorageBackend,
+    url: Url,
+    raw: String,
+    params: HashMap<String, String>,
+}
+
+impl StorageUrl {
+    pub fn parse(raw: &str) -> Result<Self> {
+        let url = Url::parse(raw).with_context(|| format!("Invalid storage URL '{}'", raw))?;
+        let backend = StorageBackend::from_scheme(url.scheme()).ok_or_else(|| {
+            anyhow!(
+                "Unsupported storage backend '{}' in '{}', expected one of: {}",
+                url.scheme(),
+                raw,
+                StorageBackend::SCHEMES.join(", ")
+            )
+        })?;
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+        if let Some(allowed) = backend.params() {
+            if let Some(unknown) = params.keys().find(|key| !allowed.contains(&key.as_str())) {
+                bail!(
+                    "Unknown {} storage option '{}', supported options: [{}]",
+                    backend,
+                    unknown,
+                    allowed.join(", ")
+                );
+            }
+        }
+        let needs_host = matches!(backend, StorageBackend::Scylla | StorageBackend::ClickHouse);
+        if needs_host && url.host_str().is_none() {
+            bail!("{} storage URL '{}' is missing a host", backend, raw)
+        }
+        if backend == StorageBackend::Car && url.path().is_empty() {
+            bail!("car storage URL '{}' is missing a directory", raw)
+        }
+
+        Ok(Self {
+            backend,
+            url,
+            raw: raw.to_string(),
+            params,
+        })
+    }
+
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params.get(key).map(String::as_str)
+    }
+
+    fn host_port(&self, default_port: u16) -> String {
+        format!(
+            "{}:{}",
+            self.url.host_str().unwrap_or_default(),
+            self.url.port().unwrap_or(default_port)
+        )
+    }
+
+    fn clickhouse_url(&self) -> Result<String> {
+        let scheme = match self.param("secure") {
+            Some("true") => "https",
+            Some("false") | None => "http",
+            Some(other) => bail!("Invalid clickhouse option secure={}, expected true or false", other),
+        };
+        Ok(format!("{}://{}", scheme, self.host_port(8123)))
+    }
+
+    /// Checks the schema against this build's migrations and, if `apply`, brings it up to
+    /// date. `None` for backends without a schema.
+    pub async fn migrate(&self, apply: bool) -> Result<Option<MigrationReport>> {
+        let report = match self.backend {
+            StorageBackend::Scylla => {
+                let keyspace = self.param("keyspace").unwrap_or("windexer");
+                ScyllaStorage::migrate(&self.host_port(9042), keyspace, apply).await?
+            }
+            StorageBackend::ClickHouse => {
+                let database = self.param("database").unwrap_or("windexer");
+                ClickHouseStorage::migrate(&self.clickhouse_url()?, database, apply).await?
+            }
+            StorageBackend::Postgres => PostgresStorage::migrate(&self.raw, apply).await?,
+            StorageBackend::Sqlite => SqliteStorage::migrate(&self.raw, apply).await?,
+            StorageBackend::Ipfs | StorageBackend::Car | StorageBackend::Memory => return Ok(None),
+        };
+        Ok(Some(report))
+    }
+
+    /// Connects to the backend, applying pending migrations first.
+    pub async fn connect(&self) -> Result<Arc<dyn Database>> {
+        Ok(self.connect_with_compression().await?.0)
+    }
+
+    /// Like [`connect`](Self::connect), also returning the same connection as a
+    /// `CompressionStore` for the backends that implement one.
+    pub async fn connect_with_compression(&self) -> Result<(Arc<dyn Database>, Option<Arc<dyn CompressionStore>>)> {
+        let storage: Arc<dyn Database> = match self.backend {
+            StorageBackend::Scylla => {
+                let keyspace = self.param("keyspace").unwrap_or("windexer");
+                let mut storage = ScyllaStorage::with_keyspace(&self.host_port(9042), keyspace).await?;
+                if let Some(max_in_flight) = self.param("max_in_flight") {
+                    let max_in_flight = max_in_flight
+                        .parse()
+                        .with_context(|| format!("Invalid scylla option max_in_flight={}", max_in_flight))?;
+                    storage = storage.with_max_in_flight(max_in_flight);
+                }
+                let storage = Arc::new(storage);
+                return Ok((storage.clone(), Some(storage)));
+            }
+            StorageBackend::ClickHouse => {
+                let database = self.param("database").unwrap_or("windexer");
+                Arc::new(ClickHouseStorage::with_database(&self.clickhouse_url()?, database).await?)
+            }
+            StorageBackend::Postgres => Arc::new(PostgresStorage::new(&self.raw).await?),
+            StorageBackend::Sqlite => Arc::new(SqliteStorage::new(&self.raw).await?),
+            StorageBackend::Ipfs => {
+                // `ipfs://` without a host means the local daemon.
+                let api_url = match self.url.host_str().filter(|host| !host.is_empty()) {
+                    Some(_) => format!("http://{}", self.host_port(5001)),
+                    None => DEFAULT_API_URL.to_string(),
+                };
+                let index = self.param("index").unwrap_or(DEFAULT_INDEX_PATH);
+                let mut storage = FilecoinStorage::open(&api_url, index).await?;
+                if let Some(checkpoint_secs) = self.param("checkpoint_secs") {
+                    let checkpoint_secs = checkpoint_secs
+                        .parse()
+                        .with_context(|| format!("Invalid ipfs option checkpoint_secs={}", checkpoint_secs))?;
+                    storage = storage.with_checkpoint_interval(Duration::from_secs(checkpoint_secs));
+                }
+                let storage = Arc::new(storage);
+                return Ok((storage.clone(), Some(storage)));
+            }
+            // `car:///var/lib/windexer/cold` is a directory of archives on this host.
+            StorageBackend::Car => Arc::new(CarStorage::open(self.url.path())?),
+            StorageBackend::Memory => {
+                let storage = Arc::new(InMemoryStorage::new());
+                return Ok((storage.clone(), Some(storage)));
+            }
+        };
+        Ok((storage, None))
+    }
+}
+
+/// Builds the `Database` backend selected by the URL scheme.
+pub async fn connect(url: &str) -> Result<Arc<dyn Database>> {
+    let url = StorageUrl::parse(url)?;
+    url.connect()
+        .await
+        .with_context(|| format!("Failed to connect to {} storage", url.backend))
+}
+
+/// Runs [`StorageUrl::migrate`] for the backend selected by the URL scheme.
+pub async fn migrate(url: &str, apply: bool) -> Result<Option<MigrationReport>> {
+    let url = StorageUrl::parse(url)?;
+    url.migrate(apply)
+        .await
+        .with_context(|| format!("Failed to migrate {} storage", url.backend))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_storage_url() {
+        let url = StorageUrl::parse("scylla://scylla:9042?keyspace=test").unwrap();
+        assert_eq!(url.backend, StorageBackend::Scylla);
+        assert_eq!(url.param("keyspace"), Some("test"));
+        assert_eq!(url.host_port(9042), "scylla:9042");
+
+        let url = StorageUrl::parse("postgresql://user@localhost/windexer?sslmode=disable").unwrap();
+        assert_eq!(url.backend, StorageBackend::Postgres);
+
+        let url = StorageUrl::parse("ipfs://ipfs?index=/var/lib/windexer/index.cid").unwrap();
+        assert_eq!(url.host_port(5001), "ipfs:5001");
+        assert_eq!(url.param("index"), Some("/var/lib/windexer/index.cid"));
+
+        assert!(StorageUrl::parse("mongodb://localhost").is_err());
+        assert!(StorageUrl::parse("scylla://scylla?keyspce=test").is_err());
+        assert!(StorageUrl::parse("clickhouse:///windexer").is_err());
+        assert_eq!(StorageUrl::parse("car:///var/lib/windexer/cold").unwrap().url.path(), "/var/lib/windexer/cold");
+        assert!(StorageUrl::parse("not a url").is_err());
+
+        let storage = connect("memory://").await.unwrap();
+        assert_eq!(storage.get_last_processed_slot().await.unwrap(), 0);
+    }
+}
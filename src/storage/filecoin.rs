@@ -1,22 +1,80 @@
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use cid::Cid;
-use ipfs_api_backend_hyper::{IpfsApi, IpfsClient};
+use futures::TryStreamExt;
+use ipfs_api_backend_hyper::request::Add;
+use ipfs_api_backend_hyper::{IpfsApi, IpfsClient, TryFromUri};
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
-use tokio::sync::RwLock;
-use tracing::{info, error, instrument};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{Cursor, ErrorKind};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, error, instrument, warn};
 
-use crate::storage::{Database, CompressedAccount, CompressedBlock, CompressedTransaction};
+use crate::storage::database::{cursor_moved_back, signature_bounds};
+use crate::storage::error::{self, StorageError};
+use crate::storage::key_index::{KeyIndex, StoredNode};
+use crate::storage::{
+    CompressionStore, Database, CompressedAccount, CompressedBlock, CompressedTransaction,
+    PruneRequest, PruneStats, SignatureInfo, SignatureQuery, VOTE_PROGRAM_ID,
+    AddressRecord, CompressedAssetRecord, CompressedStateAccount, CompressedTokenAccountRecord,
+    LeafChangeRecord, MerkleTreeRecord, TokenMintRecord, TreeRootRecord,
+};
+
+pub const DEFAULT_API_URL: &str = "http://localhost:5001";
+pub const DEFAULT_INDEX_PATH: &str = "ipfs-index.cid";
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
 
 const ACCOUNT_PREFIX: &str = "account:";
 const BLOCK_PREFIX: &str = "block:";
 const TRANSACTION_PREFIX: &str = "tx:";
+const TREE_PREFIX: &str = "tree:";
+const TREE_ROOT_PREFIX: &str = "tree_root:";
+const LEAF_CHANGE_PREFIX: &str = "leaf_change:";
+const STATE_ACCOUNT_PREFIX: &str = "state:";
+const STATE_ADDRESS_PREFIX: &str = "state_address:";
+const STATE_OWNER_PREFIX: &str = "state_owner:";
+const ADDRESS_PREFIX: &str = "address:";
+const ADDRESS_TREE_PREFIX: &str = "address_tree:";
+const TOKEN_ACCOUNT_PREFIX: &str = "token:";
+const TOKEN_OWNER_PREFIX: &str = "token_owner:";
+const TOKEN_MINT_INDEX_PREFIX: &str = "token_by_mint:";
+const TOKEN_MINT_PREFIX: &str = "mint:";
+const ASSET_PREFIX: &str = "asset:";
+const ASSET_OWNER_PREFIX: &str = "asset_owner:";
+const ACCOUNT_OWNER_PREFIX: &str = "account_owner:";
+const ACCOUNT_VERSION_PREFIX: &str = "account_version:";
+const TRANSACTION_SLOT_PREFIX: &str = "tx_slot:";
+const ADDRESS_SIGNATURE_PREFIX: &str = "address_sig:";
 const LAST_SLOT_KEY: &str = "last_processed_slot";
+const COMPRESSED_SLOT_KEY: &str = "compressed_slot";
+
+/// Root of an index checkpointed by older builds: the CID of each of its fixed shards.
+#[derive(Deserialize)]
+struct LegacyIndexRoot {
+    shards: Vec<Option<String>>,
+}
+
+struct Checkpoint {
+    root: Option<String>,
+    at: Instant,
+}
 
+/// Stores every record as a pinned IPFS object. The key-to-CID index is checkpointed to
+/// IPFS with the cursor, at most once per checkpoint interval, and its root CID is written
+/// to a local file so the index can be reloaded on startup. A restart resumes from the
+/// cursor of the last checkpoint. Objects the last checkpoint no longer references are
+/// unpinned after it; without an index path, as soon as no key refers to them.
 pub struct FilecoinStorage {
     ipfs_client: IpfsClient,
-    cache: RwLock<HashMap<String, String>>,
+    cache: RwLock<KeyIndex>,
+    index_path: Option<PathBuf>,
+    checkpoint: Mutex<Checkpoint>,
+    checkpoint_interval: Duration,
+    // Held shared while a record is added and indexed, and exclusively while unreferenced
+    // records are unpinned, so a record re-added in between is never unpinned.
+    pinning: RwLock<()>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -26,18 +84,161 @@ struct CachedData<T> {
 }
 
 impl FilecoinStorage {
-    pub fn new() -> Self {
-        Self {
-            ipfs_client: IpfsClient::default(),
-            cache: RwLock::new(HashMap::new()),
+    pub async fn new() -> Result<Self> {
+        Self::open(DEFAULT_API_URL, DEFAULT_INDEX_PATH).await
+    }
+
+    /// Connects to the IPFS HTTP API at `api_url` and loads the index checkpointed at `index_path`, if any.
+    pub async fn open(api_url: &str, index_path: impl Into<PathBuf>) -> Result<Self> {
+        let mut storage = Self::connect(api_url)?;
+        storage.index_path = Some(index_path.into());
+        storage.load_index().await?;
+        Ok(storage)
+    }
+
+    /// An index that lives only in memory; nothing written is reachable after a restart.
+    pub fn ephemeral(api_url: &str) -> Result<Self> {
+        Self::connect(api_url)
+    }
+
+    fn connect(api_url: &str) -> Result<Self> {
+        let ipfs_client =
+            IpfsClient::from_str(api_url).map_err(|e| anyhow!("Invalid IPFS API URL '{}': {}", api_url, e))?;
+        Ok(Self {
+            ipfs_client,
+            cache: RwLock::new(KeyIndex::new()),
+            index_path: None,
+            checkpoint: Mutex::new(Checkpoint {
+                root: None,
+                at: Instant::now(),
+            }),
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            pinning: RwLock::new(()),
+        })
+    }
+
+    /// Checkpoints the index at most once per `interval`. Zero checkpoints every slot.
+    pub fn with_checkpoint_interval(mut self, interval: Duration) -> Self {
+        self.checkpoint_interval = interval;
+        self
+    }
+
+    /// Checkpoints the index now. Call before shutdown.
+    pub async fn flush(&self) -> Result<()> {
+        self.checkpoint().await
+    }
+
+    pub async fn index_root(&self) -> Result<Option<String>> {
+        let Some(path) = &self.index_path else {
+            return Ok(None);
+        };
+        match tokio::fs::read_to_string(path).await {
+            Ok(cid) => Ok(Some(cid.trim().to_string())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn load_index(&self) -> Result<()> {
+        let Some(root_cid) = self.index_root().await? else {
+            return Ok(());
+        };
+        let index = match bincode::deserialize::<StoredNode>(&self.cat(&root_cid).await?) {
+            Ok(root) => {
+                let mut nodes = HashMap::new();
+                let mut pending = vec![(root_cid.clone(), root)];
+                while let Some((cid, node)) = pending.pop() {
+                    self.ipfs_client.pin_add(&cid, false).await?;
+                    if let StoredNode::Branch(children) = &node {
+                        for child in children.iter().flatten() {
+                            pending.push((child.clone(), self.retrieve_by_cid(child).await?));
+                        }
+                    }
+                    nodes.insert(cid, node);
+                }
+                KeyIndex::assemble(&root_cid, &nodes)?
+            }
+            // Rewritten as a trie by the next checkpoint, which also releases the old shards.
+            Err(_) => {
+                let legacy: LegacyIndexRoot = self.retrieve_by_cid(&root_cid).await?;
+                let mut entries = Vec::new();
+                let mut replaced = vec![root_cid.clone()];
+                for cid in legacy.shards.into_iter().flatten() {
+                    let shard: BTreeMap<String, String> = self.retrieve_by_cid(&cid).await?;
+                    entries.extend(shard);
+                    replaced.push(cid);
+                }
+                KeyIndex::rebuild(entries, replaced)
+            }
+        };
+        info!("Loaded IPFS index root {} with {} keys", root_cid, index.len());
+        *self.cache.write().await = index;
+        self.checkpoint.lock().await.root = Some(root_cid);
+        Ok(())
+    }
+
+    /// Uploads the index nodes changed since the last checkpoint and records the new root CID
+    /// locally, then unpins the objects only earlier checkpoints referenced.
+    async fn checkpoint(&self) -> Result<()> {
+        let Some(path) = &self.index_path else {
+            let released = self.cache.write().await.take_released();
+            return self.unpin_unreferenced(released, Vec::new()).await;
+        };
+        let mut checkpoint = self.checkpoint.lock().await;
+        let snapshot = self.cache.read().await.snapshot();
+        let mut cids = Vec::with_capacity(snapshot.len());
+        for i in 0..snapshot.len() {
+            cids.push(self.add_pinned(bincode::serialize(&snapshot.stored(i, &cids))?).await?);
+        }
+        let root_cid = snapshot.root(&cids).ok_or_else(|| anyhow!("Index snapshot has no root"))?;
+        if checkpoint.root.as_ref() != Some(&root_cid) {
+            let tmp = path.with_extension("tmp");
+            tokio::fs::write(&tmp, &root_cid).await?;
+            tokio::fs::rename(&tmp, path).await?;
+        }
+        let unreferenced = self.cache.write().await.commit(&snapshot, &cids);
+        info!(
+            "Checkpointed IPFS index root {} ({} nodes updated, {} objects released)",
+            root_cid,
+            cids.len(),
+            unreferenced.records.len() + unreferenced.nodes.len()
+        );
+        *checkpoint = Checkpoint {
+            root: Some(root_cid),
+            at: Instant::now(),
+        };
+        drop(checkpoint);
+        self.unpin_unreferenced(unreferenced.records, unreferenced.nodes).await
+    }
+
+    // Skips records a key has pointed at again since they were released.
+    async fn unpin_unreferenced(&self, records: Vec<String>, nodes: Vec<String>) -> Result<()> {
+        let _pinning = self.pinning.write().await;
+        let cache = self.cache.read().await;
+        let records: Vec<String> = records.into_iter().filter(|cid| !cache.is_referenced(cid)).collect();
+        drop(cache);
+        // A failed unpin only leaves the object stored.
+        for cid in records.iter().chain(&nodes) {
+            if let Err(e) = self.ipfs_client.pin_rm(cid, true).await {
+                warn!("Could not unpin {}: {}", cid, e);
+            }
         }
+        Ok(())
+    }
+
+    async fn add_pinned(&self, bytes: Vec<u8>) -> Result<String> {
+        let options = Add {
+            pin: Some(true),
+            ..Default::default()
+        };
+        Ok(self.ipfs_client.add_with_options(Cursor::new(bytes), options).await?.hash)
     }
 
     #[instrument(skip(self, data))]
     async fn store<T: Serialize>(&self, key: &str, data: &T) -> Result<String> {
         let serialized = bincode::serialize(data)?;
-        let res = self.ipfs_client.add(serialized).await?;
-        let cid = res.hash;
+        let _pinning = self.pinning.read().await;
+        let cid = self.add_pinned(serialized).await?;
 
         // Update cache
         let mut cache = self.cache.write().await;
@@ -50,43 +251,135 @@ impl FilecoinStorage {
     #[instrument(skip(self))]
     async fn retrieve<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Result<T> {
         let cache = self.cache.read().await;
-        if let Some(cid) = cache.get(key) {
+        if let Some(cid) = cache.get(key).cloned() {
             drop(cache); // Release the read lock
-            return self.retrieve_by_cid(cid).await;
+            return self.retrieve_by_cid(&cid).await;
         }
         drop(cache);
 
         error!("Data not found in cache for key: {}", key);
-        Err(anyhow!("Data not found for key: {}", key))
+        Err(StorageError::not_found(format!("IPFS index key {}", key)).into())
+    }
+    #[instrument(skip(self))]
+    async fn retrieve_optional<T: for<'de> Deserialize<'de>>(&self, key: &str) -> Result<Option<T>> {
+        if !self.cache.read().await.contains_key(key) {
+            return Ok(None);
+        }
+        self.retrieve(key).await.map(Some)
+    }
+
+    async fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.cache.read().await.keys_with_prefix(prefix)
+    }
+
+    /// Up to `limit` records stored under `prefix` whose key follows `after`, in key order.
+    async fn list_with_prefix<T: for<'de> Deserialize<'de>>(
+        &self,
+        prefix: &str,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<T>> {
+        // Hex keeps byte order.
+        let after = after.map(|after| format!("{}{}", prefix, hex::encode(after)));
+        let keys = self.keys_with_prefix(prefix).await;
+        let mut records = Vec::new();
+        for key in keys.into_iter().filter(|key| after.as_ref().map_or(true, |after| key > after)).take(limit) {
+            records.extend(self.retrieve_optional(&key).await?);
+        }
+        Ok(records)
+    }
+
+    async fn token_accounts_with_prefix(&self, prefix: &str) -> Result<Vec<CompressedTokenAccountRecord>> {
+        let mut accounts = Vec::new();
+        for key in self.keys_with_prefix(prefix).await {
+            let key = format!("{}{}", TOKEN_ACCOUNT_PREFIX, &key[prefix.len()..]);
+            if let Some(account) = self.retrieve_optional(&key).await? {
+                accounts.push(account);
+            }
+        }
+        Ok(accounts)
     }
 
     #[instrument(skip(self))]
     async fn retrieve_by_cid<T: for<'de> Deserialize<'de>>(&self, cid: &str) -> Result<T> {
+        Ok(bincode::deserialize(&self.cat(cid).await?)?)
+    }
+
+    /// Removes `keys` from the index and returns whether the record `key` pointed at is left
+    /// unreferenced, so the next checkpoint unpins it.
+    async fn forget(&self, key: &str, keys: &[String]) -> bool {
+        let mut cache = self.cache.write().await;
+        let cid = cache.get(key).cloned();
+        cache.remove(key);
+        for key in keys {
+            cache.remove(key);
+        }
+        cid.is_some_and(|cid| !cache.is_referenced(&cid))
+    }
+
+    async fn cat(&self, cid: &str) -> Result<Vec<u8>> {
         let cid = Cid::try_from(cid)?;
-        let data = self.ipfs_client.cat(&cid.to_string()).await?;
-        let deserialized: T = bincode::deserialize(&data)?;
-        Ok(deserialized)
+        Ok(self
+            .ipfs_client
+            .cat(&cid.to_string())
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await?)
     }
 }
 
+// The index keys besides `slot_key`, its `tx_slot:` key, that point at `transaction`.
+fn transaction_keys(slot_key: &str, transaction: &CompressedTransaction) -> Vec<String> {
+    let position = &slot_key[TRANSACTION_SLOT_PREFIX.len()..];
+    let mut keys = vec![format!("{}{}", TRANSACTION_PREFIX, hex::encode(&transaction.signature))];
+    keys.extend(
+        transaction
+            .accounts
+            .iter()
+            .map(|address| format!("{}{}:{}", ADDRESS_SIGNATURE_PREFIX, hex::encode(address), position)),
+    );
+    keys
+}
+
 #[async_trait]
 impl Database for FilecoinStorage {
     #[instrument(skip(self, account))]
-    async fn insert_compressed_account(&self, account: &CompressedAccount) -> Result<()> {
+    async fn insert_compressed_account(&self, account: &CompressedAccount) -> error::Result<()> {
         let key = format!("{}{}", ACCOUNT_PREFIX, hex::encode(&account.pubkey));
-        let cid = self.store(&key, account).await?;
+        let version_key = format!(
+            "{}{}:{:020}:{:020}",
+            ACCOUNT_VERSION_PREFIX,
+            hex::encode(&account.pubkey),
+            account.slot,
+            account.write_version
+        );
+        let previous: Option<CompressedAccount> = self.retrieve_optional(&key).await?;
+        let cid = self.store(&version_key, account).await?;
+        if previous.as_ref().is_some_and(|previous| previous.version() > account.version()) {
+            info!("Stored older version of compressed account with key: {}, CID: {}", version_key, cid);
+            return Ok(());
+        }
+        let mut cache = self.cache.write().await;
+        cache.insert(key.clone(), cid.clone());
+        if let Some(previous) = previous {
+            cache.remove(&format!("{}{}:{}", ACCOUNT_OWNER_PREFIX, hex::encode(&previous.owner), hex::encode(&account.pubkey)));
+        }
+        cache.insert(
+            format!("{}{}:{}", ACCOUNT_OWNER_PREFIX, hex::encode(&account.owner), hex::encode(&account.pubkey)),
+            cid.clone(),
+        );
         info!("Inserted compressed account with key: {}, CID: {}", key, cid);
         Ok(())
     }
 
     #[instrument(skip(self))]
-    async fn get_compressed_account(&self, pubkey: &[u8]) -> Result<CompressedAccount> {
+    async fn get_compressed_account(&self, pubkey: &[u8]) -> error::Result<CompressedAccount> {
         let key = format!("{}{}", ACCOUNT_PREFIX, hex::encode(pubkey));
-        self.retrieve(&key).await
+        Ok(self.retrieve(&key).await?)
     }
 
     #[instrument(skip(self, block))]
-    async fn insert_compressed_block(&self, block: &CompressedBlock) -> Result<()> {
+    async fn insert_compressed_block(&self, block: &CompressedBlock) -> error::Result<()> {
         let key = format!("{}{}", BLOCK_PREFIX, block.slot);
         let cid = self.store(&key, block).await?;
         info!("Inserted compressed block with key: {}, CID: {}", key, cid);
@@ -94,51 +387,670 @@ impl Database for FilecoinStorage {
     }
 
     #[instrument(skip(self))]
-    async fn get_compressed_block(&self, slot: u64) -> Result<CompressedBlock> {
+    async fn get_compressed_block(&self, slot: u64) -> error::Result<CompressedBlock> {
         let key = format!("{}{}", BLOCK_PREFIX, slot);
-        self.retrieve(&key).await
+        Ok(self.retrieve(&key).await?)
     }
 
     #[instrument(skip(self, transaction))]
-    async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> Result<()> {
+    async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> error::Result<()> {
         let key = format!("{}{}", TRANSACTION_PREFIX, hex::encode(&transaction.signature));
         let cid = self.store(&key, transaction).await?;
+        // Index keys point at the same CID; zero padding keeps them in (slot, index) order.
+        let position = format!("{:020}:{:010}", transaction.slot, transaction.index);
+        let mut cache = self.cache.write().await;
+        cache.insert(format!("{}{}", TRANSACTION_SLOT_PREFIX, position), cid.clone());
+        for address in &transaction.accounts {
+            cache.insert(
+                format!("{}{}:{}", ADDRESS_SIGNATURE_PREFIX, hex::encode(address), position),
+                cid.clone(),
+            );
+        }
         info!("Inserted compressed transaction with key: {}, CID: {}", key, cid);
         Ok(())
     }
 
     #[instrument(skip(self))]
-    async fn get_compressed_transaction(&self, signature: &[u8]) -> Result<CompressedTransaction> {
+    async fn get_compressed_transaction(&self, signature: &[u8]) -> error::Result<CompressedTransaction> {
         let key = format!("{}{}", TRANSACTION_PREFIX, hex::encode(signature));
-        self.retrieve(&key).await
+        Ok(self.retrieve(&key).await?)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_accounts_by_owner(&self, owner: &[u8], limit: usize) -> error::Result<Vec<CompressedAccount>> {
+        let prefix = format!("{}{}:", ACCOUNT_OWNER_PREFIX, hex::encode(owner));
+        let mut keys = self.keys_with_prefix(&prefix).await;
+        keys.sort();
+        let mut accounts = Vec::new();
+        for key in keys.into_iter().take(limit) {
+            accounts.push(self.retrieve(&key).await?);
+        }
+        Ok(accounts)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_signatures_for_address(&self, address: &[u8], query: &SignatureQuery) -> error::Result<Vec<SignatureInfo>> {
+        let (before, until) = signature_bounds(self, query).await?;
+        let prefix = format!("{}{}:", ADDRESS_SIGNATURE_PREFIX, hex::encode(address));
+        let position = |(slot, index): (u64, u32)| format!("{}{:020}:{:010}", prefix, slot, index);
+        let before = before.map(position);
+        let until = until.map(position);
+
+        let mut keys: Vec<String> = self
+            .keys_with_prefix(&prefix)
+            .await
+            .into_iter()
+            .filter(|key| before.as_ref().map_or(true, |before| key < before))
+            .filter(|key| until.as_ref().map_or(true, |until| key > until))
+            .collect();
+        keys.sort_by(|a, b| b.cmp(a));
+
+        let mut signatures = Vec::new();
+        for key in keys.into_iter().take(query.limit) {
+            let transaction: CompressedTransaction = self.retrieve(&key).await?;
+            signatures.push(SignatureInfo {
+                signature: transaction.signature,
+                slot: transaction.slot,
+                index: transaction.index,
+            });
+        }
+        Ok(signatures)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_blocks_in_range(&self, start_slot: u64, end_slot: u64) -> error::Result<Vec<CompressedBlock>> {
+        let mut slots: Vec<u64> = self
+            .keys_with_prefix(BLOCK_PREFIX)
+            .await
+            .iter()
+            .filter_map(|key| key[BLOCK_PREFIX.len()..].parse().ok())
+            .filter(|slot| (start_slot..=end_slot).contains(slot))
+            .collect();
+        slots.sort_unstable();
+        let mut blocks = Vec::new();
+        for slot in slots {
+            blocks.push(self.get_compressed_block(slot).await?);
+        }
+        Ok(blocks)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_first_block_slot(&self) -> error::Result<Option<u64>> {
+        Ok(self
+            .keys_with_prefix(BLOCK_PREFIX)
+            .await
+            .iter()
+            .filter_map(|key| key[BLOCK_PREFIX.len()..].parse().ok())
+            .min())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_transactions_in_block(&self, slot: u64) -> error::Result<Vec<CompressedTransaction>> {
+        let prefix = format!("{}{:020}:", TRANSACTION_SLOT_PREFIX, slot);
+        let mut keys = self.keys_with_prefix(&prefix).await;
+        keys.sort();
+        let mut transactions = Vec::new();
+        for key in keys {
+            transactions.push(self.retrieve(&key).await?);
+        }
+        Ok(transactions)
+    }
+
+    // The records are unpinned by the checkpoint that ends the delete.
+    #[instrument(skip(self))]
+    async fn delete_slot_range(&self, start_slot: u64, end_slot: u64) -> error::Result<u64> {
+        let range = start_slot..=end_slot;
+        let block_keys: Vec<String> = self
+            .keys_with_prefix(BLOCK_PREFIX)
+            .await
+            .into_iter()
+            .filter(|key| key[BLOCK_PREFIX.len()..].parse().map_or(false, |slot| range.contains(&slot)))
+            .collect();
+        let slot_keys: Vec<String> = self
+            .keys_with_prefix(TRANSACTION_SLOT_PREFIX)
+            .await
+            .into_iter()
+            .filter(|key| {
+                let slot = &key[TRANSACTION_SLOT_PREFIX.len()..TRANSACTION_SLOT_PREFIX.len() + 20];
+                slot.parse().map_or(false, |slot| range.contains(&slot))
+            })
+            .collect();
+
+        let mut deleted = 0;
+        for key in slot_keys {
+            let transaction: CompressedTransaction = self.retrieve(&key).await?;
+            self.forget(&key, &transaction_keys(&key, &transaction)).await;
+            deleted += 1;
+        }
+        let mut cache = self.cache.write().await;
+        for key in block_keys {
+            cache.remove(&key);
+            deleted += 1;
+        }
+        drop(cache);
+        let cursor = self.get_last_processed_slot().await?;
+        if range.contains(&cursor) {
+            self.store(LAST_SLOT_KEY, &CachedData { data: start_slot.saturating_sub(1), cid: String::new() }).await?;
+        }
+        self.checkpoint().await?;
+        Ok(deleted)
     }
 
     #[instrument(skip(self))]
-    async fn get_last_processed_slot(&self) -> Result<u64> {
-        let cached_data: CachedData<u64> = self.retrieve(LAST_SLOT_KEY).await?;
-        Ok(cached_data.data)
+    async fn get_account_at_slot(&self, pubkey: &[u8], slot: u64) -> error::Result<Option<CompressedAccount>> {
+        let prefix = format!("{}{}:", ACCOUNT_VERSION_PREFIX, hex::encode(pubkey));
+        let bound = format!("{}{:020}:{:020}", prefix, slot, u64::MAX);
+        let key = self
+            .keys_with_prefix(&prefix)
+            .await
+            .into_iter()
+            .filter(|key| *key <= bound)
+            .max();
+        match key {
+            Some(key) => Ok(Some(self.retrieve(&key).await?)),
+            None => Ok(None),
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn get_account_versions(
+        &self,
+        pubkey: &[u8],
+        start_slot: u64,
+        end_slot: u64,
+        limit: usize,
+    ) -> error::Result<Vec<CompressedAccount>> {
+        let prefix = format!("{}{}:", ACCOUNT_VERSION_PREFIX, hex::encode(pubkey));
+        let (start, end) = (
+            format!("{}{:020}:", prefix, start_slot),
+            format!("{}{:020}:{:020}", prefix, end_slot, u64::MAX),
+        );
+        let mut keys: Vec<String> = self
+            .keys_with_prefix(&prefix)
+            .await
+            .into_iter()
+            .filter(|key| *key >= start && *key <= end)
+            .collect();
+        keys.sort();
+        let mut accounts = Vec::new();
+        for key in keys.into_iter().take(limit) {
+            accounts.push(self.retrieve(&key).await?);
+        }
+        Ok(accounts)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_transaction_signatures(&self, after: Option<&[u8]>, limit: usize) -> error::Result<Vec<Vec<u8>>> {
+        let after = after.map(hex::encode);
+        let mut signatures: Vec<String> = self
+            .keys_with_prefix(TRANSACTION_PREFIX)
+            .await
+            .iter()
+            .map(|key| key[TRANSACTION_PREFIX.len()..].to_string())
+            .filter(|signature| after.as_ref().map_or(true, |after| signature > after))
+            .collect();
+        signatures.sort_unstable();
+        signatures
+            .into_iter()
+            .take(limit)
+            .map(|signature| hex::decode(signature).map_err(|e| StorageError::Corrupt(e.into())))
+            .collect()
     }
 
     #[instrument(skip(self))]
-    async fn update_last_processed_slot(&self, slot: u64) -> Result<()> {
+    async fn get_account_pubkeys(&self, after: Option<&[u8]>, limit: usize) -> error::Result<Vec<Vec<u8>>> {
+        let after = after.map(hex::encode);
+        let pubkeys: BTreeSet<String> = self
+            .keys_with_prefix(ACCOUNT_VERSION_PREFIX)
+            .await
+            .iter()
+            .filter_map(|key| key[ACCOUNT_VERSION_PREFIX.len()..].split_once(':'))
+            .map(|(pubkey, _)| pubkey.to_string())
+            .filter(|pubkey| after.as_ref().map_or(true, |after| pubkey > after))
+            .collect();
+        pubkeys
+            .into_iter()
+            .take(limit)
+            .map(|pubkey| hex::decode(pubkey).map_err(|e| StorageError::Corrupt(e.into())))
+            .collect()
+    }
+
+    // Bytes are counted for records no key refers to afterwards, which the checkpoint that ends
+    // the prune unpins. Transactions go first, so a block is only dropped once none of its
+    // transactions are kept.
+    #[instrument(skip(self, request))]
+    async fn prune(&self, request: &PruneRequest) -> error::Result<PruneStats> {
+        let mut stats = PruneStats::default();
+        if let Some(before) = request.transaction_cutoff(true) {
+            let slot_keys: Vec<String> = self
+                .keys_with_prefix(TRANSACTION_SLOT_PREFIX)
+                .await
+                .into_iter()
+                .filter(|key| {
+                    let slot = &key[TRANSACTION_SLOT_PREFIX.len()..TRANSACTION_SLOT_PREFIX.len() + 20];
+                    slot.parse().is_ok_and(|slot: u64| slot < before)
+                })
+                .collect();
+            for key in slot_keys {
+                let transaction: CompressedTransaction = self.retrieve(&key).await?;
+                let is_vote = transaction.accounts.iter().any(|address| address[..] == VOTE_PROGRAM_ID[..]);
+                if !request.transaction_cutoff(is_vote).is_some_and(|before| transaction.slot < before)
+                    || transaction.accounts.iter().any(|address| request.is_protected(address))
+                {
+                    continue;
+                }
+                let unpinned = self.forget(&key, &transaction_keys(&key, &transaction)).await;
+                let bytes = (transaction.data.len() + transaction.proof.len()) as u64;
+                stats.transactions.add(if unpinned { bytes } else { 0 });
+            }
+        }
+
+        if let Some(before) = request.blocks_before {
+            let block_keys: Vec<String> = self
+                .keys_with_prefix(BLOCK_PREFIX)
+                .await
+                .into_iter()
+                .filter(|key| key[BLOCK_PREFIX.len()..].parse().is_ok_and(|slot: u64| slot < before))
+                .collect();
+            for key in block_keys {
+                let block: CompressedBlock = self.retrieve(&key).await?;
+                let transactions = format!("{}{:020}:", TRANSACTION_SLOT_PREFIX, block.slot);
+                if !self.keys_with_prefix(&transactions).await.is_empty() {
+                    continue;
+                }
+                let unpinned = self.forget(&key, &[]).await;
+                stats.blocks.add(if unpinned { (block.data.len() + block.proof.len()) as u64 } else { 0 });
+            }
+        }
+
+        if let Some(before) = request.account_versions_before {
+            let bound = format!("{:020}:", before);
+            let version_keys: Vec<String> = self
+                .keys_with_prefix(ACCOUNT_VERSION_PREFIX)
+                .await
+                .into_iter()
+                .filter(|key| {
+                    let version = key[ACCOUNT_VERSION_PREFIX.len()..].split_once(':').map(|(_, version)| version);
+                    version.map_or(false, |version| version < bound.as_str())
+                })
+                .collect();
+            let mut current: BTreeMap<String, Option<String>> = BTreeMap::new();
+            for key in version_keys {
+                let Some((pubkey, _)) = key[ACCOUNT_VERSION_PREFIX.len()..].split_once(':') else {
+                    continue;
+                };
+                let pubkey = pubkey.to_string();
+                if !current.contains_key(&pubkey) {
+                    let account: Option<CompressedAccount> =
+                        self.retrieve_optional(&format!("{}{}", ACCOUNT_PREFIX, pubkey)).await?;
+                    let newest = account.map(|account| {
+                        format!("{}{}:{:020}:{:020}", ACCOUNT_VERSION_PREFIX, pubkey, account.slot, account.write_version)
+                    });
+                    current.insert(pubkey.clone(), newest);
+                }
+                // Versions of accounts without a current row are left alone.
+                if current[&pubkey].as_ref().map_or(true, |newest| *newest == key) {
+                    continue;
+                }
+                let account: CompressedAccount = self.retrieve(&key).await?;
+                if request.is_protected(&account.pubkey) || request.is_protected(&account.owner) {
+                    continue;
+                }
+                let unpinned = self.forget(&key, &[]).await;
+                stats.account_versions.add(if unpinned { (account.data.len() + account.proof.len()) as u64 } else { 0 });
+            }
+        }
+        self.checkpoint().await?;
+        Ok(stats)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_last_processed_slot(&self) -> error::Result<u64> {
+        let cached_data: Option<CachedData<u64>> = self.retrieve_optional(LAST_SLOT_KEY).await?;
+        Ok(cached_data.map_or(0, |cached_data| cached_data.data))
+    }
+
+    #[instrument(skip(self))]
+    async fn update_last_processed_slot(&self, slot: u64) -> error::Result<()> {
+        let cursor = self.get_last_processed_slot().await?;
+        if slot < cursor {
+            return Err(cursor_moved_back(cursor, slot));
+        }
         let cached_data = CachedData {
             data: slot,
             cid: "".to_string(), // This will be updated by the store method
         };
         let cid = self.store(LAST_SLOT_KEY, &cached_data).await?;
         info!("Updated last processed slot: {}, CID: {}", slot, cid);
+        // The cursor marks a complete slot, so it is also when the index is checkpointed.
+        if self.checkpoint.lock().await.at.elapsed() >= self.checkpoint_interval {
+            self.checkpoint().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CompressionStore for FilecoinStorage {
+    #[instrument(skip(self))]
+    async fn get_compressed_slot(&self) -> Result<u64> {
+        let cached_data: Option<CachedData<u64>> = self.retrieve_optional(COMPRESSED_SLOT_KEY).await?;
+        Ok(cached_data.map_or(0, |cached_data| cached_data.data))
+    }
+
+    // Checkpointed with the block cursor, which advances right after it.
+    #[instrument(skip(self))]
+    async fn update_compressed_slot(&self, slot: u64) -> Result<()> {
+        let cached_data = CachedData {
+            data: slot,
+            cid: "".to_string(),
+        };
+        self.store(COMPRESSED_SLOT_KEY, &cached_data).await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self, tree))]
+    async fn upsert_merkle_tree(&self, tree: &MerkleTreeRecord) -> Result<()> {
+        let key = format!("{}{}", TREE_PREFIX, hex::encode(&tree.tree));
+        let cid = self.store(&key, tree).await?;
+        info!("Upserted merkle tree with key: {}, CID: {}", key, cid);
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_merkle_tree(&self, tree: &[u8]) -> Result<Option<MerkleTreeRecord>> {
+        let key = format!("{}{}", TREE_PREFIX, hex::encode(tree));
+        self.retrieve_optional(&key).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_merkle_trees(&self) -> Result<Vec<MerkleTreeRecord>> {
+        let mut trees = Vec::new();
+        for key in self.keys_with_prefix(TREE_PREFIX).await {
+            trees.extend(self.retrieve_optional(&key).await?);
+        }
+        Ok(trees)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_merkle_tree(&self, tree: &[u8]) -> Result<()> {
+        let tree = hex::encode(tree);
+        let mut keys = self.keys_with_prefix(&format!("{}{}:", TREE_ROOT_PREFIX, tree)).await;
+        keys.extend(self.keys_with_prefix(&format!("{}{}:", LEAF_CHANGE_PREFIX, tree)).await);
+        keys.push(format!("{}{}", TREE_PREFIX, tree));
+        let mut cache = self.cache.write().await;
+        for key in &keys {
+            cache.remove(key);
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self, root))]
+    async fn insert_tree_root(&self, root: &TreeRootRecord) -> Result<()> {
+        let key = format!("{}{}:{}", TREE_ROOT_PREFIX, hex::encode(&root.tree), root.seq);
+        self.store(&key, root).await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_tree_roots(&self, tree: &[u8]) -> Result<Vec<TreeRootRecord>> {
+        let mut roots = Vec::new();
+        for key in self.keys_with_prefix(&format!("{}{}:", TREE_ROOT_PREFIX, hex::encode(tree))).await {
+            roots.extend(self.retrieve_optional::<TreeRootRecord>(&key).await?);
+        }
+        roots.sort_by_key(|root| root.seq);
+        Ok(roots)
+    }
+
+    #[instrument(skip(self, change))]
+    async fn insert_leaf_change(&self, change: &LeafChangeRecord) -> Result<()> {
+        let key = format!("{}{}:{}", LEAF_CHANGE_PREFIX, hex::encode(&change.tree), change.seq);
+        self.store(&key, change).await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_leaf_changes(&self, tree: &[u8], after_seq: u64) -> Result<Vec<LeafChangeRecord>> {
+        let mut changes = Vec::new();
+        for key in self.keys_with_prefix(&format!("{}{}:", LEAF_CHANGE_PREFIX, hex::encode(tree))).await {
+            let change: Option<LeafChangeRecord> = self.retrieve_optional(&key).await?;
+            changes.extend(change.filter(|change| change.seq > after_seq));
+        }
+        changes.sort_by_key(|change| change.seq);
+        Ok(changes)
+    }
+
+    #[instrument(skip(self, account))]
+    async fn upsert_state_account(&self, account: &CompressedStateAccount) -> Result<()> {
+        let key = format!("{}{}", STATE_ACCOUNT_PREFIX, hex::encode(&account.hash));
+        let cid = self.store(&key, account).await?;
+        let owner_key = format!("{}{}:{}", STATE_OWNER_PREFIX, hex::encode(&account.owner), hex::encode(&account.hash));
+        if account.spent {
+            self.cache.write().await.remove(&owner_key);
+        } else {
+            self.cache.write().await.insert(owner_key, cid.clone());
+        }
+        if let Some(address) = &account.address {
+            let address_key = format!("{}{}", STATE_ADDRESS_PREFIX, hex::encode(address));
+            self.cache.write().await.insert(address_key, cid.clone());
+        }
+        info!("Upserted compressed state account with key: {}, CID: {}", key, cid);
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_state_account(&self, hash: &[u8]) -> Result<Option<CompressedStateAccount>> {
+        let key = format!("{}{}", STATE_ACCOUNT_PREFIX, hex::encode(hash));
+        self.retrieve_optional(&key).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_state_account_by_address(&self, address: &[u8]) -> Result<Option<CompressedStateAccount>> {
+        let key = format!("{}{}", STATE_ADDRESS_PREFIX, hex::encode(address));
+        let account: Option<CompressedStateAccount> = self.retrieve_optional(&key).await?;
+        // The address index points at the CID of the version it was written with
+        match account {
+            Some(account) => Ok(self.get_state_account(&account.hash).await?.or(Some(account))),
+            None => Ok(None),
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn get_state_accounts_by_owner(&self, owner: &[u8]) -> Result<Vec<CompressedStateAccount>> {
+        let prefix = format!("{}{}:", STATE_OWNER_PREFIX, hex::encode(owner));
+        let mut accounts = Vec::new();
+        for key in self.keys_with_prefix(&prefix).await {
+            let hash = hex::decode(&key[prefix.len()..])?;
+            if let Some(account) = self.get_state_account(&hash).await? {
+                accounts.push(account);
+            }
+        }
+        Ok(accounts)
+    }
+
+    #[instrument(skip(self))]
+    async fn spend_state_account(&self, hash: &[u8], slot: u64) -> Result<()> {
+        if let Some(mut account) = self.get_state_account(hash).await? {
+            account.spent = true;
+            account.spent_slot = Some(slot);
+            self.upsert_state_account(&account).await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self, address))]
+    async fn insert_address(&self, address: &AddressRecord) -> Result<()> {
+        let key = format!("{}{}", ADDRESS_PREFIX, hex::encode(&address.address));
+        let cid = self.store(&key, address).await?;
+        self.cache.write().await.insert(
+            format!("{}{}:{}", ADDRESS_TREE_PREFIX, hex::encode(&address.tree), hex::encode(&address.address)),
+            cid,
+        );
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_address(&self, address: &[u8]) -> Result<Option<AddressRecord>> {
+        let key = format!("{}{}", ADDRESS_PREFIX, hex::encode(address));
+        self.retrieve_optional(&key).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_low_address(&self, tree: &[u8], address: &[u8]) -> Result<Option<AddressRecord>> {
+        let prefix = format!("{}{}:", ADDRESS_TREE_PREFIX, hex::encode(tree));
+        let address = hex::encode(address);
+        // Hex keeps byte order, so the greatest key below the address is its low element
+        let low = self
+            .keys_with_prefix(&prefix)
+            .await
+            .into_iter()
+            .map(|key| key[prefix.len()..].to_string())
+            .filter(|key| key.as_str() < address.as_str())
+            .max();
+        match low {
+            Some(low) => self.retrieve_optional(&format!("{}{}", ADDRESS_PREFIX, low)).await,
+            None => Ok(None),
+        }
+    }
+
+    #[instrument(skip(self, account))]
+    async fn upsert_token_account(&self, account: &CompressedTokenAccountRecord) -> Result<()> {
+        let key = format!("{}{}", TOKEN_ACCOUNT_PREFIX, hex::encode(&account.hash));
+        let cid = self.store(&key, account).await?;
+        let mut cache = self.cache.write().await;
+        cache.insert(
+            format!("{}{}:{}", TOKEN_OWNER_PREFIX, hex::encode(&account.owner), hex::encode(&account.hash)),
+            cid.clone(),
+        );
+        cache.insert(
+            format!("{}{}:{}", TOKEN_MINT_INDEX_PREFIX, hex::encode(&account.mint), hex::encode(&account.hash)),
+            cid.clone(),
+        );
+        info!("Upserted compressed token account with key: {}, CID: {}", key, cid);
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_token_account(&self, hash: &[u8]) -> Result<()> {
+        let Some(account) = self.get_token_account(hash).await? else {
+            return Ok(());
+        };
+        let mut cache = self.cache.write().await;
+        cache.remove(&format!("{}{}", TOKEN_ACCOUNT_PREFIX, hex::encode(hash)));
+        cache.remove(&format!("{}{}:{}", TOKEN_OWNER_PREFIX, hex::encode(&account.owner), hex::encode(hash)));
+        cache.remove(&format!("{}{}:{}", TOKEN_MINT_INDEX_PREFIX, hex::encode(&account.mint), hex::encode(hash)));
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_token_account(&self, hash: &[u8]) -> Result<Option<CompressedTokenAccountRecord>> {
+        let key = format!("{}{}", TOKEN_ACCOUNT_PREFIX, hex::encode(hash));
+        self.retrieve_optional(&key).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_token_accounts_by_owner(&self, owner: &[u8]) -> Result<Vec<CompressedTokenAccountRecord>> {
+        let prefix = format!("{}{}:", TOKEN_OWNER_PREFIX, hex::encode(owner));
+        self.token_accounts_with_prefix(&prefix).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_token_accounts_by_mint(&self, mint: &[u8]) -> Result<Vec<CompressedTokenAccountRecord>> {
+        let prefix = format!("{}{}:", TOKEN_MINT_INDEX_PREFIX, hex::encode(mint));
+        self.token_accounts_with_prefix(&prefix).await
+    }
+
+    #[instrument(skip(self, mint))]
+    async fn upsert_token_mint(&self, mint: &TokenMintRecord) -> Result<()> {
+        let key = format!("{}{}", TOKEN_MINT_PREFIX, hex::encode(&mint.mint));
+        self.store(&key, mint).await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_token_mint(&self, mint: &[u8]) -> Result<Option<TokenMintRecord>> {
+        let key = format!("{}{}", TOKEN_MINT_PREFIX, hex::encode(mint));
+        self.retrieve_optional(&key).await
+    }
+
+    #[instrument(skip(self, asset))]
+    async fn upsert_asset(&self, asset: &CompressedAssetRecord) -> Result<()> {
+        let previous = self.get_asset(&asset.asset_id).await?;
+        let key = format!("{}{}", ASSET_PREFIX, hex::encode(&asset.asset_id));
+        let cid = self.store(&key, asset).await?;
+        let mut cache = self.cache.write().await;
+        if let Some(previous) = previous {
+            cache.remove(&format!("{}{}:{}", ASSET_OWNER_PREFIX, hex::encode(&previous.owner), hex::encode(&asset.asset_id)));
+        }
+        cache.insert(
+            format!("{}{}:{}", ASSET_OWNER_PREFIX, hex::encode(&asset.owner), hex::encode(&asset.asset_id)),
+            cid.clone(),
+        );
+        info!("Upserted compressed asset with key: {}, CID: {}", key, cid);
         Ok(())
     }
+
+    #[instrument(skip(self))]
+    async fn get_asset(&self, asset_id: &[u8]) -> Result<Option<CompressedAssetRecord>> {
+        let key = format!("{}{}", ASSET_PREFIX, hex::encode(asset_id));
+        self.retrieve_optional(&key).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_assets_by_owner(&self, owner: &[u8]) -> Result<Vec<CompressedAssetRecord>> {
+        let prefix = format!("{}{}:", ASSET_OWNER_PREFIX, hex::encode(owner));
+        let mut assets = Vec::new();
+        for key in self.keys_with_prefix(&prefix).await {
+            let key = format!("{}{}", ASSET_PREFIX, &key[prefix.len()..]);
+            if let Some(asset) = self.retrieve_optional(&key).await? {
+                assets.push(asset);
+            }
+        }
+        Ok(assets)
+    }
+
+    #[instrument(skip(self))]
+    async fn list_state_accounts(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<CompressedStateAccount>> {
+        self.list_with_prefix(STATE_ACCOUNT_PREFIX, after, limit).await
+    }
+
+    #[instrument(skip(self))]
+    async fn list_addresses(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<AddressRecord>> {
+        self.list_with_prefix(ADDRESS_PREFIX, after, limit).await
+    }
+
+    #[instrument(skip(self))]
+    async fn list_token_accounts(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<CompressedTokenAccountRecord>> {
+        self.list_with_prefix(TOKEN_ACCOUNT_PREFIX, after, limit).await
+    }
+
+    #[instrument(skip(self))]
+    async fn list_token_mints(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<TokenMintRecord>> {
+        self.list_with_prefix(TOKEN_MINT_PREFIX, after, limit).await
+    }
+
+    #[instrument(skip(self))]
+    async fn list_assets(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<CompressedAssetRecord>> {
+        self.list_with_prefix(ASSET_PREFIX, after, limit).await
+    }
 }
 
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::conformance::{service_url, storage_conformance};
     use crate::storage::models::*;
 
+    storage_conformance!(
+        ignore = "needs an IPFS node at WINDEXER_TEST_IPFS_URL",
+        FilecoinStorage::ephemeral(&service_url("WINDEXER_TEST_IPFS_URL", DEFAULT_API_URL)).unwrap()
+    );
+
     #[tokio::test]
+    #[ignore = "needs an IPFS node at WINDEXER_TEST_IPFS_URL"]
     async fn test_filecoin_storage() {
-        let storage = FilecoinStorage::new();
+        let storage = FilecoinStorage::ephemeral(&service_url("WINDEXER_TEST_IPFS_URL", DEFAULT_API_URL)).unwrap();
 
         let account = CompressedAccount {
             pubkey: vec![1, 2, 3, 4],
@@ -146,6 +1058,8 @@ mod tests {
             owner: vec![5, 6, 7, 8],
             executable: false,
             rent_epoch: 0,
+            slot: 0,
+            write_version: 0,
             data: vec![9, 10, 11, 12],
             proof: vec![13, 14, 15, 16],
         };
@@ -159,21 +1073,33 @@ mod tests {
             previous_blockhash: "def456".to_string(),
             parent_slot: 12344,
             transactions: 10,
+            block_time: Some(1_700_000_000),
             data: vec![17, 18, 19, 20],
             proof: vec![21, 22, 23, 24],
         };
         storage.insert_compressed_block(&block).await.unwrap();
         let retrieved_block = storage.get_compressed_block(block.slot).await.unwrap();
-        assert_eq!(block.blockhash, retrieved_block.blockhash);
+        assert_eq!(
+            (block.blockhash, block.block_time),
+            (retrieved_block.blockhash, retrieved_block.block_time)
+        );
 
         let transaction = CompressedTransaction {
             signature: vec![25, 26, 27, 28],
+            slot: 12345,
+            index: 0,
+            accounts: vec![vec![5, 6, 7, 8]],
+            fee: 5000,
+            programs: vec![vec![5, 6, 7, 8]],
             data: vec![29, 30, 31, 32],
             proof: vec![33, 34, 35, 36],
         };
         storage.insert_compressed_transaction(&transaction).await.unwrap();
         let retrieved_transaction = storage.get_compressed_transaction(&transaction.signature).await.unwrap();
-        assert_eq!(transaction.data, retrieved_transaction.data);
+        assert_eq!(
+            (transaction.data, transaction.fee, transaction.programs),
+            (retrieved_transaction.data, retrieved_transaction.fee, retrieved_transaction.programs)
+        );
 
         storage.update_last_processed_slot(12345).await.unwrap();
         let last_slot = storage.get_last_processed_slot().await.unwrap();
@@ -0,0 +1,475 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+// Children per branch; each level consumes four bits of the key hash.
+const FANOUT: usize = 16;
+const BITS_PER_LEVEL: usize = 4;
+const MAX_DEPTH: usize = 64 / BITS_PER_LEVEL;
+// Keys a bucket holds before it is split into a branch. Buckets at the last level grow without bound.
+const MAX_BUCKET_KEYS: usize = 512;
+
+fn hash(key: &str) -> u64 {
+    // FNV-1a, so key placement is stable across builds.
+    key.bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// The low bits of FNV-1a mix best, so the trie consumes the hash from the bottom up.
+fn child_of(hash: u64, depth: usize) -> usize {
+    ((hash >> (BITS_PER_LEVEL * depth)) as usize) & (FANOUT - 1)
+}
+
+/// A trie node as uploaded to IPFS. Branches refer to their children by CID.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum StoredNode {
+    Bucket(BTreeMap<String, String>),
+    Branch(Vec<Option<String>>),
+}
+
+enum NodeKind {
+    Bucket(BTreeMap<String, String>),
+    Branch(Vec<Option<Node>>),
+}
+
+struct Node {
+    kind: NodeKind,
+    /// CID of the node as last uploaded; `None` once it changed since.
+    cid: Option<String>,
+    /// Bumped on every change, so an upload of an older state doesn't mark the node clean.
+    version: u64,
+}
+
+impl Node {
+    fn bucket() -> Self {
+        Self {
+            kind: NodeKind::Bucket(BTreeMap::new()),
+            cid: None,
+            version: 0,
+        }
+    }
+
+    fn touch(&mut self, superseded: &mut Vec<String>) {
+        superseded.extend(self.cid.take());
+        self.version += 1;
+    }
+}
+
+/// Key-to-CID index of the IPFS backend, kept as a hash array mapped trie so a checkpoint only
+/// uploads the buckets written since the previous one and the branches above them.
+///
+/// It also counts the keys pointing at each record CID. Records no key points at, and trie
+/// nodes replaced by newer ones, can be unpinned once a checkpoint without them is durable.
+pub(crate) struct KeyIndex {
+    root: Node,
+    refs: HashMap<String, usize>,
+    released: BTreeSet<String>,
+    superseded: Vec<String>,
+}
+
+/// Dirty nodes of a [`KeyIndex`], children before parents, captured for one checkpoint.
+pub(crate) struct IndexSnapshot {
+    nodes: Vec<PendingNode>,
+    root: Option<String>,
+    released: Vec<String>,
+    superseded: usize,
+}
+
+struct PendingNode {
+    path: Vec<usize>,
+    version: u64,
+    kind: PendingKind,
+}
+
+enum PendingKind {
+    Bucket(BTreeMap<String, String>),
+    Branch(Vec<PendingChild>),
+}
+
+enum PendingChild {
+    Empty,
+    Clean(String),
+    // Index of the child among the snapshot's nodes.
+    Pending(usize),
+}
+
+/// CIDs that stopped being referenced by the checkpoint just committed.
+#[derive(Debug, Default)]
+pub(crate) struct Unreferenced {
+    /// Record CIDs no key pointed at when the snapshot was taken. Check [`KeyIndex::is_referenced`]
+    /// again before unpinning, since a key may have pointed at them since.
+    pub records: Vec<String>,
+    pub nodes: Vec<String>,
+}
+
+impl KeyIndex {
+    pub(crate) fn new() -> Self {
+        Self {
+            root: Node::bucket(),
+            refs: HashMap::new(),
+            released: BTreeSet::new(),
+            superseded: Vec::new(),
+        }
+    }
+
+    /// Rebuilds the index checkpointed at `root` from its nodes, keyed by CID.
+    pub(crate) fn assemble(root: &str, nodes: &HashMap<String, StoredNode>) -> Result<Self> {
+        let mut index = Self::new();
+        index.root = index.assemble_node(root, nodes, 0)?;
+        Ok(index)
+    }
+
+    fn assemble_node(&mut self, cid: &str, nodes: &HashMap<String, StoredNode>, depth: usize) -> Result<Node> {
+        let kind = match nodes.get(cid).ok_or_else(|| anyhow!("Index node {} was not loaded", cid))? {
+            StoredNode::Bucket(entries) => {
+                for record in entries.values() {
+                    *self.refs.entry(record.clone()).or_default() += 1;
+                }
+                NodeKind::Bucket(entries.clone())
+            }
+            StoredNode::Branch(children) => {
+                if children.len() != FANOUT || depth + 1 >= MAX_DEPTH {
+                    bail!("Malformed index branch {}", cid);
+                }
+                let children = children
+                    .iter()
+                    .map(|child| child.as_deref().map(|child| self.assemble_node(child, nodes, depth + 1)).transpose())
+                    .collect::<Result<_>>()?;
+                NodeKind::Branch(children)
+            }
+        };
+        Ok(Node {
+            kind,
+            cid: Some(cid.to_string()),
+            version: 0,
+        })
+    }
+
+    /// An index holding `entries` with nothing uploaded yet, for indexes checkpointed in an older
+    /// format. `replaced` are the CIDs of that checkpoint, unpinned after the first new one.
+    pub(crate) fn rebuild(entries: impl IntoIterator<Item = (String, String)>, replaced: Vec<String>) -> Self {
+        let mut index = Self::new();
+        for (key, cid) in entries {
+            index.insert(key, cid);
+        }
+        index.superseded = replaced;
+        index
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&String> {
+        let hash = hash(key);
+        let mut node = &self.root;
+        let mut depth = 0;
+        loop {
+            match &node.kind {
+                NodeKind::Bucket(entries) => return entries.get(key),
+                NodeKind::Branch(children) => {
+                    node = children[child_of(hash, depth)].as_ref()?;
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub(crate) fn is_referenced(&self, cid: &str) -> bool {
+        self.refs.contains_key(cid)
+    }
+
+    pub(crate) fn insert(&mut self, key: String, cid: String) {
+        if self.get(&key) == Some(&cid) {
+            return;
+        }
+        let hash = hash(&key);
+        let mut node = &mut self.root;
+        let mut depth = 0;
+        loop {
+            if let NodeKind::Bucket(entries) = &node.kind {
+                if entries.len() >= MAX_BUCKET_KEYS && !entries.contains_key(&key) && depth + 1 < MAX_DEPTH {
+                    node.touch(&mut self.superseded);
+                    split(node, depth);
+                }
+            }
+            node.touch(&mut self.superseded);
+            match &mut node.kind {
+                NodeKind::Bucket(entries) => {
+                    let previous = entries.insert(key, cid.clone());
+                    *self.refs.entry(cid.clone()).or_default() += 1;
+                    self.released.remove(&cid);
+                    if let Some(previous) = previous {
+                        self.release(previous);
+                    }
+                    return;
+                }
+                NodeKind::Branch(children) => {
+                    node = children[child_of(hash, depth)].get_or_insert_with(Node::bucket);
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) {
+        if !self.contains_key(key) {
+            return;
+        }
+        let hash = hash(key);
+        let removed = remove_from(&mut self.root, key, hash, 0, &mut self.superseded);
+        if let Some(removed) = removed {
+            self.release(removed);
+        }
+    }
+
+    fn release(&mut self, cid: String) {
+        if let Some(count) = self.refs.get_mut(&cid) {
+            *count -= 1;
+            if *count == 0 {
+                self.refs.remove(&cid);
+                self.released.insert(cid);
+            }
+        }
+    }
+
+    /// Record CIDs no key points at any more, for an index that is never checkpointed.
+    pub(crate) fn take_released(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.released).into_iter().collect()
+    }
+
+    pub(crate) fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            match &node.kind {
+                NodeKind::Bucket(entries) => {
+                    keys.extend(entries.keys().filter(|key| key.starts_with(prefix)).cloned());
+                }
+                NodeKind::Branch(children) => stack.extend(children.iter().flatten()),
+            }
+        }
+        keys.sort();
+        keys
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        let mut len = 0;
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            match &node.kind {
+                NodeKind::Bucket(entries) => len += entries.len(),
+                NodeKind::Branch(children) => stack.extend(children.iter().flatten()),
+            }
+        }
+        len
+    }
+
+    /// Captures the nodes changed since the last commit for upload.
+    pub(crate) fn snapshot(&self) -> IndexSnapshot {
+        let mut nodes = Vec::new();
+        let root = match &self.root.cid {
+            Some(cid) => Some(cid.clone()),
+            None => {
+                snapshot_node(&self.root, &mut Vec::new(), &mut nodes);
+                None
+            }
+        };
+        IndexSnapshot {
+            nodes,
+            root,
+            released: self.released.iter().cloned().collect(),
+            superseded: self.superseded.len(),
+        }
+    }
+
+    /// Marks the nodes of `snapshot` uploaded as `cids` clean, unless they changed since, and
+    /// hands back what the checkpoint no longer references.
+    pub(crate) fn commit(&mut self, snapshot: &IndexSnapshot, cids: &[String]) -> Unreferenced {
+        for (pending, cid) in snapshot.nodes.iter().zip(cids) {
+            let mut node = Some(&mut self.root);
+            for &child in &pending.path {
+                node = match node.map(|node| &mut node.kind) {
+                    Some(NodeKind::Branch(children)) => children[child].as_mut(),
+                    _ => None,
+                };
+            }
+            if let Some(node) = node.filter(|node| node.version == pending.version) {
+                node.cid = Some(cid.clone());
+            }
+        }
+
+        let referenced = snapshot.referenced(cids);
+        let nodes = self
+            .superseded
+            .drain(..snapshot.superseded)
+            .filter(|cid| !referenced.contains(cid))
+            .collect();
+        let records = snapshot
+            .released
+            .iter()
+            .filter(|cid| self.released.remove(*cid))
+            .cloned()
+            .collect();
+        Unreferenced { records, nodes }
+    }
+}
+
+// Moves the entries of a full bucket into a branch of buckets one level down.
+fn split(node: &mut Node, depth: usize) {
+    let NodeKind::Bucket(entries) = std::mem::replace(&mut node.kind, NodeKind::Bucket(BTreeMap::new())) else {
+        return;
+    };
+    let mut children: Vec<Option<Node>> = (0..FANOUT).map(|_| None).collect();
+    for (key, cid) in entries {
+        let child = children[child_of(hash(&key), depth)].get_or_insert_with(Node::bucket);
+        if let NodeKind::Bucket(entries) = &mut child.kind {
+            entries.insert(key, cid);
+        }
+    }
+    node.kind = NodeKind::Branch(children);
+}
+
+// Removes `key` below `node`, dropping buckets left empty. Returns the CID the key pointed at.
+fn remove_from(node: &mut Node, key: &str, hash: u64, depth: usize, superseded: &mut Vec<String>) -> Option<String> {
+    node.touch(superseded);
+    match &mut node.kind {
+        NodeKind::Bucket(entries) => entries.remove(key),
+        NodeKind::Branch(children) => {
+            let slot = &mut children[child_of(hash, depth)];
+            let child = slot.as_mut()?;
+            let removed = remove_from(child, key, hash, depth + 1, superseded);
+            if matches!(&child.kind, NodeKind::Bucket(entries) if entries.is_empty()) {
+                superseded.extend(child.cid.take());
+                *slot = None;
+            }
+            removed
+        }
+    }
+}
+
+fn snapshot_node(node: &Node, path: &mut Vec<usize>, nodes: &mut Vec<PendingNode>) -> usize {
+    let kind = match &node.kind {
+        NodeKind::Bucket(entries) => PendingKind::Bucket(entries.clone()),
+        NodeKind::Branch(children) => {
+            let mut pending = Vec::with_capacity(FANOUT);
+            for (i, child) in children.iter().enumerate() {
+                pending.push(match child {
+                    None => PendingChild::Empty,
+                    Some(Node { cid: Some(cid), .. }) => PendingChild::Clean(cid.clone()),
+                    Some(child) => {
+                        path.push(i);
+                        let index = snapshot_node(child, path, nodes);
+                        path.pop();
+                        PendingChild::Pending(index)
+                    }
+                });
+            }
+            PendingKind::Branch(pending)
+        }
+    };
+    nodes.push(PendingNode {
+        path: path.clone(),
+        version: node.version,
+        kind,
+    });
+    nodes.len() - 1
+}
+
+impl IndexSnapshot {
+    /// Nodes to upload, in order. Node `i` refers to the CIDs the earlier ones were uploaded as.
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub(crate) fn stored(&self, i: usize, cids: &[String]) -> StoredNode {
+        match &self.nodes[i].kind {
+            PendingKind::Bucket(entries) => StoredNode::Bucket(entries.clone()),
+            PendingKind::Branch(children) => StoredNode::Branch(
+                children
+                    .iter()
+                    .map(|child| match child {
+                        PendingChild::Empty => None,
+                        PendingChild::Clean(cid) => Some(cid.clone()),
+                        PendingChild::Pending(index) => Some(cids[*index].clone()),
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The root CID once every node is uploaded as `cids`.
+    pub(crate) fn root(&self, cids: &[String]) -> Option<String> {
+        self.root.clone().or_else(|| cids.last().cloned())
+    }
+
+    fn referenced(&self, cids: &[String]) -> HashSet<String> {
+        let mut referenced: HashSet<String> = cids.iter().cloned().chain(self.root.clone()).collect();
+        for node in &self.nodes {
+            if let PendingKind::Branch(children) = &node.kind {
+                for child in children {
+                    if let PendingChild::Clean(cid) = child {
+                        referenced.insert(cid.clone());
+                    }
+                }
+            }
+        }
+        referenced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Uploads a snapshot to `store` under sequential CIDs, as a checkpoint would.
+    fn checkpoint(index: &mut KeyIndex, store: &mut HashMap<String, StoredNode>) -> (String, Unreferenced) {
+        let snapshot = index.snapshot();
+        let mut cids = Vec::new();
+        for i in 0..snapshot.len() {
+            let cid = format!("node-{}", store.len());
+            store.insert(cid.clone(), snapshot.stored(i, &cids));
+            cids.push(cid);
+        }
+        let root = snapshot.root(&cids).unwrap();
+        (root, index.commit(&snapshot, &cids))
+    }
+
+    #[test]
+    fn test_key_index() {
+        let mut index = KeyIndex::new();
+        let mut store = HashMap::new();
+        for i in 0..5000 {
+            index.insert(format!("key:{}", i), format!("cid-{}", i % 4000));
+        }
+        assert_eq!(index.len(), 5000);
+        assert_eq!(index.get("key:4321").map(String::as_str), Some("cid-321"));
+        assert_eq!(index.keys_with_prefix("key:499").len(), 11);
+
+        let (root, _) = checkpoint(&mut index, &mut store);
+        let uploaded = store.len();
+        assert!(uploaded > FANOUT);
+        assert_eq!(index.snapshot().len(), 0);
+
+        // One key changes one bucket and the branch above it.
+        index.insert("key:7".to_string(), "cid-new".to_string());
+        let (next_root, unreferenced) = checkpoint(&mut index, &mut store);
+        assert_eq!(store.len() - uploaded, 2);
+        assert_eq!(unreferenced.nodes.len(), 2);
+        assert!(unreferenced.records.is_empty());
+        assert_ne!(root, next_root);
+
+        // key:7 moved off cid-7, so key:4007 was its last key; cid-3000 only had key:3000.
+        index.remove("key:4007");
+        index.remove("key:3000");
+        let (root, unreferenced) = checkpoint(&mut index, &mut store);
+        assert_eq!(unreferenced.records, vec!["cid-3000".to_string(), "cid-7".to_string()]);
+        assert!(!index.is_referenced("cid-7"));
+
+        let reloaded = KeyIndex::assemble(&root, &store).unwrap();
+        assert_eq!(reloaded.len(), 4998);
+        assert_eq!(reloaded.get("key:7").map(String::as_str), Some("cid-new"));
+        assert!(reloaded.get("key:3000").is_none());
+        assert!(reloaded.is_referenced("cid-1"));
+    }
+}
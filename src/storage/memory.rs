@@ -0,0 +1,1001 @@
+use async_trait::async_trait;
+use anyhow::anyhow;
+use dashmap::DashMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::storage::database::cursor_moved_back;
+use crate::storage::error::{Result, StorageError};
+use crate::storage::{
+    AddressRecord, CompressedAssetRecord, CompressedStateAccount, CompressedTokenAccountRecord, CompressionStore,
+    Database, CompressedAccount, CompressedBlock, CompressedTransaction, LeafChangeRecord, MerkleTreeRecord,
+    PruneRequest, PruneStats, SignatureInfo, SignatureQuery, TokenMintRecord, TreeRootRecord, VOTE_PROGRAM_ID,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DatabaseMethod {
+    InsertCompressedAccount,
+    GetCompressedAccount,
+    InsertCompressedBlock,
+    GetCompressedBlock,
+    InsertCompressedTransaction,
+    GetCompressedTransaction,
+    GetAccountsByOwner,
+    GetSignaturesForAddress,
+    GetBlocksInRange,
+    GetFirstBlockSlot,
+    GetTransactionsInBlock,
+    DeleteSlotRange,
+    GetAccountAtSlot,
+    GetAccountVersions,
+    GetAccountPubkeys,
+    GetTransactionSignatures,
+    Prune,
+    GetLastProcessedSlot,
+    UpdateLastProcessedSlot,
+}
+
+#[derive(Debug, Clone)]
+/// Injected errors are reported as `StorageError::Unavailable`.
+pub enum Fault {
+    Latency(Duration),
+    Error(String),
+    /// The call takes effect but still reports an error, like a lost acknowledgement.
+    ErrorAfterApply(String),
+}
+
+// (slot, index within block) -> signature
+type SignaturesByPosition = BTreeMap<(u64, u32), Vec<u8>>;
+
+#[derive(Debug, Clone)]
+struct FaultRule {
+    fault: Fault,
+    skip: usize,
+    remaining: Option<usize>,
+}
+
+/// `Database` held entirely in memory, with per-method fault injection for tests.
+#[derive(Default)]
+pub struct InMemoryStorage {
+    accounts: DashMap<Vec<u8>, CompressedAccount>,
+    account_versions: DashMap<Vec<u8>, BTreeMap<(u64, u64), CompressedAccount>>,
+    blocks: DashMap<u64, CompressedBlock>,
+    transactions: DashMap<Vec<u8>, CompressedTransaction>,
+    accounts_by_owner: DashMap<Vec<u8>, BTreeSet<Vec<u8>>>,
+    signatures_by_address: DashMap<Vec<u8>, SignaturesByPosition>,
+    transactions_by_slot: DashMap<u64, BTreeMap<u32, Vec<u8>>>,
+    block_slots: Mutex<BTreeSet<u64>>,
+    last_processed_slot: AtomicU64,
+    faults: DashMap<DatabaseMethod, Vec<FaultRule>>,
+    calls: DashMap<DatabaseMethod, usize>,
+    compression: CompressionTables,
+}
+
+// `CompressionStore` rows. Fault injection only covers `Database` methods.
+#[derive(Default)]
+struct CompressionTables {
+    compressed_slot: AtomicU64,
+    trees: DashMap<Vec<u8>, MerkleTreeRecord>,
+    tree_roots: DashMap<Vec<u8>, BTreeMap<u64, TreeRootRecord>>,
+    leaf_changes: DashMap<Vec<u8>, BTreeMap<u64, LeafChangeRecord>>,
+    state_accounts: DashMap<Vec<u8>, CompressedStateAccount>,
+    addresses: DashMap<Vec<u8>, AddressRecord>,
+    token_accounts: DashMap<Vec<u8>, CompressedTokenAccountRecord>,
+    token_mints: DashMap<Vec<u8>, TokenMintRecord>,
+    assets: DashMap<Vec<u8>, CompressedAssetRecord>,
+}
+
+impl InMemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `fault` to every subsequent call of `method`.
+    pub fn inject(&self, method: DatabaseMethod, fault: Fault) {
+        self.add_rule(method, fault, 0, None);
+    }
+
+    /// Applies `fault` to the next `times` calls of `method`.
+    pub fn inject_times(&self, method: DatabaseMethod, fault: Fault, times: usize) {
+        self.add_rule(method, fault, 0, Some(times));
+    }
+
+    /// Lets `calls` calls of `method` through, then applies `fault` to the one after.
+    pub fn inject_after(&self, method: DatabaseMethod, calls: usize, fault: Fault) {
+        self.add_rule(method, fault, calls, Some(1));
+    }
+
+    pub fn clear_faults(&self) {
+        self.faults.clear();
+    }
+
+    /// Number of calls made to `method`, including failed ones.
+    pub fn calls(&self, method: DatabaseMethod) -> usize {
+        self.calls.get(&method).map(|c| *c).unwrap_or(0)
+    }
+
+    pub fn account_count(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn transaction_count(&self) -> usize {
+        self.transactions.len()
+    }
+
+    fn add_rule(&self, method: DatabaseMethod, fault: Fault, skip: usize, remaining: Option<usize>) {
+        self.faults
+            .entry(method)
+            .or_default()
+            .push(FaultRule { fault, skip, remaining });
+    }
+
+    /// Runs before each call. Returns the error to report once the call has been applied, if any.
+    async fn before(&self, method: DatabaseMethod) -> Result<Option<String>> {
+        *self.calls.entry(method).or_insert(0) += 1;
+
+        let mut fired = Vec::new();
+        if let Some(mut rules) = self.faults.get_mut(&method) {
+            for rule in rules.iter_mut() {
+                if rule.skip > 0 {
+                    rule.skip -= 1;
+                    continue;
+                }
+                if let Some(remaining) = rule.remaining.as_mut() {
+                    *remaining -= 1;
+                }
+                fired.push(rule.fault.clone());
+            }
+            rules.retain(|rule| rule.remaining != Some(0));
+        }
+
+        let mut deferred = None;
+        for fault in fired {
+            match fault {
+                Fault::Latency(delay) => tokio::time::sleep(delay).await,
+                Fault::Error(message) => return Err(StorageError::Unavailable(anyhow!("{:?}: {}", method, message))),
+                Fault::ErrorAfterApply(message) => deferred = Some(message),
+            }
+        }
+        Ok(deferred)
+    }
+
+    fn position(&self, signature: &Option<Vec<u8>>) -> Result<Option<(u64, u32)>> {
+        signature
+            .as_ref()
+            .map(|signature| {
+                self.transactions
+                    .get(signature)
+                    .map(|t| (t.slot, t.index))
+                    .ok_or_else(|| StorageError::not_found(format!("cursor signature {}", hex::encode(signature))))
+            })
+            .transpose()
+    }
+
+    fn after<T>(method: DatabaseMethod, deferred: Option<String>, value: T) -> Result<T> {
+        match deferred {
+            Some(message) => Err(StorageError::Unavailable(anyhow!("{:?}: {}", method, message))),
+            None => Ok(value),
+        }
+    }
+}
+
+#[async_trait]
+impl Database for InMemoryStorage {
+    async fn insert_compressed_account(&self, account: &CompressedAccount) -> Result<()> {
+        let deferred = self.before(DatabaseMethod::InsertCompressedAccount).await?;
+        self.account_versions
+            .entry(account.pubkey.clone())
+            .or_default()
+            .insert(account.version(), account.clone());
+        let is_newest = self
+            .accounts
+            .get(&account.pubkey)
+            .map_or(true, |current| current.version() <= account.version());
+        if is_newest {
+            if let Some(previous) = self.accounts.insert(account.pubkey.clone(), account.clone()) {
+                if let Some(mut owned) = self.accounts_by_owner.get_mut(&previous.owner) {
+                    owned.remove(&account.pubkey);
+                }
+            }
+            self.accounts_by_owner
+                .entry(account.owner.clone())
+                .or_default()
+                .insert(account.pubkey.clone());
+        }
+        Self::after(DatabaseMethod::InsertCompressedAccount, deferred, ())
+    }
+
+    async fn get_compressed_account(&self, pubkey: &[u8]) -> Result<CompressedAccount> {
+        let deferred = self.before(DatabaseMethod::GetCompressedAccount).await?;
+        let account = self
+            .accounts
+            .get(pubkey)
+            .map(|a| a.clone())
+            .ok_or_else(|| StorageError::not_found(format!("compressed account {}", hex::encode(pubkey))))?;
+        Self::after(DatabaseMethod::GetCompressedAccount, deferred, account)
+    }
+
+    async fn insert_compressed_block(&self, block: &CompressedBlock) -> Result<()> {
+        let deferred = self.before(DatabaseMethod::InsertCompressedBlock).await?;
+        self.blocks.insert(block.slot, block.clone());
+        self.block_slots.lock().unwrap().insert(block.slot);
+        Self::after(DatabaseMethod::InsertCompressedBlock, deferred, ())
+    }
+
+    async fn get_compressed_block(&self, slot: u64) -> Result<CompressedBlock> {
+        let deferred = self.before(DatabaseMethod::GetCompressedBlock).await?;
+        let block = self
+            .blocks
+            .get(&slot)
+            .map(|b| b.clone())
+            .ok_or_else(|| StorageError::not_found(format!("compressed block {}", slot)))?;
+        Self::after(DatabaseMethod::GetCompressedBlock, deferred, block)
+    }
+
+    async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> Result<()> {
+        let deferred = self.before(DatabaseMethod::InsertCompressedTransaction).await?;
+        self.transactions.insert(transaction.signature.clone(), transaction.clone());
+        let position = (transaction.slot, transaction.index);
+        for address in &transaction.accounts {
+            self.signatures_by_address
+                .entry(address.clone())
+                .or_default()
+                .insert(position, transaction.signature.clone());
+        }
+        self.transactions_by_slot
+            .entry(transaction.slot)
+            .or_default()
+            .insert(transaction.index, transaction.signature.clone());
+        Self::after(DatabaseMethod::InsertCompressedTransaction, deferred, ())
+    }
+
+    async fn get_compressed_transaction(&self, signature: &[u8]) -> Result<CompressedTransaction> {
+        let deferred = self.before(DatabaseMethod::GetCompressedTransaction).await?;
+        let transaction = self
+            .transactions
+            .get(signature)
+            .map(|t| t.clone())
+            .ok_or_else(|| StorageError::not_found(format!("compressed transaction {}", hex::encode(signature))))?;
+        Self::after(DatabaseMethod::GetCompressedTransaction, deferred, transaction)
+    }
+
+    async fn get_accounts_by_owner(&self, owner: &[u8], limit: usize) -> Result<Vec<CompressedAccount>> {
+        let deferred = self.before(DatabaseMethod::GetAccountsByOwner).await?;
+        let accounts = self
+            .accounts_by_owner
+            .get(owner)
+            .map(|owned| {
+                owned
+                    .iter()
+                    .filter_map(|pubkey| self.accounts.get(pubkey).map(|a| a.clone()))
+                    .take(limit)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self::after(DatabaseMethod::GetAccountsByOwner, deferred, accounts)
+    }
+
+    async fn get_signatures_for_address(&self, address: &[u8], query: &SignatureQuery) -> Result<Vec<SignatureInfo>> {
+        let deferred = self.before(DatabaseMethod::GetSignaturesForAddress).await?;
+        let before = self.position(&query.before)?;
+        let until = self.position(&query.until)?;
+        let signatures = self
+            .signatures_by_address
+            .get(address)
+            .map(|signatures| {
+                signatures
+                    .iter()
+                    .rev()
+                    .skip_while(|(position, _)| before.is_some_and(|before| **position >= before))
+                    .take_while(|(position, _)| match until {
+                        Some(until) => **position > until,
+                        None => true,
+                    })
+                    .take(query.limit)
+                    .map(|(&(slot, index), signature)| SignatureInfo {
+                        signature: signature.clone(),
+                        slot,
+                        index,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self::after(DatabaseMethod::GetSignaturesForAddress, deferred, signatures)
+    }
+
+    async fn get_blocks_in_range(&self, start_slot: u64, end_slot: u64) -> Result<Vec<CompressedBlock>> {
+        let deferred = self.before(DatabaseMethod::GetBlocksInRange).await?;
+        let slots: Vec<u64> = self
+            .block_slots
+            .lock()
+            .unwrap()
+            .range(start_slot..=end_slot)
+            .copied()
+            .collect();
+        let blocks = slots
+            .into_iter()
+            .filter_map(|slot| self.blocks.get(&slot).map(|b| b.clone()))
+            .collect();
+        Self::after(DatabaseMethod::GetBlocksInRange, deferred, blocks)
+    }
+
+    async fn get_first_block_slot(&self) -> Result<Option<u64>> {
+        let deferred = self.before(DatabaseMethod::GetFirstBlockSlot).await?;
+        let slot = self.block_slots.lock().unwrap().first().copied();
+        Self::after(DatabaseMethod::GetFirstBlockSlot, deferred, slot)
+    }
+
+    async fn get_transactions_in_block(&self, slot: u64) -> Result<Vec<CompressedTransaction>> {
+        let deferred = self.before(DatabaseMethod::GetTransactionsInBlock).await?;
+        let transactions = self
+            .transactions_by_slot
+            .get(&slot)
+            .map(|signatures| {
+                signatures
+                    .values()
+                    .filter_map(|signature| self.transactions.get(signature).map(|t| t.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self::after(DatabaseMethod::GetTransactionsInBlock, deferred, transactions)
+    }
+
+    async fn delete_slot_range(&self, start_slot: u64, end_slot: u64) -> Result<u64> {
+        let deferred = self.before(DatabaseMethod::DeleteSlotRange).await?;
+        let range = start_slot..=end_slot;
+        let block_slots: Vec<u64> = {
+            let mut block_slots = self.block_slots.lock().unwrap();
+            let slots: Vec<u64> = block_slots.range(range.clone()).copied().collect();
+            for slot in &slots {
+                block_slots.remove(slot);
+            }
+            slots
+        };
+        let mut deleted = 0;
+        for slot in block_slots {
+            if self.blocks.remove(&slot).is_some() {
+                deleted += 1;
+            }
+        }
+        let transaction_slots: Vec<u64> = self
+            .transactions_by_slot
+            .iter()
+            .map(|entry| *entry.key())
+            .filter(|slot| range.contains(slot))
+            .collect();
+        for slot in transaction_slots {
+            let Some((_, signatures)) = self.transactions_by_slot.remove(&slot) else {
+                continue;
+            };
+            for (index, signature) in signatures {
+                if let Some((_, transaction)) = self.transactions.remove(&signature) {
+                    deleted += 1;
+                    for address in &transaction.accounts {
+                        if let Some(mut positions) = self.signatures_by_address.get_mut(address) {
+                            positions.remove(&(slot, index));
+                        }
+                    }
+                }
+            }
+        }
+        let _ = self.last_processed_slot.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |cursor| {
+            range.contains(&cursor).then(|| start_slot.saturating_sub(1))
+        });
+        Self::after(DatabaseMethod::DeleteSlotRange, deferred, deleted)
+    }
+
+    async fn get_account_at_slot(&self, pubkey: &[u8], slot: u64) -> Result<Option<CompressedAccount>> {
+        let deferred = self.before(DatabaseMethod::GetAccountAtSlot).await?;
+        let account = self.account_versions.get(pubkey).and_then(|versions| {
+            versions
+                .range(..=(slot, u64::MAX))
+                .next_back()
+                .map(|(_, account)| account.clone())
+        });
+        Self::after(DatabaseMethod::GetAccountAtSlot, deferred, account)
+    }
+
+    async fn get_account_versions(
+        &self,
+        pubkey: &[u8],
+        start_slot: u64,
+        end_slot: u64,
+        limit: usize,
+    ) -> Result<Vec<CompressedAccount>> {
+        let deferred = self.before(DatabaseMethod::GetAccountVersions).await?;
+        let accounts = self
+            .account_versions
+            .get(pubkey)
+            .map(|versions| {
+                versions
+                    .range((start_slot, 0)..=(end_slot, u64::MAX))
+                    .take(limit)
+                    .map(|(_, account)| account.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self::after(DatabaseMethod::GetAccountVersions, deferred, accounts)
+    }
+
+    async fn get_account_pubkeys(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        let deferred = self.before(DatabaseMethod::GetAccountPubkeys).await?;
+        let mut pubkeys: Vec<Vec<u8>> = self
+            .account_versions
+            .iter()
+            .map(|versions| versions.key().clone())
+            .filter(|pubkey| after.map_or(true, |after| pubkey.as_slice() > after))
+            .collect();
+        pubkeys.sort();
+        pubkeys.truncate(limit);
+        Self::after(DatabaseMethod::GetAccountPubkeys, deferred, pubkeys)
+    }
+
+    async fn get_transaction_signatures(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        let deferred = self.before(DatabaseMethod::GetTransactionSignatures).await?;
+        let mut signatures: Vec<Vec<u8>> = self
+            .transactions
+            .iter()
+            .map(|transaction| transaction.key().clone())
+            .filter(|signature| after.map_or(true, |after| signature.as_slice() > after))
+            .collect();
+        signatures.sort();
+        signatures.truncate(limit);
+        Self::after(DatabaseMethod::GetTransactionSignatures, deferred, signatures)
+    }
+
+    async fn prune(&self, request: &PruneRequest) -> Result<PruneStats> {
+        let deferred = self.before(DatabaseMethod::Prune).await?;
+        let mut stats = PruneStats::default();
+        let expired: Vec<Vec<u8>> = self
+            .transactions
+            .iter()
+            .filter(|transaction| {
+                let is_vote = transaction.accounts.iter().any(|address| address[..] == VOTE_PROGRAM_ID[..]);
+                request.transaction_cutoff(is_vote).is_some_and(|before| transaction.slot < before)
+                    && !transaction.accounts.iter().any(|address| request.is_protected(address))
+            })
+            .map(|transaction| transaction.signature.clone())
+            .collect();
+        for signature in expired {
+            let Some((_, transaction)) = self.transactions.remove(&signature) else {
+                continue;
+            };
+            let position = (transaction.slot, transaction.index);
+            for address in &transaction.accounts {
+                if let Some(mut positions) = self.signatures_by_address.get_mut(address) {
+                    positions.remove(&position);
+                }
+            }
+            if let Some(mut signatures) = self.transactions_by_slot.get_mut(&transaction.slot) {
+                signatures.remove(&transaction.index);
+            }
+            stats.transactions.add((transaction.data.len() + transaction.proof.len()) as u64);
+        }
+
+        // After transactions, so a block is only dropped once none of its transactions are kept.
+        if let Some(before) = request.blocks_before {
+            let slots: Vec<u64> = {
+                let mut block_slots = self.block_slots.lock().unwrap();
+                let slots: Vec<u64> = block_slots
+                    .range(..before)
+                    .copied()
+                    .filter(|slot| self.transactions_by_slot.get(slot).map_or(true, |signatures| signatures.is_empty()))
+                    .collect();
+                for slot in &slots {
+                    block_slots.remove(slot);
+                }
+                slots
+            };
+            for slot in slots {
+                if let Some((_, block)) = self.blocks.remove(&slot) {
+                    stats.blocks.add((block.data.len() + block.proof.len()) as u64);
+                }
+            }
+        }
+
+        if let Some(before) = request.account_versions_before {
+            for mut versions in self.account_versions.iter_mut() {
+                let Some(newest) = versions.keys().next_back().copied() else {
+                    continue;
+                };
+                let expired: Vec<(u64, u64)> = versions
+                    .range(..(before, 0))
+                    .filter(|(version, account)| {
+                        **version != newest && !request.is_protected(&account.pubkey) && !request.is_protected(&account.owner)
+                    })
+                    .map(|(version, _)| *version)
+                    .collect();
+                for version in expired {
+                    if let Some(account) = versions.remove(&version) {
+                        stats.account_versions.add((account.data.len() + account.proof.len()) as u64);
+                    }
+                }
+            }
+        }
+        Self::after(DatabaseMethod::Prune, deferred, stats)
+    }
+
+    async fn get_last_processed_slot(&self) -> Result<u64> {
+        let deferred = self.before(DatabaseMethod::GetLastProcessedSlot).await?;
+        let slot = self.last_processed_slot.load(Ordering::SeqCst);
+        Self::after(DatabaseMethod::GetLastProcessedSlot, deferred, slot)
+    }
+
+    async fn update_last_processed_slot(&self, slot: u64) -> Result<()> {
+        let deferred = self.before(DatabaseMethod::UpdateLastProcessedSlot).await?;
+        let cursor = self.last_processed_slot.fetch_max(slot, Ordering::SeqCst);
+        if slot < cursor {
+            return Err(cursor_moved_back(cursor, slot));
+        }
+        Self::after(DatabaseMethod::UpdateLastProcessedSlot, deferred, ())
+    }
+}
+
+/// Up to `limit` rows of `table` keyed after `after`, in key order.
+fn list_page<T: Clone>(table: &DashMap<Vec<u8>, T>, after: Option<&[u8]>, limit: usize) -> Vec<T> {
+    let mut rows: Vec<(Vec<u8>, T)> = table
+        .iter()
+        .filter(|row| after.map_or(true, |after| row.key().as_slice() > after))
+        .map(|row| (row.key().clone(), row.value().clone()))
+        .collect();
+    rows.sort_by(|a, b| a.0.cmp(&b.0));
+    rows.into_iter().take(limit).map(|(_, row)| row).collect()
+}
+
+#[async_trait]
+impl CompressionStore for InMemoryStorage {
+    async fn get_compressed_slot(&self) -> anyhow::Result<u64> {
+        Ok(self.compression.compressed_slot.load(Ordering::SeqCst))
+    }
+
+    async fn update_compressed_slot(&self, slot: u64) -> anyhow::Result<()> {
+        self.compression.compressed_slot.store(slot, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn upsert_merkle_tree(&self, tree: &MerkleTreeRecord) -> anyhow::Result<()> {
+        self.compression.trees.insert(tree.tree.clone(), tree.clone());
+        Ok(())
+    }
+
+    async fn get_merkle_tree(&self, tree: &[u8]) -> anyhow::Result<Option<MerkleTreeRecord>> {
+        Ok(self.compression.trees.get(tree).map(|tree| tree.clone()))
+    }
+
+    async fn get_merkle_trees(&self) -> anyhow::Result<Vec<MerkleTreeRecord>> {
+        Ok(self.compression.trees.iter().map(|tree| tree.clone()).collect())
+    }
+
+    async fn delete_merkle_tree(&self, tree: &[u8]) -> anyhow::Result<()> {
+        self.compression.trees.remove(tree);
+        self.compression.tree_roots.remove(tree);
+        self.compression.leaf_changes.remove(tree);
+        Ok(())
+    }
+
+    async fn insert_tree_root(&self, root: &TreeRootRecord) -> anyhow::Result<()> {
+        self.compression
+            .tree_roots
+            .entry(root.tree.clone())
+            .or_default()
+            .insert(root.seq, root.clone());
+        Ok(())
+    }
+
+    async fn get_tree_roots(&self, tree: &[u8]) -> anyhow::Result<Vec<TreeRootRecord>> {
+        Ok(self
+            .compression
+            .tree_roots
+            .get(tree)
+            .map_or_else(Vec::new, |roots| roots.values().cloned().collect()))
+    }
+
+    async fn insert_leaf_change(&self, change: &LeafChangeRecord) -> anyhow::Result<()> {
+        self.compression
+            .leaf_changes
+            .entry(change.tree.clone())
+            .or_default()
+            .insert(change.seq, change.clone());
+        Ok(())
+    }
+
+    async fn get_leaf_changes(&self, tree: &[u8], after_seq: u64) -> anyhow::Result<Vec<LeafChangeRecord>> {
+        Ok(self.compression.leaf_changes.get(tree).map_or_else(Vec::new, |changes| {
+            changes.range(after_seq + 1..).map(|(_, change)| change.clone()).collect()
+        }))
+    }
+
+    async fn upsert_state_account(&self, account: &CompressedStateAccount) -> anyhow::Result<()> {
+        self.compression.state_accounts.insert(account.hash.clone(), account.clone());
+        Ok(())
+    }
+
+    async fn get_state_account(&self, hash: &[u8]) -> anyhow::Result<Option<CompressedStateAccount>> {
+        Ok(self.compression.state_accounts.get(hash).map(|account| account.clone()))
+    }
+
+    async fn get_state_account_by_address(&self, address: &[u8]) -> anyhow::Result<Option<CompressedStateAccount>> {
+        Ok(self
+            .compression
+            .state_accounts
+            .iter()
+            .find(|account| !account.spent && account.address.as_deref() == Some(address))
+            .map(|account| account.clone()))
+    }
+
+    async fn get_state_accounts_by_owner(&self, owner: &[u8]) -> anyhow::Result<Vec<CompressedStateAccount>> {
+        Ok(self
+            .compression
+            .state_accounts
+            .iter()
+            .filter(|account| !account.spent && account.owner == owner)
+            .map(|account| account.clone())
+            .collect())
+    }
+
+    async fn spend_state_account(&self, hash: &[u8], slot: u64) -> anyhow::Result<()> {
+        if let Some(mut account) = self.compression.state_accounts.get_mut(hash) {
+            account.spent = true;
+            account.spent_slot = Some(slot);
+        }
+        Ok(())
+    }
+
+    async fn insert_address(&self, address: &AddressRecord) -> anyhow::Result<()> {
+        self.compression.addresses.insert(address.address.clone(), address.clone());
+        Ok(())
+    }
+
+    async fn get_address(&self, address: &[u8]) -> anyhow::Result<Option<AddressRecord>> {
+        Ok(self.compression.addresses.get(address).map(|address| address.clone()))
+    }
+
+    async fn get_low_address(&self, tree: &[u8], address: &[u8]) -> anyhow::Result<Option<AddressRecord>> {
+        Ok(self
+            .compression
+            .addresses
+            .iter()
+            .filter(|low| low.tree == tree && low.address.as_slice() < address)
+            .max_by(|a, b| a.address.cmp(&b.address))
+            .map(|low| low.clone()))
+    }
+
+    async fn upsert_token_account(&self, account: &CompressedTokenAccountRecord) -> anyhow::Result<()> {
+        self.compression.token_accounts.insert(account.hash.clone(), account.clone());
+        Ok(())
+    }
+
+    async fn delete_token_account(&self, hash: &[u8]) -> anyhow::Result<()> {
+        self.compression.token_accounts.remove(hash);
+        Ok(())
+    }
+
+    async fn get_token_account(&self, hash: &[u8]) -> anyhow::Result<Option<CompressedTokenAccountRecord>> {
+        Ok(self.compression.token_accounts.get(hash).map(|account| account.clone()))
+    }
+
+    async fn get_token_accounts_by_owner(&self, owner: &[u8]) -> anyhow::Result<Vec<CompressedTokenAccountRecord>> {
+        Ok(self
+            .compression
+            .token_accounts
+            .iter()
+            .filter(|account| account.owner == owner)
+            .map(|account| account.clone())
+            .collect())
+    }
+
+    async fn get_token_accounts_by_mint(&self, mint: &[u8]) -> anyhow::Result<Vec<CompressedTokenAccountRecord>> {
+        Ok(self
+            .compression
+            .token_accounts
+            .iter()
+            .filter(|account| account.mint == mint)
+            .map(|account| account.clone())
+            .collect())
+    }
+
+    async fn upsert_token_mint(&self, mint: &TokenMintRecord) -> anyhow::Result<()> {
+        self.compression.token_mints.insert(mint.mint.clone(), mint.clone());
+        Ok(())
+    }
+
+    async fn get_token_mint(&self, mint: &[u8]) -> anyhow::Result<Option<TokenMintRecord>> {
+        Ok(self.compression.token_mints.get(mint).map(|mint| mint.clone()))
+    }
+
+    async fn upsert_asset(&self, asset: &CompressedAssetRecord) -> anyhow::Result<()> {
+        self.compression.assets.insert(asset.asset_id.clone(), asset.clone());
+        Ok(())
+    }
+
+    async fn get_asset(&self, asset_id: &[u8]) -> anyhow::Result<Option<CompressedAssetRecord>> {
+        Ok(self.compression.assets.get(asset_id).map(|asset| asset.clone()))
+    }
+
+    async fn get_assets_by_owner(&self, owner: &[u8]) -> anyhow::Result<Vec<CompressedAssetRecord>> {
+        Ok(self
+            .compression
+            .assets
+            .iter()
+            .filter(|asset| asset.owner == owner)
+            .map(|asset| asset.clone())
+            .collect())
+    }
+
+    async fn list_state_accounts(&self, after: Option<&[u8]>, limit: usize) -> anyhow::Result<Vec<CompressedStateAccount>> {
+        Ok(list_page(&self.compression.state_accounts, after, limit))
+    }
+
+    async fn list_addresses(&self, after: Option<&[u8]>, limit: usize) -> anyhow::Result<Vec<AddressRecord>> {
+        Ok(list_page(&self.compression.addresses, after, limit))
+    }
+
+    async fn list_token_accounts(
+        &self,
+        after: Option<&[u8]>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<CompressedTokenAccountRecord>> {
+        Ok(list_page(&self.compression.token_accounts, after, limit))
+    }
+
+    async fn list_token_mints(&self, after: Option<&[u8]>, limit: usize) -> anyhow::Result<Vec<TokenMintRecord>> {
+        Ok(list_page(&self.compression.token_mints, after, limit))
+    }
+
+    async fn list_assets(&self, after: Option<&[u8]>, limit: usize) -> anyhow::Result<Vec<CompressedAssetRecord>> {
+        Ok(list_page(&self.compression.assets, after, limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{AccountDataDecoder, BlockBatch, DataDiff, Reclaimed};
+    use crate::storage::conformance::storage_conformance;
+
+    storage_conformance!(InMemoryStorage::new());
+
+    fn block(slot: u64) -> CompressedBlock {
+        CompressedBlock {
+            slot,
+            blockhash: String::new(),
+            previous_blockhash: String::new(),
+            parent_slot: slot.saturating_sub(1),
+            transactions: 0,
+            block_time: None,
+            data: vec![],
+            proof: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fault_injection() {
+        let storage = InMemoryStorage::new();
+
+        storage.inject_after(DatabaseMethod::InsertCompressedBlock, 1, Fault::Error("disk full".to_string()));
+        storage.insert_compressed_block(&block(1)).await.unwrap();
+        assert!(storage.insert_compressed_block(&block(2)).await.is_err());
+        storage.insert_compressed_block(&block(2)).await.unwrap();
+        assert_eq!(storage.calls(DatabaseMethod::InsertCompressedBlock), 3);
+        assert_eq!(storage.block_count(), 2);
+
+        storage.inject_times(
+            DatabaseMethod::UpdateLastProcessedSlot,
+            Fault::ErrorAfterApply("timeout".to_string()),
+            1,
+        );
+        assert!(storage.update_last_processed_slot(2).await.is_err());
+        assert_eq!(storage.get_last_processed_slot().await.unwrap(), 2);
+
+        storage.inject(DatabaseMethod::GetCompressedBlock, Fault::Latency(Duration::from_millis(20)));
+        let start = std::time::Instant::now();
+        assert_eq!(storage.get_compressed_block(1).await.unwrap().slot, 1);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        storage.clear_faults();
+        assert!(storage.get_compressed_block(3).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_queries() {
+        let storage = InMemoryStorage::new();
+        for slot in [3, 1, 2] {
+            storage.insert_compressed_block(&block(slot)).await.unwrap();
+        }
+        let slots: Vec<u64> = storage.get_blocks_in_range(2, 5).await.unwrap().iter().map(|b| b.slot).collect();
+        assert_eq!(slots, vec![2, 3]);
+
+        for (signature, slot, index) in [(1u8, 1, 0), (2, 1, 1), (3, 2, 0), (4, 3, 0)] {
+            let transaction = CompressedTransaction {
+                signature: vec![signature],
+                slot,
+                index,
+                accounts: vec![vec![9], vec![signature + 10]],
+                fee: 0,
+                programs: Vec::new(),
+                data: vec![],
+                proof: vec![],
+            };
+            storage.insert_compressed_transaction(&transaction).await.unwrap();
+        }
+        let in_block: Vec<Vec<u8>> = storage
+            .get_transactions_in_block(1)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|t| t.signature)
+            .collect();
+        assert_eq!(in_block, vec![vec![1], vec![2]]);
+
+        let signatures = |query: SignatureQuery| {
+            let storage = &storage;
+            async move {
+                storage
+                    .get_signatures_for_address(&[9], &query)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|s| s.signature[0])
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(signatures(SignatureQuery::default()).await, vec![4, 3, 2, 1]);
+        let query = SignatureQuery {
+            before: Some(vec![4]),
+            until: Some(vec![1]),
+            ..Default::default()
+        };
+        assert_eq!(signatures(query).await, vec![3, 2]);
+        let query = SignatureQuery {
+            limit: 1,
+            ..Default::default()
+        };
+        assert_eq!(signatures(query).await, vec![4]);
+        let query = SignatureQuery {
+            before: Some(vec![7]),
+            ..Default::default()
+        };
+        assert!(storage.get_signatures_for_address(&[9], &query).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_commit_block_replay() {
+        let storage = InMemoryStorage::new();
+        let mut batch = BlockBatch::new(5);
+        batch.block = Some(block(5));
+        for index in 0..2u8 {
+            batch.transactions.push(CompressedTransaction {
+                signature: vec![index],
+                slot: 5,
+                index: index as u32,
+                accounts: vec![],
+                fee: 0,
+                programs: Vec::new(),
+                data: vec![],
+                proof: vec![],
+            });
+        }
+
+        storage.inject_after(DatabaseMethod::InsertCompressedTransaction, 1, Fault::Error("crash".to_string()));
+        assert!(storage.commit_block(&batch).await.is_err());
+        assert_eq!(storage.get_last_processed_slot().await.unwrap(), 0);
+
+        storage.commit_block(&batch).await.unwrap();
+        assert_eq!(storage.get_last_processed_slot().await.unwrap(), 5);
+        assert_eq!(storage.transaction_count(), 2);
+        assert_eq!(storage.get_transactions_in_block(5).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_account_history() {
+        let storage = InMemoryStorage::new();
+        let account = |slot: u64, write_version: u64, lamports: i64, data: Vec<u8>| CompressedAccount {
+            pubkey: vec![1],
+            lamports,
+            owner: vec![2],
+            executable: false,
+            rent_epoch: 0,
+            slot,
+            write_version,
+            data,
+            proof: vec![],
+        };
+        // Stored data is encoded; the diffs are of what the decoder recovers.
+        let data = |data: Vec<u8>| bincode::serialize(&data).unwrap();
+        let decode: &AccountDataDecoder = &|account| Ok(bincode::deserialize(&account.data)?);
+        storage.insert_compressed_account(&account(10, 0, 100, data(vec![0; 16]))).await.unwrap();
+        storage.insert_compressed_account(&account(20, 1, 70, data(vec![0; 16]))).await.unwrap();
+        storage
+            .insert_compressed_account(&account(20, 0, 90, data(vec![0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2])))
+            .await
+            .unwrap();
+        // Written out of order, so the current state is still the newest version.
+        assert_eq!(storage.get_compressed_account(&[1]).await.unwrap().lamports, 70);
+
+        assert!(storage.get_account_at_slot(&[1], 9).await.unwrap().is_none());
+        assert_eq!(storage.get_account_at_slot(&[1], 19).await.unwrap().unwrap().lamports, 100);
+        assert_eq!(storage.get_account_at_slot(&[1], 20).await.unwrap().unwrap().version(), (20, 1));
+
+        let changes = storage.get_account_changes(&[1], 11, 30, 10, decode).await.unwrap();
+        assert_eq!(changes.iter().map(|c| c.lamports_delta).collect::<Vec<_>>(), vec![-10, -20]);
+        assert_eq!(
+            changes[0].data_diff,
+            vec![
+                DataDiff { offset: 1, old: vec![0], new: vec![1] },
+                DataDiff { offset: 15, old: vec![0], new: vec![2] },
+            ]
+        );
+        assert_eq!((changes[1].data_len, changes[1].data_diff.len()), (16, 2));
+        assert_eq!(storage.get_account_changes(&[1], 0, 30, 1, decode).await.unwrap()[0].lamports_delta, 100);
+    }
+
+    #[tokio::test]
+    async fn test_prune() {
+        let storage = InMemoryStorage::new();
+        let transaction = |signature: u8, slot: u64, accounts: Vec<Vec<u8>>| CompressedTransaction {
+            signature: vec![signature],
+            slot,
+            index: signature as u32,
+            accounts,
+            fee: 0,
+            programs: Vec::new(),
+            data: vec![0; 10],
+            proof: vec![],
+        };
+        storage.insert_compressed_transaction(&transaction(1, 10, vec![vec![1]])).await.unwrap();
+        storage.insert_compressed_transaction(&transaction(2, 10, vec![VOTE_PROGRAM_ID.to_vec()])).await.unwrap();
+        storage.insert_compressed_transaction(&transaction(3, 10, vec![vec![1], vec![9]])).await.unwrap();
+        storage.insert_compressed_transaction(&transaction(4, 30, vec![VOTE_PROGRAM_ID.to_vec()])).await.unwrap();
+        for slot in [10, 15] {
+            let block = CompressedBlock {
+                slot,
+                blockhash: String::new(),
+                previous_blockhash: String::new(),
+                parent_slot: slot - 1,
+                transactions: 0,
+                block_time: None,
+                data: vec![],
+                proof: vec![],
+            };
+            storage.insert_compressed_block(&block).await.unwrap();
+        }
+        for (slot, write_version) in [(10, 0), (20, 0), (40, 0)] {
+            let account = CompressedAccount {
+                pubkey: vec![1],
+                lamports: 1,
+                owner: vec![2],
+                executable: false,
+                rent_epoch: 0,
+                slot,
+                write_version,
+                data: vec![],
+                proof: vec![],
+            };
+            storage.insert_compressed_account(&account).await.unwrap();
+        }
+
+        let request = PruneRequest {
+            blocks_before: Some(20),
+            transactions_before: Some(20),
+            vote_transactions_before: Some(35),
+            account_versions_before: Some(100),
+            protected_addresses: vec![vec![9]],
+        };
+        let stats = storage.prune(&request).await.unwrap();
+        assert_eq!(stats.transactions, Reclaimed { rows: 3, bytes: 30 });
+        assert_eq!(stats.account_versions.rows, 2);
+        assert_eq!(storage.transaction_count(), 1);
+        assert!(storage.get_compressed_transaction(&[3]).await.is_ok());
+        assert_eq!(storage.get_signatures_for_address(&[1], &SignatureQuery::default()).await.unwrap().len(), 1);
+        // Slot 10 still holds the protected transaction, so its block is kept.
+        assert_eq!(stats.blocks.rows, 1);
+        assert!(storage.get_compressed_block(10).await.is_ok());
+        // The current version is always kept.
+        assert_eq!(storage.get_account_versions(&[1], 0, 100, 10).await.unwrap().len(), 1);
+    }
+}
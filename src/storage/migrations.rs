@@ -0,0 +1,169 @@
+//! Versioned schema migrations for every backend. Postgres and SQLite use sqlx's migrator
+//! and its `_sqlx_migrations` table. ScyllaDB and ClickHouse have no migrator of their own,
+//! so their migrations are embedded here and recorded in a `schema_migrations` table.
+//! Either way, the history recorded in the database is checked against the migrations built
+//! into the binary before anything is applied, and a mismatch refuses to go further.
+
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+use sqlx::migrate::{Migrate, Migrator};
+
+/// A migration file, named `<version>_<description>.sql` under `migrations/<backend>/`.
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> Vec<u8> {
+        Sha256::digest(self.sql.as_bytes()).to_vec()
+    }
+
+    /// Statements in order. `--` comment lines are dropped first, so they may contain `;`.
+    pub fn statements(&self) -> Vec<String> {
+        let sql: Vec<&str> = self
+            .sql
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("--"))
+            .collect();
+        sql.join("\n")
+            .split(';')
+            .map(str::trim)
+            .filter(|statement| !statement.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+macro_rules! migration {
+    ($backend:literal, $version:literal, $description:literal) => {
+        Migration {
+            version: $version,
+            description: $description,
+            sql: include_str!(concat!(
+                "../../migrations/",
+                $backend,
+                "/",
+                stringify!($version),
+                "_",
+                $description,
+                ".sql"
+            )),
+        }
+    };
+}
+
+pub(crate) const SCYLLA: &[Migration] = &[
+    migration!("scylla", 20241027000000, "baseline"),
+    migration!("scylla", 20241028000000, "tree_divergence"),
+    migration!("scylla", 20241029000000, "tree_chain_seq"),
+    migration!("scylla", 20241030000000, "transaction_analytics"),
+];
+
+pub(crate) const CLICKHOUSE: &[Migration] = &[
+    migration!("clickhouse", 20241023000000, "analytics_schema"),
+    migration!("clickhouse", 20241025000000, "account_history"),
+    migration!("clickhouse", 20241030000000, "hourly_rollups"),
+];
+
+/// Where a database's schema stands relative to the migrations in this build.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// Newest version recorded in the database before this run.
+    pub current: Option<i64>,
+    /// Versions this run applied, or would apply when only checking, oldest first.
+    pub pending: Vec<i64>,
+}
+
+/// Compares the `(version, checksum)` pairs recorded in a database with the ones this build
+/// knows. Fails when the database has a version the build does not know, a recorded
+/// migration was edited after it was applied, or a known migration is older than the
+/// newest applied one and would be applied out of order.
+pub(crate) fn plan(backend: &str, known: &[(i64, Vec<u8>)], applied: &[(i64, Vec<u8>)]) -> Result<MigrationReport> {
+    for (version, checksum) in applied {
+        match known.iter().find(|(known, _)| known == version) {
+            None => bail!(
+                "{} schema has migration {} which this build does not know; it was migrated by a newer version",
+                backend,
+                version
+            ),
+            Some((_, known)) if known != checksum => {
+                bail!("{} migration {} was changed after it was applied", backend, version)
+            }
+            Some(_) => {}
+        }
+    }
+    let current = applied.iter().map(|(version, _)| *version).max();
+    let pending: Vec<i64> = known
+        .iter()
+        .map(|(version, _)| *version)
+        .filter(|version| !applied.iter().any(|(applied, _)| applied == version))
+        .collect();
+    if let (Some(current), Some(&oldest)) = (current, pending.iter().min()) {
+        if oldest < current {
+            bail!(
+                "{} migration {} is older than the applied schema version {} and would run out of order",
+                backend,
+                oldest,
+                current
+            );
+        }
+    }
+    Ok(MigrationReport { current, pending })
+}
+
+pub(crate) fn embedded(migrations: &[Migration]) -> Vec<(i64, Vec<u8>)> {
+    migrations.iter().map(|m| (m.version, m.checksum())).collect()
+}
+
+/// Checks and, if `apply`, runs an sqlx migrator on one connection.
+pub(crate) async fn run_sqlx<C: Migrate>(
+    migrator: &Migrator,
+    conn: &mut C,
+    backend: &str,
+    apply: bool,
+) -> Result<MigrationReport> {
+    conn.ensure_migrations_table().await?;
+    if let Some(version) = conn.dirty_version().await? {
+        bail!("{} migration {} was left partially applied", backend, version);
+    }
+    let applied: Vec<(i64, Vec<u8>)> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m.checksum.into_owned()))
+        .collect();
+    let known: Vec<(i64, Vec<u8>)> = migrator.iter().map(|m| (m.version, m.checksum.to_vec())).collect();
+    let report = plan(backend, &known, &applied)?;
+    if apply && !report.pending.is_empty() {
+        migrator.run_direct(conn).await?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let known = vec![(1, vec![1]), (2, vec![2]), (3, vec![3])];
+        let report = plan("test", &known, &[(1, vec![1])]).unwrap();
+        assert_eq!(report, MigrationReport { current: Some(1), pending: vec![2, 3] });
+        assert_eq!(plan("test", &known, &[]).unwrap().pending, vec![1, 2, 3]);
+
+        assert!(plan("test", &known, &[(4, vec![4])]).is_err());
+        assert!(plan("test", &known, &[(1, vec![9])]).is_err());
+        assert!(plan("test", &known, &[(1, vec![1]), (3, vec![3])]).is_err());
+    }
+
+    #[test]
+    fn test_statements() {
+        for migration in SCYLLA.iter().chain(CLICKHOUSE) {
+            assert!(migration.statements().iter().all(|statement| !statement.contains("--")));
+        }
+        assert!(SCYLLA[0].statements().iter().any(|s| s.starts_with("CREATE TABLE IF NOT EXISTS indexer_cursor")));
+    }
+}
@@ -1,11 +1,44 @@
 mod database;
+mod error;
+mod compression_store;
 mod scylla;
 mod clickhouse;
 mod filecoin;
+mod key_index;
+mod car;
+mod car_store;
+mod factory;
+mod memory;
+mod postgres;
+mod sqlite;
+mod tiered;
+mod cache;
+mod retention;
+mod migrations;
+mod snapshot;
+mod export;
+mod encryption;
 mod models;
+#[cfg(test)]
+mod conformance;
 
 pub use database::Database;
+pub use error::StorageError;
+pub use compression_store::CompressionStore;
 pub use scylla::ScyllaStorage;
 pub use clickhouse::ClickHouseStorage;
 pub use filecoin::FilecoinStorage;
+pub use car::{epoch_slots, export_car, export_epoch, import_car, ArchivedSlot, CarArchive, CarManifest, CarVersion, Link};
+pub use car_store::CarStorage;
+pub use factory::{connect, migrate, StorageBackend, StorageUrl};
+pub use memory::{DatabaseMethod, Fault, InMemoryStorage};
+pub use postgres::PostgresStorage;
+pub use sqlite::SqliteStorage;
+pub use tiered::{Tier, TieredStorage};
+pub use cache::CachedStorage;
+pub use retention::{Pruner, RetentionPolicy};
+pub use migrations::{Migration, MigrationReport};
+pub use snapshot::{ProvingKeyFingerprint, Snapshot, SnapshotManifest};
+pub use export::{ExportTable, ParquetExporter};
+pub use encryption::{EncryptedStorage, MasterKeys, Reencryption};
 pub use models::*;
\ No newline at end of file
@@ -1,30 +1,363 @@
 use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompressedAccount {
     pub pubkey: Vec<u8>,
     pub lamports: i64,
     pub owner: Vec<u8>,
     pub executable: bool,
     pub rent_epoch: i64,
+    /// Slot of the write that produced this state. With `write_version`, orders the versions
+    /// of an account; a write never replaces a newer version.
+    #[serde(default)]
+    pub slot: u64,
+    /// Orders writes to the same account within a slot.
+    #[serde(default)]
+    pub write_version: u64,
     pub data: Vec<u8>,
     pub proof: Vec<u8>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl CompressedAccount {
+    pub fn version(&self) -> (u64, u64) {
+        (self.slot, self.write_version)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompressedBlock {
     pub slot: u64,
     pub blockhash: String,
     pub previous_blockhash: String,
     pub parent_slot: u64,
     pub transactions: u64,
+    /// Unix timestamp of the block, when the cluster reported one.
+    #[serde(default)]
+    pub block_time: Option<i64>,
     pub data: Vec<u8>,
     pub proof: Vec<u8>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompressedTransaction {
     pub signature: Vec<u8>,
+    #[serde(default)]
+    pub slot: u64,
+    /// Position of the transaction within its block.
+    #[serde(default)]
+    pub index: u32,
+    /// Account keys referenced by the transaction, in message order.
+    #[serde(default)]
+    pub accounts: Vec<Vec<u8>>,
+    /// Fee paid in lamports.
+    #[serde(default)]
+    pub fee: u64,
+    /// Distinct programs invoked by top-level instructions.
+    #[serde(default)]
+    pub programs: Vec<Vec<u8>>,
     pub data: Vec<u8>,
     pub proof: Vec<u8>,
-}
\ No newline at end of file
+}
+
+/// Tokens of `mint` received by `owner` in one transaction, taken from the
+/// positive pre/post token balance deltas. Summing `amount` gives transfer volume.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenTransfer {
+    pub signature: Vec<u8>,
+    pub slot: u64,
+    pub mint: Vec<u8>,
+    pub owner: Vec<u8>,
+    pub amount: u64,
+}
+
+/// One version of an account compared with the version before it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountChange {
+    pub slot: u64,
+    pub write_version: u64,
+    pub lamports: i64,
+    /// Change in lamports; the whole balance for the account's first version.
+    pub lamports_delta: i64,
+    pub owner: Vec<u8>,
+    pub data_len: usize,
+    pub data_diff: Vec<DataDiff>,
+}
+
+/// Bytes `old` at `offset` were replaced by `new`. Either side is shorter than the other
+/// where the data grew or shrank.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataDiff {
+    pub offset: usize,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+// Differing runs closer than this are reported as one.
+const DATA_DIFF_GAP: usize = 8;
+
+/// Recovers the account's own data from a stored version, whose `data` is compressed.
+pub type AccountDataDecoder = dyn Fn(&CompressedAccount) -> anyhow::Result<Vec<u8>> + Send + Sync;
+
+impl AccountChange {
+    /// `data` and the data paired with `previous` are the decoded account data.
+    pub fn new(previous: Option<(&CompressedAccount, &[u8])>, account: &CompressedAccount, data: &[u8]) -> Self {
+        let old_data = previous.map_or(&[][..], |(_, data)| data);
+        Self {
+            slot: account.slot,
+            write_version: account.write_version,
+            lamports: account.lamports,
+            lamports_delta: account.lamports - previous.map_or(0, |(previous, _)| previous.lamports),
+            owner: account.owner.clone(),
+            data_len: data.len(),
+            data_diff: diff_data(old_data, data),
+        }
+    }
+}
+
+fn diff_data(old: &[u8], new: &[u8]) -> Vec<DataDiff> {
+    let len = old.len().max(new.len());
+    let differs = |offset: usize| old.get(offset) != new.get(offset);
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        if !differs(offset) {
+            offset += 1;
+            continue;
+        }
+        let start = offset;
+        while offset < len && differs(offset) {
+            offset += 1;
+        }
+        match ranges.last_mut() {
+            Some(last) if start - last.1 < DATA_DIFF_GAP => last.1 = offset,
+            _ => ranges.push((start, offset)),
+        }
+    }
+    let slice = |data: &[u8], start: usize, end: usize| data[start.min(data.len())..end.min(data.len())].to_vec();
+    ranges
+        .into_iter()
+        .map(|(start, end)| DataDiff {
+            offset: start,
+            old: slice(old, start, end),
+            new: slice(new, start, end),
+        })
+        .collect()
+}
+
+/// `Vote111111111111111111111111111111111111111`
+pub const VOTE_PROGRAM_ID: [u8; 32] = [
+    7, 97, 72, 29, 53, 116, 116, 187, 124, 77, 118, 36, 235, 211, 189, 179, 216, 53, 94, 115, 209, 16, 67, 252,
+    13, 163, 83, 128, 0, 0, 0, 0,
+];
+
+/// Rows to delete in one pruning pass. Each cutoff is exclusive and `None` keeps that kind of row.
+#[derive(Debug, Clone, Default)]
+pub struct PruneRequest {
+    pub blocks_before: Option<u64>,
+    pub transactions_before: Option<u64>,
+    /// Cutoff for transactions that reference the vote program, when sooner than `transactions_before`.
+    pub vote_transactions_before: Option<u64>,
+    /// Account versions below this slot are deleted, except each account's newest version.
+    pub account_versions_before: Option<u64>,
+    /// Transactions referencing any of these, and versions of accounts that are or are owned
+    /// by one of them, are never deleted.
+    pub protected_addresses: Vec<Vec<u8>>,
+}
+
+impl PruneRequest {
+    /// Slot below which a transaction may be deleted, given whether it references the vote program.
+    pub fn transaction_cutoff(&self, is_vote: bool) -> Option<u64> {
+        match (self.transactions_before, self.vote_transactions_before.filter(|_| is_vote)) {
+            (Some(all), Some(vote)) => Some(all.max(vote)),
+            (all, vote) => all.or(vote),
+        }
+    }
+
+    pub fn is_protected(&self, address: &[u8]) -> bool {
+        self.protected_addresses.iter().any(|protected| protected == address)
+    }
+}
+
+/// Rows deleted, and the bytes of `data` and `proof` they held.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Reclaimed {
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+impl Reclaimed {
+    pub fn add(&mut self, bytes: u64) {
+        self.rows += 1;
+        self.bytes += bytes;
+    }
+}
+
+impl std::ops::AddAssign for Reclaimed {
+    fn add_assign(&mut self, other: Self) {
+        self.rows += other.rows;
+        self.bytes += other.bytes;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruneStats {
+    pub blocks: Reclaimed,
+    pub transactions: Reclaimed,
+    pub account_versions: Reclaimed,
+}
+
+impl std::ops::AddAssign for PruneStats {
+    fn add_assign(&mut self, other: Self) {
+        self.blocks += other.blocks;
+        self.transactions += other.transactions;
+        self.account_versions += other.account_versions;
+    }
+}
+
+/// Every row written for one slot. Committing it also advances `last_processed_slot` to `slot`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockBatch {
+    pub slot: u64,
+    pub block: Option<CompressedBlock>,
+    pub transactions: Vec<CompressedTransaction>,
+    pub accounts: Vec<CompressedAccount>,
+    #[serde(default)]
+    pub token_transfers: Vec<TokenTransfer>,
+}
+
+impl BlockBatch {
+    pub fn new(slot: u64) -> Self {
+        Self {
+            slot,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureInfo {
+    pub signature: Vec<u8>,
+    pub slot: u64,
+    pub index: u32,
+}
+
+/// Signatures are returned newest first. `before` and `until` are exclusive
+/// bounds given as signatures that must already be indexed.
+#[derive(Debug, Clone)]
+pub struct SignatureQuery {
+    pub before: Option<Vec<u8>>,
+    pub until: Option<Vec<u8>>,
+    pub limit: usize,
+}
+
+impl Default for SignatureQuery {
+    fn default() -> Self {
+        Self {
+            before: None,
+            until: None,
+            limit: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MerkleTreeRecord {
+    pub tree: Vec<u8>,
+    pub max_depth: u32,
+    pub max_buffer_size: u32,
+    #[serde(default)]
+    pub hasher: u8,
+    pub leaves: Vec<Vec<u8>>,
+    pub recent_roots: Vec<Vec<u8>>,
+    pub seq: u64,
+    /// On-chain sequence number as of `seq`, when known.
+    #[serde(default)]
+    pub chain_seq: Option<u64>,
+    pub slot: u64,
+    /// Seq at which our copy stopped matching the chain. Proofs are refused until the tree
+    /// is resynced.
+    #[serde(default)]
+    pub diverged_seq: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeRootRecord {
+    pub tree: Vec<u8>,
+    pub seq: u64,
+    pub root: Vec<u8>,
+    pub slot: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeafChangeRecord {
+    pub tree: Vec<u8>,
+    pub seq: u64,
+    pub leaf_index: u32,
+    pub previous_leaf: Vec<u8>,
+    pub new_leaf: Vec<u8>,
+    pub slot: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressedStateAccount {
+    pub hash: Vec<u8>,
+    pub address: Option<Vec<u8>>,
+    pub owner: Vec<u8>,
+    pub lamports: u64,
+    pub discriminator: Option<Vec<u8>>,
+    pub data: Vec<u8>,
+    pub data_hash: Option<Vec<u8>>,
+    pub tree: Vec<u8>,
+    pub leaf_index: u32,
+    pub seq: Option<u64>,
+    pub slot_created: u64,
+    pub spent: bool,
+    pub spent_slot: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressRecord {
+    pub address: Vec<u8>,
+    pub tree: Vec<u8>,
+    pub leaf_index: u32,
+    pub next_address: Vec<u8>,
+    pub slot: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressedTokenAccountRecord {
+    pub hash: Vec<u8>,
+    pub mint: Vec<u8>,
+    pub owner: Vec<u8>,
+    pub amount: u64,
+    pub delegate: Option<Vec<u8>>,
+    pub state: u8,
+    pub tlv: Option<Vec<u8>>,
+    pub slot: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenMintRecord {
+    pub mint: Vec<u8>,
+    pub program_id: Vec<u8>,
+    pub supply: u64,
+    pub decimals: u8,
+    pub slot: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressedAssetRecord {
+    pub asset_id: Vec<u8>,
+    pub tree: Vec<u8>,
+    pub leaf_index: u32,
+    pub nonce: u64,
+    pub owner: Vec<u8>,
+    pub delegate: Vec<u8>,
+    pub data_hash: Vec<u8>,
+    pub creator_hash: Vec<u8>,
+    pub leaf_hash: Vec<u8>,
+    pub seq: u64,
+    pub burned: bool,
+    pub redeemed: bool,
+    pub slot_updated: u64,
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use log::{error, info};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::compression::{bubblegum, light, token};
+use crate::metrics;
+use crate::storage::{Database, PruneRequest, PruneStats};
+
+const SLOT_DURATION: Duration = Duration::from_millis(400);
+
+/// Number of slots produced in `age`, at 400ms per slot.
+pub(crate) fn slots_in(age: Duration) -> u64 {
+    (age.as_millis() / SLOT_DURATION.as_millis()) as u64
+}
+
+/// How long each kind of row is kept, measured back from the indexer cursor. `None` keeps a
+/// kind forever. Transactions touching a protected address are never pruned, nor are the
+/// history of protected accounts and of accounts they own.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    pub block_max_age: Option<Duration>,
+    pub transaction_max_age: Option<Duration>,
+    pub vote_transaction_max_age: Option<Duration>,
+    pub account_history_max_age: Option<Duration>,
+    pub protected_addresses: Vec<Vec<u8>>,
+}
+
+impl RetentionPolicy {
+    /// The compression programs are always protected: their transactions carry the Merkle tree
+    /// updates that proofs for still-live leaves are rebuilt from.
+    pub fn request(&self, cursor: u64) -> PruneRequest {
+        let before = |age: Option<Duration>| age.map(|age| cursor.saturating_sub(slots_in(age)));
+        let mut protected_addresses = self.protected_addresses.clone();
+        for program in [
+            light::LIGHT_SYSTEM_PROGRAM_ID,
+            light::ACCOUNT_COMPRESSION_PROGRAM_ID,
+            light::NOOP_PROGRAM_ID,
+            token::COMPRESSED_TOKEN_PROGRAM_ID,
+            bubblegum::BUBBLEGUM_PROGRAM_ID,
+            bubblegum::SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+        ] {
+            let program = program.to_bytes().to_vec();
+            if !protected_addresses.contains(&program) {
+                protected_addresses.push(program);
+            }
+        }
+        PruneRequest {
+            blocks_before: before(self.block_max_age),
+            transactions_before: before(self.transaction_max_age),
+            vote_transactions_before: before(self.vote_transaction_max_age),
+            account_versions_before: before(self.account_history_max_age),
+            protected_addresses,
+        }
+    }
+}
+
+/// Periodically deletes what `policy` no longer retains from `storage`.
+pub struct Pruner {
+    storage: Arc<dyn Database>,
+    policy: RetentionPolicy,
+}
+
+impl Pruner {
+    pub fn new(storage: Arc<dyn Database>, policy: RetentionPolicy) -> Self {
+        Self { storage, policy }
+    }
+
+    pub async fn run_once(&self) -> Result<PruneStats> {
+        let cursor = self.storage.get_last_processed_slot().await?;
+        let stats = self.storage.prune(&self.policy.request(cursor)).await?;
+        for (kind, reclaimed) in [
+            ("blocks", stats.blocks),
+            ("transactions", stats.transactions),
+            ("account_versions", stats.account_versions),
+        ] {
+            metrics::record_pruned(kind, reclaimed.rows, reclaimed.bytes).await;
+        }
+        Ok(stats)
+    }
+
+    pub async fn run(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match self.run_once().await {
+                Ok(stats) => info!(
+                    "Pruned {} blocks, {} transactions and {} account versions ({} bytes)",
+                    stats.blocks.rows,
+                    stats.transactions.rows,
+                    stats.account_versions.rows,
+                    stats.blocks.bytes + stats.transactions.bytes + stats.account_versions.bytes,
+                ),
+                Err(e) => error!("Error pruning storage: {:?}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request() {
+        let policy = RetentionPolicy {
+            block_max_age: Some(Duration::from_secs(40)),
+            vote_transaction_max_age: Some(Duration::from_secs(4000)),
+            protected_addresses: vec![vec![1; 32]],
+            ..Default::default()
+        };
+        let request = policy.request(1_000);
+        assert_eq!(request.blocks_before, Some(900));
+        assert_eq!(request.vote_transactions_before, Some(0));
+        assert_eq!(request.transactions_before, None);
+        assert!(request.is_protected(&[1; 32]));
+        assert!(request.is_protected(&light::ACCOUNT_COMPRESSION_PROGRAM_ID.to_bytes()));
+    }
+}
@@ -1,128 +1,1818 @@
 use async_trait::async_trait;
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use scylla::batch::{Batch, BatchType};
+use scylla::cql_to_rust::FromRow;
+use scylla::prepared_statement::PreparedStatement;
+use scylla::serialize::row::SerializeRow;
 use scylla::{Session, SessionBuilder};
-use crate::storage::{Database, CompressedAccount, CompressedBlock, CompressedTransaction};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::storage::database::{cursor_moved_back, signature_bounds};
+use crate::storage::error::{self, StorageError};
+use crate::storage::migrations::{embedded, plan, MigrationReport, SCYLLA};
+use crate::storage::{
+    BlockBatch, CompressionStore, Database, CompressedAccount, CompressedBlock, CompressedTransaction,
+    PruneRequest, PruneStats, Reclaimed, SignatureInfo, SignatureQuery, VOTE_PROGRAM_ID,
+    AddressRecord, CompressedAssetRecord, CompressedStateAccount, CompressedTokenAccountRecord,
+    LeafChangeRecord, MerkleTreeRecord, TokenMintRecord, TreeRootRecord,
+};
+
+const STATE_ACCOUNT_COLUMNS: &str = "hash, address, owner, lamports, discriminator, data, data_hash, tree, leaf_index, seq, slot_created, spent, spent_slot";
+
+const MERKLE_TREE_COLUMNS: &str = "tree, max_depth, max_buffer_size, hasher, leaves, recent_roots, seq, slot, diverged_seq, chain_seq";
+
+const BLOCK_COLUMNS: &str = "slot, blockhash, previous_blockhash, parent_slot, transactions, block_time, data, proof";
+
+const ACCOUNT_COLUMNS: &str = "pubkey, lamports, owner, executable, rent_epoch, slot, write_version, data, proof";
+
+// Blocks are partitioned by epoch, so slot ranges are clustering scans within a few partitions.
+const SLOTS_PER_EPOCH: u64 = 432_000;
+// Same cap as the `getBlocks` RPC method.
+const MAX_BLOCK_RANGE: u64 = 500_000;
+const DEFAULT_MAX_IN_FLIGHT: usize = 256;
+// Keeps unlogged batches under `batch_size_warn_threshold_in_kb`, 128 KiB of serialized
+// values by default.
+const MAX_BATCH_BYTES: usize = 64 * 1024;
+// Estimated size of a row's keys and fixed-width columns, to which blob columns are added.
+const ROW_BYTES: usize = 160;
+const CURSOR_NAME: &str = "last_processed_slot";
+const COMPRESSED_CURSOR_NAME: &str = "compressed_slot";
+// Blocks below this slot have all been pruned, so pruning resumes from its epoch.
+const PRUNED_BLOCKS_CURSOR_NAME: &str = "pruned_blocks_before";
+
+type AccountRow = (Vec<u8>, i64, Vec<u8>, bool, i64, Option<i64>, Option<i64>, Vec<u8>, Vec<u8>);
+
+fn account_from_row(row: AccountRow) -> CompressedAccount {
+    let (pubkey, lamports, owner, executable, rent_epoch, slot, write_version, data, proof) = row;
+    CompressedAccount {
+        pubkey,
+        lamports,
+        owner,
+        executable,
+        rent_epoch,
+        slot: slot.unwrap_or(0) as u64,
+        write_version: write_version.unwrap_or(0) as u64,
+        data,
+        proof,
+    }
+}
+
+// Write timestamp for an account version, so a write never replaces a newer one: the slot
+// above bit 24 and the write version, capped at 2^24 - 1, below it. Rows written before
+// versioning carry wall-clock microseconds, which this only exceeds from slot ~10^8 on, as
+// on mainnet; on clusters with lower slots those rows win until rewritten.
+fn version_timestamp(account: &CompressedAccount) -> i64 {
+    ((account.slot << 24) | account.write_version.min(0xff_ffff)) as i64
+}
+
+type BlockRow = (i64, String, String, i64, i64, Option<i64>, Vec<u8>, Vec<u8>);
+
+type TransactionRow = (
+    Vec<u8>,
+    Option<i64>,
+    Option<i32>,
+    Option<Vec<Vec<u8>>>,
+    Option<i64>,
+    Option<Vec<Vec<u8>>>,
+    Vec<u8>,
+    Vec<u8>,
+);
+
+fn transaction_from_row(row: TransactionRow) -> CompressedTransaction {
+    let (signature, slot, index, accounts, fee, programs, data, proof) = row;
+    CompressedTransaction {
+        signature,
+        slot: slot.unwrap_or_default() as u64,
+        index: index.unwrap_or_default() as u32,
+        accounts: accounts.unwrap_or_default(),
+        fee: fee.unwrap_or_default() as u64,
+        programs: programs.unwrap_or_default(),
+        data,
+        proof,
+    }
+}
+
+type MerkleTreeRow = (
+    Vec<u8>,
+    i32,
+    i32,
+    Option<i8>,
+    Option<Vec<Vec<u8>>>,
+    Option<Vec<Vec<u8>>>,
+    i64,
+    i64,
+    Option<i64>,
+    Option<i64>,
+);
+
+fn merkle_tree_record(
+    (tree, max_depth, max_buffer_size, hasher, leaves, recent_roots, seq, slot, diverged_seq, chain_seq): MerkleTreeRow,
+) -> MerkleTreeRecord {
+    MerkleTreeRecord {
+        tree,
+        max_depth: max_depth as u32,
+        max_buffer_size: max_buffer_size as u32,
+        hasher: hasher.unwrap_or_default() as u8,
+        leaves: leaves.unwrap_or_default(),
+        recent_roots: recent_roots.unwrap_or_default(),
+        seq: seq as u64,
+        chain_seq: chain_seq.map(|seq| seq as u64),
+        slot: slot as u64,
+        diverged_seq: diverged_seq.map(|seq| seq as u64),
+    }
+}
+
+type StateAccountRow = (
+    Vec<u8>,
+    Option<Vec<u8>>,
+    Vec<u8>,
+    i64,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Vec<u8>,
+    i32,
+    Option<i64>,
+    i64,
+    bool,
+    Option<i64>,
+);
+
+type TokenAccountRow = (Vec<u8>, Vec<u8>, Vec<u8>, i64, Option<Vec<u8>>, i8, Option<Vec<u8>>, i64);
+
+type AssetRow = (
+    Vec<u8>,
+    Vec<u8>,
+    i32,
+    i64,
+    Vec<u8>,
+    Vec<u8>,
+    Vec<u8>,
+    Vec<u8>,
+    Vec<u8>,
+    i64,
+    bool,
+    bool,
+    i64,
+);
+
+fn asset_from_row(row: AssetRow) -> CompressedAssetRecord {
+    let (asset_id, tree, leaf_index, nonce, owner, delegate, data_hash, creator_hash, leaf_hash, seq, burned, redeemed, slot_updated) = row;
+    CompressedAssetRecord {
+        asset_id,
+        tree,
+        leaf_index: leaf_index as u32,
+        nonce: nonce as u64,
+        owner,
+        delegate,
+        data_hash,
+        creator_hash,
+        leaf_hash,
+        seq: seq as u64,
+        burned,
+        redeemed,
+        slot_updated: slot_updated as u64,
+    }
+}
+
+type AddressRow = (Vec<u8>, Vec<u8>, i32, Option<Vec<u8>>, i64);
+
+fn address_from_row(row: AddressRow) -> AddressRecord {
+    let (address, tree, leaf_index, next_address, slot) = row;
+    AddressRecord {
+        address,
+        tree,
+        leaf_index: leaf_index as u32,
+        next_address: next_address.unwrap_or_default(),
+        slot: slot as u64,
+    }
+}
+
+type TokenMintRow = (Vec<u8>, Vec<u8>, i64, i8, i64);
+
+fn token_mint_from_row(row: TokenMintRow) -> TokenMintRecord {
+    let (mint, program_id, supply, decimals, slot) = row;
+    TokenMintRecord {
+        mint,
+        program_id,
+        supply: supply as u64,
+        decimals: decimals as u8,
+        slot: slot as u64,
+    }
+}
+
+fn token_account_from_row(row: TokenAccountRow) -> CompressedTokenAccountRecord {
+    let (hash, mint, owner, amount, delegate, state, tlv, slot) = row;
+    CompressedTokenAccountRecord {
+        hash,
+        mint,
+        owner,
+        amount: amount as u64,
+        delegate,
+        state: state as u8,
+        tlv,
+        slot: slot as u64,
+    }
+}
+
+fn state_account_from_row(row: StateAccountRow) -> CompressedStateAccount {
+    let (hash, address, owner, lamports, discriminator, data, data_hash, tree, leaf_index, seq, slot_created, spent, spent_slot) = row;
+    CompressedStateAccount {
+        hash,
+        address,
+        owner,
+        lamports: lamports as u64,
+        discriminator,
+        data: data.unwrap_or_default(),
+        data_hash,
+        tree,
+        leaf_index: leaf_index as u32,
+        seq: seq.map(|s| s as u64),
+        slot_created: slot_created as u64,
+        spent,
+        spent_slot: spent_slot.map(|s| s as u64),
+    }
+}
 
 pub struct ScyllaStorage {
     session: Session,
+    statements: Statements,
+    max_in_flight: usize,
+    // Cached `PRUNED_BLOCKS_CURSOR_NAME` cursor
+    pruned_blocks_before: AtomicU64,
+}
+
+/// Prepared once at connect time so writes skip parsing and route to the owning replica.
+struct Statements {
+    insert_account: PreparedStatement,
+    insert_account_by_owner: PreparedStatement,
+    select_account: PreparedStatement,
+    select_accounts_by_owner: PreparedStatement,
+    insert_account_version: PreparedStatement,
+    insert_account_version_epoch: PreparedStatement,
+    select_account_version_epochs: PreparedStatement,
+    select_account_version_epochs_before: PreparedStatement,
+    select_account_at_slot: PreparedStatement,
+    select_account_versions: PreparedStatement,
+    select_account_version_pubkeys: PreparedStatement,
+    delete_account_version: PreparedStatement,
+    insert_block: PreparedStatement,
+    select_block: PreparedStatement,
+    select_blocks_in_epoch: PreparedStatement,
+    select_block_slots_in_epoch: PreparedStatement,
+    select_first_block_slot_in_epoch: PreparedStatement,
+    delete_blocks_in_epoch: PreparedStatement,
+    insert_transaction: PreparedStatement,
+    insert_transaction_by_slot: PreparedStatement,
+    insert_signature_by_address: PreparedStatement,
+    select_transaction: PreparedStatement,
+    select_transactions_by_slot: PreparedStatement,
+    select_signatures_by_address: PreparedStatement,
+    delete_transaction: PreparedStatement,
+    delete_transactions_by_slot: PreparedStatement,
+    delete_transaction_by_slot: PreparedStatement,
+    select_transaction_slots: PreparedStatement,
+    delete_signature_by_address: PreparedStatement,
+    select_cursor: PreparedStatement,
+    update_cursor: PreparedStatement,
+    insert_merkle_tree: PreparedStatement,
+    select_merkle_tree: PreparedStatement,
+    select_merkle_trees: PreparedStatement,
+    insert_tree_root: PreparedStatement,
+    insert_leaf_change: PreparedStatement,
+    select_leaf_changes: PreparedStatement,
+    insert_state_account: PreparedStatement,
+    insert_state_account_by_owner: PreparedStatement,
+    insert_state_account_by_address: PreparedStatement,
+    select_state_account: PreparedStatement,
+    select_state_account_by_address: PreparedStatement,
+    select_state_accounts_by_owner: PreparedStatement,
+    spend_state_account: PreparedStatement,
+    delete_state_account_by_owner: PreparedStatement,
+    insert_address: PreparedStatement,
+    insert_address_by_tree: PreparedStatement,
+    select_address: PreparedStatement,
+    select_low_address: PreparedStatement,
+    insert_token_account: PreparedStatement,
+    insert_token_account_by_owner: PreparedStatement,
+    insert_token_account_by_mint: PreparedStatement,
+    delete_token_account: PreparedStatement,
+    delete_token_account_by_owner: PreparedStatement,
+    delete_token_account_by_mint: PreparedStatement,
+    select_token_account: PreparedStatement,
+    select_token_accounts_by_owner: PreparedStatement,
+    select_token_accounts_by_mint: PreparedStatement,
+    insert_token_mint: PreparedStatement,
+    select_token_mint: PreparedStatement,
+    delete_asset_by_owner: PreparedStatement,
+    insert_asset: PreparedStatement,
+    insert_asset_by_owner: PreparedStatement,
+    select_asset: PreparedStatement,
+    select_assets_by_owner: PreparedStatement,
+    delete_leaf_changes: PreparedStatement,
+    delete_tree_roots: PreparedStatement,
+    delete_merkle_tree: PreparedStatement,
+    select_tree_roots: PreparedStatement,
+    scan_account_pubkeys: TableScan,
+    scan_transaction_signatures: TableScan,
+    scan_state_accounts: TableScan,
+    scan_addresses: TableScan,
+    scan_token_accounts: TableScan,
+    scan_token_mints: TableScan,
+    scan_assets: TableScan,
+}
+
+/// Pages through a whole table in partition token order, each page starting after the token
+/// of the previous page's last key.
+struct TableScan {
+    first: PreparedStatement,
+    after: PreparedStatement,
+}
+
+impl TableScan {
+    async fn prepare(session: &Session, columns: &str, table: &str, key: &str) -> Result<Self> {
+        Ok(Self {
+            first: session.prepare(format!("SELECT {} FROM {} LIMIT ?", columns, table)).await?,
+            after: session
+                .prepare(format!("SELECT {} FROM {} WHERE token({}) > token(?) LIMIT ?", columns, table, key))
+                .await?,
+        })
+    }
+}
+
+impl Statements {
+    async fn prepare(session: &Session) -> Result<Self> {
+        Ok(Self {
+            insert_account: session
+                .prepare(format!(
+                    "INSERT INTO compressed_accounts ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) USING TIMESTAMP ?",
+                    ACCOUNT_COLUMNS
+                ))
+                .await?,
+            insert_account_by_owner: session
+                .prepare("INSERT INTO compressed_accounts_by_owner (owner, pubkey) VALUES (?, ?)")
+                .await?,
+            select_account: session
+                .prepare(format!("SELECT {} FROM compressed_accounts WHERE pubkey = ?", ACCOUNT_COLUMNS))
+                .await?,
+            select_accounts_by_owner: session
+                .prepare("SELECT pubkey FROM compressed_accounts_by_owner WHERE owner = ? LIMIT ?")
+                .await?,
+            insert_account_version: session
+                .prepare(format!(
+                    "INSERT INTO account_versions_by_epoch (epoch, {}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    ACCOUNT_COLUMNS
+                ))
+                .await?,
+            insert_account_version_epoch: session
+                .prepare("INSERT INTO account_version_epochs (pubkey, epoch) VALUES (?, ?)")
+                .await?,
+            select_account_version_epochs: session
+                .prepare(
+                    "SELECT epoch FROM account_version_epochs WHERE pubkey = ? AND epoch >= ? AND epoch <= ? \
+                     ORDER BY epoch ASC",
+                )
+                .await?,
+            select_account_version_epochs_before: session
+                .prepare("SELECT epoch FROM account_version_epochs WHERE pubkey = ? AND epoch <= ?")
+                .await?,
+            select_account_at_slot: session
+                .prepare(format!(
+                    "SELECT {} FROM account_versions_by_epoch WHERE pubkey = ? AND epoch = ? AND slot <= ? LIMIT 1",
+                    ACCOUNT_COLUMNS
+                ))
+                .await?,
+            select_account_versions: session
+                .prepare(format!(
+                    "SELECT {} FROM account_versions_by_epoch WHERE pubkey = ? AND epoch = ? AND slot >= ? AND slot <= ? \
+                     ORDER BY slot ASC, write_version ASC LIMIT ?",
+                    ACCOUNT_COLUMNS
+                ))
+                .await?,
+            select_account_version_pubkeys: session
+                .prepare("SELECT DISTINCT pubkey FROM account_version_epochs")
+                .await?,
+            delete_account_version: session
+                .prepare(
+                    "DELETE FROM account_versions_by_epoch \
+                     WHERE pubkey = ? AND epoch = ? AND slot = ? AND write_version = ?",
+                )
+                .await?,
+            insert_block: session
+                .prepare(
+                    "INSERT INTO compressed_blocks_by_epoch \
+                     (epoch, slot, blockhash, previous_blockhash, parent_slot, transactions, block_time, data, proof) \
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                )
+                .await?,
+            select_block: session
+                .prepare(format!("SELECT {} FROM compressed_blocks_by_epoch WHERE epoch = ? AND slot = ?", BLOCK_COLUMNS))
+                .await?,
+            select_blocks_in_epoch: session
+                .prepare(format!(
+                    "SELECT {} FROM compressed_blocks_by_epoch WHERE epoch = ? AND slot >= ? AND slot <= ?",
+                    BLOCK_COLUMNS
+                ))
+                .await?,
+            select_block_slots_in_epoch: session
+                .prepare("SELECT slot FROM compressed_blocks_by_epoch WHERE epoch = ? AND slot >= ? AND slot <= ?")
+                .await?,
+            select_first_block_slot_in_epoch: session
+                .prepare("SELECT slot FROM compressed_blocks_by_epoch WHERE epoch = ? LIMIT 1")
+                .await?,
+            delete_blocks_in_epoch: session
+                .prepare("DELETE FROM compressed_blocks_by_epoch WHERE epoch = ? AND slot >= ? AND slot <= ?")
+                .await?,
+            insert_transaction: session
+                .prepare(
+                    "INSERT INTO compressed_transactions \
+                     (signature, slot, tx_index, accounts, fee, programs, data, proof) \
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                )
+                .await?,
+            insert_transaction_by_slot: session
+                .prepare("INSERT INTO compressed_transactions_by_slot (slot, tx_index, signature) VALUES (?, ?, ?)")
+                .await?,
+            insert_signature_by_address: session
+                .prepare("INSERT INTO signatures_by_address (address, slot, tx_index, signature) VALUES (?, ?, ?, ?)")
+                .await?,
+            select_transaction: session
+                .prepare(
+                    "SELECT signature, slot, tx_index, accounts, fee, programs, data, proof \
+                     FROM compressed_transactions WHERE signature = ?",
+                )
+                .await?,
+            select_transactions_by_slot: session
+                .prepare("SELECT signature FROM compressed_transactions_by_slot WHERE slot = ?")
+                .await?,
+            select_signatures_by_address: session
+                .prepare(
+                    "SELECT signature, slot, tx_index FROM signatures_by_address \
+                     WHERE address = ? AND (slot, tx_index) < (?, ?) AND (slot, tx_index) > (?, ?) LIMIT ?",
+                )
+                .await?,
+            delete_transaction: session
+                .prepare("DELETE FROM compressed_transactions WHERE signature = ?")
+                .await?,
+            delete_transactions_by_slot: session
+                .prepare("DELETE FROM compressed_transactions_by_slot WHERE slot = ?")
+                .await?,
+            delete_transaction_by_slot: session
+                .prepare("DELETE FROM compressed_transactions_by_slot WHERE slot = ? AND tx_index = ?")
+                .await?,
+            select_transaction_slots: session
+                .prepare("SELECT DISTINCT slot FROM compressed_transactions_by_slot")
+                .await?,
+            delete_signature_by_address: session
+                .prepare("DELETE FROM signatures_by_address WHERE address = ? AND slot = ? AND tx_index = ?")
+                .await?,
+            select_cursor: session
+                .prepare("SELECT slot FROM indexer_cursor WHERE name = ?")
+                .await?,
+            update_cursor: session
+                .prepare("INSERT INTO indexer_cursor (name, slot) VALUES (?, ?)")
+                .await?,
+            insert_merkle_tree: session
+                .prepare(format!(
+                    "INSERT INTO merkle_trees ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    MERKLE_TREE_COLUMNS
+                ))
+                .await?,
+            select_merkle_tree: session
+                .prepare(format!("SELECT {} FROM merkle_trees WHERE tree = ?", MERKLE_TREE_COLUMNS))
+                .await?,
+            select_merkle_trees: session
+                .prepare(format!("SELECT {} FROM merkle_trees", MERKLE_TREE_COLUMNS))
+                .await?,
+            insert_tree_root: session
+                .prepare("INSERT INTO merkle_tree_roots (tree, seq, root, slot) VALUES (?, ?, ?, ?)")
+                .await?,
+            insert_leaf_change: session
+                .prepare(
+                    "INSERT INTO merkle_leaf_changes (tree, seq, leaf_index, previous_leaf, new_leaf, slot) \
+                     VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .await?,
+            select_leaf_changes: session
+                .prepare(
+                    "SELECT tree, seq, leaf_index, previous_leaf, new_leaf, slot FROM merkle_leaf_changes \
+                     WHERE tree = ? AND seq > ? ORDER BY seq ASC",
+                )
+                .await?,
+            insert_state_account: session
+                .prepare(format!(
+                    "INSERT INTO state_accounts ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    STATE_ACCOUNT_COLUMNS
+                ))
+                .await?,
+            insert_state_account_by_owner: session
+                .prepare("INSERT INTO state_accounts_by_owner (owner, hash) VALUES (?, ?)")
+                .await?,
+            insert_state_account_by_address: session
+                .prepare("INSERT INTO state_accounts_by_address (address, hash) VALUES (?, ?)")
+                .await?,
+            select_state_account: session
+                .prepare(format!("SELECT {} FROM state_accounts WHERE hash = ?", STATE_ACCOUNT_COLUMNS))
+                .await?,
+            select_state_account_by_address: session
+                .prepare("SELECT hash FROM state_accounts_by_address WHERE address = ?")
+                .await?,
+            select_state_accounts_by_owner: session
+                .prepare("SELECT hash FROM state_accounts_by_owner WHERE owner = ?")
+                .await?,
+            spend_state_account: session
+                .prepare("UPDATE state_accounts SET spent = true, spent_slot = ? WHERE hash = ?")
+                .await?,
+            delete_state_account_by_owner: session
+                .prepare("DELETE FROM state_accounts_by_owner WHERE owner = ? AND hash = ?")
+                .await?,
+            insert_address: session
+                .prepare(
+                    "INSERT INTO addresses (address, tree, leaf_index, next_address, slot) VALUES (?, ?, ?, ?, ?)",
+                )
+                .await?,
+            insert_address_by_tree: session
+                .prepare("INSERT INTO addresses_by_tree (tree, address) VALUES (?, ?)")
+                .await?,
+            select_address: session
+                .prepare(
+                    "SELECT address, tree, leaf_index, next_address, slot FROM addresses WHERE address = ?",
+                )
+                .await?,
+            select_low_address: session
+                .prepare(
+                    "SELECT address FROM addresses_by_tree WHERE tree = ? AND address < ? ORDER BY address DESC LIMIT 1",
+                )
+                .await?,
+            insert_token_account: session
+                .prepare(
+                    "INSERT INTO token_accounts (hash, mint, owner, amount, delegate, state, tlv, slot) \
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                )
+                .await?,
+            insert_token_account_by_owner: session
+                .prepare("INSERT INTO token_accounts_by_owner (owner, hash) VALUES (?, ?)")
+                .await?,
+            insert_token_account_by_mint: session
+                .prepare("INSERT INTO token_accounts_by_mint (mint, hash) VALUES (?, ?)")
+                .await?,
+            delete_token_account: session
+                .prepare("DELETE FROM token_accounts WHERE hash = ?")
+                .await?,
+            delete_token_account_by_owner: session
+                .prepare("DELETE FROM token_accounts_by_owner WHERE owner = ? AND hash = ?")
+                .await?,
+            delete_token_account_by_mint: session
+                .prepare("DELETE FROM token_accounts_by_mint WHERE mint = ? AND hash = ?")
+                .await?,
+            select_token_account: session
+                .prepare(
+                    "SELECT hash, mint, owner, amount, delegate, state, tlv, slot FROM token_accounts WHERE hash = ?",
+                )
+                .await?,
+            select_token_accounts_by_owner: session
+                .prepare("SELECT hash FROM token_accounts_by_owner WHERE owner = ?")
+                .await?,
+            select_token_accounts_by_mint: session
+                .prepare("SELECT hash FROM token_accounts_by_mint WHERE mint = ?")
+                .await?,
+            insert_token_mint: session
+                .prepare(
+                    "INSERT INTO token_mints (mint, program_id, supply, decimals, slot) VALUES (?, ?, ?, ?, ?)",
+                )
+                .await?,
+            select_token_mint: session
+                .prepare(
+                    "SELECT mint, program_id, supply, decimals, slot FROM token_mints WHERE mint = ?",
+                )
+                .await?,
+            delete_asset_by_owner: session
+                .prepare("DELETE FROM compressed_assets_by_owner WHERE owner = ? AND asset_id = ?")
+                .await?,
+            insert_asset: session
+                .prepare(
+                    "INSERT INTO compressed_assets (asset_id, tree, leaf_index, nonce, owner, delegate, data_hash, \
+                     creator_hash, leaf_hash, seq, burned, redeemed, slot_updated) \
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                )
+                .await?,
+            insert_asset_by_owner: session
+                .prepare("INSERT INTO compressed_assets_by_owner (owner, asset_id) VALUES (?, ?)")
+                .await?,
+            select_asset: session
+                .prepare(
+                    "SELECT asset_id, tree, leaf_index, nonce, owner, delegate, data_hash, creator_hash, leaf_hash, \
+                     seq, burned, redeemed, slot_updated FROM compressed_assets WHERE asset_id = ?",
+                )
+                .await?,
+            select_assets_by_owner: session
+                .prepare("SELECT asset_id FROM compressed_assets_by_owner WHERE owner = ?")
+                .await?,
+            delete_leaf_changes: session
+                .prepare("DELETE FROM merkle_leaf_changes WHERE tree = ?")
+                .await?,
+            delete_tree_roots: session
+                .prepare("DELETE FROM merkle_tree_roots WHERE tree = ?")
+                .await?,
+            delete_merkle_tree: session
+                .prepare("DELETE FROM merkle_trees WHERE tree = ?")
+                .await?,
+            select_tree_roots: session
+                .prepare("SELECT tree, seq, root, slot FROM merkle_tree_roots WHERE tree = ? ORDER BY seq ASC")
+                .await?,
+            scan_account_pubkeys: TableScan::prepare(session, "DISTINCT pubkey", "account_version_epochs", "pubkey")
+                .await?,
+            scan_transaction_signatures: TableScan::prepare(session, "signature", "compressed_transactions", "signature")
+                .await?,
+            scan_state_accounts: TableScan::prepare(session, STATE_ACCOUNT_COLUMNS, "state_accounts", "hash").await?,
+            scan_addresses: TableScan::prepare(
+                session,
+                "address, tree, leaf_index, next_address, slot",
+                "addresses",
+                "address",
+            )
+            .await?,
+            scan_token_accounts: TableScan::prepare(
+                session,
+                "hash, mint, owner, amount, delegate, state, tlv, slot",
+                "token_accounts",
+                "hash",
+            )
+            .await?,
+            scan_token_mints: TableScan::prepare(
+                session,
+                "mint, program_id, supply, decimals, slot",
+                "token_mints",
+                "mint",
+            )
+            .await?,
+            scan_assets: TableScan::prepare(
+                session,
+                "asset_id, tree, leaf_index, nonce, owner, delegate, data_hash, creator_hash, leaf_hash, seq, burned, \
+                 redeemed, slot_updated",
+                "compressed_assets",
+                "asset_id",
+            )
+            .await?,
+        })
+    }
 }
 
 impl ScyllaStorage {
     pub async fn new(uri: &str) -> Result<Self> {
+        Self::with_keyspace(uri, "windexer").await
+    }
+
+    pub async fn with_keyspace(uri: &str, keyspace: &str) -> Result<Self> {
+        // The default load balancing policy is token aware for prepared statements.
         let session = SessionBuilder::new().known_node(uri).build().await?;
-        Ok(Self { session })
+        migrate_session(&session, keyspace, true).await?;
+        let statements = Statements::prepare(&session).await?;
+        let pruned_blocks_before = session
+            .execute(&statements.select_cursor, (PRUNED_BLOCKS_CURSOR_NAME,))
+            .await?
+            .maybe_first_row_typed::<(i64,)>()?
+            .map_or(0, |(slot,)| slot as u64);
+        Ok(Self {
+            session,
+            statements,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            pruned_blocks_before: AtomicU64::new(pruned_blocks_before),
+        })
+    }
+
+    /// Applies pending migrations, or with `apply` unset only reports them.
+    pub async fn migrate(uri: &str, keyspace: &str, apply: bool) -> Result<MigrationReport> {
+        let session = SessionBuilder::new().known_node(uri).build().await?;
+        migrate_session(&session, keyspace, apply).await
+    }
+
+    /// Caps the number of concurrent requests a single write fans out to.
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// Up to `limit` rows of `scan` following the row keyed `after`.
+    async fn scan_page<R: FromRow>(&self, scan: &TableScan, after: Option<&[u8]>, limit: usize) -> Result<Vec<R>> {
+        let limit = limit.min(i32::MAX as usize) as i32;
+        let result = match after {
+            Some(after) => self.session.execute(&scan.after, (after, limit)).await?,
+            None => self.session.execute(&scan.first, (limit,)).await?,
+        };
+        Ok(result.rows_typed::<R>()?.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Executes one statement per row, with at most `max_in_flight` outstanding.
+    async fn execute_all<V: SerializeRow + Send + Sync>(&self, statement: &PreparedStatement, rows: Vec<V>) -> Result<()> {
+        stream::iter(rows)
+            .map(|values| async move { self.session.execute(statement, values).await })
+            .buffer_unordered(self.max_in_flight)
+            .try_for_each(|_| async { Ok(()) })
+            .await?;
+        Ok(())
+    }
+
+    /// Groups `(partition key, estimated bytes, values)` rows by partition and writes each
+    /// partition as unlogged batches of at most `MAX_BATCH_BYTES`. A larger row goes alone.
+    async fn batch_by_partition<K, V>(&self, statement: &PreparedStatement, rows: Vec<(K, usize, V)>) -> Result<()>
+    where
+        K: Hash + Eq,
+        V: SerializeRow + Send + Sync,
+    {
+        let mut partitions: HashMap<K, Vec<(usize, V)>> = HashMap::new();
+        for (key, bytes, values) in rows {
+            partitions.entry(key).or_default().push((bytes, values));
+        }
+        let mut chunks: Vec<Vec<V>> = Vec::new();
+        for rows in partitions.into_values() {
+            let mut chunk = Vec::new();
+            let mut chunk_bytes = 0;
+            for (bytes, values) in rows {
+                if !chunk.is_empty() && chunk_bytes + bytes > MAX_BATCH_BYTES {
+                    chunks.push(std::mem::take(&mut chunk));
+                    chunk_bytes = 0;
+                }
+                chunk.push(values);
+                chunk_bytes += bytes;
+            }
+            chunks.push(chunk);
+        }
+
+        stream::iter(chunks)
+            .map(|values| async move {
+                let mut batch = Batch::new(BatchType::Unlogged);
+                for _ in 0..values.len() {
+                    batch.append_statement(statement.clone());
+                }
+                self.session.batch(&batch, values).await
+            })
+            .buffer_unordered(self.max_in_flight)
+            .try_for_each(|_| async { Ok(()) })
+            .await?;
+        Ok(())
+    }
+
+    async fn set_pruned_blocks_before(&self, slot: u64) -> Result<()> {
+        self.session
+            .execute(&self.statements.update_cursor, (PRUNED_BLOCKS_CURSOR_NAME, slot as i64))
+            .await?;
+        self.pruned_blocks_before.store(slot, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn write_blocks(&self, blocks: &[&CompressedBlock]) -> Result<()> {
+        // Blocks written below the prune cursor, by a restore or a tier move, pull it back so
+        // the next prune still reaches them.
+        if let Some(oldest) = blocks.iter().map(|block| block.slot).min() {
+            if oldest < self.pruned_blocks_before.load(Ordering::SeqCst) {
+                self.set_pruned_blocks_before(oldest).await?;
+            }
+        }
+        let rows = blocks
+            .iter()
+            .map(|block| {
+                (
+                    epoch(block.slot),
+                    ROW_BYTES + block.data.len() + block.proof.len(),
+                    (
+                        epoch(block.slot),
+                        block.slot as i64,
+                        &block.blockhash,
+                        &block.previous_blockhash,
+                        block.parent_slot as i64,
+                        block.transactions as i64,
+                        block.block_time,
+                        &block.data,
+                        &block.proof,
+                    ),
+                )
+            })
+            .collect();
+        self.batch_by_partition(&self.statements.insert_block, rows).await
+    }
+
+    async fn write_transactions(&self, transactions: &[&CompressedTransaction]) -> Result<()> {
+        let rows = transactions
+            .iter()
+            .map(|t| {
+                (
+                    &t.signature,
+                    t.slot as i64,
+                    t.index as i32,
+                    &t.accounts,
+                    t.fee as i64,
+                    &t.programs,
+                    &t.data,
+                    &t.proof,
+                )
+            })
+            .collect();
+        self.execute_all(&self.statements.insert_transaction, rows).await?;
+
+        let by_slot = transactions
+            .iter()
+            .map(|t| (t.slot, ROW_BYTES, (t.slot as i64, t.index as i32, &t.signature)))
+            .collect();
+        self.batch_by_partition(&self.statements.insert_transaction_by_slot, by_slot)
+            .await?;
+
+        let by_address = transactions
+            .iter()
+            .flat_map(|t| {
+                t.accounts
+                    .iter()
+                    .map(move |address| (address, ROW_BYTES, (address, t.slot as i64, t.index as i32, &t.signature)))
+            })
+            .collect();
+        self.batch_by_partition(&self.statements.insert_signature_by_address, by_address)
+            .await
+    }
+
+    // Owner changes leave a stale index row behind instead of costing a read per write;
+    // `get_accounts_by_owner` filters those out.
+    async fn write_accounts(&self, accounts: &[&CompressedAccount]) -> Result<()> {
+        let rows = accounts
+            .iter()
+            .map(|a| {
+                (
+                    &a.pubkey,
+                    a.lamports,
+                    &a.owner,
+                    a.executable,
+                    a.rent_epoch,
+                    a.slot as i64,
+                    a.write_version as i64,
+                    &a.data,
+                    &a.proof,
+                    version_timestamp(a),
+                )
+            })
+            .collect();
+        self.execute_all(&self.statements.insert_account, rows).await?;
+
+        let versions = accounts
+            .iter()
+            .map(|a| {
+                (
+                    (&a.pubkey, epoch(a.slot)),
+                    ROW_BYTES + a.data.len() + a.proof.len(),
+                    (
+                        epoch(a.slot),
+                        &a.pubkey,
+                        a.lamports,
+                        &a.owner,
+                        a.executable,
+                        a.rent_epoch,
+                        a.slot as i64,
+                        a.write_version as i64,
+                        &a.data,
+                        &a.proof,
+                    ),
+                )
+            })
+            .collect();
+        self.batch_by_partition(&self.statements.insert_account_version, versions).await?;
+        let version_epochs = accounts
+            .iter()
+            .map(|a| (&a.pubkey, ROW_BYTES, (&a.pubkey, epoch(a.slot))))
+            .collect();
+        self.batch_by_partition(&self.statements.insert_account_version_epoch, version_epochs).await?;
+
+        let by_owner = accounts
+            .iter()
+            .map(|a| (&a.owner, ROW_BYTES, (&a.owner, &a.pubkey)))
+            .collect();
+        self.batch_by_partition(&self.statements.insert_account_by_owner, by_owner)
+            .await
+    }
+
+    // Slots that still hold a transaction keep their block, so transactions are pruned first.
+    // The cursor stops at the first kept block, so later prunes look at it again.
+    async fn prune_blocks(&self, before: u64) -> Result<Reclaimed> {
+        let mut reclaimed = Reclaimed::default();
+        let pruned_before = self.pruned_blocks_before.load(Ordering::SeqCst);
+        if before <= pruned_before {
+            return Ok(reclaimed);
+        }
+        let mut first_kept = None;
+        for epoch in epoch(pruned_before)..=epoch(before - 1) {
+            let first = (pruned_before as i64).max(epoch * SLOTS_PER_EPOCH as i64);
+            let last = (before - 1).min((epoch as u64 + 1) * SLOTS_PER_EPOCH - 1) as i64;
+            let rows = self.session
+                .execute(&self.statements.select_blocks_in_epoch, (epoch, first, last))
+                .await?
+                .rows_typed::<BlockRow>()?;
+            let mut expired = Vec::new();
+            for row in rows {
+                let block = block_from_row(row?);
+                let slot = block.slot as i64;
+                let kept = self.session
+                    .execute(&self.statements.select_transactions_by_slot, (slot,))
+                    .await?
+                    .maybe_first_row_typed::<(Vec<u8>,)>()?
+                    .is_some();
+                if kept {
+                    first_kept = Some(first_kept.map_or(block.slot, |first: u64| first.min(block.slot)));
+                } else {
+                    reclaimed.add((block.data.len() + block.proof.len()) as u64);
+                    expired.push((epoch, slot, slot));
+                }
+            }
+            self.execute_all(&self.statements.delete_blocks_in_epoch, expired).await?;
+        }
+        self.set_pruned_blocks_before(first_kept.unwrap_or(before)).await?;
+        Ok(reclaimed)
+    }
+
+    // Transactions are only partitioned by slot, so finding the old ones takes a scan of
+    // the slot index's partition keys.
+    async fn prune_transactions(&self, request: &PruneRequest) -> Result<Reclaimed> {
+        let mut reclaimed = Reclaimed::default();
+        let Some(before) = request.transaction_cutoff(true) else {
+            return Ok(reclaimed);
+        };
+        let slots: Vec<i64> = self.session
+            .execute_iter(self.statements.select_transaction_slots.clone(), ())
+            .await?
+            .into_typed::<(i64,)>()
+            .map_ok(|(slot,)| slot)
+            .try_filter(|slot| futures::future::ready((*slot as u64) < before))
+            .try_collect()
+            .await?;
+        for slot in slots {
+            let expired: Vec<CompressedTransaction> = self
+                .get_transactions_in_block(slot as u64)
+                .await?
+                .into_iter()
+                .filter(|t| {
+                    let is_vote = t.accounts.iter().any(|address| address[..] == VOTE_PROGRAM_ID[..]);
+                    request.transaction_cutoff(is_vote).is_some_and(|before| t.slot < before)
+                        && !t.accounts.iter().any(|address| request.is_protected(address))
+                })
+                .collect();
+            let by_address = expired
+                .iter()
+                .flat_map(|t| {
+                    t.accounts
+                        .iter()
+                        .map(move |address| (address, ROW_BYTES, (address, slot, t.index as i32)))
+                })
+                .collect();
+            self.batch_by_partition(&self.statements.delete_signature_by_address, by_address)
+                .await?;
+            let signatures = expired.iter().map(|t| (&t.signature,)).collect();
+            self.execute_all(&self.statements.delete_transaction, signatures).await?;
+            let by_slot = expired.iter().map(|t| (slot, ROW_BYTES, (slot, t.index as i32))).collect();
+            self.batch_by_partition(&self.statements.delete_transaction_by_slot, by_slot)
+                .await?;
+            for t in &expired {
+                reclaimed.add((t.data.len() + t.proof.len()) as u64);
+            }
+        }
+        Ok(reclaimed)
+    }
+
+    // The current version is never pruned. Emptied epochs stay in `account_version_epochs`,
+    // which readers already tolerate.
+    async fn prune_account_versions(&self, request: &PruneRequest, before: u64) -> Result<Reclaimed> {
+        let mut reclaimed = Reclaimed::default();
+        if before == 0 {
+            return Ok(reclaimed);
+        }
+        let pubkeys: Vec<Vec<u8>> = self.session
+            .execute_iter(self.statements.select_account_version_pubkeys.clone(), ())
+            .await?
+            .into_typed::<(Vec<u8>,)>()
+            .map_ok(|(pubkey,)| pubkey)
+            .try_collect()
+            .await?;
+        for pubkey in pubkeys.iter().filter(|pubkey| !request.is_protected(pubkey)) {
+            let current = match self.get_compressed_account(pubkey).await {
+                Ok(current) => current,
+                Err(StorageError::NotFound(_)) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let expired: Vec<CompressedAccount> = self
+                .get_account_versions(pubkey, 0, before - 1, usize::MAX)
+                .await?
+                .into_iter()
+                .filter(|account| account.version() != current.version() && !request.is_protected(&account.owner))
+                .collect();
+            let rows = expired
+                .iter()
+                .map(|a| {
+                    let key = (pubkey, epoch(a.slot));
+                    (key, ROW_BYTES, (pubkey, epoch(a.slot), a.slot as i64, a.write_version as i64))
+                })
+                .collect();
+            self.batch_by_partition(&self.statements.delete_account_version, rows).await?;
+            for account in &expired {
+                reclaimed.add((account.data.len() + account.proof.len()) as u64);
+            }
+        }
+        Ok(reclaimed)
+    }
+}
+
+// Creates the keyspace, with the replication the old setup scripts used, and the tracking
+// table if missing, then switches the session to the keyspace. CQL has no DDL transactions,
+// so migrations are written to be safe to re-run after a partial failure.
+async fn migrate_session(session: &Session, keyspace: &str, apply: bool) -> Result<MigrationReport> {
+    session
+        .query(
+            format!(
+                "CREATE KEYSPACE IF NOT EXISTS {} WITH replication = {{'class': 'SimpleStrategy', 'replication_factor': 1}}",
+                keyspace
+            ),
+            (),
+        )
+        .await?;
+    session.use_keyspace(keyspace, false).await?;
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (version bigint PRIMARY KEY, description text, checksum blob, applied_at timestamp)",
+            (),
+        )
+        .await?;
+    let applied = session
+        .query("SELECT version, checksum FROM schema_migrations", ())
+        .await?
+        .rows_typed::<(i64, Vec<u8>)>()?
+        .collect::<Result<Vec<_>, _>>()?;
+    let report = plan("scylla", &embedded(SCYLLA), &applied)?;
+    if apply {
+        for migration in SCYLLA.iter().filter(|m| report.pending.contains(&m.version)) {
+            for statement in migration.statements() {
+                session
+                    .query(statement, ())
+                    .await
+                    .with_context(|| format!("Failed to apply scylla migration {}", migration.version))?;
+            }
+            session
+                .query(
+                    "INSERT INTO schema_migrations (version, description, checksum, applied_at) VALUES (?, ?, ?, toTimestamp(now()))",
+                    (migration.version, migration.description, migration.checksum()),
+                )
+                .await?;
+        }
+    }
+    Ok(report)
+}
+
+fn epoch(slot: u64) -> i64 {
+    (slot / SLOTS_PER_EPOCH) as i64
+}
+
+fn block_from_row(row: BlockRow) -> CompressedBlock {
+    let (slot, blockhash, previous_blockhash, parent_slot, transactions, block_time, data, proof) = row;
+    CompressedBlock {
+        slot: slot as u64,
+        blockhash,
+        previous_blockhash,
+        parent_slot: parent_slot as u64,
+        transactions: transactions as u64,
+        block_time,
+        data,
+        proof,
     }
 }
 
 #[async_trait]
 impl Database for ScyllaStorage {
-    async fn insert_compressed_account(&self, account: &CompressedAccount) -> Result<()> {
+    async fn insert_compressed_account(&self, account: &CompressedAccount) -> error::Result<()> {
+        Ok(self.write_accounts(&[account]).await?)
+    }
+
+    async fn get_compressed_account(&self, pubkey: &[u8]) -> error::Result<CompressedAccount> {
+        let row = self.session
+            .execute(&self.statements.select_account, (pubkey,))
+            .await?
+            .maybe_first_row_typed::<AccountRow>()?
+            .ok_or_else(|| StorageError::not_found(format!("compressed account {}", hex::encode(pubkey))))?;
+        Ok(account_from_row(row))
+    }
+
+    async fn insert_compressed_block(&self, block: &CompressedBlock) -> error::Result<()> {
+        Ok(self.write_blocks(&[block]).await?)
+    }
+
+    async fn get_compressed_block(&self, slot: u64) -> error::Result<CompressedBlock> {
+        let row = self.session
+            .execute(&self.statements.select_block, (epoch(slot), slot as i64))
+            .await?
+            .maybe_first_row_typed::<BlockRow>()?
+            .ok_or_else(|| StorageError::not_found(format!("compressed block {}", slot)))?;
+        Ok(block_from_row(row))
+    }
+
+    async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> error::Result<()> {
+        Ok(self.write_transactions(&[transaction]).await?)
+    }
+
+    async fn get_compressed_transaction(&self, signature: &[u8]) -> error::Result<CompressedTransaction> {
+        let row = self.session
+            .execute(&self.statements.select_transaction, (signature,))
+            .await?
+            .maybe_first_row_typed::<TransactionRow>()?
+            .ok_or_else(|| StorageError::not_found(format!("compressed transaction {}", hex::encode(signature))))?;
+        Ok(transaction_from_row(row))
+    }
+
+    async fn get_accounts_by_owner(&self, owner: &[u8], limit: usize) -> error::Result<Vec<CompressedAccount>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        // Over-fetch a little to make up for stale index rows.
+        let rows = self.session
+            .execute(&self.statements.select_accounts_by_owner, (owner, (limit * 2).min(i32::MAX as usize) as i32))
+            .await?
+            .rows_typed::<(Vec<u8>,)>()?;
+        let mut accounts = Vec::new();
+        for row in rows {
+            let (pubkey,) = row?;
+            let account = self.get_compressed_account(&pubkey).await?;
+            if account.owner == owner {
+                accounts.push(account);
+            }
+            if accounts.len() == limit {
+                break;
+            }
+        }
+        Ok(accounts)
+    }
+
+    async fn get_signatures_for_address(&self, address: &[u8], query: &SignatureQuery) -> error::Result<Vec<SignatureInfo>> {
+        let (before, until) = signature_bounds(self, query).await?;
+        let before = before.map_or((i64::MAX, i32::MAX), |(slot, index)| (slot as i64, index as i32));
+        let until = until.map_or((-1, -1), |(slot, index)| (slot as i64, index as i32));
+        let rows = self.session
+            .execute(
+                &self.statements.select_signatures_by_address,
+                (address, before.0, before.1, until.0, until.1, query.limit as i32),
+            )
+            .await?
+            .rows_typed::<(Vec<u8>, i64, i32)>()?;
+        rows.map(|row| {
+            let (signature, slot, index) = row?;
+            Ok(SignatureInfo {
+                signature,
+                slot: slot as u64,
+                index: index as u32,
+            })
+        })
+        .collect()
+    }
+
+    async fn get_blocks_in_range(&self, start_slot: u64, end_slot: u64) -> error::Result<Vec<CompressedBlock>> {
+        if end_slot.saturating_sub(start_slot) > MAX_BLOCK_RANGE {
+            return Err(StorageError::Other(anyhow!(
+                "Slot range {}..={} exceeds {} slots",
+                start_slot,
+                end_slot,
+                MAX_BLOCK_RANGE
+            )));
+        }
+        let mut blocks = Vec::new();
+        for epoch in epoch(start_slot)..=epoch(end_slot) {
+            let rows = self.session
+                .execute(
+                    &self.statements.select_blocks_in_epoch,
+                    (epoch, start_slot as i64, end_slot as i64),
+                )
+                .await?
+                .rows_typed::<BlockRow>()?;
+            for row in rows {
+                blocks.push(block_from_row(row?));
+            }
+        }
+        Ok(blocks)
+    }
+
+    // Blocks are partitioned by epoch, so this probes each epoch in turn. Pruning keeps the
+    // blocks of retained transactions, so the prune low-water mark is no lower bound.
+    async fn get_first_block_slot(&self) -> error::Result<Option<u64>> {
+        let cursor = self.get_last_processed_slot().await?;
+        for epoch in 0..=epoch(cursor) {
+            let row = self.session
+                .execute(&self.statements.select_first_block_slot_in_epoch, (epoch,))
+                .await?
+                .maybe_first_row_typed::<(i64,)>()?;
+            if let Some((slot,)) = row {
+                return Ok(Some(slot as u64));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn get_transactions_in_block(&self, slot: u64) -> error::Result<Vec<CompressedTransaction>> {
+        let signatures = self.session
+            .execute(&self.statements.select_transactions_by_slot, (slot as i64,))
+            .await?
+            .rows_typed::<(Vec<u8>,)>()?
+            .map(|row| row.map(|(signature,)| signature))
+            .collect::<Result<Vec<_>, _>>()?;
+        stream::iter(signatures)
+            .map(|signature| async move { self.get_compressed_transaction(&signature).await })
+            .buffered(self.max_in_flight)
+            .try_collect()
+            .await
+    }
+
+    async fn delete_slot_range(&self, start_slot: u64, end_slot: u64) -> error::Result<u64> {
+        let mut deleted = 0;
+        for epoch in epoch(start_slot)..=epoch(end_slot) {
+            let first = start_slot.max(epoch as u64 * SLOTS_PER_EPOCH) as i64;
+            let last = end_slot.min((epoch as u64 + 1) * SLOTS_PER_EPOCH - 1) as i64;
+            let slots = self.session
+                .execute(&self.statements.select_block_slots_in_epoch, (epoch, first, last))
+                .await?
+                .rows_typed::<(i64,)>()?
+                .map(|row| row.map(|(slot,)| slot))
+                .collect::<Result<Vec<_>, _>>()?;
+            for &slot in &slots {
+                let transactions = self.get_transactions_in_block(slot as u64).await?;
+                let by_address = transactions
+                    .iter()
+                    .flat_map(|t| {
+                        t.accounts
+                            .iter()
+                            .map(move |address| (address, ROW_BYTES, (address, slot, t.index as i32)))
+                    })
+                    .collect();
+                self.batch_by_partition(&self.statements.delete_signature_by_address, by_address)
+                    .await?;
+                let signatures = transactions.iter().map(|t| (&t.signature,)).collect();
+                self.execute_all(&self.statements.delete_transaction, signatures).await?;
+                self.session
+                    .execute(&self.statements.delete_transactions_by_slot, (slot,))
+                    .await?;
+                deleted += transactions.len() as u64;
+            }
+            // A clustering range delete writes one tombstone for the whole span.
+            self.session
+                .execute(&self.statements.delete_blocks_in_epoch, (epoch, first, last))
+                .await?;
+            deleted += slots.len() as u64;
+        }
+        let cursor = self.get_last_processed_slot().await?;
+        if (start_slot..=end_slot).contains(&cursor) {
+            self.session
+                .execute(&self.statements.update_cursor, (CURSOR_NAME, start_slot.saturating_sub(1) as i64))
+                .await?;
+        }
+        Ok(deleted)
+    }
+
+    // Walks back through the epochs the account has versions in; the first one usually holds it.
+    async fn get_account_at_slot(&self, pubkey: &[u8], slot: u64) -> error::Result<Option<CompressedAccount>> {
+        let epochs = self.session
+            .execute(&self.statements.select_account_version_epochs_before, (pubkey, epoch(slot)))
+            .await?
+            .rows_typed::<(i64,)>()?
+            .map(|row| row.map(|(epoch,)| epoch))
+            .collect::<Result<Vec<_>, _>>()?;
+        for epoch in epochs {
+            let row = self.session
+                .execute(&self.statements.select_account_at_slot, (pubkey, epoch, slot.min(i64::MAX as u64) as i64))
+                .await?
+                .maybe_first_row_typed::<AccountRow>()?;
+            if let Some(row) = row {
+                return Ok(Some(account_from_row(row)));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn get_account_versions(
+        &self,
+        pubkey: &[u8],
+        start_slot: u64,
+        end_slot: u64,
+        limit: usize,
+    ) -> error::Result<Vec<CompressedAccount>> {
+        let epochs = self.session
+            .execute(&self.statements.select_account_version_epochs, (pubkey, epoch(start_slot), epoch(end_slot)))
+            .await?
+            .rows_typed::<(i64,)>()?
+            .map(|row| row.map(|(epoch,)| epoch))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (start_slot, end_slot) = (start_slot.min(i64::MAX as u64) as i64, end_slot.min(i64::MAX as u64) as i64);
+        let mut accounts = Vec::new();
+        for epoch in epochs {
+            if accounts.len() >= limit {
+                break;
+            }
+            let remaining = (limit - accounts.len()).min(i32::MAX as usize) as i32;
+            let rows = self.session
+                .execute(&self.statements.select_account_versions, (pubkey, epoch, start_slot, end_slot, remaining))
+                .await?
+                .rows_typed::<AccountRow>()?;
+            for row in rows {
+                accounts.push(account_from_row(row?));
+            }
+        }
+        Ok(accounts)
+    }
+
+    async fn get_account_pubkeys(&self, after: Option<&[u8]>, limit: usize) -> error::Result<Vec<Vec<u8>>> {
+        let rows: Vec<(Vec<u8>,)> = self.scan_page(&self.statements.scan_account_pubkeys, after, limit).await?;
+        Ok(rows.into_iter().map(|(pubkey,)| pubkey).collect())
+    }
+
+    async fn get_transaction_signatures(&self, after: Option<&[u8]>, limit: usize) -> error::Result<Vec<Vec<u8>>> {
+        let rows: Vec<(Vec<u8>,)> = self.scan_page(&self.statements.scan_transaction_signatures, after, limit).await?;
+        Ok(rows.into_iter().map(|(signature,)| signature).collect())
+    }
+
+    async fn prune(&self, request: &PruneRequest) -> error::Result<PruneStats> {
+        let mut stats = PruneStats::default();
+        stats.transactions = self.prune_transactions(request).await?;
+        if let Some(before) = request.blocks_before {
+            stats.blocks = self.prune_blocks(before).await?;
+        }
+        if let Some(before) = request.account_versions_before {
+            stats.account_versions = self.prune_account_versions(request, before).await?;
+        }
+        Ok(stats)
+    }
+
+    async fn get_last_processed_slot(&self) -> error::Result<u64> {
+        let row = self.session
+            .execute(&self.statements.select_cursor, (CURSOR_NAME,))
+            .await?
+            .maybe_first_row_typed::<(i64,)>()?;
+        Ok(row.map_or(0, |(slot,)| slot as u64))
+    }
+
+    // Only the indexer moves the cursor, so reading it first is enough to keep it from going back.
+    async fn update_last_processed_slot(&self, slot: u64) -> error::Result<()> {
+        let cursor = self.get_last_processed_slot().await?;
+        if slot < cursor {
+            return Err(cursor_moved_back(cursor, slot));
+        }
         self.session
-            .query(
-                "INSERT INTO compressed_accounts (pubkey, lamports, owner, executable, rent_epoch, data, proof) \
-                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            .execute(&self.statements.update_cursor, (CURSOR_NAME, slot as i64))
+            .await?;
+        Ok(())
+    }
+
+    // Every write is an idempotent upsert, so the cursor row doubles as the commit marker.
+    async fn commit_block(&self, batch: &BlockBatch) -> error::Result<()> {
+        let transactions: Vec<_> = batch.transactions.iter().collect();
+        let accounts: Vec<_> = batch.accounts.iter().collect();
+        let blocks: Vec<_> = batch.block.iter().collect();
+        futures::try_join!(
+            self.write_blocks(&blocks),
+            self.write_transactions(&transactions),
+            self.write_accounts(&accounts),
+        )?;
+        self.update_last_processed_slot(batch.slot).await
+    }
+}
+
+#[async_trait]
+impl CompressionStore for ScyllaStorage {
+    async fn get_compressed_slot(&self) -> Result<u64> {
+        let row = self.session
+            .execute(&self.statements.select_cursor, (COMPRESSED_CURSOR_NAME,))
+            .await?
+            .maybe_first_row_typed::<(i64,)>()?;
+        Ok(row.map_or(0, |(slot,)| slot as u64))
+    }
+
+    async fn update_compressed_slot(&self, slot: u64) -> Result<()> {
+        self.session
+            .execute(&self.statements.update_cursor, (COMPRESSED_CURSOR_NAME, slot as i64))
+            .await?;
+        Ok(())
+    }
+
+    async fn upsert_merkle_tree(&self, tree: &MerkleTreeRecord) -> Result<()> {
+        self.session
+            .execute(
+                &self.statements.insert_merkle_tree,
                 (
-                    &account.pubkey,
-                    account.lamports,
+                    &tree.tree,
+                    tree.max_depth as i32,
+                    tree.max_buffer_size as i32,
+                    tree.hasher as i8,
+                    &tree.leaves,
+                    &tree.recent_roots,
+                    tree.seq as i64,
+                    tree.slot as i64,
+                    tree.diverged_seq.map(|seq| seq as i64),
+                    tree.chain_seq.map(|seq| seq as i64),
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_merkle_tree(&self, tree: &[u8]) -> Result<Option<MerkleTreeRecord>> {
+        let row = self.session
+            .execute(&self.statements.select_merkle_tree, (tree,))
+            .await?
+            .maybe_first_row_typed::<MerkleTreeRow>()?;
+        Ok(row.map(merkle_tree_record))
+    }
+
+    async fn get_merkle_trees(&self) -> Result<Vec<MerkleTreeRecord>> {
+        let rows: Vec<MerkleTreeRow> = self.session
+            .execute_iter(self.statements.select_merkle_trees.clone(), ())
+            .await?
+            .into_typed::<MerkleTreeRow>()
+            .try_collect()
+            .await?;
+        Ok(rows.into_iter().map(merkle_tree_record).collect())
+    }
+
+    async fn delete_merkle_tree(&self, tree: &[u8]) -> Result<()> {
+        for statement in [
+            &self.statements.delete_leaf_changes,
+            &self.statements.delete_tree_roots,
+            &self.statements.delete_merkle_tree,
+        ] {
+            self.session.execute(statement, (tree,)).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_tree_root(&self, root: &TreeRootRecord) -> Result<()> {
+        self.session
+            .execute(&self.statements.insert_tree_root, (&root.tree, root.seq as i64, &root.root, root.slot as i64))
+            .await?;
+        Ok(())
+    }
+
+    async fn get_tree_roots(&self, tree: &[u8]) -> Result<Vec<TreeRootRecord>> {
+        let roots = self.session
+            .execute_iter(self.statements.select_tree_roots.clone(), (tree,))
+            .await?
+            .into_typed::<(Vec<u8>, i64, Vec<u8>, i64)>()
+            .map_ok(|(tree, seq, root, slot)| TreeRootRecord {
+                tree,
+                seq: seq as u64,
+                root,
+                slot: slot as u64,
+            })
+            .try_collect()
+            .await?;
+        Ok(roots)
+    }
+
+    async fn insert_leaf_change(&self, change: &LeafChangeRecord) -> Result<()> {
+        self.session
+            .execute(
+                &self.statements.insert_leaf_change,
+                (
+                    &change.tree,
+                    change.seq as i64,
+                    change.leaf_index as i32,
+                    &change.previous_leaf,
+                    &change.new_leaf,
+                    change.slot as i64,
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_leaf_changes(&self, tree: &[u8], after_seq: u64) -> Result<Vec<LeafChangeRecord>> {
+        // Paged, since a tree replays every change since its last checkpoint.
+        let changes = self.session
+            .execute_iter(self.statements.select_leaf_changes.clone(), (tree, after_seq as i64))
+            .await?
+            .into_typed::<(Vec<u8>, i64, i32, Vec<u8>, Vec<u8>, i64)>()
+            .map_ok(|(tree, seq, leaf_index, previous_leaf, new_leaf, slot)| LeafChangeRecord {
+                tree,
+                seq: seq as u64,
+                leaf_index: leaf_index as u32,
+                previous_leaf,
+                new_leaf,
+                slot: slot as u64,
+            })
+            .try_collect()
+            .await?;
+        Ok(changes)
+    }
+
+    async fn upsert_state_account(&self, account: &CompressedStateAccount) -> Result<()> {
+        self.session
+            .execute(
+                &self.statements.insert_state_account,
+                (
+                    &account.hash,
+                    &account.address,
                     &account.owner,
-                    account.executable,
-                    account.rent_epoch,
+                    account.lamports as i64,
+                    &account.discriminator,
                     &account.data,
-                    &account.proof,
+                    &account.data_hash,
+                    &account.tree,
+                    account.leaf_index as i32,
+                    account.seq.map(|s| s as i64),
+                    account.slot_created as i64,
+                    account.spent,
+                    account.spent_slot.map(|s| s as i64),
                 ),
             )
             .await?;
+        if !account.spent {
+            self.session
+                .execute(&self.statements.insert_state_account_by_owner, (&account.owner, &account.hash))
+                .await?;
+        }
+        if let Some(address) = &account.address {
+            self.session
+                .execute(&self.statements.insert_state_account_by_address, (address, &account.hash))
+                .await?;
+        }
         Ok(())
     }
 
-    async fn get_compressed_account(&self, pubkey: &[u8]) -> Result<CompressedAccount> {
-        let result = self.session
-            .query("SELECT * FROM compressed_accounts WHERE pubkey = ?", (pubkey,))
+    async fn get_state_account(&self, hash: &[u8]) -> Result<Option<CompressedStateAccount>> {
+        let row = self.session
+            .execute(&self.statements.select_state_account, (hash,))
             .await?
-            .first_row()?;
-        Ok(CompressedAccount {
-            pubkey: result.get("pubkey")?,
-            lamports: result.get("lamports")?,
-            owner: result.get("owner")?,
-            executable: result.get("executable")?,
-            rent_epoch: result.get("rent_epoch")?,
-            data: result.get("data")?,
-            proof: result.get("proof")?,
-        })
+            .maybe_first_row_typed::<StateAccountRow>()?;
+        Ok(row.map(state_account_from_row))
+    }
+
+    async fn get_state_account_by_address(&self, address: &[u8]) -> Result<Option<CompressedStateAccount>> {
+        let row = self.session
+            .execute(&self.statements.select_state_account_by_address, (address,))
+            .await?
+            .maybe_first_row_typed::<(Vec<u8>,)>()?;
+        match row {
+            Some((hash,)) => self.get_state_account(&hash).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_state_accounts_by_owner(&self, owner: &[u8]) -> Result<Vec<CompressedStateAccount>> {
+        let rows = self.session
+            .execute(&self.statements.select_state_accounts_by_owner, (owner,))
+            .await?
+            .rows_typed::<(Vec<u8>,)>()?;
+        let mut accounts = Vec::new();
+        for row in rows {
+            let (hash,) = row?;
+            if let Some(account) = self.get_state_account(&hash).await? {
+                accounts.push(account);
+            }
+        }
+        Ok(accounts)
     }
 
-    async fn insert_compressed_block(&self, block: &CompressedBlock) -> Result<()> {
+    async fn spend_state_account(&self, hash: &[u8], slot: u64) -> Result<()> {
+        let Some(account) = self.get_state_account(hash).await? else {
+            return Ok(());
+        };
         self.session
-            .query(
-                "INSERT INTO compressed_blocks (slot, blockhash, previous_blockhash, parent_slot, transactions, data, proof) \
-                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            .execute(&self.statements.spend_state_account, (slot as i64, hash))
+            .await?;
+        self.session
+            .execute(&self.statements.delete_state_account_by_owner, (&account.owner, hash))
+            .await?;
+        Ok(())
+    }
+
+    async fn insert_address(&self, address: &AddressRecord) -> Result<()> {
+        self.session
+            .execute(
+                &self.statements.insert_address,
                 (
-                    block.slot,
-                    &block.blockhash,
-                    &block.previous_blockhash,
-                    block.parent_slot,
-                    block.transactions,
-                    &block.data,
-                    &block.proof,
+                    &address.address,
+                    &address.tree,
+                    address.leaf_index as i32,
+                    &address.next_address,
+                    address.slot as i64,
                 ),
             )
             .await?;
+        self.session
+            .execute(&self.statements.insert_address_by_tree, (&address.tree, &address.address))
+            .await?;
         Ok(())
     }
 
-    async fn get_compressed_block(&self, slot: u64) -> Result<CompressedBlock> {
-        let result = self.session
-            .query("SELECT * FROM compressed_blocks WHERE slot = ?", (slot,))
+    async fn get_address(&self, address: &[u8]) -> Result<Option<AddressRecord>> {
+        let row = self.session
+            .execute(&self.statements.select_address, (address,))
             .await?
-            .first_row()?;
-        Ok(CompressedBlock {
-            slot: result.get("slot")?,
-            blockhash: result.get("blockhash")?,
-            previous_blockhash: result.get("previous_blockhash")?,
-            parent_slot: result.get("parent_slot")?,
-            transactions: result.get("transactions")?,
-            data: result.get("data")?,
-            proof: result.get("proof")?,
-        })
+            .maybe_first_row_typed::<AddressRow>()?;
+        Ok(row.map(address_from_row))
     }
 
-    async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> Result<()> {
+    async fn get_low_address(&self, tree: &[u8], address: &[u8]) -> Result<Option<AddressRecord>> {
+        let row = self.session
+            .execute(&self.statements.select_low_address, (tree, address))
+            .await?
+            .maybe_first_row_typed::<(Vec<u8>,)>()?;
+        match row {
+            Some((low,)) => self.get_address(&low).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn upsert_token_account(&self, account: &CompressedTokenAccountRecord) -> Result<()> {
         self.session
-            .query(
-                "INSERT INTO compressed_transactions (signature, data, proof) VALUES (?, ?, ?)",
-                (&transaction.signature, &transaction.data, &transaction.proof),
+            .execute(
+                &self.statements.insert_token_account,
+                (
+                    &account.hash,
+                    &account.mint,
+                    &account.owner,
+                    account.amount as i64,
+                    &account.delegate,
+                    account.state as i8,
+                    &account.tlv,
+                    account.slot as i64,
+                ),
             )
             .await?;
+        self.session
+            .execute(&self.statements.insert_token_account_by_owner, (&account.owner, &account.hash))
+            .await?;
+        self.session
+            .execute(&self.statements.insert_token_account_by_mint, (&account.mint, &account.hash))
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_token_account(&self, hash: &[u8]) -> Result<()> {
+        let Some(account) = self.get_token_account(hash).await? else {
+            return Ok(());
+        };
+        self.session
+            .execute(&self.statements.delete_token_account, (hash,))
+            .await?;
+        self.session
+            .execute(&self.statements.delete_token_account_by_owner, (&account.owner, hash))
+            .await?;
+        self.session
+            .execute(&self.statements.delete_token_account_by_mint, (&account.mint, hash))
+            .await?;
         Ok(())
     }
 
-    async fn get_compressed_transaction(&self, signature: &[u8]) -> Result<CompressedTransaction> {
-        let result = self.session
-            .query("SELECT * FROM compressed_transactions WHERE signature = ?", (signature,))
+    async fn get_token_account(&self, hash: &[u8]) -> Result<Option<CompressedTokenAccountRecord>> {
+        let row = self.session
+            .execute(&self.statements.select_token_account, (hash,))
             .await?
-            .first_row()?;
-        Ok(CompressedTransaction {
-            signature: result.get("signature")?,
-            data: result.get("data")?,
-            proof: result.get("proof")?,
-        })
+            .maybe_first_row_typed::<TokenAccountRow>()?;
+        Ok(row.map(token_account_from_row))
     }
 
-    async fn get_last_processed_slot(&self) -> Result<u64> {
-        let result = self.session
-            .query("SELECT MAX(slot) as last_slot FROM compressed_blocks", ())
+    async fn get_token_accounts_by_owner(&self, owner: &[u8]) -> Result<Vec<CompressedTokenAccountRecord>> {
+        let rows = self.session
+            .execute(&self.statements.select_token_accounts_by_owner, (owner,))
             .await?
-            .first_row()?;
-        Ok(result.get("last_slot")?.unwrap_or(0))
+            .rows_typed::<(Vec<u8>,)>()?;
+        let mut accounts = Vec::new();
+        for row in rows {
+            let (hash,) = row?;
+            if let Some(account) = self.get_token_account(&hash).await? {
+                accounts.push(account);
+            }
+        }
+        Ok(accounts)
     }
 
-    async fn update_last_processed_slot(&self, slot: u64) -> Result<()> {
+    async fn get_token_accounts_by_mint(&self, mint: &[u8]) -> Result<Vec<CompressedTokenAccountRecord>> {
+        let rows = self.session
+            .execute(&self.statements.select_token_accounts_by_mint, (mint,))
+            .await?
+            .rows_typed::<(Vec<u8>,)>()?;
+        let mut accounts = Vec::new();
+        for row in rows {
+            let (hash,) = row?;
+            if let Some(account) = self.get_token_account(&hash).await? {
+                accounts.push(account);
+            }
+        }
+        Ok(accounts)
+    }
+
+    async fn upsert_token_mint(&self, mint: &TokenMintRecord) -> Result<()> {
+        self.session
+            .execute(
+                &self.statements.insert_token_mint,
+                (
+                    &mint.mint,
+                    &mint.program_id,
+                    mint.supply as i64,
+                    mint.decimals as i8,
+                    mint.slot as i64,
+                ),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_token_mint(&self, mint: &[u8]) -> Result<Option<TokenMintRecord>> {
+        let row = self.session
+            .execute(&self.statements.select_token_mint, (mint,))
+            .await?
+            .maybe_first_row_typed::<TokenMintRow>()?;
+        Ok(row.map(token_mint_from_row))
+    }
+
+    async fn upsert_asset(&self, asset: &CompressedAssetRecord) -> Result<()> {
+        if let Some(previous) = self.get_asset(&asset.asset_id).await? {
+            if previous.owner != asset.owner {
+                self.session
+                    .execute(&self.statements.delete_asset_by_owner, (&previous.owner, &asset.asset_id))
+                    .await?;
+            }
+        }
         self.session
-            .query(
-                "INSERT INTO indexer_state (key, value) VALUES ('last_processed_slot', ?) ON CONFLICT (key) DO UPDATE SET value = ?",
-                (slot, slot),
+            .execute(
+                &self.statements.insert_asset,
+                (
+                    &asset.asset_id,
+                    &asset.tree,
+                    asset.leaf_index as i32,
+                    asset.nonce as i64,
+                    &asset.owner,
+                    &asset.delegate,
+                    &asset.data_hash,
+                    &asset.creator_hash,
+                    &asset.leaf_hash,
+                    asset.seq as i64,
+                    asset.burned,
+                    asset.redeemed,
+                    asset.slot_updated as i64,
+                ),
             )
             .await?;
+        self.session
+            .execute(&self.statements.insert_asset_by_owner, (&asset.owner, &asset.asset_id))
+            .await?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn get_asset(&self, asset_id: &[u8]) -> Result<Option<CompressedAssetRecord>> {
+        let row = self.session
+            .execute(&self.statements.select_asset, (asset_id,))
+            .await?
+            .maybe_first_row_typed::<AssetRow>()?;
+        Ok(row.map(asset_from_row))
+    }
+
+    async fn get_assets_by_owner(&self, owner: &[u8]) -> Result<Vec<CompressedAssetRecord>> {
+        let rows = self.session
+            .execute(&self.statements.select_assets_by_owner, (owner,))
+            .await?
+            .rows_typed::<(Vec<u8>,)>()?;
+        let mut assets = Vec::new();
+        for row in rows {
+            let (asset_id,) = row?;
+            if let Some(asset) = self.get_asset(&asset_id).await? {
+                assets.push(asset);
+            }
+        }
+        Ok(assets)
+    }
+
+    async fn list_state_accounts(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<CompressedStateAccount>> {
+        let rows = self.scan_page(&self.statements.scan_state_accounts, after, limit).await?;
+        Ok(rows.into_iter().map(state_account_from_row).collect())
+    }
+
+    async fn list_addresses(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<AddressRecord>> {
+        let rows = self.scan_page(&self.statements.scan_addresses, after, limit).await?;
+        Ok(rows.into_iter().map(address_from_row).collect())
+    }
+
+    async fn list_token_accounts(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<CompressedTokenAccountRecord>> {
+        let rows = self.scan_page(&self.statements.scan_token_accounts, after, limit).await?;
+        Ok(rows.into_iter().map(token_account_from_row).collect())
+    }
+
+    async fn list_token_mints(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<TokenMintRecord>> {
+        let rows = self.scan_page(&self.statements.scan_token_mints, after, limit).await?;
+        Ok(rows.into_iter().map(token_mint_from_row).collect())
+    }
+
+    async fn list_assets(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<CompressedAssetRecord>> {
+        let rows = self.scan_page(&self.statements.scan_assets, after, limit).await?;
+        Ok(rows.into_iter().map(asset_from_row).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance::{service_url, storage_conformance};
+
+    storage_conformance!(
+        ignore = "needs ScyllaDB with the windexer keyspace at WINDEXER_TEST_SCYLLA_URL",
+        ScyllaStorage::new(&service_url("WINDEXER_TEST_SCYLLA_URL", "localhost:9042"))
+            .await
+            .unwrap()
+    );
+}
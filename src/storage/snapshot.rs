@@ -0,0 +1,434 @@
+//! Point-in-time snapshots of the whole index, for bootstrapping a node without replaying
+//! from genesis. A snapshot is a CAR file laid out like [`export_car`](crate::storage::export_car)
+//! archives but rooted at a [`SnapshotManifest`]. It only holds backend-neutral records, so a
+//! snapshot taken from one backend restores into any other.
+
+use anyhow::{bail, Context, Result};
+use cid::Cid;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::Path;
+use crate::storage::car::CarWriter;
+use crate::storage::{CarArchive, CarVersion, CompressedAccount, CompressionStore, Database, Link};
+
+const SNAPSHOT_VERSION: u64 = 2;
+// The largest limit every backend can bind.
+const ALL_VERSIONS: usize = i64::MAX as usize;
+// Rows read per call when walking a whole table.
+const ROWS_PER_READ: usize = 1_000;
+
+/// Identifies the Groth16 keys of one validity circuit shape.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvingKeyFingerprint {
+    pub depth: u32,
+    pub inclusions: u32,
+    /// SHA-256 of the compressed verifying key.
+    #[serde(with = "serde_bytes")]
+    pub fingerprint: Vec<u8>,
+    pub non_inclusions: u32,
+}
+
+impl ProvingKeyFingerprint {
+    fn shape(&self) -> (u32, u32, u32) {
+        (self.depth, self.inclusions, self.non_inclusions)
+    }
+}
+
+/// Root node of a snapshot. `slot` is the cursor a restored node resumes from, and every
+/// link field links to pages of raw leaves as in a [`CarManifest`](crate::storage::CarManifest).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub slot: u64,
+    pub mints: Vec<Link>,
+    pub roots: Vec<Link>,
+    pub trees: Vec<Link>,
+    pub assets: Vec<Link>,
+    pub blocks: Vec<Link>,
+    pub version: u64,
+    /// Every version of each account written up to `slot`, oldest first.
+    pub accounts: Vec<Link>,
+    pub addresses: Vec<Link>,
+    pub leaf_changes: Vec<Link>,
+    pub proving_keys: Vec<ProvingKeyFingerprint>,
+    pub state_accounts: Vec<Link>,
+    pub token_accounts: Vec<Link>,
+    /// Compression store cursor the compressed state was read at, if the snapshot has any.
+    pub compressed_slot: Option<u64>,
+}
+
+/// Links to the rows of every `CompressionStore` table.
+#[derive(Default)]
+struct CompressedLinks {
+    mints: Vec<Link>,
+    roots: Vec<Link>,
+    trees: Vec<Link>,
+    assets: Vec<Link>,
+    addresses: Vec<Link>,
+    leaf_changes: Vec<Link>,
+    state_accounts: Vec<Link>,
+    token_accounts: Vec<Link>,
+}
+
+/// Creates and restores snapshots of a `Database`, and of a `CompressionStore` when one
+/// is given.
+pub struct Snapshot<'a> {
+    db: &'a dyn Database,
+    compression: Option<&'a dyn CompressionStore>,
+    proving_keys: Vec<ProvingKeyFingerprint>,
+}
+
+impl<'a> Snapshot<'a> {
+    pub fn new(db: &'a dyn Database) -> Self {
+        Self {
+            db,
+            compression: None,
+            proving_keys: Vec::new(),
+        }
+    }
+
+    pub fn with_compression_store(mut self, store: &'a dyn CompressionStore) -> Self {
+        self.compression = Some(store);
+        self
+    }
+
+    /// Fingerprints to record in created snapshots, and to check restored ones against.
+    pub fn with_proving_keys(mut self, proving_keys: Vec<ProvingKeyFingerprint>) -> Self {
+        self.proving_keys = proving_keys;
+        self
+    }
+
+    /// Writes the index as of `slot`, which must not be past the cursor, and returns the
+    /// manifest CID. Blocks up to `slot` no longer change and accounts are read at `slot`,
+    /// so the indexer can keep running.
+    ///
+    /// Compressed state only has its latest version, so it is taken as of the compression
+    /// store's cursor instead. Tables are read after the cursor, so they hold every change up
+    /// to it and possibly part of a later slot, which a restored node applies again in full.
+    pub async fn create(&self, slot: u64, path: impl AsRef<Path>) -> Result<Cid> {
+        let cursor = self.db.get_last_processed_slot().await?;
+        if slot > cursor {
+            bail!("Cannot snapshot slot {}, the index has only processed up to slot {}", slot, cursor);
+        }
+        let mut writer = CarWriter::create(path.as_ref(), CarVersion::V1).await?;
+        let slot_links = writer.put_slots(self.db, 0..=slot, None).await?;
+
+        let mut account_links = Vec::new();
+        let mut after = None;
+        loop {
+            let pubkeys = self.db.get_account_pubkeys(after.as_deref(), ROWS_PER_READ).await?;
+            for pubkey in &pubkeys {
+                for account in self.db.get_account_versions(pubkey, 0, slot, ALL_VERSIONS).await? {
+                    account_links.push(writer.put_leaf(&account).await?);
+                }
+            }
+            match pubkeys.into_iter().last() {
+                Some(last) => after = Some(last),
+                None => break,
+            }
+        }
+
+        let (compressed_slot, compressed) = match self.compression {
+            Some(store) => {
+                let compressed_slot = store.get_compressed_slot().await?;
+                (Some(compressed_slot), put_compressed(store, &mut writer).await?)
+            }
+            None => (None, CompressedLinks::default()),
+        };
+
+        let manifest = SnapshotManifest {
+            slot,
+            mints: writer.put_pages(compressed.mints).await?,
+            roots: writer.put_pages(compressed.roots).await?,
+            trees: writer.put_pages(compressed.trees).await?,
+            assets: writer.put_pages(compressed.assets).await?,
+            blocks: writer.put_pages(slot_links).await?,
+            version: SNAPSHOT_VERSION,
+            accounts: writer.put_pages(account_links).await?,
+            addresses: writer.put_pages(compressed.addresses).await?,
+            leaf_changes: writer.put_pages(compressed.leaf_changes).await?,
+            proving_keys: self.proving_keys.clone(),
+            state_accounts: writer.put_pages(compressed.state_accounts).await?,
+            token_accounts: writer.put_pages(compressed.token_accounts).await?,
+            compressed_slot,
+        };
+        let root = writer.put_node(&manifest).await?.0;
+        writer.finish(root, CarVersion::V1).await?;
+        Ok(root)
+    }
+
+    /// Loads a snapshot into an empty index. The cursor is moved last, so an interrupted
+    /// restore can simply be run again. It resumes from `slot`, or from the compressed slot
+    /// if that is earlier, since compressed state is only applied to blocks as they are indexed.
+    pub async fn restore(&self, path: impl AsRef<Path>) -> Result<SnapshotManifest> {
+        let cursor = self.db.get_last_processed_slot().await?;
+        if cursor != 0 {
+            bail!("Cannot restore a snapshot into an index that has processed up to slot {}", cursor);
+        }
+        let mut archive = CarArchive::open(path)?;
+        let root = Link(archive.root());
+        let manifest: SnapshotManifest = archive.node(&root).context("Archive is not a snapshot")?;
+        if manifest.version != SNAPSHOT_VERSION {
+            bail!("Unsupported snapshot version {}", manifest.version);
+        }
+        if manifest.compressed_slot.is_some() && self.compression.is_none() {
+            bail!("Snapshot has compressed state but the target storage has no compression store");
+        }
+        for recorded in &manifest.proving_keys {
+            let local = self.proving_keys.iter().find(|local| local.shape() == recorded.shape());
+            if let Some(local) = local.filter(|local| local.fingerprint != recorded.fingerprint) {
+                warn!(
+                    "Snapshot proving key for {:?} is {}, this node has {}",
+                    recorded.shape(),
+                    hex::encode(&recorded.fingerprint),
+                    hex::encode(&local.fingerprint)
+                );
+            }
+        }
+
+        for link in archive.paged(&manifest.blocks)? {
+            let slot = archive.slot(&link)?;
+            self.db.insert_compressed_block(&slot.block).await?;
+            for transaction in &slot.transactions {
+                self.db.insert_compressed_transaction(transaction).await?;
+            }
+        }
+        for link in archive.paged(&manifest.accounts)? {
+            let account: CompressedAccount = archive.leaf(&link)?;
+            self.db.insert_compressed_account(&account).await?;
+        }
+        let mut resume = manifest.slot;
+        if let (Some(store), Some(compressed_slot)) = (self.compression, manifest.compressed_slot) {
+            for link in archive.paged(&manifest.state_accounts)? {
+                store.upsert_state_account(&archive.leaf(&link)?).await?;
+            }
+            for link in archive.paged(&manifest.addresses)? {
+                store.insert_address(&archive.leaf(&link)?).await?;
+            }
+            for link in archive.paged(&manifest.token_accounts)? {
+                store.upsert_token_account(&archive.leaf(&link)?).await?;
+            }
+            for link in archive.paged(&manifest.mints)? {
+                store.upsert_token_mint(&archive.leaf(&link)?).await?;
+            }
+            for link in archive.paged(&manifest.assets)? {
+                store.upsert_asset(&archive.leaf(&link)?).await?;
+            }
+            for link in archive.paged(&manifest.roots)? {
+                store.insert_tree_root(&archive.leaf(&link)?).await?;
+            }
+            for link in archive.paged(&manifest.leaf_changes)? {
+                store.insert_leaf_change(&archive.leaf(&link)?).await?;
+            }
+            for link in archive.paged(&manifest.trees)? {
+                store.upsert_merkle_tree(&archive.leaf(&link)?).await?;
+            }
+            store.update_compressed_slot(compressed_slot).await?;
+            resume = resume.min(compressed_slot);
+        }
+        self.db.update_last_processed_slot(resume).await?;
+        Ok(manifest)
+    }
+}
+
+/// Writes every row of the compression store. Each tree is read before its leaf changes, so
+/// the changes cover everything past the seq it was checkpointed at.
+async fn put_compressed(store: &dyn CompressionStore, writer: &mut CarWriter) -> Result<CompressedLinks> {
+    let mut links = CompressedLinks::default();
+    for tree in store.get_merkle_trees().await? {
+        links.trees.push(writer.put_leaf(&tree).await?);
+        for root in store.get_tree_roots(&tree.tree).await? {
+            links.roots.push(writer.put_leaf(&root).await?);
+        }
+        for change in store.get_leaf_changes(&tree.tree, 0).await? {
+            links.leaf_changes.push(writer.put_leaf(&change).await?);
+        }
+    }
+    links.state_accounts = put_rows(
+        writer,
+        |after| async move { store.list_state_accounts(after.as_deref(), ROWS_PER_READ).await },
+        |account| &account.hash,
+    )
+    .await?;
+    links.addresses = put_rows(
+        writer,
+        |after| async move { store.list_addresses(after.as_deref(), ROWS_PER_READ).await },
+        |address| &address.address,
+    )
+    .await?;
+    links.token_accounts = put_rows(
+        writer,
+        |after| async move { store.list_token_accounts(after.as_deref(), ROWS_PER_READ).await },
+        |account| &account.hash,
+    )
+    .await?;
+    links.mints = put_rows(
+        writer,
+        |after| async move { store.list_token_mints(after.as_deref(), ROWS_PER_READ).await },
+        |mint| &mint.mint,
+    )
+    .await?;
+    links.assets = put_rows(
+        writer,
+        |after| async move { store.list_assets(after.as_deref(), ROWS_PER_READ).await },
+        |asset| &asset.asset_id,
+    )
+    .await?;
+    Ok(links)
+}
+
+/// Writes the rows `list` pages through, passing it the key of the last row read so far.
+async fn put_rows<T, F, Fut>(writer: &mut CarWriter, mut list: F, key: fn(&T) -> &Vec<u8>) -> Result<Vec<Link>>
+where
+    T: Serialize,
+    F: FnMut(Option<Vec<u8>>) -> Fut,
+    Fut: Future<Output = Result<Vec<T>>>,
+{
+    let mut links = Vec::new();
+    let mut after = None;
+    loop {
+        let rows = list(after.take()).await?;
+        let Some(last) = rows.last() else {
+            return Ok(links);
+        };
+        after = Some(key(last).clone());
+        for row in &rows {
+            links.push(writer.put_leaf(row).await?);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{
+        BlockBatch, CompressedBlock, CompressedTransaction, InMemoryStorage, LeafChangeRecord, MerkleTreeRecord,
+        TokenMintRecord, TreeRootRecord,
+    };
+
+    fn account(pubkey: u8, slot: u64, lamports: i64) -> CompressedAccount {
+        CompressedAccount {
+            pubkey: vec![pubkey; 32],
+            lamports,
+            owner: vec![3; 32],
+            executable: false,
+            rent_epoch: 0,
+            slot,
+            write_version: 0,
+            data: vec![],
+            proof: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        let source = InMemoryStorage::new();
+        for slot in 1..=3 {
+            let mut batch = BlockBatch::new(slot);
+            batch.block = Some(CompressedBlock {
+                slot,
+                blockhash: format!("hash-{}", slot),
+                previous_blockhash: format!("hash-{}", slot - 1),
+                parent_slot: slot - 1,
+                transactions: 1,
+                block_time: None,
+                data: vec![slot as u8; 8],
+                proof: vec![],
+            });
+            batch.transactions.push(CompressedTransaction {
+                signature: vec![slot as u8; 64],
+                slot,
+                index: 0,
+                accounts: vec![vec![1; 32]],
+                fee: 5000,
+                programs: Vec::new(),
+                data: vec![],
+                proof: vec![],
+            });
+            batch.accounts.push(account(1, slot, slot as i64 * 10));
+            // No transaction references this one.
+            batch.accounts.push(account(2, slot, slot as i64));
+            source.commit_block(&batch).await.unwrap();
+        }
+
+        // Compressed state has moved on to slot 3.
+        let tree = vec![4; 32];
+        source
+            .upsert_merkle_tree(&MerkleTreeRecord {
+                tree: tree.clone(),
+                max_depth: 26,
+                max_buffer_size: 8,
+                hasher: 0,
+                leaves: vec![vec![5; 32]],
+                recent_roots: vec![vec![6; 32]],
+                seq: 1,
+                chain_seq: None,
+                slot: 3,
+                diverged_seq: None,
+            })
+            .await
+            .unwrap();
+        source
+            .insert_tree_root(&TreeRootRecord { tree: tree.clone(), seq: 1, root: vec![6; 32], slot: 3 })
+            .await
+            .unwrap();
+        source
+            .insert_leaf_change(&LeafChangeRecord {
+                tree: tree.clone(),
+                seq: 1,
+                leaf_index: 0,
+                previous_leaf: vec![0; 32],
+                new_leaf: vec![5; 32],
+                slot: 3,
+            })
+            .await
+            .unwrap();
+        for mint in 0..3u8 {
+            source
+                .upsert_token_mint(&TokenMintRecord {
+                    mint: vec![mint; 32],
+                    program_id: vec![7; 32],
+                    supply: mint as u64,
+                    decimals: 6,
+                    slot: 3,
+                })
+                .await
+                .unwrap();
+        }
+        source.update_compressed_slot(3).await.unwrap();
+        let proving_keys = vec![ProvingKeyFingerprint {
+            depth: 26,
+            inclusions: 1,
+            fingerprint: vec![7; 32],
+            non_inclusions: 0,
+        }];
+
+        let path = std::env::temp_dir().join(format!("windexer-test-snapshot-{}.car", std::process::id()));
+        let snapshot = Snapshot::new(&source)
+            .with_compression_store(&source)
+            .with_proving_keys(proving_keys.clone());
+        assert!(snapshot.create(4, &path).await.is_err());
+        snapshot.create(2, &path).await.unwrap();
+
+        let target = InMemoryStorage::new();
+        assert!(Snapshot::new(&target).restore(&path).await.is_err());
+        let manifest = Snapshot::new(&target).with_compression_store(&target).restore(&path).await.unwrap();
+        assert_eq!(manifest.proving_keys, proving_keys);
+        assert_eq!(manifest.compressed_slot, Some(3));
+        assert_eq!(target.get_last_processed_slot().await.unwrap(), 2);
+        assert_eq!(target.block_count(), 2);
+        assert_eq!(target.get_compressed_transaction(&[2; 64]).await.unwrap().fee, 5000);
+        assert_eq!(target.get_compressed_account(&[1; 32]).await.unwrap().lamports, 20);
+        assert_eq!(target.get_account_versions(&[1; 32], 0, 2, 10).await.unwrap().len(), 2);
+        assert_eq!(target.get_compressed_account(&[2; 32]).await.unwrap().lamports, 2);
+
+        assert_eq!(target.get_compressed_slot().await.unwrap(), 3);
+        assert_eq!(target.get_merkle_tree(&tree).await.unwrap().unwrap().slot, 3);
+        assert_eq!(target.get_tree_roots(&tree).await.unwrap().len(), 1);
+        assert_eq!(target.get_leaf_changes(&tree, 0).await.unwrap().len(), 1);
+        assert_eq!(target.list_token_mints(None, 10).await.unwrap().len(), 3);
+
+        assert!(Snapshot::new(&target).with_compression_store(&target).restore(&path).await.is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,668 @@
+use async_trait::async_trait;
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteRow,
+};
+use sqlx::migrate::Migrator;
+use sqlx::{ConnectOptions, Row};
+use crate::storage::database::{cursor_moved_back, signature_bounds};
+use crate::storage::error::{Result, StorageError};
+use crate::storage::migrations::{run_sqlx, MigrationReport};
+use crate::storage::{
+    BlockBatch, Database, CompressedAccount, CompressedBlock, CompressedTransaction, PruneRequest, PruneStats,
+    Reclaimed, SignatureInfo, SignatureQuery, VOTE_PROGRAM_ID,
+};
+use std::path::Path;
+use std::str::FromStr;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+const MAX_CONNECTIONS: u32 = 8;
+// Rows deleted per statement while pruning, to keep each write lock short.
+const PRUNE_CHUNK: i64 = 10_000;
+
+/// In-process storage backed by a single SQLite file, for local development and tests.
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    pub async fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        Self::connect(options, MAX_CONNECTIONS).await
+    }
+
+    /// Accepts `sqlite://path/to/db` and `sqlite::memory:` URLs.
+    pub async fn new(url: &str) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        Self::connect(options, MAX_CONNECTIONS).await
+    }
+
+    /// Each in-memory connection is its own database, so the pool holds exactly one.
+    pub async fn in_memory() -> anyhow::Result<Self> {
+        Self::connect(SqliteConnectOptions::from_str("sqlite::memory:")?, 1).await
+    }
+
+    async fn connect(options: SqliteConnectOptions, max_connections: u32) -> anyhow::Result<Self> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await?;
+        run_sqlx(&MIGRATOR, &mut *pool.acquire().await?, "sqlite", true).await?;
+        Ok(Self { pool })
+    }
+
+    /// Applies pending migrations, or with `apply` unset only reports them.
+    pub async fn migrate(url: &str, apply: bool) -> anyhow::Result<MigrationReport> {
+        let mut conn = SqliteConnectOptions::from_str(url)?.create_if_missing(true).connect().await?;
+        run_sqlx(&MIGRATOR, &mut conn, "sqlite", apply).await
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    async fn prune_blocks(&self, before: i64) -> Result<Reclaimed> {
+        let mut reclaimed = Reclaimed::default();
+        loop {
+            let sizes: Vec<i64> = sqlx::query_scalar(
+                "DELETE FROM compressed_blocks WHERE slot IN \
+                 (SELECT b.slot FROM compressed_blocks b WHERE b.slot < ? \
+                  AND NOT EXISTS (SELECT 1 FROM compressed_transactions t WHERE t.slot = b.slot) LIMIT ?) \
+                 RETURNING length(data) + length(proof)",
+            )
+            .bind(before)
+            .bind(PRUNE_CHUNK)
+            .fetch_all(&self.pool)
+            .await?;
+            for size in &sizes {
+                reclaimed.add(*size as u64);
+            }
+            if (sizes.len() as i64) < PRUNE_CHUNK {
+                return Ok(reclaimed);
+            }
+        }
+    }
+
+    // Account lists are bincode blobs here, so addresses are matched through `signatures_by_address`.
+    async fn prune_transactions(&self, before: i64, vote_before: i64, protected: &str) -> Result<Reclaimed> {
+        let mut reclaimed = Reclaimed::default();
+        loop {
+            let mut tx = self.pool.begin().await?;
+            let rows = sqlx::query(
+                "DELETE FROM compressed_transactions WHERE signature IN \
+                 (SELECT t.signature FROM compressed_transactions t \
+                  WHERE (t.slot < ? OR (t.slot < ? AND EXISTS (SELECT 1 FROM signatures_by_address s \
+                   WHERE s.address = ? AND s.slot = t.slot AND s.tx_index = t.tx_index))) \
+                  AND NOT EXISTS (SELECT 1 FROM json_each(?) k JOIN signatures_by_address s \
+                   ON s.address = unhex(k.value) AND s.slot = t.slot AND s.tx_index = t.tx_index) \
+                  LIMIT ?) \
+                 RETURNING slot, tx_index, accounts, length(data) + length(proof) AS size",
+            )
+            .bind(before)
+            .bind(vote_before)
+            .bind(&VOTE_PROGRAM_ID[..])
+            .bind(protected)
+            .bind(PRUNE_CHUNK)
+            .fetch_all(&mut *tx)
+            .await?;
+            for row in &rows {
+                let (slot, index): (i64, i64) = (row.try_get("slot")?, row.try_get("tx_index")?);
+                let accounts: Vec<Vec<u8>> = bincode::deserialize(row.try_get::<&[u8], _>("accounts")?)?;
+                for address in accounts {
+                    sqlx::query("DELETE FROM signatures_by_address WHERE address = ? AND slot = ? AND tx_index = ?")
+                        .bind(address)
+                        .bind(slot)
+                        .bind(index)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                reclaimed.add(row.try_get::<i64, _>("size")? as u64);
+            }
+            tx.commit().await?;
+            if (rows.len() as i64) < PRUNE_CHUNK {
+                return Ok(reclaimed);
+            }
+        }
+    }
+
+    async fn prune_account_versions(&self, before: i64, protected: &str) -> Result<Reclaimed> {
+        let mut reclaimed = Reclaimed::default();
+        loop {
+            let sizes: Vec<i64> = sqlx::query_scalar(
+                "DELETE FROM account_versions WHERE (pubkey, slot, write_version) IN \
+                 (SELECT v.pubkey, v.slot, v.write_version FROM account_versions v \
+                  JOIN compressed_accounts a ON a.pubkey = v.pubkey \
+                  WHERE v.slot < ? AND (v.slot, v.write_version) < (a.slot, a.write_version) \
+                  AND NOT EXISTS (SELECT 1 FROM json_each(?) k WHERE unhex(k.value) IN (v.pubkey, v.owner)) \
+                  LIMIT ?) \
+                 RETURNING length(data) + length(proof)",
+            )
+            .bind(before)
+            .bind(protected)
+            .bind(PRUNE_CHUNK)
+            .fetch_all(&self.pool)
+            .await?;
+            for size in &sizes {
+                reclaimed.add(*size as u64);
+            }
+            if (sizes.len() as i64) < PRUNE_CHUNK {
+                return Ok(reclaimed);
+            }
+        }
+    }
+}
+
+// Unset cutoffs become -1, which no slot is below.
+fn cutoff(before: Option<u64>) -> i64 {
+    before.map_or(-1, |before| before.min(i64::MAX as u64) as i64)
+}
+
+const ACCOUNT_COLUMNS: &str = "pubkey, lamports, owner, executable, rent_epoch, slot, write_version, data, proof";
+const BLOCK_COLUMNS: &str = "slot, blockhash, previous_blockhash, parent_slot, transactions, block_time, data, proof";
+const TRANSACTION_COLUMNS: &str = "signature, slot, tx_index, accounts, fee, programs, data, proof";
+
+// SQLite integers are signed 64-bit, so slots round-trip through i64.
+fn account_from_row(row: &SqliteRow) -> Result<CompressedAccount> {
+    Ok(CompressedAccount {
+        pubkey: row.try_get("pubkey")?,
+        lamports: row.try_get("lamports")?,
+        owner: row.try_get("owner")?,
+        executable: row.try_get("executable")?,
+        rent_epoch: row.try_get("rent_epoch")?,
+        slot: row.try_get::<i64, _>("slot")? as u64,
+        write_version: row.try_get::<i64, _>("write_version")? as u64,
+        data: row.try_get("data")?,
+        proof: row.try_get("proof")?,
+    })
+}
+
+fn block_from_row(row: &SqliteRow) -> Result<CompressedBlock> {
+    Ok(CompressedBlock {
+        slot: row.try_get::<i64, _>("slot")? as u64,
+        blockhash: row.try_get("blockhash")?,
+        previous_blockhash: row.try_get("previous_blockhash")?,
+        parent_slot: row.try_get::<i64, _>("parent_slot")? as u64,
+        transactions: row.try_get::<i64, _>("transactions")? as u64,
+        block_time: row.try_get("block_time")?,
+        data: row.try_get("data")?,
+        proof: row.try_get("proof")?,
+    })
+}
+
+fn transaction_from_row(row: &SqliteRow) -> Result<CompressedTransaction> {
+    Ok(CompressedTransaction {
+        signature: row.try_get("signature")?,
+        slot: row.try_get::<i64, _>("slot")? as u64,
+        index: row.try_get::<i32, _>("tx_index")? as u32,
+        accounts: bincode::deserialize(row.try_get::<&[u8], _>("accounts")?)?,
+        fee: row.try_get::<i64, _>("fee")? as u64,
+        programs: bincode::deserialize(row.try_get::<&[u8], _>("programs")?)?,
+        data: row.try_get("data")?,
+        proof: row.try_get("proof")?,
+    })
+}
+
+// Every write is kept in `account_versions`; `compressed_accounts` holds the newest version.
+async fn insert_account(conn: &mut SqliteConnection, account: &CompressedAccount) -> Result<()> {
+    for statement in [
+        "INSERT INTO account_versions \
+         (pubkey, lamports, owner, executable, rent_epoch, slot, write_version, data, proof) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT (pubkey, slot, write_version) DO UPDATE SET \
+         lamports = EXCLUDED.lamports, owner = EXCLUDED.owner, executable = EXCLUDED.executable, \
+         rent_epoch = EXCLUDED.rent_epoch, data = EXCLUDED.data, proof = EXCLUDED.proof",
+        "INSERT INTO compressed_accounts \
+         (pubkey, lamports, owner, executable, rent_epoch, slot, write_version, data, proof) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT (pubkey) DO UPDATE SET \
+         lamports = EXCLUDED.lamports, owner = EXCLUDED.owner, executable = EXCLUDED.executable, \
+         rent_epoch = EXCLUDED.rent_epoch, slot = EXCLUDED.slot, write_version = EXCLUDED.write_version, \
+         data = EXCLUDED.data, proof = EXCLUDED.proof \
+         WHERE (compressed_accounts.slot, compressed_accounts.write_version) <= (EXCLUDED.slot, EXCLUDED.write_version)",
+    ] {
+        sqlx::query(statement)
+            .bind(&account.pubkey)
+            .bind(account.lamports)
+            .bind(&account.owner)
+            .bind(account.executable)
+            .bind(account.rent_epoch)
+            .bind(account.slot as i64)
+            .bind(account.write_version as i64)
+            .bind(&account.data)
+            .bind(&account.proof)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn insert_block(conn: &mut SqliteConnection, block: &CompressedBlock) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO compressed_blocks \
+         (slot, blockhash, previous_blockhash, parent_slot, transactions, block_time, data, proof) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT (slot) DO UPDATE SET \
+         blockhash = EXCLUDED.blockhash, previous_blockhash = EXCLUDED.previous_blockhash, \
+         parent_slot = EXCLUDED.parent_slot, transactions = EXCLUDED.transactions, \
+         block_time = EXCLUDED.block_time, data = EXCLUDED.data, proof = EXCLUDED.proof",
+    )
+    .bind(block.slot as i64)
+    .bind(&block.blockhash)
+    .bind(&block.previous_blockhash)
+    .bind(block.parent_slot as i64)
+    .bind(block.transactions as i64)
+    .bind(block.block_time)
+    .bind(&block.data)
+    .bind(&block.proof)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+async fn insert_transaction(conn: &mut SqliteConnection, transaction: &CompressedTransaction) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO compressed_transactions (signature, slot, tx_index, accounts, fee, programs, data, proof) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?) \
+         ON CONFLICT (signature) DO UPDATE SET \
+         slot = EXCLUDED.slot, tx_index = EXCLUDED.tx_index, accounts = EXCLUDED.accounts, \
+         fee = EXCLUDED.fee, programs = EXCLUDED.programs, data = EXCLUDED.data, proof = EXCLUDED.proof",
+    )
+    .bind(&transaction.signature)
+    .bind(transaction.slot as i64)
+    .bind(transaction.index as i32)
+    .bind(bincode::serialize(&transaction.accounts)?)
+    .bind(transaction.fee as i64)
+    .bind(bincode::serialize(&transaction.programs)?)
+    .bind(&transaction.data)
+    .bind(&transaction.proof)
+    .execute(&mut *conn)
+    .await?;
+    for address in &transaction.accounts {
+        sqlx::query(
+            "INSERT INTO signatures_by_address (address, slot, tx_index, signature) \
+             VALUES (?, ?, ?, ?) ON CONFLICT DO NOTHING",
+        )
+        .bind(address)
+        .bind(transaction.slot as i64)
+        .bind(transaction.index as i32)
+        .bind(&transaction.signature)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+async fn update_cursor(conn: &mut SqliteConnection, slot: u64) -> Result<()> {
+    let result = sqlx::query(
+        "INSERT INTO indexer_state (key, value) VALUES ('last_processed_slot', ?) \
+         ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value WHERE indexer_state.value <= EXCLUDED.value",
+    )
+    .bind(slot as i64)
+    .execute(&mut *conn)
+    .await?;
+    if result.rows_affected() == 0 {
+        let cursor: i64 = sqlx::query_scalar("SELECT value FROM indexer_state WHERE key = 'last_processed_slot'")
+            .fetch_one(&mut *conn)
+            .await?;
+        return Err(cursor_moved_back(cursor as u64, slot));
+    }
+    Ok(())
+}
+
+// Moves a cursor inside `start_slot..=end_slot` back to just before it.
+async fn rewind_cursor(conn: &mut SqliteConnection, start_slot: i64, end_slot: i64) -> Result<()> {
+    sqlx::query("UPDATE indexer_state SET value = ? WHERE key = 'last_processed_slot' AND value BETWEEN ? AND ?")
+        .bind((start_slot - 1).max(0))
+        .bind(start_slot)
+        .bind(end_slot)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+#[async_trait]
+impl Database for SqliteStorage {
+    async fn insert_compressed_account(&self, account: &CompressedAccount) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        insert_account(&mut tx, account).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_compressed_account(&self, pubkey: &[u8]) -> Result<CompressedAccount> {
+        let row = sqlx::query(&format!("SELECT {} FROM compressed_accounts WHERE pubkey = ?", ACCOUNT_COLUMNS))
+            .bind(pubkey)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| StorageError::not_found(format!("compressed account {}", hex::encode(pubkey))))?;
+        account_from_row(&row)
+    }
+
+    async fn insert_compressed_block(&self, block: &CompressedBlock) -> Result<()> {
+        insert_block(&mut *self.pool.acquire().await?, block).await
+    }
+
+    async fn get_compressed_block(&self, slot: u64) -> Result<CompressedBlock> {
+        let row = sqlx::query(&format!("SELECT {} FROM compressed_blocks WHERE slot = ?", BLOCK_COLUMNS))
+            .bind(slot as i64)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| StorageError::not_found(format!("compressed block {}", slot)))?;
+        block_from_row(&row)
+    }
+
+    async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        insert_transaction(&mut tx, transaction).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_compressed_transaction(&self, signature: &[u8]) -> Result<CompressedTransaction> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM compressed_transactions WHERE signature = ?",
+            TRANSACTION_COLUMNS
+        ))
+        .bind(signature)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| StorageError::not_found(format!("compressed transaction {}", hex::encode(signature))))?;
+        transaction_from_row(&row)
+    }
+
+    async fn get_accounts_by_owner(&self, owner: &[u8], limit: usize) -> Result<Vec<CompressedAccount>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM compressed_accounts WHERE owner = ? ORDER BY pubkey LIMIT ?",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(owner)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(account_from_row).collect()
+    }
+
+    async fn get_signatures_for_address(&self, address: &[u8], query: &SignatureQuery) -> Result<Vec<SignatureInfo>> {
+        let (before, until) = signature_bounds(self, query).await?;
+        let before = before.map_or((i64::MAX, i32::MAX), |(slot, index)| (slot as i64, index as i32));
+        let until = until.map_or((-1, -1), |(slot, index)| (slot as i64, index as i32));
+        let rows = sqlx::query(
+            "SELECT signature, slot, tx_index FROM signatures_by_address \
+             WHERE address = ? AND (slot, tx_index) < (?, ?) AND (slot, tx_index) > (?, ?) \
+             ORDER BY slot DESC, tx_index DESC LIMIT ?",
+        )
+        .bind(address)
+        .bind(before.0)
+        .bind(before.1)
+        .bind(until.0)
+        .bind(until.1)
+        .bind(query.limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(SignatureInfo {
+                    signature: row.try_get("signature")?,
+                    slot: row.try_get::<i64, _>("slot")? as u64,
+                    index: row.try_get::<i32, _>("tx_index")? as u32,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_blocks_in_range(&self, start_slot: u64, end_slot: u64) -> Result<Vec<CompressedBlock>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM compressed_blocks WHERE slot BETWEEN ? AND ? ORDER BY slot",
+            BLOCK_COLUMNS
+        ))
+        .bind(start_slot as i64)
+        .bind(end_slot.min(i64::MAX as u64) as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(block_from_row).collect()
+    }
+
+    async fn get_first_block_slot(&self) -> Result<Option<u64>> {
+        let slot: Option<i64> = sqlx::query_scalar("SELECT MIN(slot) FROM compressed_blocks")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(slot.map(|slot| slot as u64))
+    }
+
+    async fn get_transactions_in_block(&self, slot: u64) -> Result<Vec<CompressedTransaction>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM compressed_transactions WHERE slot = ? ORDER BY tx_index",
+            TRANSACTION_COLUMNS
+        ))
+        .bind(slot as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(transaction_from_row).collect()
+    }
+
+    async fn delete_slot_range(&self, start_slot: u64, end_slot: u64) -> Result<u64> {
+        let (start_slot, end_slot) = (start_slot.min(i64::MAX as u64) as i64, end_slot.min(i64::MAX as u64) as i64);
+        let mut tx = self.pool.begin().await?;
+        let mut deleted = 0;
+        for table in ["signatures_by_address", "compressed_transactions", "compressed_blocks"] {
+            let result = sqlx::query(&format!("DELETE FROM {} WHERE slot BETWEEN ? AND ?", table))
+                .bind(start_slot)
+                .bind(end_slot)
+                .execute(&mut *tx)
+                .await?;
+            if table != "signatures_by_address" {
+                deleted += result.rows_affected();
+            }
+        }
+        rewind_cursor(&mut tx, start_slot, end_slot).await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn get_account_at_slot(&self, pubkey: &[u8], slot: u64) -> Result<Option<CompressedAccount>> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM account_versions WHERE pubkey = ? AND slot <= ? \
+             ORDER BY slot DESC, write_version DESC LIMIT 1",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(pubkey)
+        .bind(slot.min(i64::MAX as u64) as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(account_from_row).transpose()
+    }
+
+    async fn get_account_versions(
+        &self,
+        pubkey: &[u8],
+        start_slot: u64,
+        end_slot: u64,
+        limit: usize,
+    ) -> Result<Vec<CompressedAccount>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM account_versions WHERE pubkey = ? AND slot BETWEEN ? AND ? \
+             ORDER BY slot, write_version LIMIT ?",
+            ACCOUNT_COLUMNS
+        ))
+        .bind(pubkey)
+        .bind(start_slot.min(i64::MAX as u64) as i64)
+        .bind(end_slot.min(i64::MAX as u64) as i64)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(account_from_row).collect()
+    }
+
+    async fn get_account_pubkeys(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        // Every key sorts after the empty blob.
+        let rows = sqlx::query(
+            "SELECT DISTINCT pubkey FROM account_versions WHERE pubkey > ? ORDER BY pubkey LIMIT ?",
+        )
+        .bind(after.unwrap_or_default())
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(|row| Ok(row.try_get("pubkey")?)).collect()
+    }
+
+    async fn get_transaction_signatures(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        let rows = sqlx::query(
+            "SELECT signature FROM compressed_transactions WHERE signature > ? ORDER BY signature LIMIT ?",
+        )
+        .bind(after.unwrap_or_default())
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(|row| Ok(row.try_get("signature")?)).collect()
+    }
+
+    async fn prune(&self, request: &PruneRequest) -> Result<PruneStats> {
+        // Protected addresses are bound as a JSON array of hex strings.
+        let protected = format!(
+            "[{}]",
+            request
+                .protected_addresses
+                .iter()
+                .map(|address| format!("\"{}\"", hex::encode(address)))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let mut stats = PruneStats::default();
+        if request.transactions_before.is_some() || request.vote_transactions_before.is_some() {
+            stats.transactions = self
+                .prune_transactions(
+                    cutoff(request.transactions_before),
+                    cutoff(request.vote_transactions_before),
+                    &protected,
+                )
+                .await?;
+        }
+        // After transactions, so a block is only dropped once none of its transactions are kept.
+        if request.blocks_before.is_some() {
+            stats.blocks = self.prune_blocks(cutoff(request.blocks_before)).await?;
+        }
+        if request.account_versions_before.is_some() {
+            stats.account_versions = self
+                .prune_account_versions(cutoff(request.account_versions_before), &protected)
+                .await?;
+        }
+        Ok(stats)
+    }
+
+    async fn get_last_processed_slot(&self) -> Result<u64> {
+        let slot: Option<i64> = sqlx::query_scalar("SELECT value FROM indexer_state WHERE key = 'last_processed_slot'")
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(slot.unwrap_or(0) as u64)
+    }
+
+    async fn update_last_processed_slot(&self, slot: u64) -> Result<()> {
+        update_cursor(&mut *self.pool.acquire().await?, slot).await
+    }
+
+    async fn commit_block(&self, batch: &BlockBatch) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        if let Some(block) = &batch.block {
+            insert_block(&mut tx, block).await?;
+        }
+        for transaction in &batch.transactions {
+            insert_transaction(&mut tx, transaction).await?;
+        }
+        for account in &batch.accounts {
+            insert_account(&mut tx, account).await?;
+        }
+        update_cursor(&mut tx, batch.slot).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance::storage_conformance;
+
+    storage_conformance!(SqliteStorage::in_memory().await.unwrap());
+
+    #[tokio::test]
+    async fn test_sqlite_storage() {
+        let storage = SqliteStorage::in_memory().await.unwrap();
+
+        let account = CompressedAccount {
+            pubkey: vec![1; 32],
+            lamports: 5,
+            owner: vec![2; 32],
+            executable: false,
+            rent_epoch: 0,
+            slot: 0,
+            write_version: 0,
+            data: vec![3],
+            proof: vec![],
+        };
+        storage.insert_compressed_account(&account).await.unwrap();
+        storage
+            .insert_compressed_account(&CompressedAccount { lamports: 7, ..account })
+            .await
+            .unwrap();
+        assert_eq!(storage.get_compressed_account(&[1; 32]).await.unwrap().lamports, 7);
+        assert!(storage.get_compressed_account(&[9; 32]).await.is_err());
+
+        let block = CompressedBlock {
+            slot: 7,
+            blockhash: "hash".to_string(),
+            previous_blockhash: "prev".to_string(),
+            parent_slot: 8,
+            transactions: 2,
+            block_time: Some(1_700_000_000),
+            data: vec![],
+            proof: vec![],
+        };
+        storage.insert_compressed_block(&block).await.unwrap();
+        let fetched = storage.get_compressed_block(7).await.unwrap();
+        assert_eq!((fetched.parent_slot, fetched.block_time), (8, Some(1_700_000_000)));
+
+        for (signature, index) in [(4u8, 0), (5, 1)] {
+            let transaction = CompressedTransaction {
+                signature: vec![signature; 64],
+                slot: 8,
+                index,
+                accounts: vec![vec![1; 32], vec![signature; 32]],
+                fee: 5000,
+                programs: vec![vec![signature; 32]],
+                data: vec![],
+                proof: vec![],
+            };
+            storage.insert_compressed_transaction(&transaction).await.unwrap();
+        }
+        let transactions = storage.get_transactions_in_block(8).await.unwrap();
+        assert_eq!(transactions[1].accounts[1], vec![5; 32]);
+        assert_eq!((transactions[1].fee, &transactions[1].programs[..]), (5000, &[vec![5; 32]][..]));
+        let query = SignatureQuery {
+            before: Some(vec![5; 64]),
+            ..Default::default()
+        };
+        let signatures = storage.get_signatures_for_address(&[1; 32], &query).await.unwrap();
+        assert_eq!(signatures.len(), 1);
+        assert_eq!(signatures[0].signature, vec![4; 64]);
+        assert_eq!(storage.get_accounts_by_owner(&[2; 32], 10).await.unwrap().len(), 1);
+        assert_eq!(storage.get_blocks_in_range(0, u64::MAX).await.unwrap().len(), 1);
+
+        let request = PruneRequest {
+            blocks_before: Some(8),
+            transactions_before: Some(9),
+            protected_addresses: vec![vec![5; 32]],
+            ..Default::default()
+        };
+        let stats = storage.prune(&request).await.unwrap();
+        assert_eq!((stats.blocks.rows, stats.transactions.rows), (1, 1));
+        assert_eq!(storage.get_transactions_in_block(8).await.unwrap()[0].signature, vec![5; 64]);
+        assert!(storage.get_signatures_for_address(&[4; 32], &SignatureQuery::default()).await.unwrap().is_empty());
+
+        assert_eq!(storage.get_last_processed_slot().await.unwrap(), 0);
+        storage.update_last_processed_slot(10).await.unwrap();
+        assert_eq!(storage.get_last_processed_slot().await.unwrap(), 10);
+    }
+}
@@ -0,0 +1,444 @@
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use log::{error, info};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::metrics;
+use crate::storage::database::signature_bounds;
+use crate::storage::error::{Result, StorageError};
+use crate::storage::retention::slots_in;
+use crate::storage::{
+    BlockBatch, Database, CompressedAccount, CompressedBlock, CompressedTransaction, PruneRequest, PruneStats,
+    SignatureInfo, SignatureQuery, TokenTransfer,
+};
+
+const MOVE_CHUNK_SLOTS: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    Hot,
+    Cold,
+}
+
+impl Tier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hot => "hot",
+            Self::Cold => "cold",
+        }
+    }
+}
+
+/// Keeps recent slots in `hot` and older ones in `cold`.
+///
+/// Slots up to the migration watermark live in the cold tier, everything newer in the hot one.
+/// The watermark is the cold tier's cursor; the hot tier holds the indexer cursor and all
+/// accounts. Lookups by signature or pubkey try the hot tier and fall through to the cold one.
+pub struct TieredStorage {
+    hot: Arc<dyn Database>,
+    cold: Arc<dyn Database>,
+    hot_slots: u64,
+    migrated: AtomicU64,
+    // Whether the hot copy of the last chunk moved before this process started is gone.
+    trimmed: AtomicBool,
+}
+
+impl TieredStorage {
+    /// Slots become eligible for the cold tier once they are `max_hot_age` behind the cursor,
+    /// at 400ms per slot.
+    pub async fn new(hot: Arc<dyn Database>, cold: Arc<dyn Database>, max_hot_age: Duration) -> Result<Self> {
+        let migrated = cold.get_last_processed_slot().await?;
+        Ok(Self {
+            hot,
+            cold,
+            hot_slots: slots_in(max_hot_age),
+            migrated: AtomicU64::new(migrated),
+            trimmed: AtomicBool::new(migrated == 0),
+        })
+    }
+
+    /// Highest slot that has been moved to the cold tier.
+    pub fn migrated_through(&self) -> u64 {
+        self.migrated.load(Ordering::SeqCst)
+    }
+
+    fn tier_for(&self, slot: u64) -> Tier {
+        if slot <= self.migrated_through() {
+            Tier::Cold
+        } else {
+            Tier::Hot
+        }
+    }
+
+    fn tier(&self, tier: Tier) -> &dyn Database {
+        match tier {
+            Tier::Hot => self.hot.as_ref(),
+            Tier::Cold => self.cold.as_ref(),
+        }
+    }
+
+    async fn fall_through<'a, T>(
+        &'a self,
+        read: impl Fn(&'a dyn Database) -> BoxFuture<'a, Result<T>>,
+    ) -> Result<(T, Tier)> {
+        let served = match read(self.hot.as_ref()).await {
+            Ok(value) => (value, Tier::Hot),
+            Err(hot_error) => match read(self.cold.as_ref()).await {
+                Ok(value) => (value, Tier::Cold),
+                // Only a miss in both tiers is a miss; otherwise report why the hot tier failed.
+                Err(StorageError::NotFound(_)) if !matches!(hot_error, StorageError::NotFound(_)) => return Err(hot_error),
+                Err(cold_error) => return Err(cold_error),
+            },
+        };
+        metrics::increment_storage_tier_reads(served.1.as_str()).await;
+        Ok(served)
+    }
+
+    pub async fn get_compressed_account_with_tier(&self, pubkey: &[u8]) -> Result<(CompressedAccount, Tier)> {
+        self.fall_through(|db| db.get_compressed_account(pubkey)).await
+    }
+
+    pub async fn get_compressed_block_with_tier(&self, slot: u64) -> Result<(CompressedBlock, Tier)> {
+        let tier = self.tier_for(slot);
+        let block = self.tier(tier).get_compressed_block(slot).await?;
+        metrics::increment_storage_tier_reads(tier.as_str()).await;
+        Ok((block, tier))
+    }
+
+    pub async fn get_compressed_transaction_with_tier(&self, signature: &[u8]) -> Result<(CompressedTransaction, Tier)> {
+        self.fall_through(|db| db.get_compressed_transaction(signature)).await
+    }
+
+    /// Moves every slot that has aged out of the hot window to the cold tier, one chunk at a
+    /// time: copy, advance the watermark, then delete from the hot tier. Returns the number of
+    /// slots moved.
+    ///
+    /// A run that stopped between advancing the watermark and deleting left its last chunk in
+    /// both tiers, where reads only see the cold copy. Chunks end at the watermark and span at
+    /// most `MOVE_CHUNK_SLOTS`, so the first run after a restart deletes that window again.
+    pub async fn migrate(&self) -> Result<u64> {
+        if !self.trimmed.load(Ordering::SeqCst) {
+            let migrated = self.migrated_through();
+            self.hot
+                .delete_slot_range(migrated.saturating_sub(MOVE_CHUNK_SLOTS - 1), migrated)
+                .await?;
+            self.trimmed.store(true, Ordering::SeqCst);
+        }
+        let cutoff = self
+            .hot
+            .get_last_processed_slot()
+            .await?
+            .saturating_sub(self.hot_slots);
+        let mut moved = 0;
+        while self.migrated_through() < cutoff {
+            let start = self.migrated_through() + 1;
+            let end = cutoff.min(start + MOVE_CHUNK_SLOTS - 1);
+            for block in self.hot.get_blocks_in_range(start, end).await? {
+                let mut batch = BlockBatch::new(block.slot);
+                batch.transactions = self.hot.get_transactions_in_block(block.slot).await?;
+                batch.token_transfers = self.hot.get_token_transfers_in_block(block.slot).await?;
+                batch.block = Some(block);
+                self.cold.commit_block(&batch).await?;
+            }
+            self.cold.update_last_processed_slot(end).await?;
+            self.migrated.store(end, Ordering::SeqCst);
+            self.hot.delete_slot_range(start, end).await?;
+            moved += end - start + 1;
+        }
+        Ok(moved)
+    }
+
+    pub async fn run_mover(self: Arc<Self>, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match self.migrate().await {
+                Ok(0) => {}
+                Ok(moved) => info!("Moved {} slots to cold storage, now through slot {}", moved, self.migrated_through()),
+                Err(e) => error!("Error moving slots to cold storage: {:?}", e),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Database for TieredStorage {
+    async fn insert_compressed_account(&self, account: &CompressedAccount) -> Result<()> {
+        self.hot.insert_compressed_account(account).await
+    }
+
+    async fn get_compressed_account(&self, pubkey: &[u8]) -> Result<CompressedAccount> {
+        Ok(self.get_compressed_account_with_tier(pubkey).await?.0)
+    }
+
+    async fn insert_compressed_block(&self, block: &CompressedBlock) -> Result<()> {
+        self.tier(self.tier_for(block.slot)).insert_compressed_block(block).await
+    }
+
+    async fn get_compressed_block(&self, slot: u64) -> Result<CompressedBlock> {
+        Ok(self.get_compressed_block_with_tier(slot).await?.0)
+    }
+
+    async fn insert_compressed_transaction(&self, transaction: &CompressedTransaction) -> Result<()> {
+        self.tier(self.tier_for(transaction.slot))
+            .insert_compressed_transaction(transaction)
+            .await
+    }
+
+    async fn get_compressed_transaction(&self, signature: &[u8]) -> Result<CompressedTransaction> {
+        Ok(self.get_compressed_transaction_with_tier(signature).await?.0)
+    }
+
+    async fn get_accounts_by_owner(&self, owner: &[u8], limit: usize) -> Result<Vec<CompressedAccount>> {
+        let mut accounts = self.hot.get_accounts_by_owner(owner, limit).await?;
+        metrics::increment_storage_tier_reads(Tier::Hot.as_str()).await;
+        if accounts.len() < limit {
+            let seen: HashSet<Vec<u8>> = accounts.iter().map(|account| account.pubkey.clone()).collect();
+            let cold = self.cold.get_accounts_by_owner(owner, limit).await?;
+            metrics::increment_storage_tier_reads(Tier::Cold.as_str()).await;
+            accounts.extend(cold.into_iter().filter(|account| !seen.contains(&account.pubkey)));
+            accounts.sort_by(|a, b| a.pubkey.cmp(&b.pubkey));
+            accounts.truncate(limit);
+        }
+        Ok(accounts)
+    }
+
+    // Positions above the watermark come from the hot tier and the rest from the cold one. A
+    // cursor signature is only passed to the tier that holds it; the other tier is either
+    // entirely on one side of it or not queried.
+    async fn get_signatures_for_address(&self, address: &[u8], query: &SignatureQuery) -> Result<Vec<SignatureInfo>> {
+        let (before, until) = signature_bounds(self, query).await?;
+        let migrated = self.migrated_through();
+        let in_cold = |bound: Option<(u64, u32)>| bound.is_some_and(|(slot, _)| slot <= migrated);
+
+        let mut signatures = Vec::new();
+        if !in_cold(before) {
+            let hot_query = SignatureQuery {
+                until: if in_cold(until) { None } else { query.until.clone() },
+                ..query.clone()
+            };
+            signatures.extend(
+                self.hot
+                    .get_signatures_for_address(address, &hot_query)
+                    .await?
+                    .into_iter()
+                    .filter(|signature| signature.slot > migrated),
+            );
+            metrics::increment_storage_tier_reads(Tier::Hot.as_str()).await;
+        }
+        if signatures.len() < query.limit && (until.is_none() || in_cold(until)) {
+            let cold_query = SignatureQuery {
+                before: if in_cold(before) { query.before.clone() } else { None },
+                limit: query.limit - signatures.len(),
+                ..query.clone()
+            };
+            signatures.extend(
+                self.cold
+                    .get_signatures_for_address(address, &cold_query)
+                    .await?
+                    .into_iter()
+                    .filter(|signature| signature.slot <= migrated),
+            );
+            metrics::increment_storage_tier_reads(Tier::Cold.as_str()).await;
+        }
+        signatures.truncate(query.limit);
+        Ok(signatures)
+    }
+
+    async fn get_blocks_in_range(&self, start_slot: u64, end_slot: u64) -> Result<Vec<CompressedBlock>> {
+        let migrated = self.migrated_through();
+        let mut blocks = Vec::new();
+        if start_slot <= migrated {
+            blocks.extend(self.cold.get_blocks_in_range(start_slot, end_slot.min(migrated)).await?);
+            metrics::increment_storage_tier_reads(Tier::Cold.as_str()).await;
+        }
+        if end_slot > migrated {
+            blocks.extend(self.hot.get_blocks_in_range(start_slot.max(migrated + 1), end_slot).await?);
+            metrics::increment_storage_tier_reads(Tier::Hot.as_str()).await;
+        }
+        Ok(blocks)
+    }
+
+    async fn get_first_block_slot(&self) -> Result<Option<u64>> {
+        match self.cold.get_first_block_slot().await? {
+            Some(slot) => Ok(Some(slot)),
+            None => self.hot.get_first_block_slot().await,
+        }
+    }
+
+    async fn get_transactions_in_block(&self, slot: u64) -> Result<Vec<CompressedTransaction>> {
+        let tier = self.tier_for(slot);
+        let transactions = self.tier(tier).get_transactions_in_block(slot).await?;
+        metrics::increment_storage_tier_reads(tier.as_str()).await;
+        Ok(transactions)
+    }
+
+    fn stores_token_transfers(&self) -> bool {
+        self.hot.stores_token_transfers() && self.cold.stores_token_transfers()
+    }
+
+    async fn get_token_transfers_in_block(&self, slot: u64) -> Result<Vec<TokenTransfer>> {
+        let tier = self.tier_for(slot);
+        let transfers = self.tier(tier).get_token_transfers_in_block(slot).await?;
+        metrics::increment_storage_tier_reads(tier.as_str()).await;
+        Ok(transfers)
+    }
+
+    async fn delete_slot_range(&self, start_slot: u64, end_slot: u64) -> Result<u64> {
+        Ok(self.hot.delete_slot_range(start_slot, end_slot).await?
+            + self.cold.delete_slot_range(start_slot, end_slot).await?)
+    }
+
+    // Accounts, and so their history, only live in the hot tier.
+    async fn get_account_at_slot(&self, pubkey: &[u8], slot: u64) -> Result<Option<CompressedAccount>> {
+        self.hot.get_account_at_slot(pubkey, slot).await
+    }
+
+    async fn get_account_versions(
+        &self,
+        pubkey: &[u8],
+        start_slot: u64,
+        end_slot: u64,
+        limit: usize,
+    ) -> Result<Vec<CompressedAccount>> {
+        self.hot.get_account_versions(pubkey, start_slot, end_slot, limit).await
+    }
+
+    async fn get_account_pubkeys(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        self.hot.get_account_pubkeys(after, limit).await
+    }
+
+    // A transaction lives in one tier, so the walk covers the hot tier and then the cold one,
+    // and `after` is looked up to tell which of the two it is in.
+    async fn get_transaction_signatures(&self, after: Option<&[u8]>, limit: usize) -> Result<Vec<Vec<u8>>> {
+        let in_hot = match after {
+            Some(after) => match self.hot.get_compressed_transaction(after).await {
+                Ok(_) => true,
+                Err(StorageError::NotFound(_)) => false,
+                Err(e) => return Err(e),
+            },
+            None => true,
+        };
+        let mut signatures = Vec::new();
+        if in_hot {
+            signatures = self.hot.get_transaction_signatures(after, limit).await?;
+        }
+        if signatures.len() < limit {
+            let after = if in_hot { None } else { after };
+            signatures.extend(self.cold.get_transaction_signatures(after, limit - signatures.len()).await?);
+        }
+        Ok(signatures)
+    }
+
+    async fn prune(&self, request: &PruneRequest) -> Result<PruneStats> {
+        let mut stats = self.hot.prune(request).await?;
+        stats += self.cold.prune(request).await?;
+        Ok(stats)
+    }
+
+    async fn get_last_processed_slot(&self) -> Result<u64> {
+        self.hot.get_last_processed_slot().await
+    }
+
+    async fn update_last_processed_slot(&self, slot: u64) -> Result<()> {
+        self.hot.update_last_processed_slot(slot).await
+    }
+
+    async fn commit_block(&self, batch: &BlockBatch) -> Result<()> {
+        if self.tier_for(batch.slot) == Tier::Hot {
+            return self.hot.commit_block(batch).await;
+        }
+        // Re-indexing an archived slot: its rows go to the cold tier, whose cursor is the
+        // watermark and must not move, while accounts and the cursor stay hot.
+        if let Some(block) = &batch.block {
+            self.cold.insert_compressed_block(block).await?;
+        }
+        for transaction in &batch.transactions {
+            self.cold.insert_compressed_transaction(transaction).await?;
+        }
+        for account in &batch.accounts {
+            self.hot.insert_compressed_account(account).await?;
+        }
+        self.hot.update_last_processed_slot(batch.slot).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::InMemoryStorage;
+    use crate::storage::conformance::storage_conformance;
+
+    storage_conformance!(TieredStorage::new(
+        Arc::new(InMemoryStorage::new()),
+        Arc::new(InMemoryStorage::new()),
+        Duration::from_secs(60),
+    )
+    .await
+    .unwrap());
+
+    #[tokio::test]
+    async fn test_tiered_storage() {
+        let hot = Arc::new(InMemoryStorage::new());
+        let cold = Arc::new(InMemoryStorage::new());
+        // 100 slots at 400ms each
+        let storage = TieredStorage::new(hot.clone(), cold.clone(), Duration::from_secs(40)).await.unwrap();
+
+        for slot in 1..=250 {
+            let mut batch = BlockBatch::new(slot);
+            batch.block = Some(CompressedBlock {
+                slot,
+                blockhash: slot.to_string(),
+                previous_blockhash: (slot - 1).to_string(),
+                parent_slot: slot - 1,
+                transactions: 1,
+                block_time: None,
+                data: vec![],
+                proof: vec![],
+            });
+            batch.transactions.push(CompressedTransaction {
+                signature: slot.to_be_bytes().to_vec(),
+                slot,
+                index: 0,
+                accounts: vec![vec![7; 32]],
+                fee: 0,
+                programs: Vec::new(),
+                data: vec![],
+                proof: vec![],
+            });
+            storage.commit_block(&batch).await.unwrap();
+        }
+
+        assert_eq!(storage.migrate().await.unwrap(), 150);
+        assert_eq!(storage.migrated_through(), 150);
+        assert_eq!((hot.block_count(), cold.block_count()), (100, 150));
+        assert_eq!(storage.get_last_processed_slot().await.unwrap(), 250);
+
+        let (_, tier) = storage.get_compressed_transaction_with_tier(&10u64.to_be_bytes()).await.unwrap();
+        assert_eq!(tier, Tier::Cold);
+        let (_, tier) = storage.get_compressed_block_with_tier(200).await.unwrap();
+        assert_eq!(tier, Tier::Hot);
+
+        // A restart after the watermark moved but before the hot copy was deleted.
+        let block = storage.get_compressed_block(150).await.unwrap();
+        hot.insert_compressed_block(&block).await.unwrap();
+        let storage = TieredStorage::new(hot.clone(), cold.clone(), Duration::from_secs(40)).await.unwrap();
+        assert_eq!(storage.migrate().await.unwrap(), 0);
+        assert_eq!(hot.block_count(), 100);
+        let (_, tier) = storage.get_compressed_block_with_tier(200).await.unwrap();
+        assert_eq!(tier, Tier::Hot);
+
+        let blocks = storage.get_blocks_in_range(140, 160).await.unwrap();
+        assert_eq!(blocks.iter().map(|b| b.slot).collect::<Vec<_>>(), (140..=160).collect::<Vec<_>>());
+
+        let query = SignatureQuery {
+            before: Some(155u64.to_be_bytes().to_vec()),
+            limit: 10,
+            ..Default::default()
+        };
+        let signatures = storage.get_signatures_for_address(&[7; 32], &query).await.unwrap();
+        assert_eq!(signatures.iter().map(|s| s.slot).collect::<Vec<_>>(), (145..155).rev().collect::<Vec<_>>());
+    }
+}
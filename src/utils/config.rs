@@ -8,8 +8,115 @@ pub struct Config {
     pub solana_rpc_url: String,
     pub api_port: u16,
     pub metrics_port: u16,
+    pub grpc_port: u16,
+    /// Proving key, written by `Groth16Prover::write`, that block, transaction and account
+    /// payloads are compressed and proven under.
+    pub compressor_key: String,
     pub log_level: String,
     pub wasm_dir: String,
+    #[serde(default)]
+    pub tiering: Option<TieringConfig>,
+    #[serde(default)]
+    pub cache: Option<CacheConfig>,
+    #[serde(default)]
+    pub retention: Option<RetentionConfig>,
+    #[serde(default)]
+    pub export: Option<ExportConfig>,
+    #[serde(default)]
+    pub encryption: Option<EncryptionConfig>,
+    #[serde(default)]
+    pub prover: Option<ProverConfig>,
+}
+
+/// Moves slots older than `max_hot_age_secs` from `database_url` to `cold_database_url`.
+#[derive(Debug, Deserialize)]
+pub struct TieringConfig {
+    pub cold_database_url: String,
+    pub max_hot_age_secs: u64,
+    #[serde(default = "default_move_interval_secs")]
+    pub move_interval_secs: u64,
+}
+
+fn default_move_interval_secs() -> u64 {
+    60
+}
+
+/// Caches account, block and transaction lookups in Redis.
+#[derive(Debug, Deserialize)]
+pub struct CacheConfig {
+    pub redis_url: String,
+    #[serde(default = "default_cache_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    300
+}
+
+/// Per-kind retention windows; unset kinds are kept forever. Transactions touching a watched
+/// program or account, and the history of watched accounts, are always kept.
+#[derive(Debug, Deserialize)]
+pub struct RetentionConfig {
+    pub block_max_age_secs: Option<u64>,
+    pub transaction_max_age_secs: Option<u64>,
+    pub vote_transaction_max_age_secs: Option<u64>,
+    pub account_history_max_age_secs: Option<u64>,
+    #[serde(default)]
+    pub watched_programs: Vec<String>,
+    #[serde(default)]
+    pub watched_accounts: Vec<String>,
+    #[serde(default = "default_prune_interval_secs")]
+    pub prune_interval_secs: u64,
+}
+
+fn default_prune_interval_secs() -> u64 {
+    3600
+}
+
+/// Exports newly processed slots to Parquet files under `dir`. An empty `tables` exports all.
+#[derive(Debug, Deserialize)]
+pub struct ExportConfig {
+    pub dir: String,
+    #[serde(default)]
+    pub tables: Vec<String>,
+    #[serde(default = "default_export_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_export_interval_secs() -> u64 {
+    3600
+}
+
+/// Encrypts payloads with the master keys in `key_file`, or in the `key_env` variable when no
+/// file is given. New payloads use `active_key_id`, the highest id by default, and rows under
+/// other keys are re-encrypted every `reencrypt_interval_secs` until none are left. With
+/// `require_encryption`, plaintext payloads are read as corrupt rather than passed through.
+#[derive(Debug, Deserialize)]
+pub struct EncryptionConfig {
+    pub key_file: Option<String>,
+    #[serde(default = "default_key_env")]
+    pub key_env: String,
+    pub active_key_id: Option<u32>,
+    #[serde(default = "default_reencrypt_interval_secs")]
+    pub reencrypt_interval_secs: u64,
+    #[serde(default)]
+    pub require_encryption: bool,
+}
+
+fn default_key_env() -> String {
+    "WINDEXER_MASTER_KEYS".to_string()
+}
+
+fn default_reencrypt_interval_secs() -> u64 {
+    600
+}
+
+/// Serves validity proofs from Light's prover server at `url`, checked against the verifying
+/// keys in `verifying_keys_dir` before they are returned.
+#[derive(Debug, Deserialize)]
+pub struct ProverConfig {
+    pub url: String,
+    pub verifying_keys_dir: String,
 }
 
 pub fn load_config() -> Result<Config> {
@@ -1,3 +1,4 @@
+use crate::storage::StorageError;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -13,6 +14,9 @@ pub enum Error {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
 
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+
     #[error("RPC error: {0}")]
     Rpc(#[from] solana_client::client_error::ClientError),
 
@@ -22,6 +26,10 @@ pub enum Error {
     #[error("Compression error: {0}")]
     Compression(String),
 
+    /// The request itself is malformed, e.g. an invalid pubkey.
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+
     #[error("Other error: {0}")]
     Other(String),
 }
\ No newline at end of file